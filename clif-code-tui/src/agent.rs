@@ -0,0 +1,1022 @@
+//! The agent loop as a library API.
+//!
+//! [`Agent::builder`] sets up a backend, workspace, autonomy and tools;
+//! [`Agent::run`] sends one user message and drives tool calls until the model
+//! answers or submits. Progress comes back as [`AgentEvent`]s through an
+//! [`EventHandler`] instead of terminal output, and extra tools plug in through
+//! the [`Tool`] trait. The `clifcode` binary is a terminal frontend over
+//! [`run_turn`].
+//!
+//! ```no_run
+//! use clifcode::{Agent, AgentEvent, Autonomy, ModelBackend, ToolProtocol};
+//!
+//! let mut agent = Agent::builder()
+//!     .backend(ModelBackend::Api {
+//!         url: "https://openrouter.ai/api/v1".into(),
+//!         key: std::env::var("CLIFCODE_API_KEY").ok(),
+//!         model: "anthropic/claude-sonnet-4".into(),
+//!         max_tokens: 8192,
+//!         tool_protocol: ToolProtocol::Native,
+//!     })
+//!     .workspace("/path/to/repo")
+//!     .autonomy(Autonomy::AutoEdit)
+//!     .disable_tool("run_command")
+//!     .build()?;
+//!
+//! agent.run("Add a --verbose flag", &mut |event: &AgentEvent| {
+//!     if let AgentEvent::AssistantMessage { content, .. } = event {
+//!         println!("{content}");
+//!     }
+//! })?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::backend::{self, ModelBackend, TokenUsage};
+use crate::git;
+use crate::message::Message;
+use crate::redact;
+use crate::repomap;
+use crate::session;
+use crate::telemetry;
+use crate::todo::{self, TodoItem};
+use crate::tools::{self, ApiToolCall, ToolResult};
+use crate::ui;
+use anyhow::Result;
+use std::path::Path;
+
+/// How much the agent may do without asking
+#[derive(Clone, Debug, PartialEq)]
+pub enum Autonomy {
+    /// Show diff, ask Y/n before each write/edit
+    Suggest,
+    /// Show diff, apply automatically
+    AutoEdit,
+    /// Apply silently
+    FullAuto,
+}
+
+impl std::fmt::Display for Autonomy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Autonomy::Suggest => write!(f, "suggest"),
+            Autonomy::AutoEdit => write!(f, "auto-edit"),
+            Autonomy::FullAuto => write!(f, "full-auto"),
+        }
+    }
+}
+
+impl std::str::FromStr for Autonomy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        match s {
+            "suggest" => Ok(Autonomy::Suggest),
+            "auto-edit" | "auto" => Ok(Autonomy::AutoEdit),
+            "full-auto" | "full" => Ok(Autonomy::FullAuto),
+            other => Err(format!(
+                "unknown autonomy {other} — use suggest, auto-edit or full-auto"
+            )),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Conversation state
+// ---------------------------------------------------------------------------
+
+/// The message history plus the session todo list.
+/// `messages[0]` is the system prompt.
+#[derive(Debug, Clone, Default)]
+pub struct Conversation {
+    pub messages: Vec<Message>,
+    pub todos: Vec<TodoItem>,
+}
+
+impl Conversation {
+    /// Fresh conversation with the system prompt, repo map and context files
+    pub fn new(workspace: &str, autonomy: &Autonomy, context_files: &[String]) -> Self {
+        let repo_map = repomap::scan_workspace(workspace);
+        let denied = redact::DeniedPaths::for_workspace(workspace);
+        let auto_ctx: Vec<(String, String)> = repomap::auto_context(workspace)
+            .into_iter()
+            .filter(|(name, _)| !denied.contains(Path::new(name)))
+            .collect();
+
+        let mut system_parts = vec![
+            format!(
+                "You are ClifCode, an AI assistant that helps with coding and file tasks.\n\
+                 Workspace: {workspace}\n\
+                 Mode: {autonomy}\n\
+                 Max turns: {}\n\n\
+                 CRITICAL BEHAVIOR RULES:\n\
+                 1. BE PROACTIVE. When the user asks a question, READ files to find the answer. NEVER say \"could you provide more details\" or \"let me know\" when you can look it up yourself.\n\
+                 2. When the user asks \"which is best/most likely/top\", READ the relevant data files and ANALYZE them. Give a direct answer with reasoning.\n\
+                 3. If a file was truncated, use read_file with offset to get the rest. Read the ENTIRE file before answering.\n\
+                 4. Use find_file to locate files by name when you don't know the path.\n\
+                 5. Use change_directory when the user wants to switch to a different folder.\n\
+                 6. Prefer edit_file for targeted changes, write_file for new files. For Jupyter notebooks (.ipynb) use notebook_edit.\n\
+                 7. Call submit when a coding task is done.\n\
+                 8. Remember context from earlier in the conversation.\n\
+                 9. NEVER ask the user to clarify something you can figure out from the files.\n\
+                 10. READ COMPREHENSIVELY. When asked to analyze, summarize, or make recommendations about a directory or project, FIRST use list_files to see everything available, THEN read ALL relevant files — not just 1-2. Read every doc, every config, every data file that could inform your answer. Use multiple read_file calls in the same turn. Partial reading leads to bad answers.\n\
+                 11. When creating a file based on analysis, make sure you have read ALL source material first. If there are 10 relevant files, read all 10 before writing your summary.\n\
+                 12. Use docs to check a crate's API and fetch_url for other documentation pages instead of guessing.\n\
+                 13. Use goto_definition, find_references and hover to navigate code precisely; edit results include the language server's diagnostics — fix any errors they report.\n\
+                 14. For multi-step tasks, track progress with todo_write: mark one item in_progress at a time and completed as soon as it's done.",
+                tools::MAX_TURNS
+            ),
+            format!("Repo map:\n{repo_map}"),
+        ];
+
+        // Auto-context: inject project identity files (README, Cargo.toml, etc.)
+        if !auto_ctx.is_empty() {
+            let names: Vec<&str> = auto_ctx.iter().map(|(n, _)| n.as_str()).collect();
+            ui::print_dim(&format!("  Auto-context: {}", names.join(", ")));
+            for (name, content) in &auto_ctx {
+                let content = redact::redact_context(name, content);
+                system_parts.push(format!("Project file {name}:\n```\n{content}\n```"));
+            }
+        }
+
+        // Manually added context files
+        for file_path in context_files {
+            let full = std::path::Path::new(workspace).join(file_path);
+            if denied.contains(&full) {
+                ui::print_dim(&format!("  Skipped {file_path}: in denied_paths"));
+                continue;
+            }
+            if let Ok(content) = std::fs::read_to_string(&full) {
+                let content = redact::redact_context(file_path, &content);
+                let truncated: String = content.chars().take(4000).collect();
+                system_parts.push(format!("File {file_path}:\n```\n{truncated}\n```"));
+            }
+        }
+
+        // Load .clifrules project rules file if it exists
+        let rules_path = std::path::Path::new(workspace).join(".clifrules");
+        if let Ok(rules_content) = std::fs::read_to_string(&rules_path) {
+            system_parts.push(format!("Project Rules (.clifrules):\n{rules_content}"));
+        }
+
+        let system_content = system_parts.join("\n\n");
+
+        Conversation {
+            messages: vec![Message::system(system_content)],
+            todos: Vec::new(),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Events
+// ---------------------------------------------------------------------------
+
+/// Progress reported while a turn runs
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum AgentEvent {
+    /// A model round-trip is starting. `todo` is (done, total, current item)
+    /// when the session has a todo list.
+    TurnStarted {
+        turn: usize,
+        max_turns: usize,
+        todo: Option<(usize, usize, String)>,
+    },
+    /// Waiting for the model
+    Thinking,
+    /// A streamed chunk of assistant text
+    TextDelta(String),
+    /// The complete assistant text for this round-trip. `streamed` is true when
+    /// it was already delivered as [`AgentEvent::TextDelta`]s.
+    AssistantMessage {
+        content: String,
+        streamed: bool,
+    },
+    ToolStarted {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+    ToolFinished {
+        id: String,
+        name: String,
+        result: ToolResult,
+    },
+    /// A write, edit or notebook edit succeeded. `path` is as the model gave it.
+    FileChanged {
+        path: String,
+    },
+    TodosUpdated(Vec<TodoItem>),
+    WorkspaceChanged(String),
+    /// The model called `submit`
+    TaskComplete {
+        summary: String,
+    },
+    Committed {
+        hash: String,
+    },
+    CommitSkipped {
+        reason: String,
+    },
+    /// The turn stopped after `max_turns` round-trips without an answer
+    TurnLimitReached,
+    /// Informational status, e.g. context compaction
+    Notice(String),
+    /// A recoverable problem reported to the model as a tool error
+    Error(String),
+}
+
+/// Something the agent wants the frontend to allow
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Approval<'a> {
+    /// Suggest mode: a write, edit or command is about to run. Asked for
+    /// custom tools, and for built-in tools when running headless.
+    Tool { name: &'a str, arguments: &'a str },
+    /// Commit the files changed during the turn
+    Commit { message: &'a str },
+}
+
+/// Receives [`AgentEvent`]s and answers [`Approval`] requests.
+/// Closures taking `&AgentEvent` implement it and deny every approval.
+pub trait EventHandler {
+    fn on_event(&mut self, event: &AgentEvent);
+
+    /// Default: deny
+    fn approve(&mut self, _request: &Approval<'_>) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&AgentEvent)> EventHandler for F {
+    fn on_event(&mut self, event: &AgentEvent) {
+        self(event)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Pluggable tools
+// ---------------------------------------------------------------------------
+
+/// What a [`Tool`] gets to work with
+pub struct ToolContext<'a> {
+    pub workspace: &'a str,
+    pub autonomy: &'a Autonomy,
+}
+
+/// A tool the model can call, alongside (or replacing) the built-in ones.
+/// A tool whose name matches a built-in takes its place.
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+    /// JSON Schema for the arguments object
+    fn parameters(&self) -> serde_json::Value;
+    /// Read-only tools may run in parallel, are offered in plan mode and
+    /// never need approval
+    fn read_only(&self) -> bool {
+        false
+    }
+    fn call(&self, arguments: &serde_json::Value, ctx: &ToolContext<'_>) -> ToolResult;
+}
+
+/// Per-turn settings for [`run_turn`]
+#[derive(Default)]
+pub struct TurnOptions<'a> {
+    /// Only offer read-only tools and refuse anything else (`/plan`)
+    pub plan_mode: bool,
+    pub extra_tools: &'a [Box<dyn Tool>],
+    /// Built-in tools the model may not use
+    pub disabled_tools: &'a [String],
+    /// Round-trips before giving up; `None` — [`tools::MAX_TURNS`]
+    pub max_turns: Option<usize>,
+}
+
+enum Call<'t> {
+    Builtin(tools::ToolCall),
+    Custom(&'t dyn Tool),
+    Unknown,
+}
+
+impl Call<'_> {
+    fn is_read_only(&self) -> bool {
+        match self {
+            Call::Builtin(tc) => tc.is_read_only(),
+            Call::Custom(tool) => tool.read_only(),
+            Call::Unknown => true,
+        }
+    }
+}
+
+fn resolve<'t>(api_call: &ApiToolCall, opts: &TurnOptions<'t>) -> Call<'t> {
+    if let Some(tool) = opts.extra_tools.iter().find(|t| t.name() == api_call.name) {
+        return Call::Custom(tool.as_ref());
+    }
+    if opts.disabled_tools.contains(&api_call.name) {
+        return Call::Unknown;
+    }
+    match tools::ToolCall::from_api(api_call) {
+        Some(tc) => Call::Builtin(tc),
+        None => Call::Unknown,
+    }
+}
+
+/// Built-in definitions minus disabled/overridden ones, plus the extra tools
+fn turn_tool_definitions(opts: &TurnOptions<'_>) -> serde_json::Value {
+    let builtin = if opts.plan_mode {
+        tools::read_only_tool_definitions()
+    } else {
+        tools::tool_definitions()
+    };
+    let mut defs: Vec<serde_json::Value> = builtin
+        .as_array()
+        .cloned()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|d| {
+            let name = d["function"]["name"].as_str().unwrap_or("");
+            !opts.disabled_tools.iter().any(|n| n == name)
+                && !opts.extra_tools.iter().any(|t| t.name() == name)
+        })
+        .collect();
+    for tool in opts.extra_tools {
+        if opts.plan_mode && !tool.read_only() {
+            continue;
+        }
+        defs.push(serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": tool.name(),
+                "description": tool.description(),
+                "parameters": tool.parameters()
+            }
+        }));
+    }
+    serde_json::Value::Array(defs)
+}
+
+fn execute(
+    call: &Call<'_>,
+    api_call: &ApiToolCall,
+    workspace: &str,
+    autonomy: &Autonomy,
+    confirm_writes: bool,
+) -> ToolResult {
+    let span = tracing::info_span!(
+        "tool_call",
+        tool = %api_call.name,
+        args_hash = %telemetry::args_hash(&api_call.arguments),
+        success = tracing::field::Empty,
+    );
+    let _entered = span.enter();
+    let result = match call {
+        Call::Builtin(tc) => tools::execute_tool(
+            tc,
+            workspace,
+            confirm_writes,
+            *autonomy == Autonomy::AutoEdit,
+        ),
+        Call::Custom(tool) => {
+            let args =
+                serde_json::from_str(&api_call.arguments).unwrap_or_else(|_| serde_json::json!({}));
+            tool.call(
+                &args,
+                &ToolContext {
+                    workspace,
+                    autonomy,
+                },
+            )
+        }
+        Call::Unknown => ToolResult {
+            success: false,
+            output: format!("Unknown tool: {}", api_call.name),
+        },
+    };
+    span.record("success", result.success);
+    result
+}
+
+/// Mask secrets in a tool's output before it enters the conversation
+fn redact_result(tool: &str, result: &mut ToolResult, events: &mut dyn EventHandler) {
+    let (masked, found) = redact::redact(&result.output);
+    if !found.is_empty() {
+        result.output = masked;
+        events.on_event(&AgentEvent::Notice(format!(
+            "Redacted from {tool} output: {}",
+            redact::summary(&found)
+        )));
+        tracing::info!(tool, secrets = found.len(), "redacted tool output");
+    }
+}
+
+/// One model round-trip, traced with its model, tokens and finish reason
+fn model_request(
+    bk: &ModelBackend,
+    messages: &[Message],
+    tool_defs: &serde_json::Value,
+    events: &mut dyn EventHandler,
+) -> Result<backend::ChatResponse> {
+    let span = tracing::info_span!(
+        "model_request",
+        model = bk.name(),
+        messages = messages.len(),
+        prompt_tokens = tracing::field::Empty,
+        completion_tokens = tracing::field::Empty,
+        finish_reason = tracing::field::Empty,
+        tool_calls = tracing::field::Empty,
+        error = tracing::field::Empty,
+    );
+    let _entered = span.enter();
+    let mut on_token = |t: &str| events.on_event(&AgentEvent::TextDelta(t.to_string()));
+    let result = bk.chat_stream(messages, Some(tool_defs), &mut on_token);
+    match &result {
+        Ok(response) => {
+            if let Some(usage) = &response.usage {
+                span.record("prompt_tokens", usage.prompt_tokens);
+                span.record("completion_tokens", usage.completion_tokens);
+            }
+            if let Some(reason) = &response.finish_reason {
+                span.record("finish_reason", reason.as_str());
+            }
+            span.record("tool_calls", response.tool_calls.len());
+        }
+        Err(e) => {
+            span.record("error", e.to_string().as_str());
+        }
+    }
+    result
+}
+
+/// Offer to commit what the turn changed
+fn maybe_commit(workspace: &str, message: &str, events: &mut dyn EventHandler) {
+    if !git::is_git_repo(workspace) || !events.approve(&Approval::Commit { message }) {
+        return;
+    }
+    match git::git_commit_with_confirmation(workspace, message) {
+        Ok(hash) => events.on_event(&AgentEvent::Committed { hash }),
+        Err(e) => events.on_event(&AgentEvent::CommitSkipped {
+            reason: e.to_string(),
+        }),
+    }
+}
+
+/// Is this a context-size rejection worth compacting and retrying for?
+fn context_too_large(err: &anyhow::Error) -> bool {
+    let msg = err.to_string();
+    msg.contains("status code 400")
+        || msg.contains("context_length")
+        || msg.contains("too many tokens")
+}
+
+// ---------------------------------------------------------------------------
+// Agent loop — operates on a persistent conversation
+// ---------------------------------------------------------------------------
+
+/// Send `input` and run tool calls until the model answers, submits or hits
+/// the turn limit. Returns the tokens used.
+///
+/// When the calling thread is headless ([`ui::set_headless`]) built-in tools
+/// don't prompt; suggest-mode approvals go to `events` instead.
+pub fn run_turn(
+    bk: &ModelBackend,
+    conv: &mut Conversation,
+    input: &str,
+    workspace: &mut String,
+    autonomy: &Autonomy,
+    opts: &TurnOptions<'_>,
+    events: &mut dyn EventHandler,
+) -> Result<TokenUsage> {
+    let span = tracing::info_span!(
+        "turn",
+        model = bk.name(),
+        autonomy = %autonomy,
+        plan_mode = opts.plan_mode,
+        rounds = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+        prompt_tokens = tracing::field::Empty,
+        completion_tokens = tracing::field::Empty,
+        error = tracing::field::Empty,
+    );
+    let _entered = span.enter();
+    let result = agent_loop(bk, conv, input, workspace, autonomy, opts, events);
+    match &result {
+        Ok(usage) => {
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+        }
+        Err(e) => {
+            span.record("outcome", "error");
+            span.record("error", e.to_string().as_str());
+        }
+    }
+    result
+}
+
+fn agent_loop(
+    bk: &ModelBackend,
+    conv: &mut Conversation,
+    input: &str,
+    workspace: &mut String,
+    autonomy: &Autonomy,
+    opts: &TurnOptions<'_>,
+    events: &mut dyn EventHandler,
+) -> Result<TokenUsage> {
+    let span = tracing::Span::current();
+    // Add the user message to the ongoing conversation
+    conv.messages.push(Message::user(input));
+
+    // Plan mode only offers read-only tools
+    let tool_defs = turn_tool_definitions(opts);
+    let headless = ui::is_headless();
+    let suggest = *autonomy == Autonomy::Suggest;
+    // In the terminal, built-in tools show their diff and ask themselves
+    let confirm_writes = suggest && !headless;
+    let mut files_changed = Vec::new();
+    let mut turn_usage = TokenUsage::default();
+    let max_turns = opts.max_turns.unwrap_or(tools::MAX_TURNS).max(1);
+
+    for turn in 1..=max_turns {
+        span.record("rounds", turn);
+        let (done, total) = todo::progress(&conv.todos);
+        let current = todo::current(&conv.todos).map(|t| t.content.clone());
+        events.on_event(&AgentEvent::TurnStarted {
+            turn,
+            max_turns,
+            todo: (total > 0).then(|| (done, total, current.unwrap_or_default())),
+        });
+        events.on_event(&AgentEvent::Thinking);
+
+        // Compact before sending to avoid context overflow
+        session::compact_messages(&mut conv.messages, 60_000);
+
+        // Stream where the backend supports it; retry with harder compaction
+        // if the context is still too large
+        let mut response = None;
+        for (budget, notice) in [
+            (20_000, "(context too large — compacting and retrying...)"),
+            (8_000, "(still too large — aggressive compaction...)"),
+        ] {
+            match model_request(bk, &conv.messages, &tool_defs, events) {
+                Ok(r) => {
+                    response = Some(r);
+                    break;
+                }
+                Err(e) if context_too_large(&e) => {
+                    events.on_event(&AgentEvent::Notice(notice.into()));
+                    session::compact_messages(&mut conv.messages, budget);
+                    events.on_event(&AgentEvent::Thinking);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let response = match response {
+            Some(r) => r,
+            None => model_request(bk, &conv.messages, &tool_defs, events)?,
+        };
+
+        // Accumulate token usage
+        if let Some(ref u) = response.usage {
+            turn_usage.prompt_tokens += u.prompt_tokens;
+            turn_usage.completion_tokens += u.completion_tokens;
+        }
+
+        if !response.content.is_empty() {
+            events.on_event(&AgentEvent::AssistantMessage {
+                content: response.content.clone(),
+                streamed: response.streamed,
+            });
+        }
+
+        // No tool calls — model just responded with text, conversation continues
+        if response.tool_calls.is_empty() {
+            conv.messages.push(response.message);
+            span.record("outcome", "answered");
+            return Ok(turn_usage);
+        }
+
+        conv.messages.push(response.message.clone());
+
+        // Parse all tool calls, track order for message insertion
+        let parsed: Vec<(usize, &ApiToolCall, Call)> = response
+            .tool_calls
+            .iter()
+            .enumerate()
+            .map(|(i, api_call)| (i, api_call, resolve(api_call, opts)))
+            .collect();
+
+        // Allocate result slots (index -> tool message)
+        let mut result_slots: Vec<Option<Message>> = vec![None; parsed.len()];
+
+        // Plan mode: refuse anything that isn't read-only, even if the model calls it anyway
+        if opts.plan_mode {
+            for (idx, api_call, call) in &parsed {
+                if !call.is_read_only() {
+                    result_slots[*idx] = Some(Message::tool(
+                        &api_call.id,
+                        format!(
+                            "Error: {} is not available in plan mode — only read-only tools",
+                            api_call.name
+                        ),
+                    ));
+                }
+            }
+        }
+
+        // --- Phase 1: Handle control-flow calls (submit, change_directory) immediately ---
+        for (idx, api_call, call) in &parsed {
+            if result_slots[*idx].is_some() {
+                continue; // Refused in plan mode
+            }
+            let Call::Builtin(tool_call) = call else {
+                continue;
+            };
+            match tool_call {
+                tools::ToolCall::Submit { summary } => {
+                    if response.content.is_empty() {
+                        events.on_event(&AgentEvent::AssistantMessage {
+                            content: summary.clone(),
+                            streamed: false,
+                        });
+                    }
+                    events.on_event(&AgentEvent::TaskComplete {
+                        summary: summary.clone(),
+                    });
+                    conv.messages.push(Message::tool(
+                        &api_call.id,
+                        format!("Task complete: {summary}"),
+                    ));
+                    if !files_changed.is_empty() {
+                        let msg =
+                            format!("ClifCode: {}", summary.chars().take(72).collect::<String>());
+                        maybe_commit(workspace, &msg, events);
+                    }
+                    span.record("outcome", "submitted");
+                    return Ok(turn_usage);
+                }
+                tools::ToolCall::ChangeDir { path } => {
+                    let target = Path::new(path);
+                    if target.is_dir() {
+                        let canonical = target
+                            .canonicalize()
+                            .unwrap_or_else(|_| target.to_path_buf());
+                        *workspace = canonical.to_string_lossy().to_string();
+                        events.on_event(&AgentEvent::WorkspaceChanged(workspace.clone()));
+                        result_slots[*idx] = Some(Message::tool(
+                            &api_call.id,
+                            format!(
+                                "Changed workspace to {}. The repo map for this directory:\n{}",
+                                workspace,
+                                repomap::scan_workspace(workspace)
+                            ),
+                        ));
+                    } else {
+                        events.on_event(&AgentEvent::Error(format!("Not a directory: {path}")));
+                        result_slots[*idx] = Some(Message::tool(
+                            &api_call.id,
+                            format!("Error: {path} is not a directory"),
+                        ));
+                    }
+                }
+                // Todo tools mutate conversation state, so they're handled here too
+                tools::ToolCall::TodoWrite { todos, merge } => {
+                    let output = match todo::apply_write(&mut conv.todos, todos.clone(), *merge) {
+                        Ok(summary) => {
+                            todo::pin_to_system_prompt(&mut conv.messages, &conv.todos);
+                            events.on_event(&AgentEvent::TodosUpdated(conv.todos.clone()));
+                            summary
+                        }
+                        Err(e) => {
+                            events.on_event(&AgentEvent::Error(e.clone()));
+                            format!("Error: {e}")
+                        }
+                    };
+                    result_slots[*idx] = Some(Message::tool(&api_call.id, output));
+                }
+                tools::ToolCall::TodoRead => {
+                    let (done, total) = todo::progress(&conv.todos);
+                    result_slots[*idx] = Some(Message::tool(
+                        &api_call.id,
+                        format!(
+                            "Todo list ({done}/{total} done):\n{}",
+                            todo::render_checklist(&conv.todos)
+                        ),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        // --- Phase 2: Partition remaining into parallel (read-only) and sequential ---
+        let mut parallel_indices = Vec::new();
+        let mut sequential_indices = Vec::new();
+
+        for (idx, api_call, call) in &parsed {
+            if result_slots[*idx].is_some() {
+                continue; // Already handled (change_directory, todos)
+            }
+            match call {
+                Call::Unknown => {
+                    result_slots[*idx] = Some(Message::tool(
+                        &api_call.id,
+                        format!("Unknown tool: {}", api_call.name),
+                    ));
+                }
+                c if c.is_read_only() => parallel_indices.push(*idx),
+                _ => sequential_indices.push(*idx),
+            }
+        }
+
+        // --- Phase 3: Run the read-only batch, on threads if there's more than one ---
+        for &idx in &parallel_indices {
+            let api_call = parsed[idx].1;
+            events.on_event(&AgentEvent::ToolStarted {
+                id: api_call.id.clone(),
+                name: api_call.name.clone(),
+                arguments: api_call.arguments.clone(),
+            });
+        }
+        let results: Vec<(usize, ToolResult)> = if parallel_indices.len() > 1 {
+            let ws: &str = workspace;
+            let parsed = &parsed;
+            let span = &span;
+            std::thread::scope(|s| {
+                let handles: Vec<_> = parallel_indices
+                    .iter()
+                    .map(|&idx| {
+                        s.spawn(move || {
+                            ui::set_headless(headless);
+                            // Threads don't inherit the current span
+                            let _entered = span.enter();
+                            let (_, api_call, call) = &parsed[idx];
+                            (idx, execute(call, api_call, ws, autonomy, false))
+                        })
+                    })
+                    .collect();
+                handles.into_iter().map(|h| h.join().unwrap()).collect()
+            })
+        } else {
+            parallel_indices
+                .iter()
+                .map(|&idx| {
+                    let (_, api_call, call) = &parsed[idx];
+                    (idx, execute(call, api_call, workspace, autonomy, false))
+                })
+                .collect()
+        };
+        for (idx, mut result) in results {
+            let api_call = parsed[idx].1;
+            redact_result(&api_call.name, &mut result, events);
+            result_slots[idx] = Some(Message::tool(&api_call.id, serde_json::to_string(&result)?));
+            events.on_event(&AgentEvent::ToolFinished {
+                id: api_call.id.clone(),
+                name: api_call.name.clone(),
+                result,
+            });
+        }
+
+        // --- Phase 4: Run sequential batch in order ---
+        for idx in sequential_indices {
+            let (_, api_call, call) = &parsed[idx];
+            // Track file changes
+            let changed_path = match call {
+                Call::Builtin(
+                    tools::ToolCall::WriteFile { path, .. }
+                    | tools::ToolCall::EditFile { path, .. }
+                    | tools::ToolCall::NotebookEdit { path, .. },
+                ) => Some(path),
+                _ => None,
+            };
+            if let Some(path) = changed_path {
+                if !files_changed.contains(path) {
+                    files_changed.push(path.clone());
+                }
+            }
+
+            events.on_event(&AgentEvent::ToolStarted {
+                id: api_call.id.clone(),
+                name: api_call.name.clone(),
+                arguments: api_call.arguments.clone(),
+            });
+            let needs_approval = suggest && (headless || matches!(call, Call::Custom(_)));
+            let mut result = if needs_approval
+                && !events.approve(&Approval::Tool {
+                    name: &api_call.name,
+                    arguments: &api_call.arguments,
+                }) {
+                ToolResult {
+                    success: false,
+                    output: "User declined the change".into(),
+                }
+            } else {
+                execute(call, api_call, workspace, autonomy, confirm_writes)
+            };
+            redact_result(&api_call.name, &mut result, events);
+            result_slots[idx] = Some(Message::tool(&api_call.id, serde_json::to_string(&result)?));
+            let succeeded = result.success;
+            events.on_event(&AgentEvent::ToolFinished {
+                id: api_call.id.clone(),
+                name: api_call.name.clone(),
+                result,
+            });
+            if let Some(path) = changed_path.filter(|_| succeeded) {
+                events.on_event(&AgentEvent::FileChanged { path: path.clone() });
+            }
+        }
+
+        // --- Phase 5: Push results in original order ---
+        for msg in result_slots.into_iter().flatten() {
+            conv.messages.push(msg);
+        }
+
+        // Context compaction — 60k token budget
+        session::compact_messages(&mut conv.messages, 60_000);
+    }
+
+    events.on_event(&AgentEvent::TurnLimitReached);
+    span.record("outcome", "turn_limit");
+
+    if !files_changed.is_empty() {
+        let msg = format!("ClifCode: modified {}", files_changed.join(", "));
+        maybe_commit(workspace, &msg, events);
+    }
+
+    Ok(turn_usage)
+}
+
+// ---------------------------------------------------------------------------
+// Agent
+// ---------------------------------------------------------------------------
+
+/// A headless agent over one workspace and conversation
+pub struct Agent {
+    backend: ModelBackend,
+    workspace: String,
+    autonomy: Autonomy,
+    context_files: Vec<String>,
+    tools: Vec<Box<dyn Tool>>,
+    disabled_tools: Vec<String>,
+    max_turns: Option<usize>,
+    conversation: Conversation,
+    usage: TokenUsage,
+}
+
+/// Builder for [`Agent`]. Only the backend is required.
+pub struct AgentBuilder {
+    backend: Option<ModelBackend>,
+    workspace: Option<std::path::PathBuf>,
+    autonomy: Autonomy,
+    context_files: Vec<String>,
+    tools: Vec<Box<dyn Tool>>,
+    disabled_tools: Vec<String>,
+    max_turns: Option<usize>,
+}
+
+impl AgentBuilder {
+    pub fn backend(mut self, backend: ModelBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Defaults to the current directory
+    pub fn workspace(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.workspace = Some(path.into());
+        self
+    }
+
+    /// Defaults to [`Autonomy::Suggest`], where every write, edit and command
+    /// goes through [`EventHandler::approve`]
+    pub fn autonomy(mut self, autonomy: Autonomy) -> Self {
+        self.autonomy = autonomy;
+        self
+    }
+
+    /// Add a tool, or replace the built-in tool with the same name
+    pub fn tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Box::new(tool));
+        self
+    }
+
+    /// Hide a built-in tool, e.g. `run_command`
+    pub fn disable_tool(mut self, name: &str) -> Self {
+        self.disabled_tools.push(name.to_string());
+        self
+    }
+
+    /// Round-trips per [`Agent::run`]; defaults to [`tools::MAX_TURNS`]
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// Include a workspace file in the system prompt
+    pub fn context_file(mut self, path: &str) -> Self {
+        self.context_files.push(path.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<Agent> {
+        let backend = self
+            .backend
+            .ok_or_else(|| anyhow::anyhow!("Agent needs a backend"))?;
+        let workspace = match self.workspace {
+            Some(path) => path,
+            None => std::env::current_dir()?,
+        };
+        if !workspace.is_dir() {
+            anyhow::bail!("Workspace {} is not a directory", workspace.display());
+        }
+        let workspace = workspace.canonicalize()?.to_string_lossy().to_string();
+        let conversation =
+            headless(|| Conversation::new(&workspace, &self.autonomy, &self.context_files));
+        Ok(Agent {
+            backend,
+            workspace,
+            autonomy: self.autonomy,
+            context_files: self.context_files,
+            tools: self.tools,
+            disabled_tools: self.disabled_tools,
+            max_turns: self.max_turns,
+            conversation,
+            usage: TokenUsage::default(),
+        })
+    }
+}
+
+/// Run `f` with terminal output suppressed on this thread
+fn headless<T>(f: impl FnOnce() -> T) -> T {
+    let previous = ui::is_headless();
+    ui::set_headless(true);
+    let result = f();
+    ui::set_headless(previous);
+    result
+}
+
+impl Agent {
+    pub fn builder() -> AgentBuilder {
+        AgentBuilder {
+            backend: None,
+            workspace: None,
+            autonomy: Autonomy::Suggest,
+            context_files: Vec::new(),
+            tools: Vec::new(),
+            disabled_tools: Vec::new(),
+            max_turns: None,
+        }
+    }
+
+    /// Send a message and run until the model answers or submits.
+    /// Returns the tokens used by this call.
+    pub fn run(&mut self, input: &str, events: &mut dyn EventHandler) -> Result<TokenUsage> {
+        let opts = TurnOptions {
+            plan_mode: false,
+            extra_tools: &self.tools,
+            disabled_tools: &self.disabled_tools,
+            max_turns: self.max_turns,
+        };
+        let usage = headless(|| {
+            run_turn(
+                &self.backend,
+                &mut self.conversation,
+                input,
+                &mut self.workspace,
+                &self.autonomy,
+                &opts,
+                events,
+            )
+        })?;
+        self.usage.prompt_tokens += usage.prompt_tokens;
+        self.usage.completion_tokens += usage.completion_tokens;
+        Ok(usage)
+    }
+
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    /// The current workspace — the model can move it with `change_directory`
+    pub fn workspace(&self) -> &str {
+        &self.workspace
+    }
+
+    pub fn autonomy(&self) -> &Autonomy {
+        &self.autonomy
+    }
+
+    pub fn set_autonomy(&mut self, autonomy: Autonomy) {
+        self.autonomy = autonomy;
+    }
+
+    /// Tokens used since the agent was built
+    pub fn usage(&self) -> &TokenUsage {
+        &self.usage
+    }
+
+    /// Start a fresh conversation in the current workspace
+    pub fn reset(&mut self) {
+        self.conversation =
+            headless(|| Conversation::new(&self.workspace, &self.autonomy, &self.context_files));
+    }
+
+    pub fn backend(&self) -> &ModelBackend {
+        &self.backend
+    }
+}
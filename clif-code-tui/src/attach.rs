@@ -0,0 +1,259 @@
+//! Prompt attachments for `clifcode -p` — piped stdin, git diffs and file ranges.
+//!
+//! ```text
+//! cat error.log | clifcode -p "why does this fail"
+//! slow-command | clifcode -p "summarize" --file -
+//! clifcode -p "review" --diff main
+//! clifcode -p "explain" --file src/agent.rs:10-40
+//! ```
+//!
+//! Each attachment becomes a fenced block after the prompt text. Stdin is only
+//! read unasked when it delivers data (or ends) within `STDIN_WAIT`, so a run
+//! under cron or CI, where stdin is an idle pipe, doesn't hang; `--file -`
+//! always reads it.
+
+use crate::git;
+use crate::redact;
+use std::io::{IsTerminal, Read};
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Longest attachment kept, in characters; the rest is cut with a note
+const MAX_ATTACHMENT_CHARS: usize = 100_000;
+
+/// How long piped stdin may stay silent before it's treated as not meant for us
+const STDIN_WAIT: Duration = Duration::from_secs(1);
+
+/// A labelled block of text to send with the prompt
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub label: String,
+    /// Fence language hint, e.g. `diff`
+    pub lang: &'static str,
+    pub content: String,
+}
+
+/// Whatever was piped into stdin, or `None` when stdin is a terminal, empty,
+/// or sends nothing within `STDIN_WAIT`
+pub fn from_stdin() -> Option<Attachment> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return None;
+    }
+    match read_when_ready(stdin, STDIN_WAIT) {
+        Ok(bytes) => stdin_attachment(bytes?),
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            eprintln!(
+                "clifcode: stdin sent nothing within {}s and was ignored; pass `--file -` to wait for it",
+                STDIN_WAIT.as_secs()
+            );
+            None
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => None,
+    }
+}
+
+/// `--file -`: read stdin to the end, however long that takes
+fn from_stdin_blocking() -> Result<Attachment, String> {
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("stdin: {e}"))?;
+    stdin_attachment(bytes).ok_or_else(|| "stdin: no text".to_string())
+}
+
+fn stdin_attachment(bytes: Vec<u8>) -> Option<Attachment> {
+    let content = String::from_utf8(bytes).ok()?;
+    if content.trim().is_empty() {
+        return None;
+    }
+    Some(Attachment {
+        label: "stdin".into(),
+        lang: "",
+        content,
+    })
+}
+
+/// Everything `reader` produces, provided its first bytes (or end of input)
+/// arrive within `wait`; `None` when it ends empty or fails. A reader that
+/// stays silent is a timeout and is left blocked on a background thread.
+fn read_when_ready<R: Read + Send + 'static>(
+    mut reader: R,
+    wait: Duration,
+) -> Result<Option<Vec<u8>>, mpsc::RecvTimeoutError> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut first = vec![0; 8192];
+        let read = reader.read(&mut first).map(|n| {
+            first.truncate(n);
+            first
+        });
+        let _ = tx.send((read, reader));
+    });
+    let (read, mut reader) = rx.recv_timeout(wait)?;
+    let Ok(mut bytes) = read else {
+        return Ok(None);
+    };
+    if bytes.is_empty() || reader.read_to_end(&mut bytes).is_err() {
+        return Ok(None);
+    }
+    Ok(Some(bytes))
+}
+
+/// `git diff` against `rev` (working tree against HEAD when `None`), or the
+/// staged changes
+pub fn from_diff(workspace: &str, rev: Option<&str>, staged: bool) -> Result<Attachment, String> {
+    let content = git::diff(workspace, rev, staged)?;
+    let label = match (staged, rev) {
+        (true, Some(rev)) => format!("git diff --staged {rev}"),
+        (true, None) => "git diff --staged".into(),
+        (false, Some(rev)) => format!("git diff {rev}"),
+        (false, None) => "git diff HEAD".into(),
+    };
+    if content.trim().is_empty() {
+        return Err(format!("{label}: no changes"));
+    }
+    Ok(Attachment {
+        label,
+        lang: "diff",
+        content,
+    })
+}
+
+/// A file or line range: `path`, `path:10` or `path:10-40` (1-based, inclusive).
+/// `-` reads stdin.
+pub fn from_file(workspace: &str, spec: &str) -> Result<Attachment, String> {
+    if spec == "-" {
+        return from_stdin_blocking();
+    }
+    let (path, range) = parse_file_spec(spec);
+    let full = Path::new(workspace).join(path);
+    if redact::is_denied(workspace, &full) {
+        return Err(format!("{path} is in denied_paths"));
+    }
+    let content = std::fs::read_to_string(&full).map_err(|e| format!("{path}: {e}"))?;
+    let Some((start, end)) = range else {
+        return Ok(Attachment {
+            label: path.to_string(),
+            lang: fence_lang(path),
+            content,
+        });
+    };
+    let total = content.lines().count();
+    if start == 0 || start > end || start > total {
+        return Err(format!("{spec}: {path} has {total} lines"));
+    }
+    let end = end.min(total);
+    let content = content
+        .lines()
+        .skip(start - 1)
+        .take(end - start + 1)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(Attachment {
+        label: format!("{path} (lines {start}-{end})"),
+        lang: fence_lang(path),
+        content,
+    })
+}
+
+/// Split `path:10-40` into the path and line range. A suffix that isn't a
+/// number or range is part of the path.
+fn parse_file_spec(spec: &str) -> (&str, Option<(usize, usize)>) {
+    let Some((path, range)) = spec.rsplit_once(':') else {
+        return (spec, None);
+    };
+    let parsed = match range.split_once('-') {
+        Some((start, end)) => start.parse().ok().zip(end.parse().ok()),
+        None => range.parse().ok().map(|line| (line, line)),
+    };
+    match parsed {
+        Some(range) if !path.is_empty() => (path, Some(range)),
+        _ => (spec, None),
+    }
+}
+
+fn fence_lang(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("rs") => "rust",
+        Some("py") => "python",
+        Some("js") | Some("mjs") | Some("cjs") => "javascript",
+        Some("ts") | Some("tsx") => "typescript",
+        Some("go") => "go",
+        Some("sh") => "bash",
+        Some("json") => "json",
+        Some("toml") => "toml",
+        Some("yaml") | Some("yml") => "yaml",
+        Some("md") => "markdown",
+        _ => "",
+    }
+}
+
+/// The prompt followed by each attachment as a fenced block, secrets masked
+pub fn build_prompt(prompt: &str, attachments: &[Attachment]) -> String {
+    let mut out = prompt.to_string();
+    for a in attachments {
+        let masked = redact::redact_context(&a.label, &a.content);
+        let mut content: String = masked.chars().take(MAX_ATTACHMENT_CHARS).collect();
+        if content.len() < masked.len() {
+            content.push_str(&format!(
+                "\n[... truncated at {MAX_ATTACHMENT_CHARS} characters ...]"
+            ));
+        }
+        // A fence longer than any backtick run inside keeps the block intact
+        let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest.max(2) + 1);
+        out.push_str(&format!(
+            "\n\n{}:\n{fence}{}\n{}\n{fence}",
+            a.label,
+            a.lang,
+            content.trim_end_matches('\n')
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn reads_stdin_only_when_it_delivers() {
+        let wait = Duration::from_millis(200);
+
+        let (reader, mut writer) = std::io::pipe().unwrap();
+        writer.write_all(b"error: boom\n").unwrap();
+        drop(writer);
+        assert_eq!(
+            read_when_ready(reader, wait).unwrap().unwrap(),
+            b"error: boom\n"
+        );
+
+        // Closed without data, like </dev/null
+        let (reader, writer) = std::io::pipe().unwrap();
+        drop(writer);
+        assert_eq!(read_when_ready(reader, wait), Ok(None));
+
+        // Held open and silent, like a CI runner's stdin
+        let (reader, _writer) = std::io::pipe().unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(
+            read_when_ready(reader, wait),
+            Err(mpsc::RecvTimeoutError::Timeout)
+        );
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn parses_file_specs() {
+        assert_eq!(parse_file_spec("src/a.rs"), ("src/a.rs", None));
+        assert_eq!(parse_file_spec("src/a.rs:7"), ("src/a.rs", Some((7, 7))));
+        assert_eq!(
+            parse_file_spec("src/a.rs:10-40"),
+            ("src/a.rs", Some((10, 40)))
+        );
+        assert_eq!(parse_file_spec("C:dir"), ("C:dir", None));
+    }
+}
@@ -1,29 +1,65 @@
 //! Model backend abstraction — API and stub.
+//!
+//! API backends speak the OpenAI `/chat/completions` format, except
+//! `api.anthropic.com`, which gets its native `/v1/messages` API.
 
-use crate::tools::{parse_api_tool_calls, ApiToolCall};
+use crate::cassette;
+use crate::message::{self, Message};
+use crate::tools::{parse_api_tool_calls, parse_text_tool_calls, ApiToolCall};
 use crate::ui;
 use anyhow::Result;
+use std::collections::HashSet;
 use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Token usage from a single API call
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
 pub struct TokenUsage {
     pub prompt_tokens: usize,
     pub completion_tokens: usize,
 }
 
+impl TokenUsage {
+    /// Estimated cost in USD, at $3 / $15 per million prompt / completion tokens
+    pub fn estimated_cost(&self) -> f64 {
+        (self.prompt_tokens as f64 * 3.0 + self.completion_tokens as f64 * 15.0) / 1_000_000.0
+    }
+}
+
 /// Result of a chat call — may contain text, tool calls, or both
 pub struct ChatResponse {
     pub content: String,
     pub tool_calls: Vec<ApiToolCall>,
-    /// The raw assistant message for re-sending in conversation
-    pub raw_message: serde_json::Value,
-    /// Whether content was already streamed to terminal (skip print_assistant)
+    /// The assistant message to append to the conversation
+    pub message: Message,
+    /// Whether content was already delivered token by token (skip print_assistant)
     pub streamed: bool,
     /// Token usage (if available from API)
     pub usage: Option<TokenUsage>,
+    /// Why generation stopped — `stop`, `tool_calls`, `length`… (if the API says)
+    pub finish_reason: Option<String>,
+}
+
+/// How tool definitions are offered to the model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolProtocol {
+    /// OpenAI `tools` parameter, structured `tool_calls` in the response
+    Native,
+    /// Tools described in the system prompt, calls parsed from `<tool_call>` blocks.
+    /// For small local models that don't support function calling.
+    Text,
+}
+
+impl std::fmt::Display for ToolProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolProtocol::Native => write!(f, "native"),
+            ToolProtocol::Text => write!(f, "text"),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub enum ModelBackend {
     /// OpenAI-compatible API (OpenRouter, OpenAI, Anthropic, Ollama, etc.)
     Api {
@@ -31,9 +67,17 @@ pub enum ModelBackend {
         key: Option<String>,
         model: String,
         max_tokens: usize,
+        tool_protocol: ToolProtocol,
     },
     /// Testing stub (no model)
     Stub,
+    /// Pass-through to `inner`, writing every call to a cassette
+    Record {
+        inner: Box<ModelBackend>,
+        cassette: Arc<cassette::Recorder>,
+    },
+    /// Serve recorded responses from a cassette — no model
+    Replay(Arc<cassette::Player>),
 }
 
 impl ModelBackend {
@@ -41,12 +85,14 @@ impl ModelBackend {
         match self {
             ModelBackend::Api { model, .. } => model.as_str(),
             ModelBackend::Stub => "stub",
+            ModelBackend::Record { inner, .. } => inner.name(),
+            ModelBackend::Replay(_) => "replay",
         }
     }
 
     pub fn chat_with_tools(
         &self,
-        messages: &[serde_json::Value],
+        messages: &[Message],
         tools: Option<&serde_json::Value>,
     ) -> Result<ChatResponse> {
         match self {
@@ -55,16 +101,59 @@ impl ModelBackend {
                 key,
                 model,
                 max_tokens,
-            } => api_chat_with_tools(url, key.as_deref(), model, messages, *max_tokens, tools),
+                tool_protocol,
+            } => match (effective_protocol(url, model, *tool_protocol), tools) {
+                (ToolProtocol::Text, Some(tools)) => {
+                    text_protocol_chat(url, key.as_deref(), model, messages, *max_tokens, tools)
+                }
+                _ if is_anthropic(url) => anthropic_chat(
+                    url,
+                    key.as_deref(),
+                    model,
+                    messages,
+                    *max_tokens,
+                    tools,
+                    None,
+                ),
+                _ => {
+                    match api_chat_with_tools(
+                        url,
+                        key.as_deref(),
+                        model,
+                        messages,
+                        *max_tokens,
+                        tools,
+                    ) {
+                        Err(e) if tools.is_some() && rejects_tools(&e) => {
+                            remember_text_fallback(url, model);
+                            text_protocol_chat(
+                                url,
+                                key.as_deref(),
+                                model,
+                                messages,
+                                *max_tokens,
+                                tools.unwrap(),
+                            )
+                        }
+                        other => other,
+                    }
+                }
+            },
             ModelBackend::Stub => stub_response(messages),
+            ModelBackend::Record { inner, cassette } => {
+                cassette.record(messages, tools, |_| inner.chat_with_tools(messages, tools))
+            }
+            ModelBackend::Replay(cassette) => cassette.play(messages, tools, &mut |_| {}),
         }
     }
 
-    /// Streaming chat — prints tokens live for API, falls back to non-streaming for others.
+    /// Streaming chat — passes content tokens to `on_token` as they arrive for API,
+    /// falls back to non-streaming for others.
     pub fn chat_stream(
         &self,
-        messages: &[serde_json::Value],
+        messages: &[Message],
         tools: Option<&serde_json::Value>,
+        on_token: &mut dyn FnMut(&str),
     ) -> Result<ChatResponse> {
         match self {
             ModelBackend::Api {
@@ -72,86 +161,135 @@ impl ModelBackend {
                 key,
                 model,
                 max_tokens,
-            } => api_chat_stream(url, key.as_deref(), model, messages, *max_tokens, tools),
-            // Local and stub don't support streaming — fall back
+                tool_protocol: ToolProtocol::Native,
+            } if is_anthropic(url) => anthropic_chat(
+                url,
+                key.as_deref(),
+                model,
+                messages,
+                *max_tokens,
+                tools,
+                Some(on_token),
+            ),
+            ModelBackend::Api {
+                url,
+                key,
+                model,
+                max_tokens,
+                tool_protocol,
+            } if effective_protocol(url, model, *tool_protocol) == ToolProtocol::Native => {
+                match api_chat_stream(
+                    url,
+                    key.as_deref(),
+                    model,
+                    messages,
+                    *max_tokens,
+                    tools,
+                    on_token,
+                ) {
+                    // Model can't do function calling — retry with tools in the prompt
+                    Err(e) if tools.is_some() && rejects_tools(&e) => {
+                        ui::print_dim("  (model has no native tool support — using text protocol)");
+                        remember_text_fallback(url, model);
+                        self.chat_with_tools(messages, tools)
+                    }
+                    other => other,
+                }
+            }
+            ModelBackend::Record { inner, cassette } => {
+                cassette.record(messages, tools, |record_chunk| {
+                    inner.chat_stream(messages, tools, &mut |token| {
+                        record_chunk(token);
+                        on_token(token);
+                    })
+                })
+            }
+            ModelBackend::Replay(cassette) => cassette.play(messages, tools, on_token),
+            // Text protocol, local and stub don't stream — fall back
             _ => self.chat_with_tools(messages, tools),
         }
     }
 }
 
+/// Endpoint + model pairs that rejected native tools this session
+fn text_fallbacks() -> &'static Mutex<HashSet<(String, String)>> {
+    static FALLBACKS: OnceLock<Mutex<HashSet<(String, String)>>> = OnceLock::new();
+    FALLBACKS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn remember_text_fallback(url: &str, model: &str) {
+    text_fallbacks()
+        .lock()
+        .unwrap()
+        .insert((url.to_string(), model.to_string()));
+}
+
+/// The protocol actually used for `model` at `url`: `Text` once the model has
+/// rejected native tools, so later turns don't pay for a failing request first.
+pub fn effective_protocol(url: &str, model: &str, configured: ToolProtocol) -> ToolProtocol {
+    if configured == ToolProtocol::Native
+        && text_fallbacks()
+            .lock()
+            .unwrap()
+            .contains(&(url.to_string(), model.to_string()))
+    {
+        ToolProtocol::Text
+    } else {
+        configured
+    }
+}
+
+/// Whether an API error says the model doesn't accept the `tools` parameter.
+/// Ollama: "does not support tools"; llama.cpp / vLLM phrase it differently.
+fn rejects_tools(err: &anyhow::Error) -> bool {
+    let msg = err.to_string().to_lowercase();
+    msg.contains("does not support tools")
+        || msg.contains("tools are not supported")
+        || msg.contains("tool use is not supported")
+        || msg.contains("\"auto\" tool choice requires")
+}
+
 // ---------------------------------------------------------------------------
 // Stub backend
 // ---------------------------------------------------------------------------
 
-fn stub_response(messages: &[serde_json::Value]) -> Result<ChatResponse> {
-    let last_content = messages
-        .last()
-        .and_then(|m| m.get("content"))
-        .and_then(|v| v.as_str())
-        .unwrap_or("");
+fn stub_response(messages: &[Message]) -> Result<ChatResponse> {
+    let last_content = messages.last().map(|m| m.text()).unwrap_or_default();
 
-    let has_tool_results = messages
-        .iter()
-        .any(|m| m.get("role").and_then(|v| v.as_str()) == Some("tool"));
+    let has_tool_results = messages.iter().any(|m| matches!(m, Message::Tool { .. }));
 
-    if has_tool_results {
-        Ok(ChatResponse {
-            content: String::new(),
-            tool_calls: vec![ApiToolCall {
+    let (content, tool_calls) = if has_tool_results {
+        (
+            String::new(),
+            vec![ApiToolCall {
                 id: "stub_1".into(),
                 name: "submit".into(),
                 arguments: r#"{"summary":"Explored the workspace."}"#.into(),
             }],
-            raw_message: serde_json::json!({
-                "role": "assistant",
-                "content": null,
-                "tool_calls": [{
-                    "id": "stub_1",
-                    "type": "function",
-                    "function": {
-                        "name": "submit",
-                        "arguments": "{\"summary\":\"Explored the workspace.\"}"
-                    }
-                }]
-            }),
-            streamed: false,
-            usage: None,
-        })
+        )
     } else if last_content.len() < 20 {
-        Ok(ChatResponse {
-            content: "Hello! I'm ClifCode. Give me a coding task and I'll get to work.".into(),
-            tool_calls: vec![],
-            raw_message: serde_json::json!({
-                "role": "assistant",
-                "content": "Hello! I'm ClifCode. Give me a coding task and I'll get to work."
-            }),
-            streamed: false,
-            usage: None,
-        })
+        (
+            "Hello! I'm ClifCode. Give me a coding task and I'll get to work.".into(),
+            vec![],
+        )
     } else {
-        Ok(ChatResponse {
-            content: "Let me explore the project.".into(),
-            tool_calls: vec![ApiToolCall {
+        (
+            "Let me explore the project.".into(),
+            vec![ApiToolCall {
                 id: "stub_0".into(),
                 name: "run_command".into(),
                 arguments: r#"{"command":"ls -la"}"#.into(),
             }],
-            raw_message: serde_json::json!({
-                "role": "assistant",
-                "content": "Let me explore the project.",
-                "tool_calls": [{
-                    "id": "stub_0",
-                    "type": "function",
-                    "function": {
-                        "name": "run_command",
-                        "arguments": "{\"command\":\"ls -la\"}"
-                    }
-                }]
-            }),
-            streamed: false,
-            usage: None,
-        })
-    }
+        )
+    };
+    Ok(ChatResponse {
+        message: Message::assistant(content.clone(), tool_calls.clone()),
+        content,
+        tool_calls,
+        streamed: false,
+        usage: None,
+        finish_reason: None,
+    })
 }
 
 // ---------------------------------------------------------------------------
@@ -162,7 +300,7 @@ fn api_chat_with_tools(
     base_url: &str,
     api_key: Option<&str>,
     model: &str,
-    messages: &[serde_json::Value],
+    messages: &[Message],
     max_tokens: usize,
     tools: Option<&serde_json::Value>,
 ) -> Result<ChatResponse> {
@@ -170,7 +308,7 @@ fn api_chat_with_tools(
 
     let mut body = serde_json::json!({
         "model": model,
-        "messages": messages,
+        "messages": message::to_openai(messages),
         "max_tokens": max_tokens,
         "temperature": 0.7,
     });
@@ -213,22 +351,160 @@ fn api_chat_with_tools(
 
     let tool_calls = parse_api_tool_calls(&resp_body);
 
-    let raw_message = resp_body
-        .pointer("/choices/0/message")
-        .cloned()
-        .unwrap_or_else(|| serde_json::json!({"role": "assistant", "content": content}));
+    let message = Message::assistant(content.clone(), tool_calls.clone());
 
     let usage = extract_usage(&resp_body);
+    let finish_reason = resp_body
+        .pointer("/choices/0/finish_reason")
+        .and_then(|v| v.as_str())
+        .map(String::from);
 
     Ok(ChatResponse {
         content,
         tool_calls,
-        raw_message,
+        message,
         streamed: false,
         usage,
+        finish_reason,
     })
 }
 
+// ---------------------------------------------------------------------------
+// Text tool protocol (models without native function calling)
+// ---------------------------------------------------------------------------
+
+/// Chat with tools described in the system prompt instead of the `tools` parameter.
+/// The conversation stays in OpenAI format; it is rewritten per request and the
+/// reply is parsed back into regular tool calls.
+fn text_protocol_chat(
+    base_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    messages: &[Message],
+    max_tokens: usize,
+    tools: &serde_json::Value,
+) -> Result<ChatResponse> {
+    let text_messages = to_text_protocol(messages, tools);
+    let resp = if is_anthropic(base_url) {
+        anthropic_chat(
+            base_url,
+            api_key,
+            model,
+            &text_messages,
+            max_tokens,
+            None,
+            None,
+        )?
+    } else {
+        api_chat_with_tools(base_url, api_key, model, &text_messages, max_tokens, None)?
+    };
+
+    let (content, tool_calls) = parse_text_tool_calls(&resp.content);
+
+    let message = Message::assistant(content.clone(), tool_calls.clone());
+
+    Ok(ChatResponse {
+        content,
+        tool_calls,
+        message,
+        streamed: false,
+        usage: resp.usage,
+        finish_reason: resp.finish_reason,
+    })
+}
+
+/// Rewrite a conversation for a model without tool support: tool specs go
+/// into the system prompt, assistant tool calls become `<tool_call>` blocks
+/// and tool results become user messages.
+fn to_text_protocol(messages: &[Message], tools: &serde_json::Value) -> Vec<Message> {
+    let mut specs = Vec::new();
+    for tool in tools.as_array().into_iter().flatten() {
+        let name = tool
+            .pointer("/function/name")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let desc = tool
+            .pointer("/function/description")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        let params = tool
+            .pointer("/function/parameters")
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| "{}".into());
+        specs.push(format!("- {name}: {desc}\n  parameters: {params}"));
+    }
+    let instructions = format!(
+        "TOOLS\n\
+         You can call these tools:\n{}\n\n\
+         To call a tool, reply with one block per call, exactly in this format:\n\
+         <tool_call>{{\"name\": \"read_file\", \"arguments\": {{\"path\": \"src/main.rs\"}}}}</tool_call>\n\
+         You may write a short explanation before the blocks. Results come back in \
+         <tool_result> blocks in the next message. Do not invent results.",
+        specs.join("\n")
+    );
+
+    let mut out: Vec<Message> = Vec::new();
+    let mut tool_names: std::collections::HashMap<&str, &str> = std::collections::HashMap::new();
+
+    for msg in messages {
+        match msg {
+            Message::System { content } if out.is_empty() => {
+                out.push(Message::system(format!(
+                    "{}\n\n{instructions}",
+                    content.text()
+                )));
+            }
+            Message::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let mut text = content.text();
+                for tc in tool_calls {
+                    tool_names.insert(&tc.id, &tc.name);
+                    let args: serde_json::Value = serde_json::from_str(&tc.arguments)
+                        .unwrap_or_else(|_| serde_json::json!({}));
+                    let call = serde_json::json!({"name": tc.name, "arguments": args});
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("<tool_call>{call}</tool_call>"));
+                }
+                out.push(Message::assistant(text, vec![]));
+            }
+            Message::Tool {
+                tool_call_id,
+                content,
+            } => {
+                let name = tool_names
+                    .get(tool_call_id.as_str())
+                    .copied()
+                    .unwrap_or("tool");
+                let block = format!(
+                    "<tool_result name=\"{name}\">\n{}\n</tool_result>",
+                    content.text()
+                );
+                // Chat templates of small models often require strict user/assistant
+                // alternation — merge consecutive results into one user message
+                match out.last_mut() {
+                    Some(Message::User {
+                        content: message::Content::Text(prev),
+                    }) if prev.starts_with("<tool_result") => {
+                        prev.push('\n');
+                        prev.push_str(&block);
+                    }
+                    _ => out.push(Message::user(block)),
+                }
+            }
+            other => out.push(other.clone()),
+        }
+    }
+
+    if out.is_empty() {
+        out.push(Message::system(instructions));
+    }
+    out
+}
+
 // ---------------------------------------------------------------------------
 // Streaming API (SSE)
 // ---------------------------------------------------------------------------
@@ -237,15 +513,16 @@ fn api_chat_stream(
     base_url: &str,
     api_key: Option<&str>,
     model: &str,
-    messages: &[serde_json::Value],
+    messages: &[Message],
     max_tokens: usize,
     tools: Option<&serde_json::Value>,
+    on_token: &mut dyn FnMut(&str),
 ) -> Result<ChatResponse> {
     let url = format!("{}/chat/completions", base_url.trim_end_matches('/'));
 
     let mut body = serde_json::json!({
         "model": model,
-        "messages": messages,
+        "messages": message::to_openai(messages),
         "max_tokens": max_tokens,
         "temperature": 0.7,
         "stream": true,
@@ -282,15 +559,11 @@ fn api_chat_stream(
     let reader = BufReader::new(resp.into_reader());
 
     let mut full_content = String::new();
-    let mut started_printing = false;
     let mut usage: Option<TokenUsage> = None;
 
-    // Streaming markdown state
-    let mut line_buffer = String::new();
-    let mut in_code_block = false;
-
     // Tool call accumulators: index -> (id, name, arguments_buffer)
     let mut tool_acc: Vec<(String, String, String)> = Vec::new();
+    let mut finish_reason = None;
 
     for line_result in reader.lines() {
         let line = match line_result {
@@ -315,6 +588,13 @@ fn api_chat_stream(
             Err(_) => continue,
         };
 
+        if let Some(reason) = chunk
+            .pointer("/choices/0/finish_reason")
+            .and_then(|v| v.as_str())
+        {
+            finish_reason = Some(reason.to_string());
+        }
+
         let delta = match chunk.pointer("/choices/0/delta") {
             Some(d) => d,
             None => {
@@ -326,37 +606,11 @@ fn api_chat_stream(
             }
         };
 
-        // Stream text content with line-buffered markdown rendering
+        // Stream text content to the caller
         if let Some(token) = delta.get("content").and_then(|v| v.as_str()) {
             if !token.is_empty() {
-                if !started_printing {
-                    print!(
-                        "\n  {}{}\u{2726} ClifCode{}  ",
-                        ui::BOLD,
-                        ui::BRIGHT_MAGENTA,
-                        ui::RESET
-                    );
-                    started_printing = true;
-                }
                 full_content.push_str(token);
-                line_buffer.push_str(token);
-
-                // Process completed lines
-                while let Some(nl_pos) = line_buffer.find('\n') {
-                    let completed_line: String = line_buffer[..nl_pos].to_string();
-                    line_buffer = line_buffer[nl_pos + 1..].to_string();
-
-                    // Track code block state
-                    if completed_line.trim_start().starts_with("```") {
-                        in_code_block = !in_code_block;
-                    }
-
-                    let rendered = ui::render_streaming_line(
-                        &completed_line,
-                        in_code_block && !completed_line.trim_start().starts_with("```"),
-                    );
-                    println!("{rendered}");
-                }
+                on_token(token);
             }
         }
 
@@ -397,32 +651,6 @@ fn api_chat_stream(
         }
     }
 
-    // Flush remaining line buffer
-    if !line_buffer.is_empty() {
-        if !started_printing {
-            print!(
-                "\n  {}{}\u{2726} ClifCode{}  ",
-                ui::BOLD,
-                ui::BRIGHT_MAGENTA,
-                ui::RESET
-            );
-            started_printing = true;
-        }
-        if line_buffer.trim_start().starts_with("```") {
-            in_code_block = !in_code_block;
-        }
-        let rendered = ui::render_streaming_line(
-            &line_buffer,
-            in_code_block && !line_buffer.trim_start().starts_with("```"),
-        );
-        println!("{rendered}");
-    }
-
-    // Finish the streamed output
-    if started_printing {
-        println!();
-    }
-
     // Build tool calls from accumulated deltas
     let tool_calls: Vec<ApiToolCall> = tool_acc
         .into_iter()
@@ -434,45 +662,238 @@ fn api_chat_stream(
         })
         .collect();
 
-    // Build raw_message for conversation history
-    let raw_message = if tool_calls.is_empty() {
-        serde_json::json!({"role": "assistant", "content": full_content})
-    } else {
-        let tc_json: Vec<serde_json::Value> = tool_calls
-            .iter()
-            .map(|tc| {
-                serde_json::json!({
-                    "id": tc.id,
-                    "type": "function",
-                    "function": {
-                        "name": tc.name,
-                        "arguments": tc.arguments
-                    }
-                })
-            })
-            .collect();
+    let message = Message::assistant(full_content.clone(), tool_calls.clone());
+
+    Ok(ChatResponse {
+        streamed: !full_content.is_empty(),
+        content: full_content,
+        tool_calls,
+        message,
+        usage,
+        finish_reason,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Anthropic Messages API
+// ---------------------------------------------------------------------------
+
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Whether `base_url` is Anthropic's own API (not a proxy like OpenRouter)
+fn is_anthropic(base_url: &str) -> bool {
+    url::Url::parse(base_url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h == "api.anthropic.com"))
+        .unwrap_or(false)
+}
 
-        if full_content.is_empty() {
-            serde_json::json!({
-                "role": "assistant",
-                "content": null,
-                "tool_calls": tc_json
-            })
-        } else {
-            serde_json::json!({
-                "role": "assistant",
-                "content": full_content,
-                "tool_calls": tc_json
-            })
+/// Chat through `/v1/messages`. Tokens go to `on_token` as they arrive when
+/// it's given; the reply is always streamed from the API.
+fn anthropic_chat(
+    base_url: &str,
+    api_key: Option<&str>,
+    model: &str,
+    messages: &[Message],
+    max_tokens: usize,
+    tools: Option<&serde_json::Value>,
+    on_token: Option<&mut dyn FnMut(&str)>,
+) -> Result<ChatResponse> {
+    let url = format!("{}/messages", base_url.trim_end_matches('/'));
+    let body = anthropic_body(model, messages, max_tokens, tools);
+
+    let mut req = ureq::post(&url)
+        .set("Content-Type", "application/json")
+        .set("anthropic-version", ANTHROPIC_VERSION);
+    if let Some(key) = api_key {
+        req = req.set("x-api-key", key);
+    }
+
+    let resp = match req.send_string(&body.to_string()) {
+        Ok(r) => r,
+        Err(ureq::Error::Status(code, response)) => {
+            let body_text: String = response
+                .into_string()
+                .unwrap_or_default()
+                .chars()
+                .take(300)
+                .collect();
+            return Err(anyhow::anyhow!(
+                "API request failed: {url}: status code {code} — {body_text}"
+            ));
+        }
+        Err(e) => {
+            return Err(anyhow::anyhow!("API request failed: {url}: {e}"));
         }
     };
 
+    let streamed = on_token.is_some();
+    let mut response = read_anthropic_stream(
+        BufReader::new(resp.into_reader()),
+        on_token.unwrap_or(&mut |_| {}),
+    )?;
+    response.streamed = streamed && !response.content.is_empty();
+    Ok(response)
+}
+
+/// Request body for `/v1/messages`; `tools` is left out entirely without tools,
+/// as for the text protocol
+fn anthropic_body(
+    model: &str,
+    messages: &[Message],
+    max_tokens: usize,
+    tools: Option<&serde_json::Value>,
+) -> serde_json::Value {
+    let (system, wire) = message::to_anthropic(messages);
+
+    let mut body = serde_json::json!({
+        "model": model,
+        "messages": wire,
+        "max_tokens": max_tokens,
+        "temperature": 0.7,
+        "stream": true,
+    });
+    if !system.is_empty() {
+        body["system"] = system.into();
+    }
+    if let Some(tools) = tools {
+        body["tools"] = anthropic_tools(tools);
+    }
+    body
+}
+
+/// OpenAI function specs as Anthropic tools (`input_schema` instead of `parameters`)
+fn anthropic_tools(tools: &serde_json::Value) -> serde_json::Value {
+    tools
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tool| {
+            let function = tool.get("function")?;
+            Some(serde_json::json!({
+                "name": function.get("name")?,
+                "description": function.get("description").cloned().unwrap_or_default(),
+                "input_schema": function
+                    .get("parameters")
+                    .cloned()
+                    .unwrap_or_else(|| serde_json::json!({"type": "object"})),
+            }))
+        })
+        .collect()
+}
+
+/// Assemble a reply from Anthropic's SSE events: text deltas go to `on_token`,
+/// `tool_use` blocks collect their streamed JSON input.
+fn read_anthropic_stream(
+    reader: impl BufRead,
+    on_token: &mut dyn FnMut(&str),
+) -> Result<ChatResponse> {
+    let mut full_content = String::new();
+    let mut usage = TokenUsage::default();
+    // Content block index -> (id, name, input JSON so far); text blocks stay None
+    let mut blocks: Vec<Option<(String, String, String)>> = Vec::new();
+    let mut finish_reason = None;
+
+    for line in reader.lines() {
+        let Ok(line) = line else { break };
+        let Some(data) = line.strip_prefix("data: ") else {
+            continue;
+        };
+        let Ok(event) = serde_json::from_str::<serde_json::Value>(data) else {
+            continue;
+        };
+        let index = event.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+
+        match event.get("type").and_then(|v| v.as_str()).unwrap_or("") {
+            "message_start" => {
+                if let Some(n) = event
+                    .pointer("/message/usage/input_tokens")
+                    .and_then(|v| v.as_u64())
+                {
+                    usage.prompt_tokens = n as usize;
+                }
+            }
+            "content_block_start" => {
+                while blocks.len() <= index {
+                    blocks.push(None);
+                }
+                let block = &event["content_block"];
+                if block["type"] == "tool_use" {
+                    blocks[index] = Some((
+                        block["id"].as_str().unwrap_or("").to_string(),
+                        block["name"].as_str().unwrap_or("").to_string(),
+                        String::new(),
+                    ));
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                if let Some(text) = delta.get("text").and_then(|v| v.as_str()) {
+                    full_content.push_str(text);
+                    on_token(text);
+                }
+                if let (Some(json), Some(Some((_, _, input)))) = (
+                    delta.get("partial_json").and_then(|v| v.as_str()),
+                    blocks.get_mut(index),
+                ) {
+                    input.push_str(json);
+                }
+            }
+            "message_delta" => {
+                if let Some(reason) = event.pointer("/delta/stop_reason").and_then(|v| v.as_str()) {
+                    // Same names as the OpenAI path
+                    finish_reason = Some(
+                        match reason {
+                            "end_turn" | "stop_sequence" => "stop",
+                            "tool_use" => "tool_calls",
+                            "max_tokens" => "length",
+                            other => other,
+                        }
+                        .to_string(),
+                    );
+                }
+                if let Some(n) = event
+                    .pointer("/usage/output_tokens")
+                    .and_then(|v| v.as_u64())
+                {
+                    usage.completion_tokens = n as usize;
+                }
+            }
+            "error" => {
+                let msg = event
+                    .pointer("/error/message")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown error");
+                return Err(anyhow::anyhow!("Anthropic API error: {msg}"));
+            }
+            "message_stop" => break,
+            _ => {}
+        }
+    }
+
+    let tool_calls: Vec<ApiToolCall> = blocks
+        .into_iter()
+        .flatten()
+        .map(|(id, name, input)| ApiToolCall {
+            id,
+            name,
+            arguments: if input.trim().is_empty() {
+                "{}".into()
+            } else {
+                input
+            },
+        })
+        .collect();
+
+    let message = Message::assistant(full_content.clone(), tool_calls.clone());
+
     Ok(ChatResponse {
+        streamed: false,
         content: full_content,
         tool_calls,
-        raw_message,
-        streamed: started_printing,
-        usage,
+        message,
+        usage: Some(usage),
+        finish_reason,
     })
 }
 
@@ -493,10 +914,179 @@ fn extract_usage_from_obj(u: &serde_json::Value) -> Option<TokenUsage> {
     })
 }
 
-/// Quick check if Ollama is running locally (2s timeout to avoid blocking startup)
-pub fn detect_ollama() -> bool {
+// ---------------------------------------------------------------------------
+// Local model servers
+// ---------------------------------------------------------------------------
+
+/// Local OpenAI-compatible servers and their default endpoints, in preference order
+pub const LOCAL_SERVERS: &[(&str, &str)] = &[
+    ("Ollama", "http://localhost:11434/v1"),
+    ("LM Studio", "http://localhost:1234/v1"),
+    ("llama.cpp", "http://localhost:8080/v1"),
+    ("vLLM", "http://localhost:8000/v1"),
+];
+
+/// A running local model server and the models it serves
+#[derive(Debug, Clone)]
+pub struct LocalServer {
+    pub name: &'static str,
+    pub url: String,
+    pub models: Vec<String>,
+}
+
+/// Probe all known local servers in parallel (2s timeout each to avoid blocking startup).
+/// Returns the ones that answered, in `LOCAL_SERVERS` order.
+pub fn detect_local_servers() -> Vec<LocalServer> {
+    std::thread::scope(|s| {
+        let handles: Vec<_> = LOCAL_SERVERS
+            .iter()
+            .map(|&(name, url)| s.spawn(move || probe_local_server(name, url)))
+            .collect();
+        handles
+            .into_iter()
+            .filter_map(|h| h.join().ok().flatten())
+            .collect()
+    })
+}
+
+fn probe_local_server(name: &'static str, url: &str) -> Option<LocalServer> {
     let agent = ureq::AgentBuilder::new()
         .timeout(std::time::Duration::from_secs(2))
         .build();
-    agent.get("http://localhost:11434/api/tags").call().is_ok()
+
+    let models = match agent.get(&format!("{url}/models")).call() {
+        Ok(resp) => {
+            let json: serde_json::Value = resp.into_json().ok()?;
+            json.get("data")?
+                .as_array()?
+                .iter()
+                .filter_map(|m| m.get("id").and_then(|v| v.as_str()).map(String::from))
+                .collect()
+        }
+        // Older Ollama releases have no /v1/models — fall back to the native tags API
+        Err(_) if name == "Ollama" => {
+            let tags_url = format!("{}/api/tags", url.trim_end_matches("/v1"));
+            let json: serde_json::Value = agent.get(&tags_url).call().ok()?.into_json().ok()?;
+            json.get("models")?
+                .as_array()?
+                .iter()
+                .filter_map(|m| m.get("name").and_then(|v| v.as_str()).map(String::from))
+                .collect()
+        }
+        Err(_) => return None,
+    };
+
+    Some(LocalServer {
+        name,
+        url: url.to_string(),
+        models,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_anthropic_text_and_tool_use_stream() {
+        let sse = r#"event: message_start
+data: {"type":"message_start","message":{"usage":{"input_tokens":120,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Reading "}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"it."}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_1","name":"read_file","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"path\": \"src/"}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"main.rs\"}"}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_2","name":"list_files","input":{}}}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":42}}
+
+event: message_stop
+data: {"type":"message_stop"}
+"#;
+        let mut tokens = Vec::new();
+        let resp =
+            read_anthropic_stream(sse.as_bytes(), &mut |t| tokens.push(t.to_string())).unwrap();
+        assert_eq!(tokens, ["Reading ", "it."]);
+        assert_eq!(resp.content, "Reading it.");
+        assert_eq!(resp.tool_calls.len(), 2);
+        assert_eq!(resp.tool_calls[0].id, "toolu_1");
+        assert_eq!(resp.tool_calls[0].arguments, r#"{"path": "src/main.rs"}"#);
+        // A tool with no input still gets valid JSON arguments
+        assert_eq!(resp.tool_calls[1].arguments, "{}");
+        assert_eq!(resp.finish_reason.as_deref(), Some("tool_calls"));
+        let usage = resp.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (120, 42));
+
+        let err = read_anthropic_stream(
+            &br#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#[..],
+            &mut |_| {},
+        );
+        assert!(matches!(err, Err(e) if e.to_string().contains("Overloaded")));
+    }
+
+    #[test]
+    fn anthropic_requests_only_go_to_anthropic() {
+        assert!(is_anthropic("https://api.anthropic.com/v1"));
+        assert!(!is_anthropic("https://openrouter.ai/api/v1"));
+        assert!(!is_anthropic("http://localhost:11434/v1"));
+
+        let tools = serde_json::json!([{
+            "type": "function",
+            "function": {"name": "read_file", "description": "Read a file",
+                         "parameters": {"type": "object", "properties": {"path": {"type": "string"}}}}
+        }]);
+        assert_eq!(
+            anthropic_tools(&tools),
+            serde_json::json!([{
+                "name": "read_file",
+                "description": "Read a file",
+                "input_schema": {"type": "object", "properties": {"path": {"type": "string"}}}
+            }])
+        );
+
+        let messages = [Message::system("Be brief."), Message::user("Read main.rs")];
+        assert!(anthropic_body("claude", &messages, 256, Some(&tools))["tools"].is_array());
+        // The text protocol describes tools in the system prompt instead
+        let body = anthropic_body("claude", &to_text_protocol(&messages, &tools), 256, None);
+        assert!(body.get("tools").is_none());
+        assert!(body["system"].as_str().unwrap().contains("<tool_call>"));
+    }
+
+    #[test]
+    fn text_fallback_is_remembered_per_endpoint_and_model() {
+        let url = "http://localhost:1/v1";
+        assert_eq!(
+            effective_protocol(url, "small", ToolProtocol::Native),
+            ToolProtocol::Native
+        );
+        remember_text_fallback(url, "small");
+        assert_eq!(
+            effective_protocol(url, "small", ToolProtocol::Native),
+            ToolProtocol::Text
+        );
+        assert_eq!(
+            effective_protocol(url, "large", ToolProtocol::Native),
+            ToolProtocol::Native
+        );
+        assert_eq!(
+            effective_protocol("http://localhost:2/v1", "small", ToolProtocol::Native),
+            ToolProtocol::Native
+        );
+    }
 }
@@ -0,0 +1,409 @@
+//! `clifcode batch` — run independent tasks from a JSONL file.
+//!
+//! Each line is a task:
+//!
+//! ```json
+//! {"id": "bump-serde", "prompt": "Bump serde to 1.0.200", "workspace": "../api", "worktree": true, "autonomy": "full-auto", "max_turns": 15}
+//! ```
+//!
+//! Only `prompt` is required. Ids may use letters, digits, `-`, `_` and `.`,
+//! and must be unique. Relative workspaces are resolved against the task
+//! file's directory. With `worktree` the task runs in a git worktree on branch
+//! `clifcode/<id>`, so tasks against one repo don't collide; rerunning a task
+//! picks up its worktree and branch from the last run. Tasks run
+//! headless on a bounded pool of threads; each writes one result line as it
+//! finishes. Suggest mode declines every change, since nobody is there to ask.
+
+use crate::agent::{Agent, AgentEvent, Autonomy, EventHandler};
+use crate::backend::{ModelBackend, TokenUsage};
+use crate::git;
+use crate::ui;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// One line of the task file
+#[derive(Debug, Clone, Deserialize)]
+pub struct Task {
+    /// Defaults to `task-<line number>`
+    #[serde(default)]
+    pub id: Option<String>,
+    pub prompt: String,
+    #[serde(default)]
+    pub workspace: Option<PathBuf>,
+    /// Run in a new git worktree of the workspace
+    #[serde(default)]
+    pub worktree: bool,
+    #[serde(default)]
+    pub autonomy: Option<String>,
+    #[serde(default)]
+    pub max_turns: Option<usize>,
+}
+
+/// One line of the results file
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskResult {
+    pub id: String,
+    /// submitted, answered, turn_limit or error
+    pub status: &'static str,
+    pub workspace: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub files_changed: Vec<String>,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub cost_usd: f64,
+    /// The `submit` summary, or the model's last message
+    pub summary: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u128,
+}
+
+/// Settings shared by every task
+pub struct BatchOptions {
+    pub concurrency: usize,
+    pub output: PathBuf,
+    /// For tasks that don't set their own
+    pub workspace: PathBuf,
+    pub autonomy: Autonomy,
+}
+
+/// Parse the task file; errors name the offending line
+pub fn load_tasks(path: &Path) -> Result<Vec<Task>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+    let base = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut tasks = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+        let mut task: Task =
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?;
+        match &task.id {
+            None => task.id = Some(format!("task-{}", i + 1)),
+            Some(id) if !valid_id(id) => {
+                return Err(format!(
+                    "{}:{}: invalid task id {id:?} — use letters, digits, '-', '_' and '.'",
+                    path.display(),
+                    i + 1
+                ));
+            }
+            Some(_) => {}
+        }
+        task.workspace = task.workspace.map(|w| base.join(w));
+        tasks.push(task);
+    }
+    // Ids name directories and branches, which may be case-insensitive
+    let mut ids = std::collections::HashSet::new();
+    for task in &tasks {
+        let id = task.id.as_deref().unwrap_or_default();
+        if !ids.insert(id.to_lowercase()) {
+            return Err(format!("Duplicate task id {id}"));
+        }
+    }
+    Ok(tasks)
+}
+
+/// Whether `id` is safe as a path component and in a branch name
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 100
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !id.starts_with(['.', '-'])
+        && !id.ends_with('.')
+        && !id.ends_with(".lock")
+        && !id.contains("..")
+}
+
+/// Collects what a run did from its events
+#[derive(Default)]
+struct Recorder {
+    files_changed: Vec<String>,
+    submitted: Option<String>,
+    last_message: String,
+    turn_limit: bool,
+}
+
+impl EventHandler for Recorder {
+    fn on_event(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::FileChanged { path } if !self.files_changed.contains(path) => {
+                self.files_changed.push(path.clone());
+            }
+            AgentEvent::AssistantMessage { content, .. } => self.last_message = content.clone(),
+            AgentEvent::TaskComplete { summary } => self.submitted = Some(summary.clone()),
+            AgentEvent::TurnLimitReached => self.turn_limit = true,
+            _ => {}
+        }
+    }
+}
+
+/// Where a task's worktree goes: a sibling of the repo, so relative paths in
+/// the repo's config keep working
+fn worktree_path(repo: &Path, id: &str) -> PathBuf {
+    let name = repo
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "repo".into());
+    repo.parent()
+        .unwrap_or(repo)
+        .join(format!("{name}.clifcode-worktrees"))
+        .join(id)
+}
+
+fn run_task(backend: &ModelBackend, task: &Task, opts: &BatchOptions) -> TaskResult {
+    let started = Instant::now();
+    let id = task.id.clone().unwrap_or_default();
+    let mut result = TaskResult {
+        id: id.clone(),
+        status: "error",
+        workspace: String::new(),
+        branch: None,
+        files_changed: Vec::new(),
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        cost_usd: 0.0,
+        summary: String::new(),
+        error: None,
+        duration_ms: 0,
+    };
+
+    let outcome = (|| -> Result<(), String> {
+        let mut workspace = task
+            .workspace
+            .clone()
+            .unwrap_or_else(|| opts.workspace.clone());
+        result.workspace = workspace.to_string_lossy().to_string();
+        let autonomy = match &task.autonomy {
+            Some(a) => a.parse()?,
+            None => opts.autonomy.clone(),
+        };
+        workspace = workspace
+            .canonicalize()
+            .map_err(|e| format!("Workspace {}: {e}", workspace.display()))?;
+        result.workspace = workspace.to_string_lossy().to_string();
+
+        if task.worktree {
+            if !git::is_git_repo(&result.workspace) {
+                return Err(format!("{} is not a git repository", result.workspace));
+            }
+            let branch = format!("clifcode/{id}");
+            let path = worktree_path(&workspace, &id);
+            if git::add_or_reuse_worktree(&result.workspace, &path, &branch)? {
+                ui::print_dim(&format!("  {id}: reusing worktree {}", path.display()));
+            }
+            result.workspace = path.to_string_lossy().to_string();
+            result.branch = Some(branch);
+        }
+
+        // Changes made by commands don't show up as tool events, so diff git status too
+        let is_repo = git::is_git_repo(&result.workspace);
+        let dirty_before = if is_repo {
+            git::changed_files(&result.workspace).unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let mut builder = Agent::builder()
+            .backend(backend.clone())
+            .workspace(&result.workspace)
+            .autonomy(autonomy);
+        if let Some(max_turns) = task.max_turns {
+            builder = builder.max_turns(max_turns);
+        }
+        let mut agent = builder.build().map_err(|e| e.to_string())?;
+
+        let mut recorder = Recorder::default();
+        let run = agent.run(&task.prompt, &mut recorder);
+        let usage: &TokenUsage = agent.usage();
+        result.prompt_tokens = usage.prompt_tokens;
+        result.completion_tokens = usage.completion_tokens;
+        result.cost_usd = usage.estimated_cost();
+
+        result.files_changed = recorder.files_changed;
+        if is_repo {
+            for path in git::changed_files(agent.workspace()).unwrap_or_default() {
+                if !dirty_before.contains(&path) && !result.files_changed.contains(&path) {
+                    result.files_changed.push(path);
+                }
+            }
+        }
+        run.map_err(|e| e.to_string())?;
+
+        (result.status, result.summary) = match recorder.submitted {
+            Some(summary) => ("submitted", summary),
+            None if recorder.turn_limit => ("turn_limit", recorder.last_message),
+            None => ("answered", recorder.last_message),
+        };
+        Ok(())
+    })();
+
+    if let Err(e) = outcome {
+        result.status = "error";
+        result.error = Some(e);
+    }
+    result.duration_ms = started.elapsed().as_millis();
+    result
+}
+
+/// Run every task, at most `concurrency` at a time, appending results to
+/// `opts.output` as they finish. Returns the results in task order.
+pub fn run_batch(
+    backend: &ModelBackend,
+    tasks: &[Task],
+    opts: &BatchOptions,
+) -> Result<Vec<TaskResult>, String> {
+    let file = std::fs::File::create(&opts.output)
+        .map_err(|e| format!("Cannot write {}: {e}", opts.output.display()))?;
+    let out = Mutex::new(std::io::BufWriter::new(file));
+    let results: Mutex<Vec<Option<TaskResult>>> = Mutex::new(vec![None; tasks.len()]);
+    let next = AtomicUsize::new(0);
+    let workers = opts.concurrency.clamp(1, tasks.len().max(1));
+
+    ui::print_dim(&format!(
+        "  Running {} tasks ({workers} at a time) → {}",
+        tasks.len(),
+        opts.output.display()
+    ));
+
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(task) = tasks.get(i) else {
+                    break;
+                };
+                let result = run_task(backend, task, opts);
+                report(&result);
+                if let Ok(line) = serde_json::to_string(&result) {
+                    let mut out = out.lock().unwrap();
+                    let _ = writeln!(out, "{line}");
+                    let _ = out.flush();
+                }
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+
+    Ok(results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+fn report(result: &TaskResult) {
+    let tokens = result.prompt_tokens + result.completion_tokens;
+    let detail = format!(
+        "{} — {} files, {tokens} tokens, {:.1}s",
+        result.status,
+        result.files_changed.len(),
+        result.duration_ms as f64 / 1000.0
+    );
+    match &result.error {
+        Some(e) => ui::print_error(&format!("{}: {e}", result.id)),
+        None if result.status == "turn_limit" => {
+            ui::print_dim(&format!("  {}: {detail}", result.id))
+        }
+        None => ui::print_success(&format!("{}: {detail}", result.id)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("clifcode-batch-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=t", "-c", "user.email=t@t"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?}");
+    }
+
+    #[test]
+    fn rejects_unsafe_and_duplicate_ids() {
+        let dir = temp_dir("ids");
+        let file = dir.join("tasks.jsonl");
+        for bad in ["../escape", "a/b", "-x", "x.lock", "two words"] {
+            std::fs::write(&file, format!("{{\"id\": \"{bad}\", \"prompt\": \"p\"}}\n")).unwrap();
+            let err = load_tasks(&file).unwrap_err();
+            assert!(err.contains("invalid task id"), "{bad}: {err}");
+        }
+        std::fs::write(
+            &file,
+            "{\"id\": \"Fix-1\", \"prompt\": \"p\"}\n{\"id\": \"fix-1\", \"prompt\": \"q\"}\n",
+        )
+        .unwrap();
+        assert_eq!(load_tasks(&file).unwrap_err(), "Duplicate task id fix-1");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn runs_tasks_in_worktrees_and_reruns_them() {
+        ui::set_headless(true);
+        let dir = temp_dir("run");
+        let repo = dir.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        std::fs::write(repo.join("README.md"), "hi\n").unwrap();
+        git(&repo, &["init", "-q"]);
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "init"]);
+
+        let file = dir.join("tasks.jsonl");
+        std::fs::write(
+            &file,
+            "{\"id\": \"explore\", \"prompt\": \"Explore the repository layout\", \"workspace\": \"repo\", \"worktree\": true}\n\
+             {\"prompt\": \"hello\", \"workspace\": \"repo\"}\n",
+        )
+        .unwrap();
+        let tasks = load_tasks(&file).unwrap();
+        let opts = BatchOptions {
+            concurrency: 2,
+            output: dir.join("results.jsonl"),
+            workspace: dir.clone(),
+            autonomy: Autonomy::FullAuto,
+        };
+
+        // The stub backend runs `ls -la`, then submits
+        for _ in 0..2 {
+            let results = run_batch(&ModelBackend::Stub, &tasks, &opts).unwrap();
+            assert_eq!(results.len(), 2);
+            assert_eq!(results[0].status, "submitted", "{:?}", results[0].error);
+            assert_eq!(results[0].branch.as_deref(), Some("clifcode/explore"));
+            assert!(results[0]
+                .workspace
+                .ends_with("repo.clifcode-worktrees/explore"));
+            assert_eq!(results[1].id, "task-2");
+            assert_eq!(results[1].status, "answered");
+            assert_eq!(results[1].branch, None);
+        }
+        let written = std::fs::read_to_string(&opts.output).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
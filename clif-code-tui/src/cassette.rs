@@ -0,0 +1,438 @@
+//! Record/replay cassettes for model calls.
+//!
+//! `--record` wraps a backend and appends every request/response pair —
+//! including streamed chunks — to a JSON-lines cassette. `--replay` serves
+//! them back without a model: requests are matched by a SHA-256 of the
+//! messages and tool definitions, so tool execution and the agent loop run
+//! for real and deterministically. The workspace path is stored as
+//! `<workspace>` so a cassette recorded in one checkout replays in another.
+//!
+//! A request with no recorded match is an error that names the first
+//! unplayed entry and the message where the two diverge.
+
+use crate::backend::{ChatResponse, TokenUsage};
+use crate::message::{self, Message};
+use crate::tools::ApiToolCall;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const WORKSPACE_PLACEHOLDER: &str = "<workspace>";
+
+/// One model call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// Position in the recording, from 0
+    pub index: usize,
+    pub hash: String,
+    /// `{"messages": [...], "tools": [...]}` with the workspace replaced
+    pub request: Value,
+    /// Streamed text in the order it arrived; empty for non-streaming calls
+    #[serde(default)]
+    pub chunks: Vec<String>,
+    pub response: Recorded,
+}
+
+/// What the backend returned
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recorded {
+    pub content: String,
+    pub tool_calls: Vec<ApiToolCall>,
+    /// Named `raw_message` in older cassettes
+    #[serde(alias = "raw_message")]
+    pub message: Message,
+    pub streamed: bool,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// Replace `from` with `to` in every string inside `value`
+fn substitute(value: &Value, from: &str, to: &str) -> Value {
+    if from.is_empty() {
+        return value.clone();
+    }
+    match value {
+        Value::String(s) => Value::String(s.replace(from, to)),
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| substitute(v, from, to)).collect())
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.clone(), substitute(v, from, to)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn normalized_request(messages: &[Message], tools: Option<&Value>, workspace: &str) -> Value {
+    let request = serde_json::json!({
+        "messages": message::to_openai(messages),
+        "tools": tools.cloned().unwrap_or(Value::Null),
+    });
+    substitute(&request, workspace, WORKSPACE_PLACEHOLDER)
+}
+
+/// Object keys serialize sorted, so equal requests hash equal
+fn request_hash(request: &Value) -> String {
+    let digest = Sha256::digest(request.to_string().as_bytes());
+    digest.iter().take(8).map(|b| format!("{b:02x}")).collect()
+}
+
+// ---------------------------------------------------------------------------
+// Recording
+// ---------------------------------------------------------------------------
+
+/// Appends entries to a cassette file as calls complete
+pub struct Recorder {
+    path: PathBuf,
+    workspace: String,
+    state: Mutex<(std::fs::File, usize)>,
+}
+
+impl Recorder {
+    /// Start a new cassette, replacing any file at `path`
+    pub fn create(path: &Path, workspace: &str) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Cannot create cassette {}: {e}", path.display()))?;
+        Ok(Recorder {
+            path: path.to_path_buf(),
+            workspace: workspace.to_string(),
+            state: Mutex::new((file, 0)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run `call` and write what it returned. Failed calls aren't recorded.
+    pub fn record(
+        &self,
+        messages: &[Message],
+        tools: Option<&Value>,
+        call: impl FnOnce(&mut dyn FnMut(&str)) -> Result<ChatResponse>,
+    ) -> Result<ChatResponse> {
+        let request = normalized_request(messages, tools, &self.workspace);
+        let mut chunks = Vec::new();
+        let response = call(&mut |chunk: &str| chunks.push(chunk.to_string()))?;
+
+        let recorded = Recorded {
+            content: response.content.clone(),
+            tool_calls: response.tool_calls.clone(),
+            message: response.message.clone(),
+            streamed: response.streamed,
+            usage: response.usage.clone(),
+            finish_reason: response.finish_reason.clone(),
+        };
+        let recorded: Recorded = serde_json::from_value(substitute(
+            &serde_json::to_value(recorded)?,
+            &self.workspace,
+            WORKSPACE_PLACEHOLDER,
+        ))?;
+        let chunks = chunks
+            .iter()
+            .map(|c| c.replace(&self.workspace, WORKSPACE_PLACEHOLDER))
+            .collect();
+
+        let mut state = self.state.lock().unwrap();
+        let entry = Entry {
+            index: state.1,
+            hash: request_hash(&request),
+            request,
+            chunks,
+            response: recorded,
+        };
+        writeln!(state.0, "{}", serde_json::to_string(&entry)?)?;
+        state.0.flush()?;
+        state.1 += 1;
+        Ok(response)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Replay
+// ---------------------------------------------------------------------------
+
+/// Serves a cassette's responses by request hash
+pub struct Player {
+    path: PathBuf,
+    workspace: String,
+    entries: Vec<Entry>,
+    /// Which entries have been served, and how many requests were made
+    state: Mutex<(Vec<bool>, usize)>,
+}
+
+impl Player {
+    pub fn open(path: &Path, workspace: &str) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Cannot read cassette {}: {e}", path.display()))?;
+        let mut entries = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("{}:{}: {e}", path.display(), i + 1))?;
+            entries.push(entry);
+        }
+        Ok(Player {
+            path: path.to_path_buf(),
+            workspace: workspace.to_string(),
+            state: Mutex::new((vec![false; entries.len()], 0)),
+            entries,
+        })
+    }
+
+    /// Entries not yet served — a complete replay leaves none
+    pub fn remaining(&self) -> usize {
+        self.state.lock().unwrap().0.iter().filter(|u| !**u).count()
+    }
+
+    /// Serve the first unplayed entry with this request's hash, streaming its
+    /// chunks to `on_token`
+    pub fn play(
+        &self,
+        messages: &[Message],
+        tools: Option<&Value>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<ChatResponse> {
+        let request = normalized_request(messages, tools, &self.workspace);
+        let hash = request_hash(&request);
+
+        let entry = {
+            let mut state = self.state.lock().unwrap();
+            let (used, requests) = &mut *state;
+            *requests += 1;
+            let found = (0..self.entries.len()).find(|&i| !used[i] && self.entries[i].hash == hash);
+            match found {
+                Some(i) => {
+                    used[i] = true;
+                    &self.entries[i]
+                }
+                None => {
+                    let next = used.iter().position(|u| !u).map(|i| &self.entries[i]);
+                    anyhow::bail!(self.miss_report(*requests, &hash, &request, next));
+                }
+            }
+        };
+
+        for chunk in &entry.chunks {
+            on_token(&chunk.replace(WORKSPACE_PLACEHOLDER, &self.workspace));
+        }
+        let recorded: Recorded = serde_json::from_value(substitute(
+            &serde_json::to_value(&entry.response)?,
+            WORKSPACE_PLACEHOLDER,
+            &self.workspace,
+        ))?;
+        Ok(ChatResponse {
+            content: recorded.content,
+            tool_calls: recorded.tool_calls,
+            message: recorded.message,
+            streamed: recorded.streamed,
+            usage: recorded.usage,
+            finish_reason: recorded.finish_reason,
+        })
+    }
+
+    /// Explain a miss: which entry was expected next and where it diverges
+    fn miss_report(
+        &self,
+        request_no: usize,
+        hash: &str,
+        actual: &Value,
+        next: Option<&Entry>,
+    ) -> String {
+        let head = format!(
+            "Cassette {}: no recorded response for request #{request_no} (hash {hash})",
+            self.path.display()
+        );
+        let Some(entry) = next else {
+            return format!("{head} — all {} entries already played", self.entries.len());
+        };
+        let recorded = entry.request["messages"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let actual_msgs = actual["messages"].as_array().cloned().unwrap_or_default();
+        let diverged = (0..recorded.len().max(actual_msgs.len()))
+            .find(|&i| recorded.get(i) != actual_msgs.get(i));
+        let detail = match diverged {
+            Some(i) => format!(
+                "diverges at messages[{i}]\n  recorded: {}\n  actual:   {}",
+                preview(recorded.get(i)),
+                preview(actual_msgs.get(i))
+            ),
+            None => "messages match; the tool definitions differ".into(),
+        };
+        format!(
+            "{head}. Next unplayed entry #{} (hash {}) {detail}",
+            entry.index, entry.hash
+        )
+    }
+}
+
+/// One message, cut to a readable length
+fn preview(message: Option<&Value>) -> String {
+    let Some(message) = message else {
+        return "(none)".into();
+    };
+    let text = message.to_string();
+    if text.chars().count() > 300 {
+        format!("{}…", text.chars().take(300).collect::<String>())
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{self, Autonomy, Conversation, TurnOptions};
+    use crate::backend::{ModelBackend, ToolProtocol};
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// Stream one SSE chat completion per queued delta, reading each request in full
+    fn serve(deltas: Vec<Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for delta in deltas {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                        length = v.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let event = serde_json::json!({"choices": [{"delta": delta}]});
+                let mut stream = reader.into_inner();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\ndata: {event}\n\ndata: [DONE]\n\n"
+                );
+            }
+        });
+        format!("http://127.0.0.1:{}/v1", addr.port())
+    }
+
+    fn tool_call(id: &str, name: &str, args: Value) -> Value {
+        serde_json::json!({"tool_calls": [{"index": 0, "id": id, "type": "function",
+            "function": {"name": name, "arguments": args.to_string()}}]})
+    }
+
+    fn workspace(name: &str) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("clifcode-cassette-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hello.txt"), "hello world\n").unwrap();
+        dir.canonicalize().unwrap().to_string_lossy().to_string()
+    }
+
+    fn run(bk: &ModelBackend, ws: &str, input: &str) -> anyhow::Result<Conversation> {
+        crate::ui::set_headless(true);
+        let mut conv = Conversation::new(ws, &Autonomy::AutoEdit, &[]);
+        let mut workspace = ws.to_string();
+        agent::run_turn(
+            bk,
+            &mut conv,
+            input,
+            &mut workspace,
+            &Autonomy::AutoEdit,
+            &TurnOptions::default(),
+            &mut |_: &agent::AgentEvent| {},
+        )?;
+        Ok(conv)
+    }
+
+    #[test]
+    fn replays_a_recorded_turn_in_another_workspace() {
+        let url = serve(vec![
+            serde_json::json!({"content": "Reading it.\n"}),
+            tool_call("c1", "read_file", serde_json::json!({"path": "hello.txt"})),
+            tool_call(
+                "c2",
+                "edit_file",
+                serde_json::json!({"path": "hello.txt", "old_string": "hello", "new_string": "goodbye"}),
+            ),
+            tool_call(
+                "c3",
+                "submit",
+                serde_json::json!({"summary": "Said goodbye"}),
+            ),
+        ]);
+        // The first reply is text only, so the turn ends; the second turn does the edit
+        let first = workspace("record");
+        let cassette_path =
+            std::env::temp_dir().join(format!("clifcode-cassette-{}.jsonl", std::process::id()));
+        let recorder = Recorder::create(&cassette_path, &first).unwrap();
+        let recording = ModelBackend::Record {
+            inner: Box::new(ModelBackend::Api {
+                url,
+                key: None,
+                model: "test".into(),
+                max_tokens: 100,
+                tool_protocol: ToolProtocol::Native,
+            }),
+            cassette: Arc::new(recorder),
+        };
+        run(&recording, &first, "hi").unwrap();
+        let recorded = run(&recording, &first, "Say goodbye in hello.txt").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(Path::new(&first).join("hello.txt")).unwrap(),
+            "goodbye world\n"
+        );
+        let cassette = std::fs::read_to_string(&cassette_path).unwrap();
+        assert!(!cassette.contains(&first));
+
+        // Replay elsewhere: same edits and messages, no server
+        let second = workspace("replay");
+        let player = Arc::new(Player::open(&cassette_path, &second).unwrap());
+        let replay = ModelBackend::Replay(player.clone());
+        run(&replay, &second, "hi").unwrap();
+        let replayed = run(&replay, &second, "Say goodbye in hello.txt").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(Path::new(&second).join("hello.txt")).unwrap(),
+            "goodbye world\n"
+        );
+        assert_eq!(player.remaining(), 0);
+        assert_eq!(
+            substitute(&message::to_openai(&recorded.messages), &first, "WS"),
+            substitute(&message::to_openai(&replayed.messages), &second, "WS")
+        );
+
+        // A different prompt points at the entry that diverged
+        let player = Arc::new(Player::open(&cassette_path, &second).unwrap());
+        let err = run(&ModelBackend::Replay(player), &second, "hello")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("request #1"), "{err}");
+        assert!(err.contains("entry #0"), "{err}");
+        assert!(err.contains("diverges at messages[1]"), "{err}");
+
+        for dir in [&first, &second] {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        let _ = std::fs::remove_file(&cassette_path);
+    }
+}
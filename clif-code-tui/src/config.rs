@@ -90,6 +90,82 @@ pub fn saved_api_url() -> Option<String> {
         .map(|s| s.to_string())
 }
 
+/// Domains the `fetch_url` tool may download from (subdomains included).
+/// Set `fetch_allowlist` in config.json to override; `["*"]` allows any domain.
+pub const DEFAULT_FETCH_ALLOWLIST: &[&str] = &[
+    "docs.rs",
+    "doc.rust-lang.org",
+    "crates.io",
+    "developer.mozilla.org",
+    "docs.python.org",
+    "pkg.go.dev",
+    "nodejs.org",
+    "github.com",
+    "raw.githubusercontent.com",
+];
+
+pub fn fetch_allowlist() -> Vec<String> {
+    match load_config()
+        .get("fetch_allowlist")
+        .and_then(|v| v.as_array())
+    {
+        Some(list) => list
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        None => DEFAULT_FETCH_ALLOWLIST
+            .iter()
+            .map(|s| s.to_string())
+            .collect(),
+    }
+}
+
+/// Paths the agent may never read (gitignore syntax, relative to the workspace)
+pub fn denied_paths() -> Vec<String> {
+    load_config()
+        .get("denied_paths")
+        .and_then(|v| v.as_array())
+        .map(|list| {
+            list.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Base URL for crate docs when `target/doc` has no local copy (docs.rs or a mirror)
+pub fn docs_mirror() -> String {
+    load_config()
+        .get("docs_mirror")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "https://docs.rs".into())
+}
+
+/// Whether language servers may be started for code intelligence (`"lsp": false` disables)
+pub fn lsp_enabled() -> bool {
+    load_config()
+        .get("lsp")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true)
+}
+
+/// JSON-lines file that receives a line per finished span
+pub fn trace_file() -> Option<PathBuf> {
+    load_config()
+        .get("trace_file")
+        .and_then(|v| v.as_str())
+        .map(PathBuf::from)
+}
+
+/// OTLP/HTTP collector for spans, e.g. `http://localhost:4318`
+pub fn otlp_endpoint() -> Option<String> {
+    load_config()
+        .get("otlp_endpoint")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
 /// Interactive first-run setup. Returns (key, url, model) or None on cancel.
 pub fn interactive_setup() -> Option<(String, String, String)> {
     fn fetch_openai_models(api_key: &str) -> Vec<String> {
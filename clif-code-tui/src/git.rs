@@ -84,7 +84,11 @@ pub fn git_undo(workspace: &str) -> Result<String, String> {
         return Err("Last commit was not by ClifCode — refusing to undo".into());
     }
 
-    let message = log_str.splitn(2, '|').nth(1).unwrap_or("").to_string();
+    let message = log_str
+        .split_once('|')
+        .map(|x| x.1)
+        .unwrap_or("")
+        .to_string();
 
     // Soft reset (keeps changes staged)
     let output = Command::new("git")
@@ -109,3 +113,89 @@ pub fn git_status(workspace: &str) -> Result<String, String> {
         .map_err(|e| format!("git status failed: {e}"))?;
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
+
+/// Paths with uncommitted changes, including untracked files
+pub fn changed_files(workspace: &str) -> Result<Vec<String>, String> {
+    let output = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=all"])
+        .current_dir(workspace)
+        .output()
+        .map_err(|e| format!("git status failed: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.get(3..))
+        // Renames are "old -> new"
+        .map(|path| path.rsplit(" -> ").next().unwrap_or(path).to_string())
+        .collect())
+}
+
+/// Check out `branch` in a separate worktree at `path`, creating the branch
+/// from HEAD if it doesn't exist. A worktree already at `path` on `branch` —
+/// left by an earlier run — is reused; returns whether it was.
+pub fn add_or_reuse_worktree(workspace: &str, path: &Path, branch: &str) -> Result<bool, String> {
+    let git = |dir: &Path, args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .map_err(|e| format!("git {} failed: {e}", args[0]))
+    };
+    // Forget worktrees whose directory was deleted, so their branch is free
+    let _ = git(Path::new(workspace), &["worktree", "prune"]);
+
+    if path.exists() {
+        let head = git(path, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+        if head.status.success() && String::from_utf8_lossy(&head.stdout).trim() == branch {
+            return Ok(true);
+        }
+        return Err(format!(
+            "{} already exists and is not a worktree on {branch}",
+            path.display()
+        ));
+    }
+
+    let path_arg = path.to_string_lossy();
+    let branch_ref = format!("refs/heads/{branch}");
+    let exists = git(
+        Path::new(workspace),
+        &["rev-parse", "--verify", "--quiet", &branch_ref],
+    )?
+    .status
+    .success();
+    let args: Vec<&str> = if exists {
+        vec!["worktree", "add", &path_arg, branch]
+    } else {
+        vec!["worktree", "add", "-b", branch, &path_arg]
+    };
+    let output = git(Path::new(workspace), &args)?;
+    if output.status.success() {
+        Ok(false)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+/// `git diff` output: against `rev` (HEAD when `None`), or the index with `staged`
+pub fn diff(workspace: &str, rev: Option<&str>, staged: bool) -> Result<String, String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("diff");
+    if staged {
+        cmd.arg("--staged");
+    }
+    if let Some(rev) = rev {
+        cmd.arg(rev);
+    } else if !staged {
+        cmd.arg("HEAD");
+    }
+    let output = cmd
+        .current_dir(workspace)
+        .output()
+        .map_err(|e| format!("git diff failed: {e}"))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
@@ -0,0 +1,53 @@
+//! ClifCode — an AI coding agent you can embed.
+//!
+//! The stable API is [`Agent`] (see [`agent`]), the [`Tool`] and
+//! [`EventHandler`] traits, and the backend and tool-result types they use.
+//! The remaining modules back the `clifcode` binary and may change between
+//! releases.
+
+pub mod agent;
+pub mod backend;
+pub mod cassette;
+pub mod message;
+pub mod tools;
+
+#[doc(hidden)]
+pub mod attach;
+#[doc(hidden)]
+pub mod batch;
+#[doc(hidden)]
+pub mod config;
+#[doc(hidden)]
+pub mod git;
+#[doc(hidden)]
+pub mod lsp;
+#[doc(hidden)]
+pub mod notebook;
+#[doc(hidden)]
+pub mod plan;
+#[doc(hidden)]
+pub mod redact;
+#[doc(hidden)]
+pub mod repomap;
+#[doc(hidden)]
+pub mod search;
+#[doc(hidden)]
+pub mod session;
+#[doc(hidden)]
+pub mod telemetry;
+#[doc(hidden)]
+pub mod todo;
+#[doc(hidden)]
+pub mod ui;
+#[doc(hidden)]
+pub mod update;
+#[doc(hidden)]
+pub mod web;
+
+pub use agent::{
+    Agent, AgentBuilder, AgentEvent, Approval, Autonomy, Conversation, EventHandler, Tool,
+    ToolContext, TurnOptions,
+};
+pub use backend::{ModelBackend, TokenUsage, ToolProtocol};
+pub use message::Message;
+pub use tools::ToolResult;
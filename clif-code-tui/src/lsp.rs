@@ -0,0 +1,818 @@
+//! Language-server code intelligence — `goto_definition`, `find_references`,
+//! `hover` and `diagnostics`, plus fresh diagnostics after every edit.
+//!
+//! Servers are spawned on demand by the LSP tools, one per (workspace,
+//! language), and live for the rest of the session. Post-edit diagnostics
+//! only come from servers that are already running. Framing is the same Content-Length JSON-RPC as the
+//! IDE's `commands/lsp.rs`; here a reader thread routes responses back to the
+//! waiting request and keeps the latest `publishDiagnostics` for each file.
+
+use crate::config;
+use crate::tools::ToolResult;
+use crate::ui;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+const INIT_TIMEOUT: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Longest a query waits for the server to finish indexing
+const INDEX_WAIT: Duration = Duration::from_secs(60);
+/// Diagnostics wait for the `diagnostics` tool vs. after an edit
+const DIAGNOSTICS_WAIT: Duration = Duration::from_secs(20);
+const EDIT_DIAGNOSTICS_WAIT: Duration = Duration::from_secs(5);
+/// Diagnostics are considered settled after this long without a new publish
+const QUIET_PERIOD: Duration = Duration::from_millis(600);
+const MAX_LOCATIONS: usize = 100;
+const MAX_DIAGNOSTICS: usize = 50;
+const MAX_HOVER_CHARS: usize = 6000;
+
+type ServerCandidates = &'static [(&'static str, &'static [&'static str])];
+
+/// (server group, LSP language id) for a file, by extension
+fn language_for(path: &Path) -> Option<(&'static str, &'static str)> {
+    let ext = path.extension()?.to_str()?;
+    Some(match ext {
+        "rs" => ("rust", "rust"),
+        "ts" | "mts" | "cts" => ("typescript", "typescript"),
+        "tsx" => ("typescript", "typescriptreact"),
+        "js" | "mjs" | "cjs" => ("typescript", "javascript"),
+        "jsx" => ("typescript", "javascriptreact"),
+        "py" | "pyi" => ("python", "python"),
+        "go" => ("go", "go"),
+        _ => return None,
+    })
+}
+
+/// Server binaries per group — the first one found on PATH is used
+fn server_candidates(group: &str) -> ServerCandidates {
+    match group {
+        "rust" => &[("rust-analyzer", &[])],
+        "typescript" => &[("typescript-language-server", &["--stdio"])],
+        "python" => &[("pyright-langserver", &["--stdio"]), ("pylsp", &[])],
+        "go" => &[("gopls", &["serve"])],
+        _ => &[],
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Server process
+// ---------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Shared {
+    /// uri → (publish generation, diagnostics)
+    diagnostics: HashMap<String, (u64, Vec<Value>)>,
+    last_publish: Option<Instant>,
+    /// Active `$/progress` tokens (indexing, cargo check, ...)
+    progress: HashSet<String>,
+    exited: bool,
+}
+
+struct Server {
+    name: &'static str,
+    child: Mutex<Child>,
+    stdin: Mutex<ChildStdin>,
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<u64, mpsc::Sender<Result<Value, String>>>>,
+    shared: Mutex<Shared>,
+    changed: Condvar,
+    /// uri → document version of files we've opened
+    open: Mutex<HashMap<String, i64>>,
+}
+
+/// Encode a JSON-RPC message as LSP framed bytes.
+fn encode_message(msg: &Value) -> Vec<u8> {
+    let body = msg.to_string();
+    let mut out = format!("Content-Length: {}\r\n\r\n", body.len()).into_bytes();
+    out.extend_from_slice(body.as_bytes());
+    out
+}
+
+/// Read one framed message; `None` at EOF
+fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut len = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            if len.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some(rest) = header.strip_prefix("Content-Length:") {
+            len = rest.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0u8; len?];
+    reader.read_exact(&mut body).ok()?;
+    Some(serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+impl Server {
+    fn send(&self, msg: &Value) -> Result<(), String> {
+        let mut stdin = self.stdin.lock().unwrap();
+        stdin
+            .write_all(&encode_message(msg))
+            .and_then(|_| stdin.flush())
+            .map_err(|e| format!("{} write error: {e}", self.name))
+    }
+
+    fn notify(&self, method: &str, params: Value) -> Result<(), String> {
+        self.send(&json!({"jsonrpc": "2.0", "method": method, "params": params}))
+    }
+
+    fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let (tx, rx) = mpsc::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))?;
+        match rx.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.pending.lock().unwrap().remove(&id);
+                Err(format!("{} timed out on {method}", self.name))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(format!("{} exited", self.name)),
+        }
+    }
+
+    /// Route server → client traffic until the server exits
+    fn read_loop(self: Arc<Self>, stdout: ChildStdout) {
+        let mut reader = BufReader::new(stdout);
+        while let Some(msg) = read_message(&mut reader) {
+            let method = msg.get("method").and_then(|v| v.as_str());
+            let id = msg.get("id").cloned();
+            match (method, id) {
+                // Response to one of our requests
+                (None, Some(id)) => {
+                    let Some(id) = id.as_u64() else { continue };
+                    if let Some(tx) = self.pending.lock().unwrap().remove(&id) {
+                        let result = match msg.get("error") {
+                            Some(err) => Err(format!(
+                                "{}: {}",
+                                self.name,
+                                err.get("message")
+                                    .and_then(|v| v.as_str())
+                                    .unwrap_or("error")
+                            )),
+                            None => Ok(msg.get("result").cloned().unwrap_or(Value::Null)),
+                        };
+                        let _ = tx.send(result);
+                    }
+                }
+                // Server → client request: answer so the server doesn't stall
+                (Some(method), Some(id)) => {
+                    let result = if method == "workspace/configuration" {
+                        let items = msg["params"]["items"].as_array().map_or(0, |a| a.len());
+                        Value::Array(vec![Value::Null; items])
+                    } else {
+                        Value::Null
+                    };
+                    let _ = self.send(&json!({"jsonrpc": "2.0", "id": id, "result": result}));
+                }
+                (Some("textDocument/publishDiagnostics"), None) => {
+                    let uri = msg["params"]["uri"].as_str().unwrap_or("").to_string();
+                    let diags = msg["params"]["diagnostics"]
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default();
+                    let mut shared = self.shared.lock().unwrap();
+                    let entry = shared.diagnostics.entry(uri).or_insert((0, Vec::new()));
+                    entry.0 += 1;
+                    entry.1 = diags;
+                    shared.last_publish = Some(Instant::now());
+                    self.changed.notify_all();
+                }
+                (Some("$/progress"), None) => {
+                    let token = msg["params"]["token"].to_string();
+                    let mut shared = self.shared.lock().unwrap();
+                    match msg["params"]["value"]["kind"].as_str() {
+                        Some("begin") => {
+                            shared.progress.insert(token);
+                        }
+                        Some("end") => {
+                            shared.progress.remove(&token);
+                        }
+                        _ => {}
+                    }
+                    self.changed.notify_all();
+                }
+                _ => {}
+            }
+        }
+
+        // EOF — fail anything still waiting
+        self.pending.lock().unwrap().clear();
+        self.shared.lock().unwrap().exited = true;
+        self.changed.notify_all();
+    }
+
+    fn initialize(&self, root: &Path) -> Result<(), String> {
+        let root_uri = url::Url::from_directory_path(root)
+            .map(|u| u.to_string())
+            .map_err(|_| format!("Invalid workspace path {}", root.display()))?;
+        let name = root
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("workspace");
+        self.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": root_uri,
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "definition": { "linkSupport": true },
+                        "references": {},
+                        "hover": { "contentFormat": ["markdown", "plaintext"] },
+                        "publishDiagnostics": { "relatedInformation": false }
+                    },
+                    "window": { "workDoneProgress": true },
+                    "workspace": { "configuration": true, "workspaceFolders": true }
+                },
+                "workspaceFolders": [{ "uri": root_uri, "name": name }]
+            }),
+            INIT_TIMEOUT,
+        )?;
+        self.notify("initialized", json!({}))
+    }
+
+    /// Wait until no progress (indexing, cargo check) is running
+    fn wait_idle(&self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        let mut shared = self.shared.lock().unwrap();
+        while !shared.progress.is_empty() && !shared.exited {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break;
+            }
+            shared = self.changed.wait_timeout(shared, left).unwrap().0;
+        }
+    }
+
+    /// Open or update a document with its on-disk content.
+    /// Returns the diagnostics generation before the change.
+    fn sync(&self, uri: &str, language_id: &str, text: &str) -> Result<u64, String> {
+        let generation = self
+            .shared
+            .lock()
+            .unwrap()
+            .diagnostics
+            .get(uri)
+            .map_or(0, |d| d.0);
+        let mut open = self.open.lock().unwrap();
+        match open.get_mut(uri) {
+            Some(version) => {
+                *version += 1;
+                self.notify(
+                    "textDocument/didChange",
+                    json!({
+                        "textDocument": { "uri": uri, "version": *version },
+                        "contentChanges": [{ "text": text }]
+                    }),
+                )?;
+            }
+            None => {
+                self.notify(
+                    "textDocument/didOpen",
+                    json!({
+                        "textDocument": {
+                            "uri": uri,
+                            "languageId": language_id,
+                            "version": 1,
+                            "text": text
+                        }
+                    }),
+                )?;
+                open.insert(uri.to_string(), 1);
+            }
+        }
+        // Some servers (rust-analyzer's cargo check) only re-check on save
+        self.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri } }),
+        )?;
+        Ok(generation)
+    }
+
+    /// Diagnostics published after `since`, once they've settled.
+    /// `None` if the server published nothing for the file in time.
+    fn wait_diagnostics(&self, uri: &str, since: u64, timeout: Duration) -> Option<Vec<Value>> {
+        let deadline = Instant::now() + timeout;
+        let mut shared = self.shared.lock().unwrap();
+        loop {
+            let fresh = shared.diagnostics.get(uri).filter(|d| d.0 > since);
+            let quiet = shared
+                .last_publish
+                .is_some_and(|t| t.elapsed() >= QUIET_PERIOD);
+            if fresh.is_some() && quiet && shared.progress.is_empty() {
+                return fresh.map(|d| d.1.clone());
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || shared.exited {
+                return fresh.map(|d| d.1.clone());
+            }
+            let wait = if fresh.is_some() {
+                left.min(QUIET_PERIOD)
+            } else {
+                left
+            };
+            shared = self.changed.wait_timeout(shared, wait).unwrap().0;
+        }
+    }
+}
+
+/// A server slot, filled once the server has started (or failed to)
+type Slot = Arc<OnceLock<Result<Arc<Server>, String>>>;
+
+/// Servers per (workspace, group)
+type Registry = Mutex<HashMap<(String, &'static str), Slot>>;
+
+fn registry() -> &'static Registry {
+    static SERVERS: OnceLock<Registry> = OnceLock::new();
+    SERVERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn spawn_server(workspace: &str, group: &str) -> Result<Arc<Server>, String> {
+    let candidates = server_candidates(group);
+    for (bin, args) in candidates {
+        let mut child = match Command::new(bin)
+            .args(*args)
+            .current_dir(workspace)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to spawn {bin}: {e}")),
+        };
+        ui::print_dim(&format!("    starting {bin}…"));
+        let stdout = child.stdout.take().ok_or("no stdout")?;
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let server = Arc::new(Server {
+            name: bin,
+            child: Mutex::new(child),
+            stdin: Mutex::new(stdin),
+            next_id: AtomicU64::new(1),
+            pending: Mutex::new(HashMap::new()),
+            shared: Mutex::new(Shared::default()),
+            changed: Condvar::new(),
+            open: Mutex::new(HashMap::new()),
+        });
+        let reader = Arc::clone(&server);
+        std::thread::spawn(move || reader.read_loop(stdout));
+
+        if let Err(e) = server.initialize(Path::new(workspace)) {
+            let _ = server.child.lock().unwrap().kill();
+            return Err(e);
+        }
+        return Ok(server);
+    }
+    let names: Vec<&str> = candidates.iter().map(|(bin, _)| *bin).collect();
+    Err(format!(
+        "No language server found on PATH — install {} for {group} support",
+        names.join(" or ")
+    ))
+}
+
+/// The server for a file's language, starting it on first use. The registry
+/// lock is only held to find the slot: a server that takes a while to
+/// initialize blocks callers of its own language, not every LSP call.
+fn server_for(workspace: &str, group: &'static str) -> Result<Arc<Server>, String> {
+    let slot = Arc::clone(
+        registry()
+            .lock()
+            .unwrap()
+            .entry((workspace.to_string(), group))
+            .or_default(),
+    );
+    slot.get_or_init(|| spawn_server(workspace, group)).clone()
+}
+
+/// The server for a group if it has already started — never spawns one
+fn running_server(workspace: &str, group: &'static str) -> Option<Arc<Server>> {
+    let slot = registry()
+        .lock()
+        .unwrap()
+        .get(&(workspace.to_string(), group))
+        .cloned()?;
+    let server = Arc::clone(slot.get()?.as_ref().ok()?);
+    let exited = server.shared.lock().unwrap().exited;
+    (!exited).then_some(server)
+}
+
+// ---------------------------------------------------------------------------
+// Formatting helpers
+// ---------------------------------------------------------------------------
+
+fn full_path(workspace: &str, path: &str) -> PathBuf {
+    let full = Path::new(workspace).join(path);
+    full.canonicalize().unwrap_or(full)
+}
+
+/// Show paths relative to the workspace when they're inside it
+fn display_path(workspace: &str, path: &Path) -> String {
+    let base = Path::new(workspace)
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(workspace));
+    path.strip_prefix(&base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// LSP position for a 1-based line, at `symbol` on that line, a 1-based
+/// `column`, or the first non-blank character
+fn position(
+    text: &str,
+    line: usize,
+    symbol: Option<&str>,
+    column: Option<usize>,
+) -> Result<Value, String> {
+    let total = text.lines().count();
+    let line_text = line
+        .checked_sub(1)
+        .and_then(|i| text.lines().nth(i))
+        .ok_or_else(|| format!("Line {line} is out of range (file has {total} lines)"))?;
+
+    let byte = match (symbol.filter(|s| !s.is_empty()), column) {
+        (Some(sym), _) => {
+            let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+            // Prefer a whole-word occurrence, then any occurrence
+            line_text
+                .match_indices(sym)
+                .map(|(i, _)| i)
+                .find(|&i| {
+                    !line_text[..i].chars().next_back().is_some_and(is_ident)
+                        && !line_text[i + sym.len()..]
+                            .chars()
+                            .next()
+                            .is_some_and(is_ident)
+                })
+                .or_else(|| line_text.find(sym))
+                .ok_or_else(|| format!("`{sym}` not found on line {line}: {}", line_text.trim()))?
+        }
+        (None, Some(col)) => line_text
+            .char_indices()
+            .nth(col.saturating_sub(1))
+            .map_or(line_text.len(), |(i, _)| i),
+        (None, None) => line_text.len() - line_text.trim_start().len(),
+    };
+    let character = line_text[..byte].encode_utf16().count();
+    Ok(json!({ "line": line - 1, "character": character }))
+}
+
+/// Normalize Location | Location[] | LocationLink[] into (path, line, col), 1-based
+fn locations(result: &Value) -> Vec<(PathBuf, u64, u64)> {
+    let items = match result {
+        Value::Array(items) => items.clone(),
+        Value::Null => Vec::new(),
+        other => vec![other.clone()],
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let uri = item
+                .get("uri")
+                .or_else(|| item.get("targetUri"))?
+                .as_str()?;
+            let range = item
+                .get("range")
+                .or_else(|| item.get("targetSelectionRange"))?;
+            let path = url::Url::parse(uri).ok()?.to_file_path().ok()?;
+            let line = range["start"]["line"].as_u64()? + 1;
+            let col = range["start"]["character"].as_u64()? + 1;
+            Some((path, line, col))
+        })
+        .collect()
+}
+
+/// `path:line:col` plus the source line, grouped by file like `search`
+fn format_locations(workspace: &str, locs: &[(PathBuf, u64, u64)]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut current: Option<&PathBuf> = None;
+    let mut lines: Vec<String> = Vec::new();
+    for (path, line, col) in locs.iter().take(MAX_LOCATIONS) {
+        if current != Some(path) {
+            if !out.is_empty() {
+                out.push(String::new());
+            }
+            out.push(display_path(workspace, path));
+            lines = std::fs::read_to_string(path)
+                .map(|t| t.lines().map(|l| l.to_string()).collect())
+                .unwrap_or_default();
+            current = Some(path);
+        }
+        let source = lines
+            .get(*line as usize - 1)
+            .map(|l| l.trim())
+            .unwrap_or("");
+        out.push(format!("{line}:{col}: {source}"));
+    }
+    if locs.len() > MAX_LOCATIONS {
+        out.push(format!("\n[{} more not shown]", locs.len() - MAX_LOCATIONS));
+    }
+    out.join("\n")
+}
+
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(hover_text)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Value::Object(obj) => {
+            let value = obj.get("value").and_then(|v| v.as_str()).unwrap_or("");
+            match obj.get("language").and_then(|v| v.as_str()) {
+                Some(lang) => format!("```{lang}\n{value}\n```"),
+                None => value.to_string(),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+fn severity(diag: &Value) -> u64 {
+    // Servers may omit severity; the spec says treat it as an error
+    diag.get("severity").and_then(|v| v.as_u64()).unwrap_or(1)
+}
+
+fn format_diagnostics(file: &str, diags: &[Value]) -> String {
+    let mut sorted: Vec<&Value> = diags.iter().collect();
+    sorted.sort_by_key(|d| (severity(d), d["range"]["start"]["line"].as_u64()));
+    let mut lines: Vec<String> = sorted
+        .iter()
+        .take(MAX_DIAGNOSTICS)
+        .map(|d| {
+            let label = match severity(d) {
+                1 => "error",
+                2 => "warning",
+                3 => "info",
+                _ => "hint",
+            };
+            let line = d["range"]["start"]["line"].as_u64().unwrap_or(0) + 1;
+            let col = d["range"]["start"]["character"].as_u64().unwrap_or(0) + 1;
+            let message = d["message"].as_str().unwrap_or("").replace('\n', " ");
+            let code = match &d["code"] {
+                Value::String(c) => format!(" [{c}]"),
+                Value::Number(n) => format!(" [{n}]"),
+                _ => String::new(),
+            };
+            format!("{file}:{line}:{col} {label}: {message}{code}")
+        })
+        .collect();
+    if sorted.len() > MAX_DIAGNOSTICS {
+        lines.push(format!("[{} more]", sorted.len() - MAX_DIAGNOSTICS));
+    }
+    lines.join("\n")
+}
+
+fn file_uri(path: &Path) -> Result<String, String> {
+    url::Url::from_file_path(path)
+        .map(|u| u.to_string())
+        .map_err(|_| format!("Invalid path {}", path.display()))
+}
+
+/// Sync the file to its language server, starting it unless `only_running`.
+/// Returns (server, uri, text, diagnostics generation before the sync).
+fn open_file(
+    workspace: &str,
+    path: &str,
+    only_running: bool,
+) -> Result<(Arc<Server>, String, String, u64), String> {
+    if !config::lsp_enabled() {
+        return Err("Language servers are disabled (\"lsp\": false in config)".into());
+    }
+    let full = full_path(workspace, path);
+    let (group, language_id) =
+        language_for(&full).ok_or_else(|| format!("No language server support for {path}"))?;
+    let server = if only_running {
+        running_server(workspace, group)
+            .ok_or_else(|| format!("No {group} language server running"))?
+    } else {
+        server_for(workspace, group)?
+    };
+    let text = std::fs::read_to_string(&full).map_err(|e| format!("Cannot read {path}: {e}"))?;
+    let uri = file_uri(&full)?;
+    let generation = server.sync(&uri, language_id, &text)?;
+    Ok((server, uri, text, generation))
+}
+
+// ---------------------------------------------------------------------------
+// Tools
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy)]
+pub enum Query {
+    Definition,
+    References,
+    Hover,
+}
+
+/// `goto_definition` / `find_references` / `hover`
+pub fn exec_query(
+    workspace: &str,
+    query: Query,
+    path: &str,
+    line: usize,
+    symbol: Option<&str>,
+    column: Option<usize>,
+) -> ToolResult {
+    let (action, method) = match query {
+        Query::Definition => ("definition", "textDocument/definition"),
+        Query::References => ("references", "textDocument/references"),
+        Query::Hover => ("hover", "textDocument/hover"),
+    };
+    let target = symbol.map(|s| format!(" `{s}`")).unwrap_or_default();
+    ui::print_tool_action(action, &format!("{path}:{line}{target}"));
+
+    let result = open_file(workspace, path, false).and_then(|(server, uri, text, _)| {
+        let position = position(&text, line, symbol, column)?;
+        server.wait_idle(INDEX_WAIT);
+        let mut params = json!({ "textDocument": { "uri": uri }, "position": position });
+        if let Query::References = query {
+            params["context"] = json!({ "includeDeclaration": true });
+        }
+        server.request(method, params, REQUEST_TIMEOUT)
+    });
+
+    let output = match (query, result) {
+        (_, Err(e)) => {
+            ui::print_error(&format!("    {e}"));
+            return ToolResult {
+                success: false,
+                output: format!("Error: {e}"),
+            };
+        }
+        (Query::Hover, Ok(value)) => {
+            let text = hover_text(&value["contents"]);
+            if text.trim().is_empty() {
+                "No hover information at that position".to_string()
+            } else {
+                text.chars().take(MAX_HOVER_CHARS).collect()
+            }
+        }
+        (_, Ok(value)) => {
+            let locs = locations(&value);
+            ui::print_dim(&format!("    {} locations", locs.len()));
+            if locs.is_empty() {
+                "No locations found (the symbol may be unresolved, or the server is still indexing)"
+                    .to_string()
+            } else {
+                format_locations(workspace, &locs)
+            }
+        }
+    };
+    ToolResult {
+        success: true,
+        output,
+    }
+}
+
+/// `diagnostics` — current errors and warnings for a file
+pub fn exec_diagnostics(workspace: &str, path: &str) -> ToolResult {
+    ui::print_tool_action("diagnostics", path);
+    let result = open_file(workspace, path, false).map(|(server, uri, _, since)| {
+        // Unchanged files may not be republished — fall back to the last report
+        let diags = server
+            .wait_diagnostics(&uri, since, DIAGNOSTICS_WAIT)
+            .or_else(|| {
+                let shared = server.shared.lock().unwrap();
+                shared.diagnostics.get(&uri).map(|d| d.1.clone())
+            });
+        (server.name, diags)
+    });
+    match result {
+        Ok((_, Some(diags))) if !diags.is_empty() => {
+            ui::print_dim(&format!("    {} diagnostics", diags.len()));
+            ToolResult {
+                success: true,
+                output: format_diagnostics(path, &diags),
+            }
+        }
+        Ok((name, Some(_))) => ToolResult {
+            success: true,
+            output: format!("No diagnostics reported by {name} for {path}"),
+        },
+        Ok((name, None)) => ToolResult {
+            success: true,
+            output: format!("{name} published no diagnostics for {path} in time"),
+        },
+        Err(e) => {
+            ui::print_error(&format!("    {e}"));
+            ToolResult {
+                success: false,
+                output: format!("Error: {e}"),
+            }
+        }
+    }
+}
+
+/// Errors and warnings for a file that was just written, for appending to the
+/// edit's tool result. Only servers that are already running are asked, so an
+/// edit never waits for a cold start. `None` when there's no running server
+/// for the file type or it didn't publish in time — edits never fail because
+/// of the language server.
+pub fn diagnostics_after_edit(workspace: &str, path: &str) -> Option<String> {
+    language_for(Path::new(path))?;
+    let (server, uri, _, since) = open_file(workspace, path, true).ok()?;
+    let diags = server.wait_diagnostics(&uri, since, EDIT_DIAGNOSTICS_WAIT)?;
+    let relevant: Vec<Value> = diags.into_iter().filter(|d| severity(d) <= 2).collect();
+
+    let errors = relevant.iter().filter(|d| severity(d) == 1).count();
+    let warnings = relevant.len() - errors;
+    if errors > 0 {
+        ui::print_error(&format!(
+            "    {errors} errors, {warnings} warnings ({})",
+            server.name
+        ));
+    } else if warnings > 0 {
+        ui::print_dim(&format!("    {warnings} warnings ({})", server.name));
+    }
+
+    Some(if relevant.is_empty() {
+        format!("Diagnostics ({}): no errors or warnings", server.name)
+    } else {
+        format!(
+            "Diagnostics ({}):\n{}",
+            server.name,
+            format_diagnostics(path, &relevant)
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn framing_round_trips_and_skips_extra_headers() {
+        let msg = json!({ "jsonrpc": "2.0", "id": 1, "result": { "text": "é😀" } });
+        let mut bytes = encode_message(&msg);
+        bytes.extend_from_slice(
+            b"Content-Type: application/vscode-jsonrpc\r\nContent-Length: 2\r\n\r\n{}",
+        );
+        let mut reader = Cursor::new(bytes);
+        assert_eq!(read_message(&mut reader), Some(msg));
+        assert_eq!(read_message(&mut reader), Some(json!({})));
+        assert_eq!(read_message(&mut reader), None);
+        // A body cut short by the server exiting
+        assert_eq!(
+            read_message(&mut Cursor::new(b"Content-Length: 10\r\n\r\n{}".to_vec())),
+            None
+        );
+    }
+
+    #[test]
+    fn file_uris_round_trip_through_locations() {
+        let path = std::env::temp_dir().join("dir with space/ünï.rs");
+        let uri = file_uri(&path).unwrap();
+        assert!(uri.starts_with("file:///"), "{uri}");
+        assert!(uri.contains("dir%20with%20space"), "{uri}");
+
+        let result = json!([
+            { "uri": uri, "range": { "start": { "line": 4, "character": 2 } } },
+            { "targetUri": uri, "targetSelectionRange": { "start": { "line": 0, "character": 0 } } },
+        ]);
+        assert_eq!(locations(&result), vec![(path.clone(), 5, 3), (path, 1, 1)]);
+        assert!(file_uri(Path::new("relative.rs")).is_err());
+    }
+
+    #[test]
+    fn positions_prefer_whole_words_and_count_utf16() {
+        let text = "fn main() {\n    let idx = id(\"é😀\") + id;\n}\n";
+        // `id` inside `idx` is skipped for the whole word
+        assert_eq!(
+            position(text, 2, Some("id"), None).unwrap(),
+            json!({ "line": 1, "character": 14 })
+        );
+        // Column 24 is the `+`; é is one UTF-16 unit before it, 😀 two
+        assert_eq!(
+            position(text, 2, None, Some(24)).unwrap(),
+            json!({ "line": 1, "character": 24 })
+        );
+        assert_eq!(
+            position(text, 2, None, None).unwrap(),
+            json!({ "line": 1, "character": 4 })
+        );
+        assert!(position(text, 9, None, None).is_err());
+        assert!(position(text, 1, Some("missing"), None).is_err());
+    }
+}
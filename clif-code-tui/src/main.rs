@@ -4,6 +4,7 @@
 //! Supports multiple backends:
 //!   - API: OpenRouter, OpenAI, Anthropic, or any OpenAI-compatible endpoint
 //!   - Ollama: local LLM server
+//!   - Local: any local OpenAI-compatible server (Ollama, LM Studio, llama.cpp, vLLM)
 //!   - Stub: testing mode (no model needed)
 //!
 //! Install: cargo install --path clifcode
@@ -11,20 +12,19 @@
 //!   clifcode                                    # auto-detect backend
 //!   clifcode --backend api --api-model gpt-4o
 //!   clifcode --backend ollama --api-model codellama
-
-mod backend;
-mod config;
-mod git;
-mod repomap;
-mod session;
-mod tools;
-mod ui;
-mod update;
+//!   clifcode --backend local --text-tools
 
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use std::io::{self, BufRead, Write};
+use clap::{Parser, Subcommand, ValueEnum};
+use clifcode::agent::{
+    self, AgentEvent, Approval, Autonomy, Conversation, EventHandler, TurnOptions,
+};
+use clifcode::{
+    attach, backend, batch, cassette, config, git, plan, session, telemetry, todo, ui, update,
+};
+use std::io::{self, BufRead, IsTerminal, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 // ---------------------------------------------------------------------------
 // CLI
@@ -32,36 +32,18 @@ use std::path::PathBuf;
 
 #[derive(Clone, ValueEnum, Debug)]
 enum Backend {
-    /// Auto-detect: try api → ollama → stub
+    /// Auto-detect: try api → local servers → stub
     Auto,
     /// OpenAI-compatible API
     Api,
     /// Ollama local server
     Ollama,
+    /// First local server found (Ollama, LM Studio, llama.cpp, vLLM)
+    Local,
     /// Testing stub (no model)
     Stub,
 }
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum Autonomy {
-    /// Show diff, ask Y/n before each write/edit
-    Suggest,
-    /// Show diff, apply automatically
-    AutoEdit,
-    /// Apply silently
-    FullAuto,
-}
-
-impl std::fmt::Display for Autonomy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Autonomy::Suggest => write!(f, "suggest"),
-            Autonomy::AutoEdit => write!(f, "auto-edit"),
-            Autonomy::FullAuto => write!(f, "full-auto"),
-        }
-    }
-}
-
 #[derive(Parser)]
 #[command(
     name = "clifcode",
@@ -96,10 +78,30 @@ struct Cli {
     #[arg(long, default_value = "4096")]
     max_tokens: usize,
 
-    /// Non-interactive: run a single prompt and exit
+    /// Non-interactive: run a single prompt and exit. Piped stdin is
+    /// attached to it; output is plain text when stdout is not a terminal.
     #[arg(long, short = 'p')]
     prompt: Option<String>,
 
+    /// Attach `git diff REV` (default HEAD) to the prompt
+    #[arg(
+        long,
+        value_name = "REV",
+        num_args = 0..=1,
+        default_missing_value = "HEAD",
+        requires = "prompt"
+    )]
+    diff: Option<String>,
+
+    /// Attach staged changes (`git diff --staged`) to the prompt
+    #[arg(long, requires = "prompt")]
+    staged: bool,
+
+    /// Attach a file or line range (path, path:10 or path:10-40), or `-` for
+    /// stdin; repeatable
+    #[arg(long = "file", value_name = "PATH[:START-END]", requires = "prompt")]
+    files: Vec<String>,
+
     /// Autonomy level: suggest, auto-edit, full-auto
     #[arg(long, default_value = "auto-edit")]
     autonomy: String,
@@ -107,339 +109,249 @@ struct Cli {
     /// Resume a previous session by ID
     #[arg(long)]
     resume: Option<String>,
-}
-
-// ---------------------------------------------------------------------------
-// Conversation state
-// ---------------------------------------------------------------------------
 
-struct Conversation {
-    messages: Vec<serde_json::Value>,
+    /// Describe tools in the prompt instead of native function calling
+    /// (for local models without `tools` support)
+    #[arg(long, env = "CLIFCODE_TEXT_TOOLS")]
+    text_tools: bool,
+
+    /// Release channel for updates: stable, beta
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        env = "CLIFCODE_UPDATE_CHANNEL",
+        default_value = "stable"
+    )]
+    channel: update::Channel,
+
+    /// Never contact GitHub for update checks
+    #[arg(long, global = true, env = "CLIFCODE_OFFLINE")]
+    offline: bool,
+
+    /// Append a JSON line per traced span (turns, model requests, tool calls)
+    #[arg(long, value_name = "FILE", env = "CLIFCODE_TRACE_FILE")]
+    trace_file: Option<PathBuf>,
+
+    /// Record every model call to a cassette file
+    #[arg(long, value_name = "FILE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Answer model calls from a recorded cassette instead of a backend
+    #[arg(long, value_name = "FILE")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl Conversation {
-    fn new(workspace: &str, autonomy: &Autonomy, context_files: &[String]) -> Self {
-        let repo_map = repomap::scan_workspace(workspace);
-        let auto_ctx = repomap::auto_context(workspace);
-
-        let mut system_parts = vec![
-            format!(
-                "You are ClifCode, an AI assistant that helps with coding and file tasks.\n\
-                 Workspace: {workspace}\n\
-                 Mode: {autonomy}\n\
-                 Max turns: {}\n\n\
-                 CRITICAL BEHAVIOR RULES:\n\
-                 1. BE PROACTIVE. When the user asks a question, READ files to find the answer. NEVER say \"could you provide more details\" or \"let me know\" when you can look it up yourself.\n\
-                 2. When the user asks \"which is best/most likely/top\", READ the relevant data files and ANALYZE them. Give a direct answer with reasoning.\n\
-                 3. If a file was truncated, use read_file with offset to get the rest. Read the ENTIRE file before answering.\n\
-                 4. Use find_file to locate files by name when you don't know the path.\n\
-                 5. Use change_directory when the user wants to switch to a different folder.\n\
-                 6. Prefer edit_file for targeted changes, write_file for new files.\n\
-                 7. Call submit when a coding task is done.\n\
-                 8. Remember context from earlier in the conversation.\n\
-                 9. NEVER ask the user to clarify something you can figure out from the files.\n\
-                 10. READ COMPREHENSIVELY. When asked to analyze, summarize, or make recommendations about a directory or project, FIRST use list_files to see everything available, THEN read ALL relevant files — not just 1-2. Read every doc, every config, every data file that could inform your answer. Use multiple read_file calls in the same turn. Partial reading leads to bad answers.\n\
-                 11. When creating a file based on analysis, make sure you have read ALL source material first. If there are 10 relevant files, read all 10 before writing your summary.",
-                tools::MAX_TURNS
-            ),
-            format!("Repo map:\n{repo_map}"),
-        ];
-
-        // Auto-context: inject project identity files (README, Cargo.toml, etc.)
-        if !auto_ctx.is_empty() {
-            let names: Vec<&str> = auto_ctx.iter().map(|(n, _)| n.as_str()).collect();
-            ui::print_dim(&format!("  Auto-context: {}", names.join(", ")));
-            for (name, content) in &auto_ctx {
-                system_parts.push(format!("Project file {name}:\n```\n{content}\n```"));
-            }
-        }
-
-        // Manually added context files
-        for file_path in context_files {
-            let full = std::path::Path::new(workspace).join(file_path);
-            if let Ok(content) = std::fs::read_to_string(&full) {
-                let truncated: String = content.chars().take(4000).collect();
-                system_parts.push(format!("File {file_path}:\n```\n{truncated}\n```"));
-            }
-        }
-
-        // Load .clifrules project rules file if it exists
-        let rules_path = std::path::Path::new(workspace).join(".clifrules");
-        if let Ok(rules_content) = std::fs::read_to_string(&rules_path) {
-            system_parts.push(format!("Project Rules (.clifrules):\n{rules_content}"));
-        }
-
-        let system_content = system_parts.join("\n\n");
+#[derive(Subcommand)]
+enum Command {
+    /// Check for and install a signed update
+    Update {
+        /// Restore the binary replaced by the last update
+        #[arg(long)]
+        rollback: bool,
+    },
+    /// Run independent tasks from a JSONL file and write one result per task
+    Batch {
+        /// Task file: one {"prompt", "workspace", "worktree", "autonomy", "max_turns", "id"} per line
+        tasks: PathBuf,
+        /// Tasks to run at once
+        #[arg(long, short = 'j', default_value = "4")]
+        concurrency: usize,
+        /// Results file (default: <tasks>.results.jsonl)
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+}
 
-        Conversation {
-            messages: vec![serde_json::json!({"role": "system", "content": system_content})],
+impl Cli {
+    fn tool_protocol(&self) -> backend::ToolProtocol {
+        if self.text_tools {
+            backend::ToolProtocol::Text
+        } else {
+            backend::ToolProtocol::Native
         }
     }
 }
 
-// ---------------------------------------------------------------------------
-// Agent loop — operates on a persistent conversation
-// ---------------------------------------------------------------------------
-
-fn run_turn(
-    bk: &backend::ModelBackend,
-    conv: &mut Conversation,
-    input: &str,
-    workspace: &mut String,
-    autonomy: &Autonomy,
-) -> Result<backend::TokenUsage> {
-    // Add the user message to the ongoing conversation
-    conv.messages
-        .push(serde_json::json!({"role": "user", "content": input}));
-
-    let tool_defs = tools::tool_definitions();
-    let confirm_writes = *autonomy == Autonomy::Suggest;
-    let collapse_diffs = *autonomy == Autonomy::AutoEdit;
-    let mut files_changed = Vec::new();
-    let mut turn_usage = backend::TokenUsage::default();
-
-    for turn in 1..=tools::MAX_TURNS {
-        ui::print_turn_indicator(turn, tools::MAX_TURNS);
-        ui::print_thinking();
-
-        // Compact before sending to avoid context overflow
-        session::compact_messages(&mut conv.messages, 60_000);
-
-        // Use streaming for API backend — tokens print live
-        let response = match bk.chat_stream(&conv.messages, Some(&tool_defs)) {
-            Ok(r) => r,
-            Err(e) => {
-                let msg = format!("{e}");
-                if msg.contains("status code 400")
-                    || msg.contains("context_length")
-                    || msg.contains("too many tokens")
-                {
-                    ui::clear_thinking();
-                    ui::print_dim("  (context too large — compacting and retrying...)");
-                    session::compact_messages(&mut conv.messages, 20_000);
-                    ui::print_thinking();
-                    match bk.chat_stream(&conv.messages, Some(&tool_defs)) {
-                        Ok(r) => r,
-                        Err(e2) => {
-                            let msg2 = format!("{e2}");
-                            if msg2.contains("status code 400") || msg2.contains("context_length") {
-                                ui::clear_thinking();
-                                ui::print_dim("  (still too large — aggressive compaction...)");
-                                session::compact_messages(&mut conv.messages, 8_000);
-                                ui::print_thinking();
-                                bk.chat_stream(&conv.messages, Some(&tool_defs))?
-                            } else {
-                                return Err(e2);
-                            }
-                        }
-                    }
-                } else {
-                    return Err(e);
-                }
-            }
-        };
-        ui::clear_thinking();
+/// Renders agent events in the terminal and asks the user for approvals
+#[derive(Default)]
+struct TerminalEvents {
+    thinking: bool,
+    stream: ui::StreamPrinter,
+}
 
-        // Accumulate token usage
-        if let Some(ref u) = response.usage {
-            turn_usage.prompt_tokens += u.prompt_tokens;
-            turn_usage.completion_tokens += u.completion_tokens;
+impl TerminalEvents {
+    /// Clear the spinner and flush any partly streamed line
+    fn finish(&mut self) {
+        if std::mem::take(&mut self.thinking) {
+            ui::clear_thinking();
         }
+        self.stream.finish();
+    }
+}
 
-        // Only print via render_markdown if content wasn't already streamed
-        if !response.content.is_empty() && !response.streamed {
-            ui::print_assistant(&response.content);
+impl EventHandler for TerminalEvents {
+    fn on_event(&mut self, event: &AgentEvent) {
+        if std::mem::take(&mut self.thinking) {
+            ui::clear_thinking();
         }
-
-        // No tool calls — model just responded with text, conversation continues
-        if response.tool_calls.is_empty() {
-            conv.messages.push(response.raw_message);
-            return Ok(turn_usage);
+        if !matches!(event, AgentEvent::TextDelta(_)) {
+            self.stream.finish();
         }
-
-        conv.messages.push(response.raw_message.clone());
-
-        // Parse all tool calls, track order for message insertion
-        let parsed: Vec<(usize, &tools::ApiToolCall, Option<tools::ToolCall>)> = response
-            .tool_calls
-            .iter()
-            .enumerate()
-            .map(|(i, api_call)| (i, api_call, tools::ToolCall::from_api(api_call)))
-            .collect();
-
-        // Allocate result slots (index -> tool message JSON)
-        let mut result_slots: Vec<Option<serde_json::Value>> = vec![None; parsed.len()];
-
-        // --- Phase 1: Handle control-flow calls (submit, change_directory) immediately ---
-        for (idx, api_call, tool_call) in &parsed {
-            if let Some(tools::ToolCall::Submit { ref summary }) = tool_call {
-                if response.content.is_empty() {
-                    ui::print_assistant(summary);
-                }
-                conv.messages.push(serde_json::json!({
-                    "role": "tool",
-                    "tool_call_id": api_call.id,
-                    "content": format!("Task complete: {summary}")
-                }));
-                if !files_changed.is_empty() && git::is_git_repo(workspace) {
-                    if ui::confirm("Commit changes?") {
-                        let msg =
-                            format!("ClifCode: {}", summary.chars().take(72).collect::<String>());
-                        match git::git_commit_with_confirmation(workspace, &msg) {
-                            Ok(hash) => ui::print_dim(&format!("    [committed {hash}]")),
-                            Err(e) => ui::print_dim(&format!("    [commit skipped: {e}]")),
-                        }
-                    }
-                }
-                return Ok(turn_usage);
-            }
-
-            if let Some(tools::ToolCall::ChangeDir { ref path }) = tool_call {
-                let target = std::path::Path::new(path);
-                if target.is_dir() {
-                    let canonical = target
-                        .canonicalize()
-                        .unwrap_or_else(|_| target.to_path_buf());
-                    *workspace = canonical.to_string_lossy().to_string();
-                    ui::print_tool_action("cd", workspace);
-                    ui::print_success(&format!("  Workspace: {workspace}"));
-                    result_slots[*idx] = Some(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": api_call.id,
-                        "content": format!("Changed workspace to {}. The repo map for this directory:\n{}", workspace, repomap::scan_workspace(workspace))
-                    }));
-                } else {
-                    ui::print_error(&format!("  Not a directory: {path}"));
-                    result_slots[*idx] = Some(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": api_call.id,
-                        "content": format!("Error: {path} is not a directory")
-                    }));
-                }
+        match event {
+            AgentEvent::TurnStarted {
+                turn,
+                max_turns,
+                todo,
+            } => ui::print_turn_indicator(
+                *turn,
+                *max_turns,
+                todo.as_ref().map(|(d, t, c)| (*d, *t, c.as_str())),
+            ),
+            AgentEvent::Thinking => {
+                ui::print_thinking();
+                self.thinking = true;
             }
-        }
-
-        // --- Phase 2: Partition remaining into parallel (read-only) and sequential ---
-        let mut parallel_indices = Vec::new();
-        let mut sequential_indices = Vec::new();
-
-        for (idx, api_call, tool_call) in &parsed {
-            if result_slots[*idx].is_some() {
-                continue; // Already handled (change_directory)
+            AgentEvent::TextDelta(text) => self.stream.push(text),
+            AgentEvent::AssistantMessage {
+                content,
+                streamed: false,
+            } => ui::print_assistant(content),
+            AgentEvent::TodosUpdated(todos) => todo::print_checklist(todos),
+            AgentEvent::WorkspaceChanged(path) => {
+                ui::print_tool_action("cd", path);
+                ui::print_success(&format!("  Workspace: {path}"));
             }
-            match tool_call {
-                None => {
-                    result_slots[*idx] = Some(serde_json::json!({
-                        "role": "tool",
-                        "tool_call_id": api_call.id,
-                        "content": format!("Unknown tool: {}", api_call.name)
-                    }));
-                }
-                Some(tc) if tc.is_read_only() => {
-                    parallel_indices.push(*idx);
-                }
-                Some(_) => {
-                    sequential_indices.push(*idx);
-                }
+            AgentEvent::Committed { hash } => ui::print_dim(&format!("    [committed {hash}]")),
+            AgentEvent::CommitSkipped { reason } => {
+                ui::print_dim(&format!("    [commit skipped: {reason}]"))
             }
+            AgentEvent::TurnLimitReached => ui::print_dim("  (reached turn limit)"),
+            AgentEvent::Notice(text) => ui::print_dim(&format!("  {text}")),
+            AgentEvent::Error(text) => ui::print_error(&format!("  {text}")),
+            // Built-in tools print their own progress in the terminal
+            _ => {}
         }
+    }
 
-        // --- Phase 3: Run parallel batch on threads ---
-        if parallel_indices.len() > 1 {
-            let ws: &str = workspace;
-            let parallel_items: Vec<(usize, &tools::ToolCall, &str)> = parallel_indices
-                .iter()
-                .filter_map(|&idx| {
-                    parsed[idx]
-                        .2
-                        .as_ref()
-                        .map(|tc| (idx, tc, parsed[idx].1.id.as_str()))
-                })
-                .collect();
-
-            let results: Vec<(usize, String, String)> = std::thread::scope(|s| {
-                let handles: Vec<_> = parallel_items
-                    .iter()
-                    .map(|&(idx, tc, call_id)| {
-                        s.spawn(move || {
-                            let result = tools::execute_tool(tc, ws, false, false);
-                            let json = serde_json::to_string(&result).unwrap_or_default();
-                            (idx, call_id.to_string(), json)
-                        })
-                    })
-                    .collect();
-                handles.into_iter().map(|h| h.join().unwrap()).collect()
-            });
-
-            for (idx, call_id, result_json) in results {
-                result_slots[idx] = Some(serde_json::json!({
-                    "role": "tool",
-                    "tool_call_id": call_id,
-                    "content": result_json
-                }));
-            }
-        } else if parallel_indices.len() == 1 {
-            // Single read-only call — no need for threads
-            let idx = parallel_indices[0];
-            if let Some(ref tc) = parsed[idx].2 {
-                let result = tools::execute_tool(tc, workspace, confirm_writes, collapse_diffs);
-                let result_json = serde_json::to_string(&result)?;
-                result_slots[idx] = Some(serde_json::json!({
-                    "role": "tool",
-                    "tool_call_id": parsed[idx].1.id,
-                    "content": result_json
-                }));
+    fn approve(&mut self, request: &Approval<'_>) -> bool {
+        match request {
+            Approval::Commit { .. } => ui::confirm("Commit changes?"),
+            Approval::Tool { name, arguments } => {
+                ui::print_tool_action(name, arguments);
+                ui::confirm("Run this tool?")
             }
+            _ => false,
         }
+    }
+}
 
-        // --- Phase 4: Run sequential batch in order ---
-        for idx in sequential_indices {
-            if let Some(ref tc) = parsed[idx].2 {
-                // Track file changes
-                match tc {
-                    tools::ToolCall::WriteFile { path, .. }
-                    | tools::ToolCall::EditFile { path, .. } => {
-                        if !files_changed.contains(path) {
-                            files_changed.push(path.clone());
-                        }
-                    }
-                    _ => {}
-                }
+/// Collects the final answer for text-only output, when stdout is a pipe
+#[derive(Default)]
+struct TextEvents {
+    last_message: String,
+    submitted: Option<String>,
+}
 
-                let result = tools::execute_tool(tc, workspace, confirm_writes, collapse_diffs);
-                let result_json = serde_json::to_string(&result)?;
-                result_slots[idx] = Some(serde_json::json!({
-                    "role": "tool",
-                    "tool_call_id": parsed[idx].1.id,
-                    "content": result_json
-                }));
-            }
+impl EventHandler for TextEvents {
+    fn on_event(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::AssistantMessage { content, .. } => self.last_message = content.clone(),
+            AgentEvent::TaskComplete { summary } => self.submitted = Some(summary.clone()),
+            AgentEvent::Error(text) => eprintln!("clifcode: {text}"),
+            _ => {}
         }
+    }
+}
 
-        // --- Phase 5: Push results in original order ---
-        for slot in result_slots {
-            if let Some(msg) = slot {
-                conv.messages.push(msg);
-            }
-        }
+/// One agent turn with terminal output
+fn run_turn(
+    bk: &backend::ModelBackend,
+    conv: &mut Conversation,
+    input: &str,
+    workspace: &mut String,
+    autonomy: &Autonomy,
+    plan_mode: bool,
+) -> Result<backend::TokenUsage> {
+    let mut events = TerminalEvents::default();
+    let opts = TurnOptions {
+        plan_mode,
+        ..Default::default()
+    };
+    let result = agent::run_turn(bk, conv, input, workspace, autonomy, &opts, &mut events);
+    events.finish();
+    result
+}
 
-        // Context compaction — 60k token budget
-        session::compact_messages(&mut conv.messages, 60_000);
+/// `/plan` — explore read-only, let the user edit and approve the numbered plan,
+/// then execute it in the chosen autonomy with the plan as the todo list.
+fn run_plan(
+    bk: &backend::ModelBackend,
+    conv: &mut Conversation,
+    task: &str,
+    workspace: &mut String,
+    autonomy: &mut Autonomy,
+    session_id: &str,
+) -> Result<backend::TokenUsage> {
+    ui::print_dim("  Plan mode — read-only tools");
+    let prompt = format!("{}\n\nTask: {task}", plan::PLAN_PROMPT);
+    let mut usage = run_turn(bk, conv, &prompt, workspace, autonomy, true)?;
+
+    let plan_text = conv
+        .messages
+        .last()
+        .filter(|m| m.role() == "assistant")
+        .map(|m| m.text())
+        .unwrap_or_default();
+    let mut steps = plan::parse_steps(&plan_text);
+    if steps.is_empty() {
+        ui::print_dim("  (no plan produced)");
+        return Ok(usage);
     }
 
-    ui::print_dim("  (reached turn limit)");
-
-    if !files_changed.is_empty() && git::is_git_repo(workspace) {
-        if ui::confirm("Commit changes?") {
-            let msg = format!("ClifCode: modified {}", files_changed.join(", "));
-            match git::git_commit_with_confirmation(workspace, &msg) {
-                Ok(hash) => ui::print_dim(&format!("    [committed {hash}]")),
-                Err(e) => ui::print_dim(&format!("    [commit skipped: {e}]")),
+    let plan_file = std::env::temp_dir().join(format!("clifcode-plan-{session_id}.md"));
+    loop {
+        plan::print_plan(&steps);
+        match ui::select_menu("Plan:", &["Approve", "Edit in $EDITOR", "Cancel"]) {
+            Some(0) => break,
+            Some(1) => match plan::edit_in_editor(&steps, &plan_file) {
+                Ok(edited) if !edited.is_empty() => steps = edited,
+                Ok(_) => ui::print_dim("  (plan is empty — keeping the previous version)"),
+                Err(e) => ui::print_error(&format!("  {e}")),
+            },
+            _ => {
+                let _ = std::fs::remove_file(&plan_file);
+                ui::print_dim("  Plan discarded.");
+                return Ok(usage);
             }
         }
     }
-
-    Ok(turn_usage)
+    let _ = std::fs::remove_file(&plan_file);
+
+    let modes = &["suggest", "auto-edit", "full-auto"];
+    if let Some(choice) = ui::select_menu("Execute with autonomy:", modes) {
+        *autonomy = match choice {
+            0 => Autonomy::Suggest,
+            1 => Autonomy::AutoEdit,
+            _ => Autonomy::FullAuto,
+        };
+    }
+    ui::print_success(&format!("  Executing plan ({autonomy})"));
+
+    conv.todos = todo::from_steps(&steps);
+    todo::pin_to_system_prompt(&mut conv.messages, &conv.todos);
+    todo::print_checklist(&conv.todos);
+    let exec_prompt = format!(
+        "The plan is approved. Execute it now, step by step:\n{}",
+        plan::format_steps(&steps)
+    );
+    let exec_usage = run_turn(bk, conv, &exec_prompt, workspace, autonomy, false)?;
+    usage.prompt_tokens += exec_usage.prompt_tokens;
+    usage.completion_tokens += exec_usage.completion_tokens;
+    Ok(usage)
 }
 
 /// Simple ISO-ish timestamp without external deps
@@ -500,7 +412,23 @@ fn chrono_now() -> String {
 // Backend resolution
 // ---------------------------------------------------------------------------
 
-fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
+/// The backend from the CLI, wrapped for `--record` or replaced by `--replay`
+fn resolve_backend(cli: &Cli, workspace: &str) -> Result<backend::ModelBackend> {
+    if let Some(path) = &cli.replay {
+        let player = cassette::Player::open(path, workspace)?;
+        return Ok(backend::ModelBackend::Replay(Arc::new(player)));
+    }
+    let bk = resolve_model_backend(cli)?;
+    match &cli.record {
+        Some(path) => Ok(backend::ModelBackend::Record {
+            inner: Box::new(bk),
+            cassette: Arc::new(cassette::Recorder::create(path, workspace)?),
+        }),
+        None => Ok(bk),
+    }
+}
+
+fn resolve_model_backend(cli: &Cli) -> Result<backend::ModelBackend> {
     match cli.backend {
         Backend::Api => {
             let url = cli
@@ -517,6 +445,7 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
                 key,
                 model,
                 max_tokens: cli.max_tokens,
+                tool_protocol: cli.tool_protocol(),
             })
         }
         Backend::Ollama => {
@@ -533,8 +462,20 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
                 key: None,
                 model,
                 max_tokens: cli.max_tokens,
+                tool_protocol: cli.tool_protocol(),
             })
         }
+        Backend::Local => match local_backend(cli) {
+            Some(bk) => Ok(bk),
+            None => Err(anyhow::anyhow!(
+                "No local model server found (tried {})",
+                backend::LOCAL_SERVERS
+                    .iter()
+                    .map(|(name, url)| format!("{name} at {url}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        },
         Backend::Stub => Ok(backend::ModelBackend::Stub),
         Backend::Auto => {
             // 1. CLI/env API key
@@ -552,6 +493,7 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
                     key: cli.api_key.clone(),
                     model,
                     max_tokens: cli.max_tokens,
+                    tool_protocol: cli.tool_protocol(),
                 });
             }
             // 3. Saved config
@@ -571,20 +513,12 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
                     key: Some(key),
                     model,
                     max_tokens: cli.max_tokens,
+                    tool_protocol: cli.tool_protocol(),
                 });
             }
-            // 4. Ollama
-            if backend::detect_ollama() {
-                let model = cli
-                    .api_model
-                    .clone()
-                    .unwrap_or_else(|| "qwen2.5-coder:7b".into());
-                return Ok(backend::ModelBackend::Api {
-                    url: "http://localhost:11434/v1".into(),
-                    key: None,
-                    model,
-                    max_tokens: cli.max_tokens,
-                });
+            // 4. Local servers (Ollama, LM Studio, llama.cpp, vLLM)
+            if let Some(bk) = local_backend(cli) {
+                return Ok(bk);
             }
             // 5. Interactive setup
             if let Some((key, url, model)) = config::interactive_setup() {
@@ -593,6 +527,7 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
                     key: Some(key),
                     model,
                     max_tokens: cli.max_tokens,
+                    tool_protocol: cli.tool_protocol(),
                 });
             }
             // 6. Stub fallback
@@ -601,6 +536,25 @@ fn resolve_backend(cli: &Cli) -> Result<backend::ModelBackend> {
     }
 }
 
+/// Connect to the first local server that answers. Uses `--api-model` if given,
+/// otherwise the first model the server lists.
+fn local_backend(cli: &Cli) -> Option<backend::ModelBackend> {
+    let server = backend::detect_local_servers().into_iter().next()?;
+    let model = cli
+        .api_model
+        .clone()
+        .or_else(|| server.models.first().cloned())
+        .unwrap_or_else(|| "qwen2.5-coder:7b".into());
+    ui::print_dim(&format!("  Found {} at {}", server.name, server.url));
+    Some(backend::ModelBackend::Api {
+        url: server.url,
+        key: None,
+        model,
+        max_tokens: cli.max_tokens,
+        tool_protocol: cli.tool_protocol(),
+    })
+}
+
 // ---------------------------------------------------------------------------
 // Slash commands help
 // ---------------------------------------------------------------------------
@@ -680,8 +634,13 @@ fn print_help() {
         ui::RESET
     );
     let tools_cmds = [
+        (
+            "plan <task>",
+            "Read-only plan, edit and approve, then execute",
+        ),
         ("mode", "Switch autonomy level"),
         ("backend", "Show current backend"),
+        ("local", "Pick a model from local servers"),
         ("config", "Re-run provider setup"),
     ];
     for (cmd, desc) in &tools_cmds {
@@ -706,7 +665,10 @@ fn print_help() {
         ui::RESET
     );
     let update_cmds = [
-        ("update", "Check for and install updates"),
+        (
+            "update",
+            "Check for and install signed updates (--rollback to undo)",
+        ),
         ("version", "Show current version"),
     ];
     for (cmd, desc) in &update_cmds {
@@ -731,7 +693,7 @@ fn print_help() {
         ui::RESET
     );
     let git_cmds = [
-        ("status", "Git status"),
+        ("status", "Todo progress and git status"),
         ("undo", "Undo last ClifCode commit"),
     ];
     for (cmd, desc) in &git_cmds {
@@ -749,12 +711,11 @@ fn print_help() {
 
     println!();
     println!(
-        "  {}{}Tip:{} {}{}{} to expand diffs in auto-edit mode",
+        "  {}{}Tip:{} {}Ctrl+O{} to expand diffs in auto-edit mode",
         ui::BOLD,
         ui::WHITE,
         ui::RESET,
         ui::BOLD,
-        "Ctrl+O",
         ui::RESET
     );
     println!();
@@ -778,6 +739,13 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    // Spans go to the trace file and/or OTLP collector, if configured; the
+    // guard flushes queued spans on exit
+    let _trace_guard = telemetry::init(cli.trace_file.clone()).unwrap_or_else(|e| {
+        ui::print_error(&format!("  Tracing disabled: {e}"));
+        None
+    });
+
     let workspace = cli
         .workspace
         .clone()
@@ -791,16 +759,105 @@ fn main() -> Result<()> {
         _ => Autonomy::AutoEdit,
     };
 
+    if let Some(Command::Update { rollback }) = &cli.command {
+        let result = if *rollback {
+            update::rollback()
+        } else if cli.offline {
+            Err("Updates are disabled in offline mode".into())
+        } else {
+            match update::check_for_update(cli.channel) {
+                Some((version, url)) => update::perform_update(cli.channel, &url, &version),
+                None => {
+                    ui::print_success(&format!(
+                        "  Already on latest {} version ({})",
+                        cli.channel,
+                        update::current_version()
+                    ));
+                    Ok(())
+                }
+            }
+        };
+        return result.map_err(|e| anyhow::anyhow!(e));
+    }
+
+    if let Some(Command::Batch {
+        tasks,
+        concurrency,
+        output,
+    }) = &cli.command
+    {
+        let task_list = batch::load_tasks(tasks).map_err(|e| anyhow::anyhow!(e))?;
+        let bk = resolve_backend(&cli, &workspace_str)?;
+        let opts = batch::BatchOptions {
+            concurrency: *concurrency,
+            output: output
+                .clone()
+                .unwrap_or_else(|| tasks.with_extension("results.jsonl")),
+            workspace: workspace.clone(),
+            autonomy,
+        };
+        let results = batch::run_batch(&bk, &task_list, &opts).map_err(|e| anyhow::anyhow!(e))?;
+        let failed = results.iter().filter(|r| r.status == "error").count();
+        let usage = backend::TokenUsage {
+            prompt_tokens: results.iter().map(|r| r.prompt_tokens).sum(),
+            completion_tokens: results.iter().map(|r| r.completion_tokens).sum(),
+        };
+        ui::print_session_cost(usage.prompt_tokens, usage.completion_tokens);
+        if failed > 0 {
+            anyhow::bail!("{failed} of {} tasks failed", results.len());
+        }
+        return Ok(());
+    }
+
     // Non-interactive mode
-    if cli.prompt.is_some() {
-        let bk = resolve_backend(&cli)?;
+    if let Some(prompt) = &cli.prompt {
+        // Piped or redirected: no colors or progress, just the answer
+        let text_only = !io::stdout().is_terminal();
+        ui::set_headless(text_only);
+        // `--file -` reads stdin itself
+        let mut attachments: Vec<attach::Attachment> = if cli.files.iter().any(|f| f == "-") {
+            Vec::new()
+        } else {
+            attach::from_stdin().into_iter().collect()
+        };
+        if cli.diff.is_some() || cli.staged {
+            attachments.push(
+                attach::from_diff(&workspace_str, cli.diff.as_deref(), cli.staged)
+                    .map_err(|e| anyhow::anyhow!(e))?,
+            );
+        }
+        for spec in &cli.files {
+            attachments
+                .push(attach::from_file(&workspace_str, spec).map_err(|e| anyhow::anyhow!(e))?);
+        }
+        let prompt = attach::build_prompt(prompt, &attachments);
+
+        if text_only {
+            let bk = resolve_backend(&cli, &workspace_str)?;
+            let mut conv = Conversation::new(&workspace_str, &autonomy, &[]);
+            let mut events = TextEvents::default();
+            agent::run_turn(
+                &bk,
+                &mut conv,
+                &prompt,
+                &mut workspace_str,
+                &autonomy,
+                &TurnOptions::default(),
+                &mut events,
+            )?;
+            println!("{}", events.submitted.unwrap_or(events.last_message));
+            return Ok(());
+        }
+
+        let bk = resolve_backend(&cli, &workspace_str)?;
         let mut conv = Conversation::new(&workspace_str, &autonomy, &[]);
         let usage = run_turn(
             &bk,
             &mut conv,
-            cli.prompt.as_ref().unwrap(),
+            &prompt,
             &mut workspace_str,
             &autonomy,
+            false,
         )?;
         if usage.prompt_tokens > 0 || usage.completion_tokens > 0 {
             ui::print_usage(usage.prompt_tokens, usage.completion_tokens);
@@ -810,10 +867,14 @@ fn main() -> Result<()> {
 
     // Interactive mode
     ui::print_logo();
-    let mut bk = resolve_backend(&cli)?;
+    let mut bk = resolve_backend(&cli, &workspace_str)?;
 
     // Background update check (non-blocking, cached 24h)
-    let update_rx = update::check_in_background();
+    let update_rx = if cli.offline {
+        std::sync::mpsc::channel().1
+    } else {
+        update::check_in_background(cli.channel)
+    };
     let mut pending_update: Option<(String, String)> = None;
 
     let mut context_files: Vec<String> = Vec::new();
@@ -838,6 +899,7 @@ fn main() -> Result<()> {
                 };
                 conv = Conversation {
                     messages: s.messages,
+                    todos: s.todos,
                 };
                 ui::print_success(&format!("  Resumed session {resume_id}"));
             }
@@ -968,6 +1030,7 @@ fn main() -> Result<()> {
                         };
                         conv = Conversation {
                             messages: s.messages,
+                            todos: s.todos,
                         };
                         session_prompt_tokens = 0;
                         session_completion_tokens = 0;
@@ -1059,6 +1122,46 @@ fn main() -> Result<()> {
                 }
                 continue;
             }
+            "plan" => {
+                // Keep the task's original casing — `cmd` is lowercased
+                let task = input
+                    .trim_start_matches('/')
+                    .split_once(' ')
+                    .map(|(_, t)| t.trim())
+                    .unwrap_or("");
+                if task.is_empty() {
+                    ui::print_dim("  Usage: plan <task>");
+                    continue;
+                }
+                match run_plan(
+                    &bk,
+                    &mut conv,
+                    task,
+                    &mut workspace_str,
+                    &mut autonomy,
+                    &session_id,
+                ) {
+                    Ok(usage) => {
+                        if usage.prompt_tokens > 0 || usage.completion_tokens > 0 {
+                            ui::print_usage(usage.prompt_tokens, usage.completion_tokens);
+                            session_prompt_tokens += usage.prompt_tokens;
+                            session_completion_tokens += usage.completion_tokens;
+                        }
+                    }
+                    Err(e) => ui::print_error(&format!("  Error: {e}")),
+                }
+                let _ = session::save_session(&session::Session {
+                    id: session_id.clone(),
+                    workspace: workspace_str.clone(),
+                    messages: conv.messages.clone(),
+                    context_files: context_files.clone(),
+                    autonomy: autonomy.to_string(),
+                    created_at: chrono_now(),
+                    todos: conv.todos.clone(),
+                });
+                println!();
+                continue;
+            }
             "mode" => {
                 let modes = &["suggest", "auto-edit", "full-auto"];
                 if let Some(choice) = ui::select_menu("Autonomy level:", modes) {
@@ -1079,6 +1182,10 @@ fn main() -> Result<()> {
                 continue;
             }
             "status" | "st" => {
+                if !conv.todos.is_empty() {
+                    println!();
+                    todo::print_checklist(&conv.todos);
+                }
                 match git::git_status(&workspace_str) {
                     Ok(s) if s.is_empty() => ui::print_dim("  Clean working tree"),
                     Ok(s) => {
@@ -1095,18 +1202,71 @@ fn main() -> Result<()> {
             "backend" => {
                 println!();
                 match &bk {
-                    backend::ModelBackend::Api { url, model, .. } => {
+                    backend::ModelBackend::Api {
+                        url,
+                        model,
+                        tool_protocol,
+                        ..
+                    } => {
                         println!("  Backend: {}api{}", ui::CYAN, ui::RESET);
                         println!("  Model:   {}{}{}", ui::CYAN, model, ui::RESET);
                         println!("  URL:     {}{}{}", ui::DIM, url, ui::RESET);
+                        let protocol = backend::effective_protocol(url, model, *tool_protocol);
+                        println!("  Tools:   {}{}{}", ui::DIM, protocol, ui::RESET);
                     }
                     backend::ModelBackend::Stub => {
                         println!("  Backend: {}stub{} (testing)", ui::YELLOW, ui::RESET);
                     }
+                    backend::ModelBackend::Record { inner, cassette } => {
+                        println!("  Backend: {}{}{}", ui::CYAN, inner.name(), ui::RESET);
+                        println!(
+                            "  Record:  {}{}{}",
+                            ui::DIM,
+                            cassette.path().display(),
+                            ui::RESET
+                        );
+                    }
+                    backend::ModelBackend::Replay(cassette) => {
+                        println!(
+                            "  Backend: {}replay{} ({} entries left)",
+                            ui::YELLOW,
+                            ui::RESET,
+                            cassette.remaining()
+                        );
+                    }
                 }
                 println!();
                 continue;
             }
+            "local" => {
+                ui::print_dim("  Scanning local model servers...");
+                let servers = backend::detect_local_servers();
+                let choices: Vec<(String, String, String)> = servers
+                    .iter()
+                    .flat_map(|srv| {
+                        srv.models.iter().map(move |m| {
+                            (format!("{}  {}", srv.name, m), srv.url.clone(), m.clone())
+                        })
+                    })
+                    .collect();
+                if choices.is_empty() {
+                    ui::print_dim("  No local servers with models found.");
+                    continue;
+                }
+                let labels: Vec<&str> = choices.iter().map(|(l, _, _)| l.as_str()).collect();
+                if let Some(choice) = ui::select_menu("Local model:", &labels) {
+                    let (_, url, model) = &choices[choice];
+                    bk = backend::ModelBackend::Api {
+                        url: url.clone(),
+                        key: None,
+                        model: model.clone(),
+                        max_tokens: cli.max_tokens,
+                        tool_protocol: cli.tool_protocol(),
+                    };
+                    ui::print_success(&format!("  Switched to {model} via {url}"));
+                }
+                continue;
+            }
             "config" | "setup" => {
                 if let Some((key, url, model)) = config::interactive_setup() {
                     let key_opt = if key.is_empty() { None } else { Some(key) };
@@ -1115,6 +1275,7 @@ fn main() -> Result<()> {
                         key: key_opt,
                         model: model.clone(),
                         max_tokens: cli.max_tokens,
+                        tool_protocol: cli.tool_protocol(),
                     };
                     println!();
                     ui::print_success(&format!(
@@ -1133,12 +1294,23 @@ fn main() -> Result<()> {
                 continue;
             }
             "update" | "upgrade" => {
+                if parts.get(1).map(|a| a.trim()) == Some("--rollback") {
+                    if let Err(e) = update::rollback() {
+                        ui::print_error(&e);
+                    }
+                    continue;
+                }
+                if cli.offline {
+                    ui::print_dim("  Updates are disabled in offline mode.");
+                    continue;
+                }
                 if pending_update.is_none() {
                     ui::print_dim("  Checking for updates...");
-                    pending_update = update::check_for_update();
+                    pending_update = update::check_for_update(cli.channel);
                 }
                 match &pending_update {
-                    Some((version, url)) => match update::perform_update(url, version) {
+                    Some((version, url)) => match update::perform_update(cli.channel, url, version)
+                    {
                         Ok(()) => {}
                         Err(e) => ui::print_error(&e),
                     },
@@ -1177,7 +1349,7 @@ fn main() -> Result<()> {
         }
 
         // It's a message — send to the ongoing conversation
-        match run_turn(&bk, &mut conv, input, &mut workspace_str, &autonomy) {
+        match run_turn(&bk, &mut conv, input, &mut workspace_str, &autonomy, false) {
             Ok(usage) => {
                 if usage.prompt_tokens > 0 || usage.completion_tokens > 0 {
                     ui::print_usage(usage.prompt_tokens, usage.completion_tokens);
@@ -1198,6 +1370,7 @@ fn main() -> Result<()> {
             context_files: context_files.clone(),
             autonomy: autonomy.to_string(),
             created_at: chrono_now(),
+            todos: conv.todos.clone(),
         });
 
         println!();
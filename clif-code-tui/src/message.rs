@@ -0,0 +1,428 @@
+//! Conversation messages — what the agent sends to and gets back from a model.
+//!
+//! [`Message`] serializes to the OpenAI chat format, which is also the format
+//! sessions and cassettes are stored in, so files written before these types
+//! existed load unchanged. [`to_openai`] and [`to_anthropic`] build the
+//! `messages` part of a request body for each provider.
+
+use crate::tools::ApiToolCall;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+/// One message in a conversation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+pub enum Message {
+    System {
+        content: Content,
+    },
+    User {
+        content: Content,
+    },
+    Assistant {
+        /// Empty when the reply is only tool calls (sent as `null`)
+        #[serde(
+            default,
+            deserialize_with = "nullable",
+            serialize_with = "empty_as_null"
+        )]
+        content: Content,
+        #[serde(
+            default,
+            skip_serializing_if = "Vec::is_empty",
+            with = "wire_tool_calls"
+        )]
+        tool_calls: Vec<ApiToolCall>,
+    },
+    Tool {
+        tool_call_id: String,
+        #[serde(default, deserialize_with = "nullable")]
+        content: Content,
+    },
+}
+
+/// Message text, either a plain string or a list of parts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// One part of a multi-part message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+/// An image by URL or `data:` URI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageUrl {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Content::Text(text)
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Content::Text(text.to_string())
+    }
+}
+
+impl Content {
+    /// The text, with text parts joined by newlines and images left out
+    pub fn text(&self) -> String {
+        match self {
+            Content::Text(text) => text.clone(),
+            Content::Parts(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    ContentPart::Text { text } => Some(text.as_str()),
+                    ContentPart::ImageUrl { .. } => None,
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Content::Text(text) => text.is_empty(),
+            Content::Parts(parts) => parts.is_empty(),
+        }
+    }
+}
+
+impl Message {
+    pub fn system(content: impl Into<Content>) -> Self {
+        Message::System {
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<Content>) -> Self {
+        Message::User {
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<Content>, tool_calls: Vec<ApiToolCall>) -> Self {
+        Message::Assistant {
+            content: content.into(),
+            tool_calls,
+        }
+    }
+
+    pub fn tool(tool_call_id: impl Into<String>, content: impl Into<Content>) -> Self {
+        Message::Tool {
+            tool_call_id: tool_call_id.into(),
+            content: content.into(),
+        }
+    }
+
+    pub fn role(&self) -> &'static str {
+        match self {
+            Message::System { .. } => "system",
+            Message::User { .. } => "user",
+            Message::Assistant { .. } => "assistant",
+            Message::Tool { .. } => "tool",
+        }
+    }
+
+    pub fn content(&self) -> &Content {
+        match self {
+            Message::System { content }
+            | Message::User { content }
+            | Message::Assistant { content, .. }
+            | Message::Tool { content, .. } => content,
+        }
+    }
+
+    pub fn content_mut(&mut self) -> &mut Content {
+        match self {
+            Message::System { content }
+            | Message::User { content }
+            | Message::Assistant { content, .. }
+            | Message::Tool { content, .. } => content,
+        }
+    }
+
+    /// Shorthand for `content().text()`
+    pub fn text(&self) -> String {
+        self.content().text()
+    }
+
+    /// Tool calls of an assistant message; empty for other roles
+    pub fn tool_calls(&self) -> &[ApiToolCall] {
+        match self {
+            Message::Assistant { tool_calls, .. } => tool_calls,
+            _ => &[],
+        }
+    }
+}
+
+/// `content: null` reads as empty
+fn nullable<'de, D: Deserializer<'de>>(de: D) -> Result<Content, D::Error> {
+    Ok(Option::<Content>::deserialize(de)?.unwrap_or_default())
+}
+
+/// Empty assistant content goes out as `null`; some providers reject `""`
+/// next to tool calls
+fn empty_as_null<S: Serializer>(content: &Content, ser: S) -> Result<S::Ok, S::Error> {
+    if content.is_empty() {
+        ser.serialize_none()
+    } else {
+        content.serialize(ser)
+    }
+}
+
+/// `ApiToolCall` in the OpenAI wire shape:
+/// `{"id", "type": "function", "function": {"name", "arguments"}}`
+mod wire_tool_calls {
+    use super::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct WireCall {
+        #[serde(default)]
+        id: String,
+        #[serde(rename = "type", default = "function_type")]
+        kind: String,
+        function: WireFunction,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct WireFunction {
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    }
+
+    fn function_type() -> String {
+        "function".into()
+    }
+
+    pub fn serialize<S: Serializer>(calls: &[ApiToolCall], ser: S) -> Result<S::Ok, S::Error> {
+        calls
+            .iter()
+            .map(|c| WireCall {
+                id: c.id.clone(),
+                kind: function_type(),
+                function: WireFunction {
+                    name: c.name.clone(),
+                    arguments: c.arguments.clone(),
+                },
+            })
+            .collect::<Vec<_>>()
+            .serialize(ser)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<ApiToolCall>, D::Error> {
+        Ok(Option::<Vec<WireCall>>::deserialize(de)?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| ApiToolCall {
+                id: c.id,
+                name: c.function.name,
+                arguments: c.function.arguments,
+            })
+            .collect())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Provider serializers
+// ---------------------------------------------------------------------------
+
+/// `messages` for an OpenAI-compatible `/chat/completions` request
+pub fn to_openai(messages: &[Message]) -> Value {
+    serde_json::to_value(messages).unwrap_or_else(|_| json!([]))
+}
+
+/// `system` and `messages` for an Anthropic `/v1/messages` request.
+///
+/// System messages are joined into the top-level `system` string. Tool calls
+/// become `tool_use` blocks; tool results become `tool_result` blocks in a
+/// user message, merged with neighbouring results since Anthropic requires
+/// strict user/assistant alternation.
+pub fn to_anthropic(messages: &[Message]) -> (String, Vec<Value>) {
+    let mut system: Vec<String> = Vec::new();
+    let mut out: Vec<Value> = Vec::new();
+
+    for msg in messages {
+        match msg {
+            Message::System { content } => system.push(content.text()),
+            Message::User { content } => {
+                push_anthropic(&mut out, "user", anthropic_blocks(content));
+            }
+            Message::Assistant {
+                content,
+                tool_calls,
+            } => {
+                let mut blocks = anthropic_blocks(content);
+                for tc in tool_calls {
+                    let input: Value =
+                        serde_json::from_str(&tc.arguments).unwrap_or_else(|_| json!({}));
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": tc.id,
+                        "name": tc.name,
+                        "input": input,
+                    }));
+                }
+                push_anthropic(&mut out, "assistant", blocks);
+            }
+            Message::Tool {
+                tool_call_id,
+                content,
+            } => {
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": tool_call_id,
+                    "content": content.text(),
+                });
+                push_anthropic(&mut out, "user", vec![block]);
+            }
+        }
+    }
+    (system.join("\n\n"), out)
+}
+
+/// Append `blocks` as a `role` message, merging into the previous message when
+/// it has the same role
+fn push_anthropic(out: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(prev) = out.last_mut() {
+        if prev["role"] == role {
+            if let Some(existing) = prev["content"].as_array_mut() {
+                existing.extend(blocks);
+                return;
+            }
+        }
+    }
+    out.push(json!({"role": role, "content": blocks}));
+}
+
+/// Anthropic content blocks for `content`; empty text is dropped since the API
+/// rejects empty text blocks
+fn anthropic_blocks(content: &Content) -> Vec<Value> {
+    let parts = match content {
+        Content::Text(text) => vec![ContentPart::Text { text: text.clone() }],
+        Content::Parts(parts) => parts.clone(),
+    };
+    parts
+        .into_iter()
+        .filter_map(|part| match part {
+            ContentPart::Text { text } if text.is_empty() => None,
+            ContentPart::Text { text } => Some(json!({"type": "text", "text": text})),
+            ContentPart::ImageUrl { image_url } => Some(anthropic_image(&image_url.url)),
+        })
+        .collect()
+}
+
+/// `data:image/png;base64,…` becomes a base64 source, anything else a URL source
+fn anthropic_image(url: &str) -> Value {
+    let inline = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"));
+    match inline {
+        Some((media_type, data)) => json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": media_type, "data": data},
+        }),
+        None => json!({
+            "type": "image",
+            "source": {"type": "url", "url": url},
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(id: &str, name: &str, arguments: &str) -> ApiToolCall {
+        ApiToolCall {
+            id: id.into(),
+            name: name.into(),
+            arguments: arguments.into(),
+        }
+    }
+
+    #[test]
+    fn loads_untyped_session_messages() {
+        // As written by earlier versions: raw API messages, null content,
+        // extra provider fields
+        let old = json!([
+            {"role": "system", "content": "You are ClifCode."},
+            {"role": "user", "content": "list files"},
+            {"role": "assistant", "content": null, "refusal": null, "tool_calls": [{
+                "id": "call_1", "type": "function",
+                "function": {"name": "run_command", "arguments": "{\"command\":\"ls\"}"}
+            }]},
+            {"role": "tool", "tool_call_id": "call_1", "content": "Cargo.toml\nsrc"},
+            {"role": "assistant", "content": [{"type": "text", "text": "Two entries."}]}
+        ]);
+        let messages: Vec<Message> = serde_json::from_value(old.clone()).unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(
+            messages[2].tool_calls(),
+            [call("call_1", "run_command", r#"{"command":"ls"}"#)]
+        );
+        assert_eq!(messages[3].text(), "Cargo.toml\nsrc");
+        assert_eq!(messages[4].text(), "Two entries.");
+
+        // Round-trips to the same wire format, minus unknown fields
+        let wire = to_openai(&messages);
+        assert_eq!(wire[2]["content"], Value::Null);
+        assert_eq!(wire[2]["tool_calls"], old[2]["tool_calls"]);
+        assert_eq!(wire[3], old[3]);
+        assert!(wire[2].get("refusal").is_none());
+    }
+
+    #[test]
+    fn anthropic_format_pairs_tool_use_with_results() {
+        let messages = vec![
+            Message::system("Be brief."),
+            Message::user("read both"),
+            Message::assistant(
+                "",
+                vec![
+                    call("t1", "read_file", r#"{"path":"a.rs"}"#),
+                    call("t2", "read_file", r#"{"path":"b.rs"}"#),
+                ],
+            ),
+            Message::tool("t1", "fn a() {}"),
+            Message::tool("t2", "fn b() {}"),
+            Message::user("now compare"),
+        ];
+        let (system, wire) = to_anthropic(&messages);
+        assert_eq!(system, "Be brief.");
+        assert_eq!(wire.len(), 3);
+        assert_eq!(wire[1]["role"], "assistant");
+        assert_eq!(wire[1]["content"].as_array().unwrap().len(), 2);
+        assert_eq!(wire[1]["content"][0]["input"], json!({"path": "a.rs"}));
+        // Both results and the follow-up share one user turn
+        assert_eq!(wire[2]["role"], "user");
+        let blocks = wire[2]["content"].as_array().unwrap();
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0]["type"], "tool_result");
+        assert_eq!(blocks[1]["tool_use_id"], "t2");
+        assert_eq!(blocks[2], json!({"type": "text", "text": "now compare"}));
+    }
+}
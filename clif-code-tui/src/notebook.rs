@@ -0,0 +1,512 @@
+//! Jupyter notebook support — readable rendering for `read_file` and
+//! cell-level edits for `notebook_edit`.
+//!
+//! Notebooks are nbformat 4 JSON. Rendering shows each cell with its index,
+//! source and truncated text outputs; images and other binary outputs are
+//! dropped. Edits keep the document valid nbformat and are written the way
+//! Jupyter writes them (sorted keys, one-space indent).
+
+use crate::tools::ToolResult;
+use crate::ui;
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// Text output kept per cell
+const MAX_OUTPUT_CHARS: usize = 1500;
+
+pub fn is_notebook(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "ipynb")
+}
+
+/// nbformat stores multi-line strings as either a string or a list of lines
+fn joined(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts.iter().filter_map(|p| p.as_str()).collect(),
+        _ => String::new(),
+    }
+}
+
+/// Split source into nbformat's list-of-lines form (newlines kept)
+fn to_lines(source: &str) -> Value {
+    Value::Array(
+        source
+            .split_inclusive('\n')
+            .map(|l| Value::String(l.to_string()))
+            .collect(),
+    )
+}
+
+/// Drop ANSI colour codes from tracebacks
+fn strip_ansi(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' && chars.peek() == Some(&'[') {
+            chars.next();
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn render_outputs(outputs: &[Value]) -> String {
+    let mut parts = Vec::new();
+    for output in outputs {
+        match output["output_type"].as_str().unwrap_or("") {
+            "stream" => parts.push(joined(&output["text"])),
+            "execute_result" | "display_data" => {
+                let data = &output["data"];
+                if let Some(text) = data.get("text/plain") {
+                    parts.push(joined(text));
+                }
+                if let Some(obj) = data.as_object() {
+                    for mime in obj.keys().filter(|m| *m != "text/plain") {
+                        parts.push(format!("[{mime} output omitted]"));
+                    }
+                }
+            }
+            "error" => {
+                let traceback = output["traceback"]
+                    .as_array()
+                    .map(|lines| {
+                        lines
+                            .iter()
+                            .filter_map(|l| l.as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n")
+                    })
+                    .unwrap_or_default();
+                // IPython tracebacks already end with "ename: evalue"
+                parts.push(if traceback.is_empty() {
+                    format!(
+                        "{}: {}",
+                        output["ename"].as_str().unwrap_or("Error"),
+                        output["evalue"].as_str().unwrap_or("")
+                    )
+                } else {
+                    strip_ansi(&traceback)
+                });
+            }
+            _ => {}
+        }
+    }
+    let text = parts
+        .iter()
+        .map(|p| p.trim_end_matches('\n'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text = text.trim_end();
+    if text.chars().count() > MAX_OUTPUT_CHARS {
+        let cut: String = text.chars().take(MAX_OUTPUT_CHARS).collect();
+        format!("{cut}\n[output truncated]")
+    } else {
+        text.to_string()
+    }
+}
+
+/// Render a notebook as text: a header, then each cell with its index
+pub fn render(content: &str) -> Result<String, String> {
+    let nb: Value =
+        serde_json::from_str(content).map_err(|e| format!("Invalid notebook JSON: {e}"))?;
+    let cells = nb["cells"]
+        .as_array()
+        .ok_or("Invalid notebook: no cells array")?;
+    let kernel = nb["metadata"]["kernelspec"]["name"]
+        .as_str()
+        .or_else(|| nb["metadata"]["language_info"]["name"].as_str())
+        .unwrap_or("unknown");
+
+    let mut out = vec![format!(
+        "Notebook ({} cells, kernel: {kernel}). Edit cells with notebook_edit by index.",
+        cells.len()
+    )];
+    for (i, cell) in cells.iter().enumerate() {
+        let cell_type = cell["cell_type"].as_str().unwrap_or("code");
+        let exec = match cell["execution_count"].as_u64() {
+            Some(n) => format!(" [{n}]"),
+            None => String::new(),
+        };
+        out.push(format!("\n### cell {i} ({cell_type}){exec}"));
+        out.push(joined(&cell["source"]).trim_end().to_string());
+        if let Some(outputs) = cell["outputs"].as_array().filter(|o| !o.is_empty()) {
+            let rendered = render_outputs(outputs);
+            if !rendered.is_empty() {
+                out.push(format!("--- output ---\n{rendered}"));
+            }
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+/// Serialize like Jupyter does: sorted keys, one-space indent, trailing newline
+fn to_nbformat_json(nb: &Value) -> Result<String, String> {
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b" ");
+    let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    serde::Serialize::serialize(nb, &mut ser).map_err(|e| e.to_string())?;
+    let mut text = String::from_utf8(buf).map_err(|e| e.to_string())?;
+    text.push('\n');
+    Ok(text)
+}
+
+fn empty_notebook() -> Value {
+    json!({
+        "cells": [],
+        "metadata": {},
+        "nbformat": 4,
+        "nbformat_minor": 5
+    })
+}
+
+/// Cell ids are required from nbformat 4.5
+fn new_cell_id() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!(
+        "{:08x}",
+        (nanos ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15)) as u32
+    )
+}
+
+fn new_cell(cell_type: &str, source: &str, with_id: bool) -> Value {
+    let mut cell = json!({
+        "cell_type": cell_type,
+        "metadata": {},
+        "source": to_lines(source)
+    });
+    if cell_type == "code" {
+        cell["execution_count"] = Value::Null;
+        cell["outputs"] = json!([]);
+    }
+    if with_id {
+        cell["id"] = Value::String(new_cell_id());
+    }
+    cell
+}
+
+/// Apply one edit to the notebook; returns a summary of what changed
+fn apply_edit(
+    nb: &mut Value,
+    action: &str,
+    index: usize,
+    source: Option<&str>,
+    cell_type: Option<&str>,
+) -> Result<String, String> {
+    if let Some(t) = cell_type {
+        if !["code", "markdown", "raw"].contains(&t) {
+            return Err(format!("cell_type must be code, markdown or raw (got {t})"));
+        }
+    }
+    let with_id =
+        nb["nbformat"].as_u64().unwrap_or(4) > 4 || nb["nbformat_minor"].as_u64().unwrap_or(0) >= 5;
+    let cells = nb["cells"]
+        .as_array_mut()
+        .ok_or("Invalid notebook: no cells array")?;
+    let len = cells.len();
+
+    match action {
+        "insert" => {
+            if index > len {
+                return Err(format!("cell_index {index} out of range (0..={len})"));
+            }
+            let source = source.ok_or("insert needs source")?;
+            cells.insert(
+                index,
+                new_cell(cell_type.unwrap_or("code"), source, with_id),
+            );
+            Ok(format!("Inserted cell {index}"))
+        }
+        "replace" => {
+            let cell = cells
+                .get_mut(index)
+                .ok_or_else(|| format!("cell_index {index} out of range (0..{len})"))?;
+            let source = source.ok_or("replace needs source")?;
+            let old_type = cell["cell_type"].as_str().unwrap_or("code").to_string();
+            let new_type = cell_type.unwrap_or(&old_type);
+            if new_type != old_type {
+                // Changing type: rebuild so code-only fields come and go correctly
+                let mut fresh = new_cell(new_type, source, false);
+                if let Some(id) = cell.get("id") {
+                    fresh["id"] = id.clone();
+                }
+                fresh["metadata"] = cell["metadata"].clone();
+                *cell = fresh;
+            } else {
+                cell["source"] = to_lines(source);
+                if new_type == "code" {
+                    // Outputs belong to the old source
+                    cell["outputs"] = json!([]);
+                    cell["execution_count"] = Value::Null;
+                }
+            }
+            Ok(format!("Replaced cell {index}"))
+        }
+        "delete" => {
+            if index >= len {
+                return Err(format!("cell_index {index} out of range (0..{len})"));
+            }
+            cells.remove(index);
+            Ok(format!("Deleted cell {index}"))
+        }
+        other => Err(format!(
+            "Unknown action {other} — use replace, insert or delete"
+        )),
+    }
+}
+
+/// `notebook_edit` arguments
+#[derive(Debug, Clone)]
+pub struct CellEdit {
+    /// replace, insert or delete
+    pub action: String,
+    pub cell_index: usize,
+    pub source: Option<String>,
+    /// code, markdown or raw
+    pub cell_type: Option<String>,
+}
+
+/// `notebook_edit` — replace, insert or delete a cell by index
+pub fn exec_notebook_edit(
+    workspace: &str,
+    path: &str,
+    edit: &CellEdit,
+    confirm: bool,
+    collapse_diffs: bool,
+) -> ToolResult {
+    let CellEdit {
+        action,
+        cell_index,
+        source,
+        cell_type,
+    } = edit;
+    let (action, cell_index) = (action.as_str(), *cell_index);
+    let full = Path::new(workspace).join(path);
+    ui::print_tool_action("notebook", &format!("{action} cell {cell_index} in {path}"));
+
+    let fail = |msg: String| {
+        ui::print_error(&format!("    {msg}"));
+        ToolResult {
+            success: false,
+            output: format!("Error: {msg}"),
+        }
+    };
+    if !is_notebook(&full) {
+        return fail(format!("{path} is not a .ipynb notebook"));
+    }
+
+    let mut nb = if full.exists() {
+        let content = match std::fs::read_to_string(&full) {
+            Ok(c) => c,
+            Err(e) => return fail(format!("Cannot read {path}: {e}")),
+        };
+        match serde_json::from_str::<Value>(&content) {
+            Ok(nb) => nb,
+            Err(e) => return fail(format!("Invalid notebook JSON: {e}")),
+        }
+    } else if action == "insert" {
+        empty_notebook()
+    } else {
+        return fail(format!("{path} does not exist"));
+    };
+
+    let before = render(&nb.to_string()).unwrap_or_default();
+    let summary = match apply_edit(
+        &mut nb,
+        action,
+        cell_index,
+        source.as_deref(),
+        cell_type.as_deref(),
+    ) {
+        Ok(s) => s,
+        Err(e) => return fail(e),
+    };
+    let after = render(&nb.to_string()).unwrap_or_default();
+
+    if collapse_diffs {
+        ui::print_diff_collapsible(path, &before, &after);
+    } else {
+        ui::print_diff(path, &before, &after);
+    }
+    if confirm && !ui::confirm("Apply this change?") {
+        return ToolResult {
+            success: false,
+            output: "User declined the change".into(),
+        };
+    }
+
+    let text = match to_nbformat_json(&nb) {
+        Ok(t) => t,
+        Err(e) => return fail(e),
+    };
+    if let Some(parent) = full.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    match std::fs::write(&full, text) {
+        Ok(()) => {
+            let count = nb["cells"].as_array().map_or(0, |c| c.len());
+            ui::print_success(&format!("  {summary} in {path} ({count} cells)"));
+            ToolResult {
+                success: true,
+                output: format!("{summary} in {path} (now {count} cells)"),
+            }
+        }
+        Err(e) => fail(format!("Cannot write {}: {e}", full.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notebook(minor: u64) -> Value {
+        json!({
+            "cells": [
+                {"cell_type": "markdown", "metadata": {}, "source": ["# Title\n", "intro"]},
+                {"cell_type": "code", "metadata": {"tags": ["x"]}, "execution_count": 3,
+                 "source": "print(1)", "outputs": [{"output_type": "stream", "text": "1\n"}]}
+            ],
+            "metadata": {"kernelspec": {"name": "python3"}},
+            "nbformat": 4,
+            "nbformat_minor": minor
+        })
+    }
+
+    fn sources(nb: &Value) -> Vec<String> {
+        nb["cells"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| joined(&c["source"]))
+            .collect()
+    }
+
+    #[test]
+    fn edits_cells_by_index() {
+        let mut nb = notebook(4);
+
+        // Inserting at len appends; past it is out of range
+        apply_edit(&mut nb, "insert", 2, Some("x = 2\ny = 3"), None).unwrap();
+        assert!(apply_edit(&mut nb, "insert", 4, Some("z"), None).is_err());
+        apply_edit(&mut nb, "insert", 0, Some("Intro"), Some("raw")).unwrap();
+        assert_eq!(
+            sources(&nb),
+            ["Intro", "# Title\nintro", "print(1)", "x = 2\ny = 3"]
+        );
+        assert_eq!(nb["cells"][3]["source"], json!(["x = 2\n", "y = 3"]));
+        assert_eq!(nb["cells"][3]["outputs"], json!([]));
+        // nbformat 4.4 cells carry no ids
+        assert!(nb["cells"][3].get("id").is_none());
+
+        // Replacing code source clears its stale outputs
+        apply_edit(&mut nb, "replace", 2, Some("print(2)"), None).unwrap();
+        assert_eq!(nb["cells"][2]["outputs"], json!([]));
+        assert_eq!(nb["cells"][2]["execution_count"], Value::Null);
+        assert_eq!(nb["cells"][2]["metadata"], json!({"tags": ["x"]}));
+
+        // Changing type drops code-only fields and keeps metadata
+        apply_edit(&mut nb, "replace", 2, Some("Now prose"), Some("markdown")).unwrap();
+        let cell = &nb["cells"][2];
+        assert_eq!(cell["cell_type"], "markdown");
+        assert!(cell.get("outputs").is_none() && cell.get("execution_count").is_none());
+        assert_eq!(cell["metadata"], json!({"tags": ["x"]}));
+
+        // Delete uses the current indices; the last index is len - 1
+        assert!(apply_edit(&mut nb, "delete", 4, None, None).is_err());
+        apply_edit(&mut nb, "delete", 3, None, None).unwrap();
+        apply_edit(&mut nb, "delete", 0, None, None).unwrap();
+        assert_eq!(sources(&nb), ["# Title\nintro", "Now prose"]);
+
+        assert!(apply_edit(&mut nb, "replace", 2, Some("x"), None).is_err());
+        assert!(apply_edit(&mut nb, "replace", 0, None, None).is_err());
+        assert!(apply_edit(&mut nb, "insert", 0, Some("x"), Some("python")).is_err());
+        assert!(apply_edit(&mut nb, "move", 0, None, None).is_err());
+    }
+
+    #[test]
+    fn new_cells_get_ids_from_nbformat_4_5() {
+        let mut nb = notebook(5);
+        apply_edit(&mut nb, "insert", 1, Some("a"), None).unwrap();
+        apply_edit(&mut nb, "insert", 1, Some("b"), None).unwrap();
+        let (a, b) = (&nb["cells"][2]["id"], &nb["cells"][1]["id"]);
+        assert!(a.as_str().is_some_and(|id| id.len() == 8));
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn renders_cells_and_text_outputs() {
+        let mut nb = notebook(4);
+        nb["cells"][1]["outputs"] = json!([
+            {"output_type": "execute_result", "execution_count": 3,
+             "data": {"text/plain": ["1"], "image/png": "iVBOR..."}},
+            {"output_type": "error", "ename": "ValueError", "evalue": "bad",
+             "traceback": ["\u{1b}[0;31mValueError\u{1b}[0m: bad"]},
+            {"output_type": "stream", "text": "x".repeat(MAX_OUTPUT_CHARS + 10)}
+        ]);
+        let text = render(&nb.to_string()).unwrap();
+        assert!(text.starts_with("Notebook (2 cells, kernel: python3)"));
+        assert!(text.contains("### cell 0 (markdown)\n# Title\nintro"));
+        assert!(text.contains("### cell 1 (code) [3]\nprint(1)\n--- output ---\n1\n[image/png output omitted]\nValueError: bad\n"));
+        assert!(text.ends_with("[output truncated]"));
+        assert!(render("{\"cells\": 1}").is_err());
+    }
+
+    #[test]
+    fn edit_writes_jupyter_style_json() {
+        ui::set_headless(true);
+        let dir = std::env::temp_dir().join(format!("clifcode-nb-{}", std::process::id()));
+        let edit = |action: &str, index: usize, source: Option<&str>| CellEdit {
+            action: action.into(),
+            cell_index: index,
+            source: source.map(String::from),
+            cell_type: None,
+        };
+        let ws = dir.to_str().unwrap();
+
+        // Inserting into a missing notebook creates it
+        let result = exec_notebook_edit(
+            ws,
+            "nb/new.ipynb",
+            &edit("insert", 0, Some("1 + 1")),
+            false,
+            false,
+        );
+        assert!(result.success, "{}", result.output);
+        let written = std::fs::read_to_string(dir.join("nb/new.ipynb")).unwrap();
+        assert!(written.starts_with("{\n \"cells\": [\n  {\n   \"cell_type\": \"code\""));
+        assert!(written.ends_with("}\n"));
+
+        assert!(
+            !exec_notebook_edit(
+                ws,
+                "nb/missing.ipynb",
+                &edit("delete", 0, None),
+                false,
+                false
+            )
+            .success
+        );
+        assert!(
+            !exec_notebook_edit(
+                ws,
+                "nb/new.json",
+                &edit("insert", 0, Some("x")),
+                false,
+                false
+            )
+            .success
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,102 @@
+//! Plan mode — read-only exploration that ends in a numbered plan the user
+//! can edit, approve, and then execute with the plan as the session todo list.
+
+use crate::ui;
+use std::path::Path;
+
+/// Instruction prepended to the task when running in plan mode
+pub const PLAN_PROMPT: &str = "PLAN MODE — you can only read files, list, find and search. \
+     Do NOT attempt edits or commands. Explore the workspace as much as you need, then reply \
+     with a short numbered plan (1., 2., 3., ...) of concrete steps to accomplish the task. \
+     Each step should name the files it touches. Reply with the plan only.";
+
+/// Pull numbered steps (`1.`, `2)`, ...) out of the model's plan text.
+/// Falls back to every non-empty line if the model didn't number anything.
+pub fn parse_steps(text: &str) -> Vec<String> {
+    let numbered: Vec<String> = text
+        .lines()
+        .filter_map(|line| {
+            let trimmed = line.trim_start();
+            let digits = trimmed.chars().take_while(|c| c.is_ascii_digit()).count();
+            if digits == 0 {
+                return None;
+            }
+            let rest = trimmed[digits..].strip_prefix(['.', ')'])?;
+            let step = rest.trim();
+            (!step.is_empty()).then(|| step.to_string())
+        })
+        .collect();
+    if !numbered.is_empty() {
+        return numbered;
+    }
+    text.lines()
+        .map(|l| l.trim().trim_start_matches(['-', '*']).trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// Render steps as the numbered list written to the edit file
+pub fn format_steps(steps: &[String]) -> String {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!("{}. {s}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Open the plan in `$VISUAL` / `$EDITOR` and return the edited steps.
+pub fn edit_in_editor(steps: &[String], file: &Path) -> Result<Vec<String>, String> {
+    let header =
+        "# Edit the plan. One numbered step per line; lines starting with # are ignored.\n";
+    std::fs::write(file, format!("{header}{}\n", format_steps(steps)))
+        .map_err(|e| format!("Cannot write {}: {e}", file.display()))?;
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            if cfg!(windows) {
+                "notepad".into()
+            } else {
+                "vi".into()
+            }
+        });
+    // $EDITOR may carry flags, e.g. "code -w"
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().ok_or("EDITOR is empty")?;
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(file)
+        .status()
+        .map_err(|e| format!("Cannot launch {editor}: {e}"))?;
+    if !status.success() {
+        return Err(format!("{editor} exited with {status}"));
+    }
+
+    let edited = std::fs::read_to_string(file)
+        .map_err(|e| format!("Cannot read {}: {e}", file.display()))?;
+    let without_comments: String = edited
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(parse_steps(&without_comments))
+}
+
+/// Print the plan as a numbered list
+pub fn print_plan(steps: &[String]) {
+    println!();
+    println!("  {}{}Plan{}", ui::BOLD, ui::WHITE, ui::RESET);
+    for (i, step) in steps.iter().enumerate() {
+        println!(
+            "  {}{}{:>2}.{} {}",
+            ui::BOLD,
+            ui::BRIGHT_CYAN,
+            i + 1,
+            ui::RESET,
+            step
+        );
+    }
+    println!();
+}
@@ -59,7 +59,7 @@ pub fn scan_workspace(workspace: &str) -> String {
     let root = Path::new(workspace);
     lines.push(format!("Workspace: {workspace}"));
     lines.push(String::new());
-    walk_dir(root, root, 0, &mut lines);
+    walk_dir(root, 0, &mut lines);
 
     // Truncate to ~4000 chars for LLM context window
     let mut result = String::new();
@@ -74,7 +74,7 @@ pub fn scan_workspace(workspace: &str) -> String {
     result
 }
 
-fn walk_dir(base: &Path, dir: &Path, depth: usize, lines: &mut Vec<String>) {
+fn walk_dir(dir: &Path, depth: usize, lines: &mut Vec<String>) {
     if depth > 4 {
         return;
     }
@@ -108,7 +108,7 @@ fn walk_dir(base: &Path, dir: &Path, depth: usize, lines: &mut Vec<String>) {
     // Directories first, then files
     for (name, path) in &dirs {
         lines.push(format!("{indent}{name}/"));
-        walk_dir(base, path, depth + 1, lines);
+        walk_dir(path, depth + 1, lines);
     }
     for name in &files {
         lines.push(format!("{indent}{name}"));
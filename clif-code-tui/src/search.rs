@@ -0,0 +1,472 @@
+//! Workspace search — the `search` and `find_file` tools.
+//!
+//! Built on the same crates as ripgrep: `ignore` walks the tree honouring
+//! `.gitignore`/`.ignore` and hidden files, `grep-searcher` does the line
+//! matching with context. Results are grouped by file and paged by match.
+
+use crate::redact::DeniedPaths;
+use crate::tools::ToolResult;
+use crate::ui;
+use grep_regex::RegexMatcherBuilder;
+use grep_searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use std::path::Path;
+
+pub const DEFAULT_MAX_RESULTS: usize = 50;
+const MAX_RESULTS_CAP: usize = 500;
+const MAX_CONTEXT_LINES: usize = 10;
+/// Long lines (minified JS, lockfiles) are cut to keep the output readable
+const MAX_LINE_CHARS: usize = 240;
+
+/// Optional `search` arguments
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// Treat the query as a regex instead of a literal string
+    pub regex: bool,
+    /// Glob filter, e.g. `*.rs`, `src/**/*.ts`, or `!*.md` to exclude
+    pub glob: Option<String>,
+    pub context_lines: usize,
+    /// `None` — smart case: insensitive unless the query has uppercase
+    pub case_sensitive: Option<bool>,
+    pub max_results: usize,
+    /// Number of matches to skip, for paging through large result sets
+    pub offset: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            regex: false,
+            glob: None,
+            context_lines: 0,
+            case_sensitive: None,
+            max_results: DEFAULT_MAX_RESULTS,
+            offset: 0,
+        }
+    }
+}
+
+/// One match plus the context lines around it: (line number, text, is_match)
+struct Hit {
+    lines: Vec<(u64, String, bool)>,
+}
+
+/// Collects hits for a single file
+struct FileSink<'a> {
+    hits: Vec<Hit>,
+    pending_before: Vec<(u64, String, bool)>,
+    /// Matches seen across all files so far
+    seen: &'a mut usize,
+    /// Stop once this many matches have been seen
+    limit: usize,
+}
+
+fn line_text(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let text = text.trim_end_matches(['\n', '\r']);
+    if text.chars().count() > MAX_LINE_CHARS {
+        let cut: String = text.chars().take(MAX_LINE_CHARS).collect();
+        format!("{cut}…")
+    } else {
+        text.to_string()
+    }
+}
+
+impl Sink for FileSink<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        if *self.seen >= self.limit {
+            return Ok(false);
+        }
+        *self.seen += 1;
+        let mut lines = std::mem::take(&mut self.pending_before);
+        let first = mat.line_number().unwrap_or(0);
+        for (i, line) in mat.lines().enumerate() {
+            lines.push((first + i as u64, line_text(line), true));
+        }
+        self.hits.push(Hit { lines });
+        Ok(*self.seen < self.limit)
+    }
+
+    fn context(&mut self, _: &Searcher, ctx: &SinkContext<'_>) -> Result<bool, Self::Error> {
+        let line = (
+            ctx.line_number().unwrap_or(0),
+            line_text(ctx.bytes()),
+            false,
+        );
+        match ctx.kind() {
+            grep_searcher::SinkContextKind::After => {
+                if let Some(last) = self.hits.last_mut() {
+                    last.lines.push(line);
+                }
+            }
+            _ => self.pending_before.push(line),
+        }
+        Ok(true)
+    }
+
+    fn context_break(&mut self, _: &Searcher) -> Result<bool, Self::Error> {
+        self.pending_before.clear();
+        Ok(true)
+    }
+}
+
+pub fn exec_search(
+    workspace: &str,
+    query: &str,
+    path: Option<&str>,
+    opts: &SearchOptions,
+) -> ToolResult {
+    let dir = path
+        .map(|p| Path::new(workspace).join(p))
+        .unwrap_or_else(|| Path::new(workspace).to_path_buf());
+    let glob_label = opts
+        .glob
+        .as_deref()
+        .map(|g| format!(" ({g})"))
+        .unwrap_or_default();
+    ui::print_tool_action(
+        "search",
+        &format!("\"{query}\" in {}{glob_label}", dir.display()),
+    );
+
+    match search(workspace, &dir, query, opts) {
+        Ok((output, matches, files)) => {
+            if matches > 0 {
+                ui::print_dim(&format!("    {matches} matches in {files} files"));
+            } else {
+                ui::print_dim("    No matches");
+            }
+            ToolResult {
+                success: true,
+                output,
+            }
+        }
+        Err(e) => {
+            ui::print_error(&format!("    {e}"));
+            ToolResult {
+                success: false,
+                output: format!("Search error: {e}"),
+            }
+        }
+    }
+}
+
+/// Run the search; returns (tool output, matches shown, files shown)
+fn search(
+    workspace: &str,
+    dir: &Path,
+    query: &str,
+    opts: &SearchOptions,
+) -> Result<(String, usize, usize), String> {
+    if query.is_empty() {
+        return Err("query is empty".into());
+    }
+    if !dir.exists() {
+        return Err(format!("{} does not exist", dir.display()));
+    }
+
+    let mut builder = RegexMatcherBuilder::new();
+    builder.fixed_strings(!opts.regex);
+    match opts.case_sensitive {
+        Some(sensitive) => builder.case_insensitive(!sensitive),
+        None => builder.case_smart(true),
+    };
+    let matcher = builder
+        .build(query)
+        .map_err(|e| format!("invalid regex: {e}"))?;
+
+    let context = opts.context_lines.min(MAX_CONTEXT_LINES);
+    let mut searcher = SearcherBuilder::new()
+        .line_number(true)
+        .before_context(context)
+        .after_context(context)
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .build();
+
+    let mut walker = WalkBuilder::new(dir);
+    walker.require_git(false).sort_by_file_name(|a, b| a.cmp(b));
+    if let Some(glob) = opts.glob.as_deref().filter(|g| !g.trim().is_empty()) {
+        let mut overrides = OverrideBuilder::new(dir);
+        overrides
+            .add(glob.trim())
+            .map_err(|e| format!("invalid glob: {e}"))?;
+        walker.overrides(
+            overrides
+                .build()
+                .map_err(|e| format!("invalid glob: {e}"))?,
+        );
+    }
+
+    let max_results = opts.max_results.clamp(1, MAX_RESULTS_CAP);
+    // One past the page so we know whether there's more
+    let limit = opts.offset + max_results + 1;
+    let mut seen = 0usize;
+    let mut per_file: Vec<(String, Vec<Hit>)> = Vec::new();
+    let denied = DeniedPaths::for_workspace(workspace);
+
+    for entry in walker.build().flatten() {
+        if !entry.file_type().is_some_and(|t| t.is_file()) || denied.contains(entry.path()) {
+            continue;
+        }
+        let mut sink = FileSink {
+            hits: Vec::new(),
+            pending_before: Vec::new(),
+            seen: &mut seen,
+            limit,
+        };
+        // Unreadable files are skipped, as ripgrep does
+        let _ = searcher.search_path(&matcher, entry.path(), &mut sink);
+        if !sink.hits.is_empty() {
+            let rel = entry
+                .path()
+                .strip_prefix(workspace)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .to_string();
+            per_file.push((rel, sink.hits));
+        }
+        if seen >= limit {
+            break;
+        }
+    }
+
+    // Page by match: skip `offset`, keep `max_results`
+    let mut index = 0usize;
+    let mut shown = 0usize;
+    let mut sections = Vec::new();
+    for (file, hits) in per_file {
+        let page: Vec<Hit> = hits
+            .into_iter()
+            .filter(|_| {
+                index += 1;
+                index > opts.offset && index <= opts.offset + max_results
+            })
+            .collect();
+        if page.is_empty() {
+            continue;
+        }
+        shown += page.len();
+        sections.push(render_file(&file, &page));
+    }
+
+    if sections.is_empty() {
+        let msg = if opts.offset > 0 && seen > 0 {
+            format!("No matches past offset {}", opts.offset)
+        } else {
+            "No matches".into()
+        };
+        return Ok((msg, 0, 0));
+    }
+    let files = sections.len();
+    let mut output = sections.join("\n\n");
+    if seen > opts.offset + max_results {
+        output.push_str(&format!(
+            "\n\n[More matches — search again with offset: {}]",
+            opts.offset + max_results
+        ));
+    }
+    Ok((output, shown, files))
+}
+
+/// ripgrep-style block: path, then `N:` for matches and `N-` for context,
+/// with `--` between non-adjacent hits
+fn render_file(file: &str, hits: &[Hit]) -> String {
+    let mut out = vec![file.to_string()];
+    let mut last_line: Option<u64> = None;
+    for hit in hits {
+        let first = hit.lines.first().map(|l| l.0).unwrap_or(0);
+        if let Some(prev) = last_line {
+            if first > prev + 1 {
+                out.push("--".into());
+            }
+        }
+        for (num, text, is_match) in &hit.lines {
+            let sep = if *is_match { ':' } else { '-' };
+            out.push(format!("{num}{sep}{text}"));
+            last_line = Some(*num);
+        }
+    }
+    out.join("\n")
+}
+
+/// `find_file` — case-insensitive partial name match, honouring ignore files
+pub fn exec_find_file(name: &str, dir: Option<&str>) -> ToolResult {
+    // Default to home directory
+    let search_dir = dir.unwrap_or("~");
+    let expanded = if search_dir == "~" {
+        std::env::var("HOME").unwrap_or_else(|_| "/".into())
+    } else {
+        search_dir.to_string()
+    };
+
+    ui::print_tool_action("find", &format!("\"{name}\" in {expanded}"));
+
+    if !Path::new(&expanded).is_dir() {
+        return ToolResult {
+            success: false,
+            output: format!("Find error: {expanded} is not a directory"),
+        };
+    }
+
+    // Heavy directories that are rarely gitignored outside a repo
+    const SKIP_DIRS: &[&str] = &["node_modules", "target", "__pycache__", "Library", ".Trash"];
+    let needle = name.to_lowercase();
+    let results: Vec<String> = WalkBuilder::new(&expanded)
+        .max_depth(Some(5))
+        .require_git(false)
+        .filter_entry(|e| {
+            !(e.file_type().is_some_and(|t| t.is_dir())
+                && SKIP_DIRS.contains(&e.file_name().to_string_lossy().as_ref()))
+        })
+        .build()
+        .flatten()
+        .filter(|e| e.depth() > 0)
+        .filter(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .to_lowercase()
+                .contains(&needle)
+        })
+        .take(30)
+        .map(|e| e.path().to_string_lossy().to_string())
+        .collect();
+
+    let count = results.len();
+    if count > 0 {
+        ui::print_dim(&format!("    {count} results"));
+    } else {
+        ui::print_dim("    No results");
+    }
+    ToolResult {
+        success: true,
+        output: results.join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("clifcode-search-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        let files = [
+            (".gitignore", "ignored.rs\n"),
+            ("ignored.rs", "fn needle() {}\n"),
+            (".hidden.rs", "fn needle() {}\n"),
+            ("notes.md", "needle in the docs\n"),
+            (
+                "src/a.rs",
+                "fn one() {}\nfn needle() {}\nfn two() {}\n\n\n\nlet Needle = 1;\n",
+            ),
+            ("src/b.rs", "// abc\n// a.c\nconst NEEDLE: u8 = 0;\n"),
+        ];
+        for (path, content) in files {
+            std::fs::write(dir.join(path), content).unwrap();
+        }
+        std::fs::write(dir.join("src/blob.rs"), b"needle\x00\x01").unwrap();
+        dir
+    }
+
+    fn run(dir: &Path, query: &str, opts: &SearchOptions) -> (String, usize, usize) {
+        let ws = dir.to_str().unwrap();
+        search(ws, dir, query, opts).unwrap()
+    }
+
+    #[test]
+    fn honours_ignore_files_case_and_literal_queries() {
+        let dir = workspace("basic");
+
+        // Smart case: lowercase matches every case; ignored, hidden and binary files are skipped
+        let (out, matches, files) = run(&dir, "needle", &SearchOptions::default());
+        assert_eq!((matches, files), (4, 3), "{out}");
+        assert_eq!(
+            out,
+            "notes.md\n1:needle in the docs\n\n\
+             src/a.rs\n2:fn needle() {}\n--\n7:let Needle = 1;\n\n\
+             src/b.rs\n3:const NEEDLE: u8 = 0;"
+        );
+        assert!(!out.contains("ignored.rs") && !out.contains(".hidden") && !out.contains("blob"));
+
+        let (_, matches, _) = run(&dir, "Needle", &SearchOptions::default());
+        assert_eq!(matches, 1);
+        let insensitive = SearchOptions {
+            case_sensitive: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(run(&dir, "Needle", &insensitive).1, 4);
+
+        // Literal by default: `.` is not a wildcard
+        assert_eq!(run(&dir, "a.c", &SearchOptions::default()).1, 1);
+        let regex = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        assert_eq!(run(&dir, "a.c", &regex).1, 2);
+        assert!(search(dir.to_str().unwrap(), &dir, "(", &regex).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn filters_by_glob_and_shows_context() {
+        let dir = workspace("glob");
+        let only_src = SearchOptions {
+            glob: Some("src/**".into()),
+            ..Default::default()
+        };
+        let (out, _, files) = run(&dir, "needle", &only_src);
+        assert_eq!(files, 2);
+        assert!(!out.contains("notes.md"));
+
+        let no_docs = SearchOptions {
+            glob: Some("!*.md".into()),
+            ..Default::default()
+        };
+        assert!(!run(&dir, "needle", &no_docs).0.contains("notes.md"));
+
+        let context = SearchOptions {
+            glob: Some("src/a.rs".into()),
+            context_lines: 1,
+            ..Default::default()
+        };
+        let (out, _, _) = run(&dir, "needle", &context);
+        assert_eq!(
+            out,
+            "src/a.rs\n1-fn one() {}\n2:fn needle() {}\n3-fn two() {}\n--\n6-\n7:let Needle = 1;"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pages_through_matches() {
+        let dir = workspace("page");
+        let first = SearchOptions {
+            max_results: 3,
+            ..Default::default()
+        };
+        let (out, matches, _) = run(&dir, "needle", &first);
+        assert_eq!(matches, 3);
+        assert!(out.ends_with("[More matches — search again with offset: 3]"));
+
+        let second = SearchOptions {
+            max_results: 3,
+            offset: 3,
+            ..Default::default()
+        };
+        let (out, matches, files) = run(&dir, "needle", &second);
+        assert_eq!((matches, files), (1, 1));
+        assert_eq!(out, "src/b.rs\n3:const NEEDLE: u8 = 0;");
+
+        let past = SearchOptions {
+            offset: 10,
+            ..Default::default()
+        };
+        assert_eq!(run(&dir, "needle", &past).0, "No matches past offset 10");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,5 +1,7 @@
 //! Session persistence and context compaction.
 
+use crate::message::Message;
+use crate::todo::TodoItem;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
@@ -7,12 +9,14 @@ use std::path::PathBuf;
 pub struct Session {
     pub id: String,
     pub workspace: String,
-    pub messages: Vec<serde_json::Value>,
+    pub messages: Vec<Message>,
     #[serde(default)]
     pub context_files: Vec<String>,
     #[serde(default)]
     pub autonomy: String,
     pub created_at: String,
+    #[serde(default)]
+    pub todos: Vec<TodoItem>,
 }
 
 fn sessions_dir() -> PathBuf {
@@ -50,10 +54,9 @@ pub fn list_sessions() -> Vec<(String, String, String)> {
                     let preview = session
                         .messages
                         .iter()
-                        .find(|m| m.get("role").and_then(|v| v.as_str()) == Some("user"))
-                        .and_then(|m| m.get("content"))
-                        .and_then(|v| v.as_str())
-                        .unwrap_or("(empty)")
+                        .find(|m| matches!(m, Message::User { .. }))
+                        .map(|m| m.text())
+                        .unwrap_or_else(|| "(empty)".into())
                         .chars()
                         .take(50)
                         .collect::<String>();
@@ -78,24 +81,13 @@ pub fn new_session_id() -> String {
 
 /// Token estimate (~4 chars per token). Counts all message fields: content,
 /// tool_calls arguments, tool_call IDs, and structural overhead.
-pub fn estimate_tokens(messages: &[serde_json::Value]) -> usize {
+pub fn estimate_tokens(messages: &[Message]) -> usize {
     messages
         .iter()
         .map(|m| {
-            let mut chars: usize = 0;
-            if let Some(c) = m.get("content").and_then(|v| v.as_str()) {
-                chars += c.len();
-            }
-            if let Some(tc) = m.get("tool_calls").and_then(|v| v.as_array()) {
-                for call in tc {
-                    if let Some(args) = call.pointer("/function/arguments").and_then(|v| v.as_str())
-                    {
-                        chars += args.len();
-                    }
-                    if let Some(name) = call.pointer("/function/name").and_then(|v| v.as_str()) {
-                        chars += name.len();
-                    }
-                }
+            let mut chars: usize = m.text().len();
+            for call in m.tool_calls() {
+                chars += call.arguments.len() + call.name.len();
             }
             chars += 20; // role, structure overhead per message
             chars / 4
@@ -111,7 +103,7 @@ pub fn estimate_tokens(messages: &[serde_json::Value]) -> usize {
 ///   3. Last resort: drop old conversation turns, keep system prompt + last 6
 ///
 /// Each tier re-checks the token count and stops as soon as we're under budget.
-pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize) {
+pub fn compact_messages(messages: &mut Vec<Message>, max_tokens: usize) {
     if estimate_tokens(messages) < max_tokens || messages.len() < 6 {
         return;
     }
@@ -124,14 +116,14 @@ pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize
     const KEEP_LINES: usize = 40;
 
     for msg in messages.iter_mut() {
-        if msg.get("role").and_then(|v| v.as_str()) != Some("tool") {
+        let Message::Tool { content, .. } = msg else {
             continue;
-        }
-        let content = match msg.get("content").and_then(|v| v.as_str()) {
-            Some(c) if c.len() > TRUNCATE_THRESHOLD => c.to_string(),
-            _ => continue,
         };
-        let lines: Vec<&str> = content.lines().collect();
+        let text = content.text();
+        if text.len() <= TRUNCATE_THRESHOLD {
+            continue;
+        }
+        let lines: Vec<&str> = text.lines().collect();
         if lines.len() <= KEEP_LINES * 2 {
             continue;
         }
@@ -143,7 +135,7 @@ pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize
             lines.len() - KEEP_LINES * 2,
             tail.join("\n")
         );
-        msg["content"] = serde_json::Value::String(truncated);
+        *content = truncated.into();
     }
 
     if estimate_tokens(messages) < max_tokens {
@@ -159,12 +151,11 @@ pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize
         if i >= recent_start {
             break;
         }
-        if msg.get("role").and_then(|v| v.as_str()) != Some("tool") {
+        let Message::Tool { content, .. } = msg else {
             continue;
-        }
-        let content = msg.get("content").and_then(|v| v.as_str()).unwrap_or("");
-        if content.len() > 200 {
-            msg["content"] = serde_json::Value::String("[compacted — tool result omitted]".into());
+        };
+        if content.text().len() > 200 {
+            *content = "[compacted — tool result omitted]".into();
         }
     }
 
@@ -184,13 +175,12 @@ pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize
 
     let mut summary_parts = Vec::new();
     for msg in &messages[keep_start..keep_end] {
-        let role = msg.get("role").and_then(|v| v.as_str()).unwrap_or("?");
         // Skip tool messages in summary — they were already compacted
-        if role == "tool" {
+        if matches!(msg, Message::Tool { .. }) {
             continue;
         }
-        let content = msg.get("content").and_then(|v| v.as_str()).unwrap_or("");
-        let preview: String = content.chars().take(120).collect();
+        let role = msg.role();
+        let preview: String = msg.text().chars().take(120).collect();
         if !preview.is_empty() {
             summary_parts.push(format!("[{role}] {preview}..."));
         }
@@ -204,9 +194,6 @@ pub fn compact_messages(messages: &mut Vec<serde_json::Value>, max_tokens: usize
 
     let tail: Vec<_> = messages[keep_end..].to_vec();
     messages.truncate(keep_start);
-    messages.push(serde_json::json!({
-        "role": "system",
-        "content": summary
-    }));
+    messages.push(Message::system(summary));
     messages.extend(tail);
 }
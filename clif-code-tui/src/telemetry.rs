@@ -0,0 +1,28 @@
+//! Trace export — agent turns, model requests and tool calls as spans.
+//!
+//! The span layer, trace file and OTLP exporter live in `clif-telemetry`,
+//! shared with ClifPad. This module picks the destinations:
+//!   - a JSON-lines trace file: `--trace-file`, `CLIFCODE_TRACE_FILE` or
+//!     `"trace_file"` in config
+//!   - an OTLP/HTTP collector: `OTEL_EXPORTER_OTLP_ENDPOINT` or
+//!     `"otlp_endpoint"` in config. `OTEL_EXPORTER_OTLP_HEADERS` adds headers.
+
+use clif_telemetry::{Service, TraceConfig};
+use std::path::PathBuf;
+
+pub use clif_telemetry::{args_hash, TraceGuard};
+
+const SERVICE: Service = Service {
+    name: "clifcode",
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+/// Install the trace layer for `file` (from the command line), else the
+/// configured destinations. Returns `None` when there are none.
+pub fn init(file: Option<PathBuf>) -> Result<Option<TraceGuard>, String> {
+    let config = TraceConfig::from_env(
+        file.or_else(crate::config::trace_file),
+        crate::config::otlp_endpoint(),
+    );
+    clif_telemetry::init(SERVICE, &config)
+}
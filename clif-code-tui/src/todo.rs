@@ -0,0 +1,243 @@
+//! Session todo list — `todo_write` / `todo_read` state for multi-step tasks.
+//!
+//! The list lives on the conversation (and in saved sessions) and is mirrored
+//! into the system prompt, which compaction never touches, so the model keeps
+//! track of progress across long tasks.
+
+use crate::message::Message;
+use crate::ui;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TodoItem {
+    pub id: String,
+    pub content: String,
+    /// pending, in_progress, completed, cancelled
+    pub status: String,
+}
+
+const STATUSES: &[&str] = &["pending", "in_progress", "completed", "cancelled"];
+
+/// Marker that starts the pinned list inside the system prompt
+const PIN_MARKER: &str = "\n\nTODO LIST";
+
+/// Apply a `todo_write` call. With `merge`, items are upserted by id; otherwise
+/// the list is replaced. Returns a short summary for the tool result.
+pub fn apply_write(
+    todos: &mut Vec<TodoItem>,
+    incoming: Vec<TodoItem>,
+    merge: bool,
+) -> Result<String, String> {
+    for (i, item) in incoming.iter().enumerate() {
+        if incoming[..i].iter().any(|t| t.id == item.id) {
+            return Err(format!("Duplicate todo id: {}", item.id));
+        }
+        if item.id.trim().is_empty()
+            || item.content.trim().is_empty()
+            || !STATUSES.contains(&item.status.as_str())
+        {
+            return Err(
+                "Each todo item must include non-empty id/content and status in: pending, in_progress, completed, cancelled."
+                    .into(),
+            );
+        }
+    }
+
+    if merge {
+        for item in incoming {
+            match todos.iter_mut().find(|t| t.id == item.id) {
+                Some(existing) => *existing = item,
+                None => todos.push(item),
+            }
+        }
+    } else {
+        *todos = incoming;
+    }
+
+    let (done, total) = progress(todos);
+    Ok(format!(
+        "Todo list updated ({done}/{total} done):\n{}",
+        render_checklist(todos)
+    ))
+}
+
+/// (completed, total) — cancelled items count as neither
+pub fn progress(todos: &[TodoItem]) -> (usize, usize) {
+    let total = todos.iter().filter(|t| t.status != "cancelled").count();
+    let done = todos.iter().filter(|t| t.status == "completed").count();
+    (done, total)
+}
+
+/// The item being worked on: first in_progress, else first pending
+pub fn current(todos: &[TodoItem]) -> Option<&TodoItem> {
+    todos
+        .iter()
+        .find(|t| t.status == "in_progress")
+        .or_else(|| todos.iter().find(|t| t.status == "pending"))
+}
+
+/// Plain-text checklist for the model
+pub fn render_checklist(todos: &[TodoItem]) -> String {
+    if todos.is_empty() {
+        return "(empty)".into();
+    }
+    todos
+        .iter()
+        .map(|t| {
+            let mark = match t.status.as_str() {
+                "completed" => "[x]",
+                "in_progress" => "[~]",
+                "cancelled" => "[-]",
+                _ => "[ ]",
+            };
+            format!("- {mark} {} (id: {})", t.content, t.id)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Build a pending list from plan steps (ids "1", "2", ...)
+pub fn from_steps(steps: &[String]) -> Vec<TodoItem> {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, s)| TodoItem {
+            id: (i + 1).to_string(),
+            content: s.clone(),
+            status: "pending".into(),
+        })
+        .collect()
+}
+
+/// Mirror the list into the system prompt, replacing any previous copy.
+/// An empty list removes it.
+pub fn pin_to_system_prompt(messages: &mut [Message], todos: &[TodoItem]) {
+    let Some(system) = messages.first_mut() else {
+        return;
+    };
+    let content = system.text();
+    let base = match content.find(PIN_MARKER) {
+        Some(pos) => content[..pos].to_string(),
+        None => content,
+    };
+    let pinned = if todos.is_empty() {
+        base
+    } else {
+        format!(
+            "{base}{PIN_MARKER} — current progress. Work through it in order, keep it \
+             updated with todo_write (merge: true) as items start and finish, and don't \
+             expand the scope without asking:\n{}",
+            render_checklist(todos)
+        )
+    };
+    *system.content_mut() = pinned.into();
+}
+
+/// Print the checklist for the user
+pub fn print_checklist(todos: &[TodoItem]) {
+    let (done, total) = progress(todos);
+    println!(
+        "  {}{}Todo{} {}{done}/{total}{}",
+        ui::BOLD,
+        ui::WHITE,
+        ui::RESET,
+        ui::DIM,
+        ui::RESET
+    );
+    for t in todos {
+        let (mark, color) = match t.status.as_str() {
+            "completed" => ("\u{2713}", ui::GREEN),
+            "in_progress" => ("\u{25b8}", ui::BRIGHT_YELLOW),
+            "cancelled" => ("\u{2717}", ui::DIM),
+            _ => ("\u{25cb}", ui::DIM),
+        };
+        println!("    {color}{mark}{} {}", ui::RESET, t.content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, content: &str, status: &str) -> TodoItem {
+        TodoItem {
+            id: id.into(),
+            content: content.into(),
+            status: status.into(),
+        }
+    }
+
+    #[test]
+    fn write_validates_then_replaces_or_merges() {
+        let mut todos = vec![item("1", "Read code", "completed")];
+
+        for bad in [
+            vec![item("2", "Fix", "done")],
+            vec![item("2", "  ", "pending")],
+            vec![item("", "Fix", "pending")],
+            vec![item("2", "Fix", "pending"), item("2", "Test", "pending")],
+        ] {
+            assert!(apply_write(&mut todos, bad, true).is_err());
+        }
+        // A rejected write leaves the list alone
+        assert_eq!(todos, [item("1", "Read code", "completed")]);
+
+        let summary = apply_write(
+            &mut todos,
+            vec![
+                item("1", "Read code", "in_progress"),
+                item("2", "Fix", "pending"),
+            ],
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            todos,
+            [
+                item("1", "Read code", "in_progress"),
+                item("2", "Fix", "pending")
+            ]
+        );
+        assert!(summary.starts_with("Todo list updated (0/2 done)"));
+        assert!(summary.contains("- [~] Read code (id: 1)"));
+
+        apply_write(&mut todos, vec![item("9", "Ship", "pending")], false).unwrap();
+        assert_eq!(todos, [item("9", "Ship", "pending")]);
+    }
+
+    #[test]
+    fn progress_skips_cancelled_and_current_prefers_in_progress() {
+        let mut todos = vec![
+            item("1", "Read", "completed"),
+            item("2", "Drop", "cancelled"),
+            item("3", "Fix", "pending"),
+            item("4", "Test", "in_progress"),
+        ];
+        assert_eq!(progress(&todos), (1, 3));
+        assert_eq!(current(&todos).map(|t| t.id.as_str()), Some("4"));
+
+        todos[3].status = "completed".into();
+        assert_eq!(current(&todos).map(|t| t.id.as_str()), Some("3"));
+
+        todos[2].status = "completed".into();
+        assert_eq!(progress(&todos), (3, 3));
+        assert!(current(&todos).is_none());
+    }
+
+    #[test]
+    fn pinned_list_is_replaced_not_appended() {
+        let mut messages = vec![Message::system("You are ClifCode."), Message::user("hi")];
+
+        pin_to_system_prompt(&mut messages, &from_steps(&["Read".into(), "Fix".into()]));
+        pin_to_system_prompt(&mut messages, &[item("1", "Read", "completed")]);
+        let prompt = messages[0].text();
+        assert!(prompt.starts_with("You are ClifCode."));
+        assert_eq!(prompt.matches(PIN_MARKER).count(), 1);
+        assert!(prompt.contains("- [x] Read (id: 1)"));
+        assert!(!prompt.contains("Fix"));
+
+        pin_to_system_prompt(&mut messages, &[]);
+        assert_eq!(messages[0].text(), "You are ClifCode.");
+        assert_eq!(messages[1].text(), "hi");
+    }
+}
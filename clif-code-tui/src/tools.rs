@@ -1,13 +1,19 @@
 //! Tool definitions and execution for the ClifCode agent.
 
+use crate::lsp;
+use crate::notebook::{self, CellEdit};
+use crate::redact;
+use crate::search::{self, SearchOptions};
+use crate::todo::TodoItem;
 use crate::ui;
+use crate::web;
 use serde::{Deserialize, Serialize};
 use std::path::Path;
 
 pub const MAX_TURNS: usize = 25;
 
 /// Tool call from the API response (OpenAI format)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ApiToolCall {
     pub id: String,
     pub name: String,
@@ -34,16 +40,56 @@ pub enum ToolCall {
         old_string: String,
         new_string: String,
     },
+    NotebookEdit {
+        path: String,
+        edit: CellEdit,
+    },
     ListFiles {
         path: Option<String>,
     },
     Search {
         query: String,
         path: Option<String>,
+        options: SearchOptions,
     },
     RunCommand {
         command: String,
     },
+    FetchUrl {
+        url: String,
+        offset: Option<usize>,
+    },
+    Docs {
+        crate_name: String,
+        item: Option<String>,
+        offset: Option<usize>,
+    },
+    GotoDefinition {
+        path: String,
+        line: usize,
+        symbol: Option<String>,
+        column: Option<usize>,
+    },
+    FindReferences {
+        path: String,
+        line: usize,
+        symbol: Option<String>,
+        column: Option<usize>,
+    },
+    Hover {
+        path: String,
+        line: usize,
+        symbol: Option<String>,
+        column: Option<usize>,
+    },
+    Diagnostics {
+        path: String,
+    },
+    TodoWrite {
+        todos: Vec<TodoItem>,
+        merge: bool,
+    },
+    TodoRead,
     ChangeDir {
         path: String,
     },
@@ -52,16 +98,47 @@ pub enum ToolCall {
     },
 }
 
+/// Tools that never modify anything — safe to run in parallel and the only
+/// ones offered in plan mode
+pub const READ_ONLY_TOOLS: &[&str] = &[
+    "read_file",
+    "find_file",
+    "list_files",
+    "search",
+    "goto_definition",
+    "find_references",
+    "hover",
+    "diagnostics",
+];
+
 impl ToolCall {
+    /// API name of the tool
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToolCall::ReadFile { .. } => "read_file",
+            ToolCall::FindFile { .. } => "find_file",
+            ToolCall::WriteFile { .. } => "write_file",
+            ToolCall::EditFile { .. } => "edit_file",
+            ToolCall::NotebookEdit { .. } => "notebook_edit",
+            ToolCall::ListFiles { .. } => "list_files",
+            ToolCall::Search { .. } => "search",
+            ToolCall::RunCommand { .. } => "run_command",
+            ToolCall::FetchUrl { .. } => "fetch_url",
+            ToolCall::Docs { .. } => "docs",
+            ToolCall::GotoDefinition { .. } => "goto_definition",
+            ToolCall::FindReferences { .. } => "find_references",
+            ToolCall::Hover { .. } => "hover",
+            ToolCall::Diagnostics { .. } => "diagnostics",
+            ToolCall::TodoWrite { .. } => "todo_write",
+            ToolCall::TodoRead => "todo_read",
+            ToolCall::ChangeDir { .. } => "change_directory",
+            ToolCall::Submit { .. } => "submit",
+        }
+    }
+
     /// Whether this tool call is read-only and safe to run in parallel
     pub fn is_read_only(&self) -> bool {
-        matches!(
-            self,
-            ToolCall::ReadFile { .. }
-                | ToolCall::FindFile { .. }
-                | ToolCall::ListFiles { .. }
-                | ToolCall::Search { .. }
-        )
+        READ_ONLY_TOOLS.contains(&self.name())
     }
 
     pub fn from_api(call: &ApiToolCall) -> Option<Self> {
@@ -90,22 +167,128 @@ impl ToolCall {
                 old_string: args.get("old_string")?.as_str()?.to_string(),
                 new_string: args.get("new_string")?.as_str()?.to_string(),
             }),
+            "notebook_edit" => Some(ToolCall::NotebookEdit {
+                path: args.get("path")?.as_str()?.to_string(),
+                edit: CellEdit {
+                    action: args.get("action")?.as_str()?.to_string(),
+                    cell_index: args.get("cell_index")?.as_u64()? as usize,
+                    source: args
+                        .get("source")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    cell_type: args
+                        .get("cell_type")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                },
+            }),
             "list_files" => Some(ToolCall::ListFiles {
                 path: args
                     .get("path")
                     .and_then(|v| v.as_str())
                     .map(|s| s.to_string()),
             }),
-            "search" => Some(ToolCall::Search {
-                query: args.get("query")?.as_str()?.to_string(),
-                path: args
-                    .get("path")
+            "search" => {
+                let num = |k: &str| args.get(k).and_then(|v| v.as_u64()).map(|n| n as usize);
+                Some(ToolCall::Search {
+                    query: args.get("query")?.as_str()?.to_string(),
+                    path: args
+                        .get("path")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string()),
+                    options: SearchOptions {
+                        regex: args.get("regex").and_then(|v| v.as_bool()).unwrap_or(false),
+                        glob: args
+                            .get("glob")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string()),
+                        context_lines: num("context_lines").unwrap_or(0),
+                        case_sensitive: args.get("case_sensitive").and_then(|v| v.as_bool()),
+                        max_results: num("max_results").unwrap_or(search::DEFAULT_MAX_RESULTS),
+                        offset: num("offset").unwrap_or(0),
+                    },
+                })
+            }
+            "run_command" => Some(ToolCall::RunCommand {
+                command: args.get("command")?.as_str()?.to_string(),
+            }),
+            "fetch_url" => Some(ToolCall::FetchUrl {
+                url: args.get("url")?.as_str()?.to_string(),
+                offset: args
+                    .get("offset")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize),
+            }),
+            "docs" => Some(ToolCall::Docs {
+                crate_name: args.get("crate")?.as_str()?.to_string(),
+                item: args
+                    .get("item")
                     .and_then(|v| v.as_str())
                     .map(|s| s.to_string()),
+                offset: args
+                    .get("offset")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize),
             }),
-            "run_command" => Some(ToolCall::RunCommand {
-                command: args.get("command")?.as_str()?.to_string(),
+            "goto_definition" | "find_references" | "hover" => {
+                let path = args.get("path")?.as_str()?.to_string();
+                let line = args.get("line")?.as_u64()? as usize;
+                let symbol = args
+                    .get("symbol")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+                let column = args
+                    .get("column")
+                    .and_then(|v| v.as_u64())
+                    .map(|n| n as usize);
+                Some(match call.name.as_str() {
+                    "goto_definition" => ToolCall::GotoDefinition {
+                        path,
+                        line,
+                        symbol,
+                        column,
+                    },
+                    "find_references" => ToolCall::FindReferences {
+                        path,
+                        line,
+                        symbol,
+                        column,
+                    },
+                    _ => ToolCall::Hover {
+                        path,
+                        line,
+                        symbol,
+                        column,
+                    },
+                })
+            }
+            "diagnostics" => Some(ToolCall::Diagnostics {
+                path: args.get("path")?.as_str()?.to_string(),
+            }),
+            "todo_write" => Some(ToolCall::TodoWrite {
+                // Missing fields stay empty so validation can report them
+                todos: args
+                    .get("todos")?
+                    .as_array()?
+                    .iter()
+                    .map(|t| {
+                        let field = |k: &str| {
+                            t.get(k)
+                                .and_then(|v| v.as_str())
+                                .unwrap_or("")
+                                .trim()
+                                .to_string()
+                        };
+                        TodoItem {
+                            id: field("id"),
+                            content: field("content"),
+                            status: field("status"),
+                        }
+                    })
+                    .collect(),
+                merge: args.get("merge").and_then(|v| v.as_bool()).unwrap_or(false),
             }),
+            "todo_read" => Some(ToolCall::TodoRead),
             "change_directory" => Some(ToolCall::ChangeDir {
                 path: args.get("path")?.as_str()?.to_string(),
             }),
@@ -145,7 +328,7 @@ pub fn tool_definitions() -> serde_json::Value {
             "type": "function",
             "function": {
                 "name": "find_file",
-                "description": "Find files by name anywhere on the filesystem. Searches recursively. Use this when you don't know where a file is.",
+                "description": "Find files by name anywhere on the filesystem. Searches recursively (5 levels), skipping gitignored and hidden paths. Use this when you don't know where a file is.",
                 "parameters": {
                     "type": "object",
                     "properties": {
@@ -187,6 +370,24 @@ pub fn tool_definitions() -> serde_json::Value {
                 }
             }
         },
+        {
+            "type": "function",
+            "function": {
+                "name": "notebook_edit",
+                "description": "Edit a Jupyter notebook (.ipynb) by cell index: replace a cell's source, insert a new cell before an index, or delete a cell. Keeps valid nbformat. Use read_file first to see cell indices. Never use edit_file or write_file on notebooks.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "Notebook path relative to workspace" },
+                        "action": { "type": "string", "enum": ["replace", "insert", "delete"], "description": "replace, insert or delete" },
+                        "cell_index": { "type": "integer", "description": "0-based cell index. For insert, the new cell goes at this index (use the cell count to append)." },
+                        "source": { "type": "string", "description": "New cell source (replace/insert)" },
+                        "cell_type": { "type": "string", "enum": ["code", "markdown", "raw"], "description": "Cell type for insert (default code), or to change a cell's type on replace" }
+                    },
+                    "required": ["path", "action", "cell_index"]
+                }
+            }
+        },
         {
             "type": "function",
             "function": {
@@ -204,12 +405,18 @@ pub fn tool_definitions() -> serde_json::Value {
             "type": "function",
             "function": {
                 "name": "search",
-                "description": "Search for text pattern in files using grep. Returns matching lines with file paths and line numbers.",
+                "description": "Search file contents like ripgrep, respecting .gitignore. Returns matches grouped by file with line numbers (N: match, N- context).",
                 "parameters": {
                     "type": "object",
                     "properties": {
-                        "query": { "type": "string", "description": "Text pattern to search for" },
-                        "path": { "type": "string", "description": "Directory to search in. Defaults to workspace root." }
+                        "query": { "type": "string", "description": "Text to search for (a regex if regex is true)" },
+                        "path": { "type": "string", "description": "Directory or file to search in. Defaults to workspace root." },
+                        "regex": { "type": "boolean", "description": "Treat query as a regular expression. Default false (literal)." },
+                        "glob": { "type": "string", "description": "Only search matching files, e.g. \"*.rs\", \"src/**/*.{ts,tsx}\". Prefix with ! to exclude." },
+                        "context_lines": { "type": "integer", "description": "Lines of context before and after each match (max 10). Default 0." },
+                        "case_sensitive": { "type": "boolean", "description": "Force case sensitivity. Default: smart case (insensitive unless the query has uppercase)." },
+                        "max_results": { "type": "integer", "description": "Maximum matches to return (max 500). Default 50." },
+                        "offset": { "type": "integer", "description": "Skip this many matches, to page through results." }
                     },
                     "required": ["query"]
                 }
@@ -229,6 +436,140 @@ pub fn tool_definitions() -> serde_json::Value {
                 }
             }
         },
+        {
+            "type": "function",
+            "function": {
+                "name": "fetch_url",
+                "description": "Download a web page and return it as Markdown (navigation stripped). Only allowlisted documentation domains can be fetched. Returns up to 16000 chars at a time.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "url": { "type": "string", "description": "Full http(s) URL" },
+                        "offset": { "type": "integer", "description": "Character offset to continue reading a long page" }
+                    },
+                    "required": ["url"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "docs",
+                "description": "Look up Rust crate documentation — local `cargo doc` output (target/doc) first, then docs.rs.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "crate": { "type": "string", "description": "Crate name, e.g. serde_json" },
+                        "item": { "type": "string", "description": "Item path within the crate, e.g. Value or de::from_str" },
+                        "offset": { "type": "integer", "description": "Character offset to continue reading" }
+                    },
+                    "required": ["crate"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "goto_definition",
+                "description": "Jump to where a symbol is defined, using the language server (rust-analyzer, typescript-language-server, pyright, gopls). More precise than search for code navigation.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "File path relative to workspace" },
+                        "line": { "type": "integer", "description": "1-based line number" },
+                        "symbol": { "type": "string", "description": "Identifier on that line to look up (preferred over column)" },
+                        "column": { "type": "integer", "description": "1-based column, if symbol is not given" }
+                    },
+                    "required": ["path", "line"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "find_references",
+                "description": "Find all references to a symbol across the project, using the language server.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "File path relative to workspace" },
+                        "line": { "type": "integer", "description": "1-based line number" },
+                        "symbol": { "type": "string", "description": "Identifier on that line to look up (preferred over column)" },
+                        "column": { "type": "integer", "description": "1-based column, if symbol is not given" }
+                    },
+                    "required": ["path", "line"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "hover",
+                "description": "Show the type signature and docs for a symbol, using the language server.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "File path relative to workspace" },
+                        "line": { "type": "integer", "description": "1-based line number" },
+                        "symbol": { "type": "string", "description": "Identifier on that line to look up (preferred over column)" },
+                        "column": { "type": "integer", "description": "1-based column, if symbol is not given" }
+                    },
+                    "required": ["path", "line"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "diagnostics",
+                "description": "Get compile errors and warnings for a file from the language server, without running a build.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "path": { "type": "string", "description": "File path relative to workspace" }
+                    },
+                    "required": ["path"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "todo_write",
+                "description": "Create or update the todo list for this session. Use it for multi-step tasks to track progress; it survives context compaction.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {
+                        "todos": {
+                            "type": "array",
+                            "description": "List of todo items.",
+                            "items": {
+                                "type": "object",
+                                "properties": {
+                                    "id": { "type": "string", "description": "Unique id for the task." },
+                                    "content": { "type": "string", "description": "Task description." },
+                                    "status": { "type": "string", "description": "One of: pending, in_progress, completed, cancelled." }
+                                },
+                                "required": ["id", "content", "status"]
+                            }
+                        },
+                        "merge": { "type": "boolean", "description": "If true, merge by id into the existing list. If false or omitted, replace the list." }
+                    },
+                    "required": ["todos"]
+                }
+            }
+        },
+        {
+            "type": "function",
+            "function": {
+                "name": "todo_read",
+                "description": "Read the current todo list for this session.",
+                "parameters": {
+                    "type": "object",
+                    "properties": {}
+                }
+            }
+        },
         {
             "type": "function",
             "function": {
@@ -260,6 +601,23 @@ pub fn tool_definitions() -> serde_json::Value {
     ])
 }
 
+/// Tool definitions restricted to `READ_ONLY_TOOLS` (plan mode)
+pub fn read_only_tool_definitions() -> serde_json::Value {
+    let all = tool_definitions();
+    let filtered: Vec<serde_json::Value> = all
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|t| {
+            t.pointer("/function/name")
+                .and_then(|v| v.as_str())
+                .is_some_and(|name| READ_ONLY_TOOLS.contains(&name))
+        })
+        .cloned()
+        .collect();
+    serde_json::Value::Array(filtered)
+}
+
 /// Parse tool_calls from an OpenAI-format API response
 pub fn parse_api_tool_calls(resp: &serde_json::Value) -> Vec<ApiToolCall> {
     let mut calls = Vec::new();
@@ -295,6 +653,81 @@ pub fn parse_api_tool_calls(resp: &serde_json::Value) -> Vec<ApiToolCall> {
     calls
 }
 
+/// Parse tool calls written into message text by models without native function calling.
+/// Accepts `<tool_call>{"name": ..., "arguments": {...}}</tool_call>` blocks and, as a
+/// fallback, fenced ```json blocks of the same shape. Returns the text with the blocks
+/// removed and the calls in order.
+pub fn parse_text_tool_calls(content: &str) -> (String, Vec<ApiToolCall>) {
+    let mut calls = Vec::new();
+    let mut text = String::new();
+    let mut rest = content;
+
+    while let Some(start) = rest.find("<tool_call>") {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + "<tool_call>".len()..];
+        let (body, next) = match after.find("</tool_call>") {
+            Some(end) => (&after[..end], &after[end + "</tool_call>".len()..]),
+            // Unterminated block (model hit max_tokens or forgot the tag)
+            None => (after, ""),
+        };
+        if let Some(call) = text_call_from_json(body) {
+            calls.push(call);
+        }
+        rest = next;
+    }
+    text.push_str(rest);
+
+    if calls.is_empty() {
+        let mut stripped = String::new();
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find("```") {
+            let after = &rest[start + 3..];
+            let body_start = after.find('\n').map(|n| n + 1).unwrap_or(0);
+            let Some(end) = after[body_start..].find("```") else {
+                break;
+            };
+            let body = &after[body_start..body_start + end];
+            match text_call_from_json(body) {
+                Some(call) => {
+                    calls.push(call);
+                    stripped.push_str(&rest[..start]);
+                }
+                None => stripped.push_str(&rest[..start + 3 + body_start + end + 3]),
+            }
+            rest = &after[body_start + end + 3..];
+        }
+        stripped.push_str(rest);
+        if !calls.is_empty() {
+            text = stripped;
+        }
+    }
+
+    (text.trim().to_string(), calls)
+}
+
+/// Build an `ApiToolCall` from `{"name": ..., "arguments": ...}`; arguments may be
+/// an object or an already-encoded JSON string.
+fn text_call_from_json(body: &str) -> Option<ApiToolCall> {
+    // Ids must stay unique across the whole conversation
+    static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    let value: serde_json::Value = serde_json::from_str(body.trim()).ok()?;
+    let name = value.get("name")?.as_str()?.to_string();
+    let arguments = match value.get("arguments").or_else(|| value.get("parameters")) {
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(v) => v.to_string(),
+        None => "{}".into(),
+    };
+    Some(ApiToolCall {
+        id: format!(
+            "text_call_{}",
+            NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ),
+        name,
+        arguments,
+    })
+}
+
 /// Execute a tool call.
 /// `confirm_writes` — prompt Y/n before writes (suggest mode).
 /// `collapse_diffs` — show collapsed diff with Ctrl+O to expand (auto-edit mode).
@@ -304,27 +737,100 @@ pub fn execute_tool(
     confirm_writes: bool,
     collapse_diffs: bool,
 ) -> ToolResult {
+    if let Some(refused) = guard(call, workspace) {
+        return refused;
+    }
     match call {
         ToolCall::ReadFile { path, offset } => exec_read_file(workspace, path, *offset),
-        ToolCall::FindFile { name, dir } => exec_find_file(name, dir.as_deref()),
-        ToolCall::WriteFile { path, content } => {
-            exec_write_file(workspace, path, content, confirm_writes, collapse_diffs)
-        }
+        ToolCall::FindFile { name, dir } => search::exec_find_file(name, dir.as_deref()),
+        ToolCall::WriteFile { path, content } => with_diagnostics(
+            workspace,
+            path,
+            exec_write_file(workspace, path, content, confirm_writes, collapse_diffs),
+        ),
         ToolCall::EditFile {
             path,
             old_string,
             new_string,
-        } => exec_edit_file(
+        } => with_diagnostics(
             workspace,
             path,
-            old_string,
-            new_string,
-            confirm_writes,
-            collapse_diffs,
+            exec_edit_file(
+                workspace,
+                path,
+                old_string,
+                new_string,
+                confirm_writes,
+                collapse_diffs,
+            ),
         ),
+        ToolCall::NotebookEdit { path, edit } => {
+            notebook::exec_notebook_edit(workspace, path, edit, confirm_writes, collapse_diffs)
+        }
         ToolCall::ListFiles { path } => exec_list_files(workspace, path.as_deref()),
-        ToolCall::Search { query, path } => exec_search(workspace, query, path.as_deref()),
+        ToolCall::Search {
+            query,
+            path,
+            options,
+        } => search::exec_search(workspace, query, path.as_deref(), options),
         ToolCall::RunCommand { command } => exec_run_command(workspace, command, confirm_writes),
+        ToolCall::FetchUrl { url, offset } => web::exec_fetch_url(url, *offset, confirm_writes),
+        ToolCall::Docs {
+            crate_name,
+            item,
+            offset,
+        } => web::exec_docs(
+            workspace,
+            crate_name,
+            item.as_deref(),
+            *offset,
+            confirm_writes,
+        ),
+        ToolCall::GotoDefinition {
+            path,
+            line,
+            symbol,
+            column,
+        } => lsp::exec_query(
+            workspace,
+            lsp::Query::Definition,
+            path,
+            *line,
+            symbol.as_deref(),
+            *column,
+        ),
+        ToolCall::FindReferences {
+            path,
+            line,
+            symbol,
+            column,
+        } => lsp::exec_query(
+            workspace,
+            lsp::Query::References,
+            path,
+            *line,
+            symbol.as_deref(),
+            *column,
+        ),
+        ToolCall::Hover {
+            path,
+            line,
+            symbol,
+            column,
+        } => lsp::exec_query(
+            workspace,
+            lsp::Query::Hover,
+            path,
+            *line,
+            symbol.as_deref(),
+            *column,
+        ),
+        ToolCall::Diagnostics { path } => lsp::exec_diagnostics(workspace, path),
+        ToolCall::TodoWrite { .. } | ToolCall::TodoRead => ToolResult {
+            // Session state lives in run_turn — this is a fallback
+            success: false,
+            output: "Error: todo tools need an active session".into(),
+        },
         ToolCall::ChangeDir { path } => {
             // Handled in run_turn — this is a fallback
             ui::print_tool_action("cd", path);
@@ -343,6 +849,46 @@ pub fn execute_tool(
     }
 }
 
+/// Refuse calls that touch a denied path, or that would write a redaction
+/// placeholder copied from masked output over the real secret
+fn guard(call: &ToolCall, workspace: &str) -> Option<ToolResult> {
+    let (path, new_texts): (&str, Vec<&str>) = match call {
+        ToolCall::WriteFile { path, content } => (path, vec![content]),
+        ToolCall::EditFile {
+            path,
+            old_string,
+            new_string,
+        } => (path, vec![old_string, new_string]),
+        ToolCall::NotebookEdit { path, edit } => {
+            (path, edit.source.as_deref().into_iter().collect())
+        }
+        ToolCall::GotoDefinition { path, .. }
+        | ToolCall::FindReferences { path, .. }
+        | ToolCall::Hover { path, .. }
+        | ToolCall::Diagnostics { path } => (path, Vec::new()),
+        _ => return None,
+    };
+    let full = Path::new(workspace).join(path);
+    let refuse = |output: String| {
+        ui::print_error(&format!("Refused: {}", full.display()));
+        Some(ToolResult {
+            success: false,
+            output,
+        })
+    };
+    if redact::is_denied(workspace, &full) {
+        return refuse(redact::denied_message(path));
+    }
+    if new_texts.is_empty() {
+        return None;
+    }
+    let existing = std::fs::read_to_string(&full).unwrap_or_default();
+    new_texts
+        .iter()
+        .find_map(|text| redact::copied_placeholder(text, &existing))
+        .and_then(|placeholder| refuse(redact::placeholder_message(path, placeholder)))
+}
+
 // ---------------------------------------------------------------------------
 // Tool implementations
 // ---------------------------------------------------------------------------
@@ -358,7 +904,23 @@ fn exec_read_file(workspace: &str, path: &str, offset: Option<usize>) -> ToolRes
     };
     let offset = offset.unwrap_or(0);
     ui::print_tool_action("read", &format!("{}", full.display()));
-    match std::fs::read_to_string(&full) {
+    if redact::is_denied(workspace, &full) {
+        ui::print_error(&format!("Denied: {}", full.display()));
+        return ToolResult {
+            success: false,
+            output: redact::denied_message(path),
+        };
+    }
+    // Notebooks are rendered as cells instead of raw JSON
+    let content = std::fs::read_to_string(&full).and_then(|c| {
+        if notebook::is_notebook(&full) {
+            notebook::render(&c)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(c)
+        }
+    });
+    match content {
         Ok(content) => {
             let total = content.len();
             let chunk: String = content.chars().skip(offset).take(READ_CHUNK).collect();
@@ -391,67 +953,15 @@ fn exec_read_file(workspace: &str, path: &str, offset: Option<usize>) -> ToolRes
     }
 }
 
-fn exec_find_file(name: &str, dir: Option<&str>) -> ToolResult {
-    let search_dir = dir.unwrap_or_else(|| {
-        // Default to home directory
-        "~"
-    });
-    let expanded = if search_dir == "~" {
-        std::env::var("HOME").unwrap_or_else(|_| "/".into())
-    } else {
-        search_dir.to_string()
-    };
-
-    ui::print_tool_action("find", &format!("\"{name}\" in {expanded}"));
-
-    let output = std::process::Command::new("find")
-        .args([
-            &expanded,
-            "-maxdepth",
-            "5",
-            "-iname",
-            &format!("*{name}*"),
-            "-not",
-            "-path",
-            "*/node_modules/*",
-            "-not",
-            "-path",
-            "*/.git/*",
-            "-not",
-            "-path",
-            "*/target/*",
-            "-not",
-            "-path",
-            "*/__pycache__/*",
-            "-not",
-            "-path",
-            "*/Library/*",
-            "-not",
-            "-path",
-            "*/.Trash/*",
-        ])
-        .output();
-
-    match output {
-        Ok(out) => {
-            let text = String::from_utf8_lossy(&out.stdout);
-            let results: Vec<&str> = text.lines().take(30).collect();
-            let count = results.len();
-            if count > 0 {
-                ui::print_dim(&format!("    {count} results"));
-            } else {
-                ui::print_dim("    No results");
-            }
-            ToolResult {
-                success: true,
-                output: results.join("\n"),
-            }
+/// Append language-server diagnostics to a successful write/edit
+fn with_diagnostics(workspace: &str, path: &str, mut result: ToolResult) -> ToolResult {
+    if result.success {
+        if let Some(diagnostics) = lsp::diagnostics_after_edit(workspace, path) {
+            result.output.push_str("\n\n");
+            result.output.push_str(&diagnostics);
         }
-        Err(e) => ToolResult {
-            success: false,
-            output: format!("Find error: {e}"),
-        },
     }
+    result
 }
 
 fn exec_write_file(
@@ -483,13 +993,11 @@ fn exec_write_file(
     }
 
     // Confirm in suggest mode
-    if confirm && full.exists() {
-        if !ui::confirm("Apply this change?") {
-            return ToolResult {
-                success: false,
-                output: "User declined the change".into(),
-            };
-        }
+    if confirm && full.exists() && !ui::confirm("Apply this change?") {
+        return ToolResult {
+            success: false,
+            output: "User declined the change".into(),
+        };
     }
 
     if let Some(parent) = full.parent() {
@@ -525,6 +1033,16 @@ fn exec_edit_file(
     let full = Path::new(workspace).join(path);
     ui::print_tool_action("edit", &format!("{}", full.display()));
 
+    if notebook::is_notebook(&full) {
+        ui::print_error("Use notebook_edit for notebooks");
+        return ToolResult {
+            success: false,
+            output:
+                "Error: edit_file can't edit .ipynb notebooks — use notebook_edit with a cell index"
+                    .into(),
+        };
+    }
+
     let content = match std::fs::read_to_string(&full) {
         Ok(c) => c,
         Err(e) => {
@@ -772,63 +1290,6 @@ fn list_dir_recursive(
     }
 }
 
-fn exec_search(workspace: &str, query: &str, path: Option<&str>) -> ToolResult {
-    let dir = path
-        .map(|p| Path::new(workspace).join(p).to_string_lossy().to_string())
-        .unwrap_or_else(|| workspace.to_string());
-    ui::print_tool_action("search", &format!("\"{query}\" in {dir}"));
-
-    let output = std::process::Command::new("grep")
-        .args([
-            "-rn",
-            "--include=*.py",
-            "--include=*.rs",
-            "--include=*.ts",
-            "--include=*.tsx",
-            "--include=*.js",
-            "--include=*.jsx",
-            "--include=*.go",
-            "--include=*.toml",
-            "--include=*.json",
-            "--include=*.md",
-            "--include=*.yaml",
-            "--include=*.yml",
-            "--include=*.c",
-            "--include=*.cpp",
-            "--include=*.h",
-            "--include=*.java",
-            "--include=*.swift",
-            "--include=*.kt",
-            "--include=*.rb",
-            query,
-            &dir,
-        ])
-        .output();
-
-    match output {
-        Ok(out) => {
-            let text: String = String::from_utf8_lossy(&out.stdout)
-                .chars()
-                .take(4096)
-                .collect();
-            let count = text.lines().count();
-            if count > 0 {
-                ui::print_dim(&format!("    {count} matches"));
-            } else {
-                ui::print_dim("    No matches");
-            }
-            ToolResult {
-                success: true,
-                output: text,
-            }
-        }
-        Err(e) => ToolResult {
-            success: false,
-            output: format!("Search error: {e}"),
-        },
-    }
-}
-
 fn exec_run_command(workspace: &str, command: &str, confirm: bool) -> ToolResult {
     ui::print_tool_action("run", command);
 
@@ -880,3 +1341,60 @@ fn exec_run_command(workspace: &str, command: &str, confirm: bool) -> ToolResult
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tool_call_blocks_and_keeps_surrounding_text() {
+        let (text, calls) = parse_text_tool_calls(
+            "Reading it first.\n<tool_call>{\"name\": \"read_file\", \"arguments\": {\"path\": \"src/main.rs\"}}</tool_call>\nthen\n<tool_call>{\"name\": \"run_command\", \"parameters\": \"{\\\"command\\\":\\\"ls\\\"}\"}</tool_call>",
+        );
+        assert_eq!(text, "Reading it first.\n\nthen");
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].name, "read_file");
+        assert_eq!(calls[0].arguments, r#"{"path":"src/main.rs"}"#);
+        // `parameters` and string-encoded arguments are accepted too
+        assert_eq!(calls[1].name, "run_command");
+        assert_eq!(calls[1].arguments, r#"{"command":"ls"}"#);
+        assert_ne!(calls[0].id, calls[1].id);
+    }
+
+    #[test]
+    fn handles_unterminated_and_invalid_blocks() {
+        // Cut off by max_tokens before the closing tag
+        let (text, calls) =
+            parse_text_tool_calls("ok <tool_call>{\"name\": \"submit\", \"arguments\": {}}");
+        assert_eq!(text, "ok");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "submit");
+
+        let (text, calls) = parse_text_tool_calls("<tool_call>not json</tool_call> done");
+        assert_eq!(text, "done");
+        assert!(calls.is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_fenced_json_only_without_tool_call_tags() {
+        let reply = "Plan:\n```json\n{\"name\": \"list_files\"}\n```\n```rust\nfn main() {}\n```";
+        let (text, calls) = parse_text_tool_calls(reply);
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "list_files");
+        assert_eq!(calls[0].arguments, "{}");
+        // Code fences that aren't tool calls stay in the text
+        assert_eq!(text, "Plan:\n\n```rust\nfn main() {}\n```");
+
+        let (text, calls) = parse_text_tool_calls("```json\n{\"a\": 1}\n```");
+        assert!(calls.is_empty());
+        assert_eq!(text, "```json\n{\"a\": 1}\n```");
+
+        // With a tagged call present, fenced examples are left alone
+        let (text, calls) = parse_text_tool_calls(
+            "```json\n{\"name\": \"x\"}\n```\n<tool_call>{\"name\": \"y\"}</tool_call>",
+        );
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "y");
+        assert!(text.contains("\"name\": \"x\""));
+    }
+}
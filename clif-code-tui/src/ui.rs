@@ -1,10 +1,28 @@
 //! Pretty printing, colors, diffs, interactive menus.
+//!
+//! Everything here writes to the terminal. When the agent runs headless (as a
+//! library, see [`crate::agent`]) output is suppressed on that thread and
+//! [`confirm`] answers no — approvals go through the event handler instead.
 
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use crossterm::terminal;
 use similar::ChangeTag;
+use std::cell::Cell;
 use std::io::{self, BufRead, Write};
 
+thread_local! {
+    static HEADLESS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Suppress terminal output on the current thread
+pub fn set_headless(headless: bool) {
+    HEADLESS.with(|h| h.set(headless));
+}
+
+pub fn is_headless() -> bool {
+    HEADLESS.with(|h| h.get())
+}
+
 pub const RESET: &str = "\x1b[0m";
 pub const BOLD: &str = "\x1b[1m";
 pub const DIM: &str = "\x1b[2m";
@@ -31,7 +49,6 @@ const C_CYAN: &str = "\x1b[38;5;44m"; // teal
 const C_TEAL: &str = "\x1b[38;5;43m"; // green-teal
 const C_GREEN: &str = "\x1b[38;5;48m"; // bright green
 const C_LIME: &str = "\x1b[38;5;83m"; // lime
-const C_PURPLE: &str = "\x1b[38;5;141m"; // soft purple
 
 pub fn print_logo() {
     println!();
@@ -73,43 +90,71 @@ pub fn print_prompt() {
 }
 
 pub fn print_thinking() {
+    if is_headless() {
+        return;
+    }
     print!("{DIM}{ITALIC}\u{2022}\u{2022}\u{2022} thinking{RESET}");
     io::stdout().flush().unwrap();
 }
 
 pub fn clear_thinking() {
+    if is_headless() {
+        return;
+    }
     print!("\r\x1b[K");
     io::stdout().flush().unwrap();
 }
 
 pub fn print_tool_action(action: &str, detail: &str) {
+    if is_headless() {
+        return;
+    }
     let icon = match action {
         "read" => "\u{25b6}",  // play triangle
         "write" => "\u{270e}", // pencil
         "edit" => "\u{270e}",
-        "find" => "\u{25c7}",   // diamond outline
-        "search" => "\u{2315}", // search
-        "list" => "\u{2630}",   // trigram / hamburger
-        "run" => "\u{25b8}",    // small play
-        "cd" => "\u{2192}",     // arrow
-        _ => "\u{2022}",        // bullet
+        "find" => "\u{25c7}",        // diamond outline
+        "search" => "\u{2315}",      // search
+        "list" => "\u{2630}",        // trigram / hamburger
+        "run" => "\u{25b8}",         // small play
+        "cd" => "\u{2192}",          // arrow
+        "fetch" => "\u{21e3}",       // downwards dashed arrow
+        "docs" => "\u{2261}",        // identical to (book-ish)
+        "definition" => "\u{2197}",  // north-east arrow
+        "references" => "\u{21c4}",  // arrows left/right
+        "hover" => "\u{2139}",       // information
+        "diagnostics" => "\u{26a0}", // warning sign
+        "notebook" => "\u{25a4}",    // square with lines
+        _ => "\u{2022}",             // bullet
     };
     println!("    {BRIGHT_YELLOW}{icon} {BOLD}{action}{RESET} {DIM}{detail}{RESET}");
 }
 
 pub fn print_dim(text: &str) {
+    if is_headless() {
+        return;
+    }
     println!("{DIM}{text}{RESET}");
 }
 
 pub fn print_success(text: &str) {
+    if is_headless() {
+        return;
+    }
     println!("  {BRIGHT_GREEN}\u{2713}{RESET} {GREEN}{text}{RESET}");
 }
 
 pub fn print_error(text: &str) {
+    if is_headless() {
+        return;
+    }
     println!("  {RED}\u{2717} {BOLD}{text}{RESET}");
 }
 
 pub fn print_assistant(text: &str) {
+    if is_headless() {
+        return;
+    }
     println!();
     print!("  {BOLD}{BRIGHT_MAGENTA}\u{2726} ClifCode{RESET}  ");
     let rendered = render_markdown(text);
@@ -153,18 +198,15 @@ fn render_markdown(text: &str) -> String {
         let trimmed = line.trim_start();
 
         // Headers: # ## ###
-        if trimmed.starts_with("### ") {
-            let content = &trimmed[4..];
+        if let Some(content) = trimmed.strip_prefix("### ") {
             out.push_str(&format!("{BOLD}{content}{RESET}\n"));
             continue;
         }
-        if trimmed.starts_with("## ") {
-            let content = &trimmed[3..];
+        if let Some(content) = trimmed.strip_prefix("## ") {
             out.push_str(&format!("{BOLD}{content}{RESET}\n"));
             continue;
         }
-        if trimmed.starts_with("# ") {
-            let content = &trimmed[2..];
+        if let Some(content) = trimmed.strip_prefix("# ") {
             out.push_str(&format!("{BOLD}{CYAN}{content}{RESET}\n"));
             continue;
         }
@@ -266,18 +308,20 @@ fn find_closing(chars: &[char], start: usize, pattern: &[char; 2]) -> Option<usi
 
 /// Find closing single char
 fn find_single_closing(chars: &[char], start: usize, ch: char) -> Option<usize> {
-    for i in start..chars.len() {
-        if chars[i] == ch {
-            return Some(i);
-        }
-    }
-    None
+    chars[start..]
+        .iter()
+        .position(|&c| c == ch)
+        .map(|i| start + i)
 }
 
 /// Print token usage and estimated cost for a turn
 pub fn print_usage(prompt_tokens: usize, completion_tokens: usize) {
     let total = prompt_tokens + completion_tokens;
-    let cost = (prompt_tokens as f64 * 3.0 + completion_tokens as f64 * 15.0) / 1_000_000.0;
+    let cost = crate::backend::TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+    }
+    .estimated_cost();
 
     let total_str = if total >= 1000 {
         format!("{:.1}k", total as f64 / 1000.0)
@@ -291,7 +335,11 @@ pub fn print_usage(prompt_tokens: usize, completion_tokens: usize) {
 /// Print cumulative session cost summary
 pub fn print_session_cost(prompt_tokens: usize, completion_tokens: usize) {
     let total = prompt_tokens + completion_tokens;
-    let cost = (prompt_tokens as f64 * 3.0 + completion_tokens as f64 * 15.0) / 1_000_000.0;
+    let cost = crate::backend::TokenUsage {
+        prompt_tokens,
+        completion_tokens,
+    }
+    .estimated_cost();
 
     let total_str = if total >= 1000 {
         format!("{:.1}k", total as f64 / 1000.0)
@@ -309,7 +357,8 @@ pub fn print_session_cost(prompt_tokens: usize, completion_tokens: usize) {
     println!();
 }
 
-pub fn print_turn_indicator(turn: usize, max: usize) {
+/// `todo` — (done, total, current item) when the session has a todo list
+pub fn print_turn_indicator(turn: usize, max: usize, todo: Option<(usize, usize, &str)>) {
     // Color the turn number: green early, yellow mid, red near limit
     let color = if turn <= max / 3 {
         BRIGHT_GREEN
@@ -319,6 +368,10 @@ pub fn print_turn_indicator(turn: usize, max: usize) {
         RED
     };
     print!("  {DIM}[{RESET}{color}{BOLD}{turn}{RESET}{DIM}/{max}]{RESET} ");
+    if let Some((done, total, current)) = todo {
+        let current: String = current.chars().take(48).collect();
+        print!("{BRIGHT_CYAN}\u{2611} {done}/{total}{RESET} {DIM}{current}{RESET} ");
+    }
     io::stdout().flush().unwrap();
 }
 
@@ -326,8 +379,8 @@ pub fn print_turn_indicator(turn: usize, max: usize) {
 pub fn print_diff(path: &str, old: &str, new: &str) -> bool {
     let diff = similar::TextDiff::from_lines(old, new);
     let has_changes = diff.iter_all_changes().any(|c| c.tag() != ChangeTag::Equal);
-    if !has_changes {
-        return false;
+    if !has_changes || is_headless() {
+        return has_changes;
     }
     println!("    {DIM}--- {path}{RESET}");
     println!("    {DIM}+++ {path}{RESET}");
@@ -359,6 +412,9 @@ pub fn print_diff_collapsible(path: &str, old: &str, new: &str) -> bool {
     if adds == 0 && dels == 0 {
         return false;
     }
+    if is_headless() {
+        return true;
+    }
 
     // Show compact summary with expand hint
     print!(
@@ -441,14 +497,74 @@ pub fn prompt_input_default(label: &str, default: &str) -> String {
 
 /// Confirm yes/no (default yes)
 pub fn confirm(prompt: &str) -> bool {
+    if is_headless() {
+        return false;
+    }
     print!("  {BOLD}{prompt}{RESET} {DIM}[Y/n]{RESET} ");
     io::stdout().flush().unwrap();
     let mut input = String::new();
-    io::stdin().lock().read_line(&mut input).unwrap_or(0);
+    // Stdin at EOF (e.g. piped and already read) is a no, not an empty "yes"
+    if io::stdin().lock().read_line(&mut input).unwrap_or(0) == 0 {
+        println!();
+        return false;
+    }
     let input = input.trim().to_lowercase();
     input.is_empty() || input == "y" || input == "yes"
 }
 
+/// Renders streamed assistant tokens line by line with markdown formatting
+#[derive(Default)]
+pub struct StreamPrinter {
+    line_buffer: String,
+    in_code_block: bool,
+    started: bool,
+}
+
+impl StreamPrinter {
+    pub fn push(&mut self, token: &str) {
+        if token.is_empty() {
+            return;
+        }
+        if !self.started {
+            print!("\n  {BOLD}{BRIGHT_MAGENTA}\u{2726} ClifCode{RESET}  ");
+            self.started = true;
+        }
+        self.line_buffer.push_str(token);
+
+        // Process completed lines
+        while let Some(nl_pos) = self.line_buffer.find('\n') {
+            let completed_line: String = self.line_buffer[..nl_pos].to_string();
+            self.line_buffer = self.line_buffer[nl_pos + 1..].to_string();
+            self.print_line(&completed_line);
+        }
+        io::stdout().flush().unwrap();
+    }
+
+    fn print_line(&mut self, line: &str) {
+        // Track code block state
+        let fence = line.trim_start().starts_with("```");
+        if fence {
+            self.in_code_block = !self.in_code_block;
+        }
+        println!(
+            "{}",
+            render_streaming_line(line, self.in_code_block && !fence)
+        );
+    }
+
+    /// Flush the last partial line and end the message. No-op if nothing streamed.
+    pub fn finish(&mut self) {
+        if !self.line_buffer.is_empty() {
+            let rest = std::mem::take(&mut self.line_buffer);
+            self.print_line(&rest);
+        }
+        if self.started {
+            println!();
+        }
+        *self = StreamPrinter::default();
+    }
+}
+
 /// Render a single completed line during streaming with markdown formatting.
 /// Returns the ANSI-formatted string ready for printing.
 pub fn render_streaming_line(line: &str, in_code_block: bool) -> String {
@@ -468,16 +584,13 @@ pub fn render_streaming_line(line: &str, in_code_block: bool) -> String {
     }
 
     // Headers
-    if trimmed.starts_with("### ") {
-        let content = &trimmed[4..];
+    if let Some(content) = trimmed.strip_prefix("### ") {
         return format!("  {BOLD}{content}{RESET}");
     }
-    if trimmed.starts_with("## ") {
-        let content = &trimmed[3..];
+    if let Some(content) = trimmed.strip_prefix("## ") {
         return format!("  {BOLD}{content}{RESET}");
     }
-    if trimmed.starts_with("# ") {
-        let content = &trimmed[2..];
+    if let Some(content) = trimmed.strip_prefix("# ") {
         return format!("  {BOLD}{CYAN}{content}{RESET}");
     }
 
@@ -535,14 +648,10 @@ pub fn select_menu(title: &str, items: &[&str]) -> Option<usize> {
             }
             match key.code {
                 KeyCode::Up | KeyCode::Char('k') => {
-                    if selected > 0 {
-                        selected -= 1;
-                    }
+                    selected = selected.saturating_sub(1);
                 }
-                KeyCode::Down | KeyCode::Char('j') => {
-                    if selected < items.len() - 1 {
-                        selected += 1;
-                    }
+                KeyCode::Down | KeyCode::Char('j') if selected < items.len() - 1 => {
+                    selected += 1;
                 }
                 KeyCode::Enter => {
                     terminal::disable_raw_mode().ok();
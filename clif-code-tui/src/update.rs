@@ -1,6 +1,17 @@
 //! Auto-update: version check against GitHub releases and self-replace binary.
+//!
+//! Every download is verified before it replaces the running executable:
+//!   1. SHA-256 of the binary must match the `<asset>.sha256` release file
+//!   2. `<asset>.minisig` must be a valid minisign signature from the key
+//!      embedded at build time (`CLIFCODE_UPDATE_PUBKEY`)
+//!   3. the signed trusted comment (`clifcode v<version> <asset>`) must name the
+//!      release being installed, and that version must be newer than this one —
+//!      so an old, validly signed binary can't be replayed as a downgrade
+//!
+//! The replaced binary is kept in `~/.clifcode/rollback/` for `clifcode update --rollback`.
 
 use crate::ui;
+use sha2::{Digest, Sha256};
 use std::io::Read;
 use std::path::PathBuf;
 
@@ -8,6 +19,28 @@ const GITHUB_REPO: &str = "DLhugly/Clif-Code";
 const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const CHECK_INTERVAL_SECS: u64 = 86400; // 24 hours
 
+/// Minisign public key (base64) that release binaries are signed with.
+/// Set by the release workflow; builds without it refuse to self-update.
+const UPDATE_PUBLIC_KEY: Option<&str> = option_env!("CLIFCODE_UPDATE_PUBKEY");
+
+/// Release channel to follow
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Channel {
+    /// Full releases only
+    Stable,
+    /// Pre-releases (`clifcode-vX.Y.Z-beta.N`) as well
+    Beta,
+}
+
+impl std::fmt::Display for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Channel::Stable => write!(f, "stable"),
+            Channel::Beta => write!(f, "beta"),
+        }
+    }
+}
+
 fn cache_path() -> PathBuf {
     let home = std::env::var("HOME").unwrap_or_else(|_| ".".into());
     PathBuf::from(home)
@@ -22,17 +55,26 @@ fn now_secs() -> u64 {
         .as_secs()
 }
 
-fn load_cache() -> Option<(String, String, u64)> {
+fn load_cache(channel: Channel) -> Option<(String, String, u64)> {
     let text = std::fs::read_to_string(cache_path()).ok()?;
     let json: serde_json::Value = serde_json::from_str(&text).ok()?;
+    // Caches written before channels existed are stable-only
+    let cached_channel = json
+        .get("channel")
+        .and_then(|v| v.as_str())
+        .unwrap_or("stable");
+    if cached_channel != channel.to_string() {
+        return None;
+    }
     let version = json.get("latest_version")?.as_str()?.to_string();
     let url = json.get("download_url")?.as_str()?.to_string();
     let checked_at = json.get("checked_at")?.as_u64()?;
     Some((version, url, checked_at))
 }
 
-fn save_cache(version: &str, url: &str) {
+fn save_cache(channel: Channel, version: &str, url: &str) {
     let json = serde_json::json!({
+        "channel": channel.to_string(),
         "latest_version": version,
         "download_url": url,
         "checked_at": now_secs(),
@@ -45,14 +87,27 @@ fn save_cache(version: &str, url: &str) {
 }
 
 /// True if semver `a` is strictly newer than `b`.
+/// A pre-release (`1.2.0-beta.1`) sorts before its release (`1.2.0`).
 fn is_newer(a: &str, b: &str) -> bool {
-    let parse = |s: &str| -> Vec<u64> {
-        s.trim_start_matches('v')
-            .split('.')
-            .filter_map(|p| p.parse().ok())
-            .collect()
+    let parse = |s: &str| -> (Vec<u64>, Option<Vec<u64>>) {
+        let s = s.trim_start_matches('v');
+        let (core, pre) = match s.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (s, None),
+        };
+        let nums = |p: &str| -> Vec<u64> { p.split('.').filter_map(|n| n.parse().ok()).collect() };
+        (nums(core), pre.map(nums))
     };
-    parse(a) > parse(b)
+    let (a_core, a_pre) = parse(a);
+    let (b_core, b_pre) = parse(b);
+    if a_core != b_core {
+        return a_core > b_core;
+    }
+    match (a_pre, b_pre) {
+        (None, Some(_)) => true,
+        (Some(a), Some(b)) => a > b,
+        _ => false,
+    }
 }
 
 /// Map the current OS + arch to the GitHub release artifact name.
@@ -68,11 +123,11 @@ fn platform_binary() -> Option<&'static str> {
     }
 }
 
-/// Query GitHub releases API for the latest `clifcode-v*` release.
+/// Query GitHub releases API for the latest `clifcode-v*` release on `channel`.
 /// Returns `(version, download_url)` only when a newer version exists.
-pub fn check_for_update() -> Option<(String, String)> {
+pub fn check_for_update(channel: Channel) -> Option<(String, String)> {
     // Honour the 24-hour cache
-    if let Some((version, url, checked_at)) = load_cache() {
+    if let Some((version, url, checked_at)) = load_cache(channel) {
         if now_secs().saturating_sub(checked_at) < CHECK_INTERVAL_SECS {
             return if is_newer(&version, CURRENT_VERSION) {
                 Some((version, url))
@@ -100,6 +155,15 @@ pub fn check_for_update() -> Option<(String, String)> {
         }
         let version = tag.trim_start_matches("clifcode-v").to_string();
 
+        let prerelease = release
+            .get("prerelease")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+            || version.contains('-');
+        if prerelease && channel == Channel::Stable {
+            continue;
+        }
+
         let assets = release.get("assets").and_then(|v| v.as_array())?;
         for asset in assets {
             let name = asset.get("name").and_then(|v| v.as_str()).unwrap_or("");
@@ -108,7 +172,7 @@ pub fn check_for_update() -> Option<(String, String)> {
                     .get("browser_download_url")
                     .and_then(|v| v.as_str())?
                     .to_string();
-                save_cache(&version, &url);
+                save_cache(channel, &version, &url);
                 return if is_newer(&version, CURRENT_VERSION) {
                     Some((version, url))
                 } else {
@@ -123,10 +187,10 @@ pub fn check_for_update() -> Option<(String, String)> {
 
 /// Non-blocking background version check. Returns a receiver that will
 /// contain `(version, download_url)` if an update is available.
-pub fn check_in_background() -> std::sync::mpsc::Receiver<(String, String)> {
+pub fn check_in_background(channel: Channel) -> std::sync::mpsc::Receiver<(String, String)> {
     let (tx, rx) = std::sync::mpsc::channel();
     std::thread::spawn(move || {
-        if let Some(update) = check_for_update() {
+        if let Some(update) = check_for_update(channel) {
             let _ = tx.send(update);
         }
     });
@@ -147,9 +211,12 @@ pub fn print_update_notification(version: &str) {
     println!();
 }
 
-/// Download the release binary and replace the running executable.
-pub fn perform_update(url: &str, version: &str) -> Result<(), String> {
-    platform_binary().ok_or("Unsupported platform for auto-update")?;
+/// Download the release binary, verify checksum and signature, and replace the
+/// running executable. The previous binary is kept for `rollback`.
+pub fn perform_update(channel: Channel, url: &str, version: &str) -> Result<(), String> {
+    let binary_name = platform_binary().ok_or("Unsupported platform for auto-update")?;
+    let public_key = UPDATE_PUBLIC_KEY
+        .ok_or("This build has no update signing key — install a signed release to self-update")?;
 
     println!();
     println!(
@@ -158,30 +225,188 @@ pub fn perform_update(url: &str, version: &str) -> Result<(), String> {
         ui::RESET
     );
 
+    let bytes = download(url)?;
+    if bytes.is_empty() {
+        return Err("Downloaded empty file".into());
+    }
+
+    let size_kb = bytes.len() / 1024;
+    ui::print_dim(&format!("  ({size_kb} KB downloaded)"));
+
+    let checksum = String::from_utf8(download(&format!("{url}.sha256"))?)
+        .map_err(|_| "Checksum file is not text".to_string())?;
+    verify_checksum(&bytes, &checksum)?;
+    ui::print_dim("  (SHA-256 verified)");
+
+    let signature = String::from_utf8(download(&format!("{url}.minisig"))?)
+        .map_err(|_| "Signature file is not text".to_string())?;
+    verify_signature(&bytes, &signature, public_key, version, binary_name)?;
+    ui::print_dim("  (signature verified)");
+
+    let current_exe =
+        std::env::current_exe().map_err(|e| format!("Cannot locate current binary: {e}"))?;
+
+    // Keep the running binary so the update can be undone
+    let dir = rollback_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    std::fs::copy(&current_exe, rollback_binary())
+        .map_err(|e| format!("Cannot back up current binary: {e}"))?;
+    let meta = serde_json::json!({ "version": CURRENT_VERSION, "replaced_at": now_secs() });
+    let _ = std::fs::write(
+        dir.join("rollback.json"),
+        serde_json::to_string_pretty(&meta).unwrap_or_default(),
+    );
+
+    replace_current_exe(&current_exe, &bytes)?;
+
+    // Clear the cache so next startup doesn't show "update available"
+    save_cache(channel, version, url);
+
+    println!(
+        "  {}{}\u{2713} Updated to v{version}{}",
+        ui::BOLD,
+        ui::BRIGHT_GREEN,
+        ui::RESET
+    );
+    println!(
+        "  {}Restart ClifCode to use the new version. Undo with: clifcode update --rollback{}",
+        ui::DIM,
+        ui::RESET
+    );
+    println!();
+
+    Ok(())
+}
+
+/// Restore the binary replaced by the last update. The current binary takes its
+/// place in the rollback slot, so a second rollback re-applies the update.
+pub fn rollback() -> Result<(), String> {
+    let backup = rollback_binary();
+    let bytes = std::fs::read(&backup).map_err(|_| "No previous version to roll back to")?;
+    let previous = std::fs::read_to_string(rollback_dir().join("rollback.json"))
+        .ok()
+        .and_then(|t| serde_json::from_str::<serde_json::Value>(&t).ok())
+        .and_then(|v| v.get("version").and_then(|v| v.as_str()).map(String::from))
+        .unwrap_or_else(|| "previous".into());
+
+    let current_exe =
+        std::env::current_exe().map_err(|e| format!("Cannot locate current binary: {e}"))?;
+    std::fs::copy(&current_exe, &backup)
+        .map_err(|e| format!("Cannot back up current binary: {e}"))?;
+    let meta = serde_json::json!({ "version": CURRENT_VERSION, "replaced_at": now_secs() });
+    let _ = std::fs::write(
+        rollback_dir().join("rollback.json"),
+        serde_json::to_string_pretty(&meta).unwrap_or_default(),
+    );
+
+    replace_current_exe(&current_exe, &bytes)?;
+
+    ui::print_success(&format!("Rolled back to v{previous}"));
+    Ok(())
+}
+
+fn rollback_dir() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".into());
+    PathBuf::from(home).join(".clifcode").join("rollback")
+}
+
+fn rollback_binary() -> PathBuf {
+    rollback_dir().join(if cfg!(windows) {
+        "clifcode-previous.exe"
+    } else {
+        "clifcode-previous"
+    })
+}
+
+fn download(url: &str) -> Result<Vec<u8>, String> {
     let resp = ureq::get(url)
         .set("User-Agent", "clifcode-updater")
         .call()
-        .map_err(|e| format!("Download failed: {e}"))?;
+        .map_err(|e| format!("Download failed: {url}: {e}"))?;
 
     let mut bytes = Vec::new();
     resp.into_reader()
         .read_to_end(&mut bytes)
         .map_err(|e| format!("Read failed: {e}"))?;
+    Ok(bytes)
+}
 
-    if bytes.is_empty() {
-        return Err("Downloaded empty file".into());
+/// Compare against a `sha256sum`-style file (`<hex>  <name>`) or a bare hex digest.
+fn verify_checksum(bytes: &[u8], checksum_file: &str) -> Result<(), String> {
+    let expected = checksum_file
+        .split_whitespace()
+        .next()
+        .ok_or("Checksum file is empty")?
+        .to_lowercase();
+    let actual: String = Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "Checksum mismatch — expected {expected}, got {actual}. Update aborted."
+        ))
     }
+}
 
-    let size_kb = bytes.len() / 1024;
-    ui::print_dim(&format!("  ({size_kb} KB downloaded)"));
+/// Verify the minisign signature, then the version and asset bound in its
+/// trusted comment (which the global signature covers).
+fn verify_signature(
+    bytes: &[u8],
+    signature: &str,
+    public_key: &str,
+    version: &str,
+    asset: &str,
+) -> Result<(), String> {
+    let pk = minisign_verify::PublicKey::from_base64(public_key)
+        .map_err(|e| format!("Invalid embedded public key: {e}"))?;
+    let sig = minisign_verify::Signature::decode(signature)
+        .map_err(|e| format!("Invalid signature file: {e}"))?;
+    pk.verify(bytes, &sig, false)
+        .map_err(|e| format!("Signature verification failed: {e}. Update aborted."))?;
+    check_trusted_comment(sig.trusted_comment(), version, asset, CURRENT_VERSION)
+}
 
-    let current_exe =
-        std::env::current_exe().map_err(|e| format!("Cannot locate current binary: {e}"))?;
+/// The trusted comment must read `clifcode v<version> <asset>` for the release
+/// being installed, and that version must be newer than `current`.
+fn check_trusted_comment(
+    comment: &str,
+    version: &str,
+    asset: &str,
+    current: &str,
+) -> Result<(), String> {
+    let signed = comment
+        .trim()
+        .strip_prefix("clifcode v")
+        .and_then(|rest| rest.split_once(' '))
+        .filter(|(_, signed_asset)| *signed_asset == asset)
+        .map(|(signed_version, _)| signed_version)
+        .ok_or_else(|| {
+            format!(
+                "Signature does not cover {asset} (trusted comment: {comment:?}). Update aborted."
+            )
+        })?;
+    if signed != version.trim_start_matches('v') {
+        return Err(format!(
+            "Signature is for v{signed}, not v{version}. Update aborted."
+        ));
+    }
+    if !is_newer(signed, current) {
+        return Err(format!(
+            "Signed version v{signed} is not newer than v{current}. Update aborted."
+        ));
+    }
+    Ok(())
+}
 
+/// Swap `bytes` in as the executable at `current_exe`, restoring it on failure.
+fn replace_current_exe(current_exe: &std::path::Path, bytes: &[u8]) -> Result<(), String> {
     let temp_path = current_exe.with_extension("update");
     let backup_path = current_exe.with_extension("old");
 
-    std::fs::write(&temp_path, &bytes).map_err(|e| format!("Cannot write temp file: {e}"))?;
+    std::fs::write(&temp_path, bytes).map_err(|e| format!("Cannot write temp file: {e}"))?;
 
     #[cfg(unix)]
     {
@@ -191,35 +416,76 @@ pub fn perform_update(url: &str, version: &str) -> Result<(), String> {
 
     let _ = std::fs::remove_file(&backup_path);
 
-    std::fs::rename(&current_exe, &backup_path)
+    std::fs::rename(current_exe, &backup_path)
         .map_err(|e| format!("Cannot replace binary (try with sudo): {e}"))?;
 
-    std::fs::rename(&temp_path, &current_exe).map_err(|e| {
-        let _ = std::fs::rename(&backup_path, &current_exe);
+    std::fs::rename(&temp_path, current_exe).map_err(|e| {
+        let _ = std::fs::rename(&backup_path, current_exe);
         format!("Cannot install update: {e}")
     })?;
 
     let _ = std::fs::remove_file(&backup_path);
-
-    // Clear the cache so next startup doesn't show "update available"
-    save_cache(version, url);
-
-    println!(
-        "  {}{}\u{2713} Updated to v{version}{}",
-        ui::BOLD,
-        ui::BRIGHT_GREEN,
-        ui::RESET
-    );
-    println!(
-        "  {}Restart ClifCode to use the new version.{}",
-        ui::DIM,
-        ui::RESET
-    );
-    println!();
-
     Ok(())
 }
 
 pub fn current_version() -> &'static str {
     CURRENT_VERSION
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_versions_and_pre_releases() {
+        assert!(is_newer("1.10.0", "1.9.0"));
+        assert!(is_newer("v2.0.0", "1.99.99"));
+        assert!(is_newer("1.2.0", "1.2.0-beta.1"));
+        assert!(is_newer("1.2.0-beta.2", "1.2.0-beta.1"));
+        assert!(is_newer("1.2.0-beta.10", "1.2.0-beta.9"));
+        assert!(is_newer("1.2.0-beta.1", "1.1.9"));
+        assert!(!is_newer("1.2.0-beta.1", "1.2.0"));
+        assert!(!is_newer("1.2.0", "v1.2.0"));
+        assert!(!is_newer("1.1.0", "1.2.0-beta.1"));
+    }
+
+    #[test]
+    fn trusted_comment_must_name_a_newer_matching_release() {
+        let asset = "clifcode-x86_64-unknown-linux-gnu";
+        assert!(check_trusted_comment(
+            &format!("clifcode v1.3.0 {asset}"),
+            "1.3.0",
+            asset,
+            "1.2.0"
+        )
+        .is_ok());
+        // Comments from before versions were signed
+        assert!(
+            check_trusted_comment(&format!("clifcode {asset}"), "1.3.0", asset, "1.2.0").is_err()
+        );
+        // An older signed binary served under a newer release
+        assert!(check_trusted_comment(
+            &format!("clifcode v1.1.0 {asset}"),
+            "1.3.0",
+            asset,
+            "1.2.0"
+        )
+        .is_err());
+        // A genuine but older release
+        assert!(check_trusted_comment(
+            &format!("clifcode v1.1.0 {asset}"),
+            "1.1.0",
+            asset,
+            "1.2.0"
+        )
+        .is_err());
+        // Another platform's binary
+        assert!(check_trusted_comment(
+            "clifcode v1.3.0 clifcode-aarch64-apple-darwin",
+            "1.3.0",
+            asset,
+            "1.2.0"
+        )
+        .is_err());
+    }
+}
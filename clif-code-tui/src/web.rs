@@ -0,0 +1,721 @@
+//! Web access for the agent — `fetch_url` and `docs` tools.
+//!
+//! Pages are downloaded with a size cap, converted from HTML to Markdown with
+//! navigation chrome stripped, and cached under `~/.clifcode/web_cache/`.
+//! Only domains on the configured allowlist can be fetched.
+
+use crate::config;
+use crate::tools::ToolResult;
+use crate::ui;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Hard cap on bytes read from the network per page
+const MAX_DOWNLOAD: u64 = 2 * 1024 * 1024;
+/// Chars returned per call — same paging scheme as read_file
+const FETCH_CHUNK: usize = 16000;
+/// Cached pages older than this are fetched again
+const CACHE_TTL_SECS: u64 = 3600;
+const MAX_REDIRECTS: usize = 5;
+
+fn cache_dir() -> PathBuf {
+    config::config_dir().join("web_cache")
+}
+
+/// Host part of an http(s) URL, lowercased, without port or userinfo.
+fn url_host(url: &str) -> Option<String> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.split(':').next()?;
+    if host.is_empty() {
+        None
+    } else {
+        Some(host.to_lowercase())
+    }
+}
+
+/// True if `host` is an allowlisted domain or a subdomain of one. `*` allows everything.
+fn host_allowed(host: &str, allowlist: &[String]) -> bool {
+    allowlist.iter().any(|d| {
+        let d = d.trim().trim_start_matches("*.").to_lowercase();
+        d == "*" || host == d || host.ends_with(&format!(".{d}"))
+    })
+}
+
+/// Resolve a redirect `Location` against the URL it came from.
+fn resolve_location(base: &str, location: &str) -> String {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        return location.to_string();
+    }
+    let scheme_end = base.find("://").map(|i| i + 3).unwrap_or(0);
+    let origin_end = base[scheme_end..]
+        .find('/')
+        .map(|i| scheme_end + i)
+        .unwrap_or(base.len());
+    if location.starts_with('/') {
+        format!("{}{location}", &base[..origin_end])
+    } else {
+        let dir_end = base
+            .rfind('/')
+            .filter(|&i| i >= origin_end)
+            .unwrap_or(origin_end);
+        format!("{}/{location}", &base[..dir_end])
+    }
+}
+
+/// Download a page, following redirects only to allowlisted hosts.
+/// Returns `(final_url, body, is_html)`.
+fn download(url: &str, allowlist: &[String]) -> Result<(String, String, bool), String> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(std::time::Duration::from_secs(20))
+        .redirects(0)
+        .build();
+
+    let mut current = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let host = url_host(&current).ok_or_else(|| format!("Not an http(s) URL: {current}"))?;
+        if !host_allowed(&host, allowlist) {
+            return Err(format!(
+                "Domain {host} is not on the fetch allowlist (config key `fetch_allowlist` in ~/.clifcode/config.json)"
+            ));
+        }
+
+        let resp = match agent
+            .get(&current)
+            .set("User-Agent", "clifcode-fetch")
+            .call()
+        {
+            Ok(r) => r,
+            Err(ureq::Error::Status(code, _)) => {
+                return Err(format!("HTTP {code} fetching {current}"));
+            }
+            Err(e) => return Err(format!("Fetch failed: {current}: {e}")),
+        };
+
+        if (300..400).contains(&resp.status()) {
+            let location = resp
+                .header("Location")
+                .ok_or_else(|| format!("Redirect without Location from {current}"))?;
+            current = resolve_location(&current, location);
+            continue;
+        }
+
+        let is_html = resp.content_type().contains("html");
+        let mut bytes = Vec::new();
+        resp.into_reader()
+            .take(MAX_DOWNLOAD + 1)
+            .read_to_end(&mut bytes)
+            .map_err(|e| format!("Read failed: {e}"))?;
+        if bytes.len() as u64 > MAX_DOWNLOAD {
+            return Err(format!(
+                "Page exceeds the {} KB download limit",
+                MAX_DOWNLOAD / 1024
+            ));
+        }
+        let body = String::from_utf8_lossy(&bytes).to_string();
+        return Ok((current, body, is_html));
+    }
+
+    Err(format!("Too many redirects fetching {url}"))
+}
+
+fn cache_path(url: &str) -> PathBuf {
+    let key: String = Sha256::digest(url.as_bytes())
+        .iter()
+        .take(16)
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    cache_dir().join(format!("{key}.md"))
+}
+
+fn load_cached(url: &str) -> Option<String> {
+    let path = cache_path(url);
+    let age = std::fs::metadata(&path)
+        .ok()?
+        .modified()
+        .ok()?
+        .elapsed()
+        .ok()?
+        .as_secs();
+    if age > CACHE_TTL_SECS {
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+fn save_cached(url: &str, markdown: &str) {
+    let _ = std::fs::create_dir_all(cache_dir());
+    let _ = std::fs::write(cache_path(url), markdown);
+}
+
+/// Fetch `url` as Markdown (cached), returning the page text.
+fn fetch_markdown(url: &str, allowlist: &[String]) -> Result<String, String> {
+    // Check before the cache so narrowing the allowlist takes effect immediately
+    let host = url_host(url).ok_or_else(|| format!("Not an http(s) URL: {url}"))?;
+    if !host_allowed(&host, allowlist) {
+        return Err(format!(
+            "Domain {host} is not on the fetch allowlist (config key `fetch_allowlist` in ~/.clifcode/config.json)"
+        ));
+    }
+    if let Some(cached) = load_cached(url) {
+        ui::print_dim("    (cached)");
+        return Ok(cached);
+    }
+    let (final_url, body, is_html) = download(url, allowlist)?;
+    let markdown = if is_html {
+        html_to_markdown(&body)
+    } else {
+        body
+    };
+    let markdown = if final_url != url {
+        format!("Source: {final_url}\n\n{markdown}")
+    } else {
+        markdown
+    };
+    save_cached(url, &markdown);
+    Ok(markdown)
+}
+
+/// Slice a long page into READ_CHUNK-style pages with a continuation hint.
+fn paginate(text: &str, offset: usize, tool: &str) -> String {
+    let total = text.chars().count();
+    let mut output: String = text.chars().skip(offset).take(FETCH_CHUNK).collect();
+    let end = offset + output.chars().count();
+    if end < total {
+        output.push_str(&format!(
+            "\n\n[{} more chars remaining — call {tool} with offset={end} to continue]",
+            total - end
+        ));
+    }
+    output
+}
+
+/// `fetch_url` tool. `confirm` — ask Y/n before each fetch (suggest mode).
+pub fn exec_fetch_url(url: &str, offset: Option<usize>, confirm: bool) -> ToolResult {
+    ui::print_tool_action("fetch", url);
+
+    if confirm && !ui::confirm("Fetch this URL?") {
+        return ToolResult {
+            success: false,
+            output: "User declined the fetch".into(),
+        };
+    }
+
+    match fetch_markdown(url, &config::fetch_allowlist()) {
+        Ok(markdown) => {
+            ui::print_dim(&format!("    {} chars", markdown.len()));
+            ToolResult {
+                success: true,
+                output: paginate(&markdown, offset.unwrap_or(0), "fetch_url"),
+            }
+        }
+        Err(e) => {
+            ui::print_error(&format!("    {e}"));
+            ToolResult {
+                success: false,
+                output: format!("Error: {e}"),
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Crate documentation lookup
+// ---------------------------------------------------------------------------
+
+/// `docs` tool — look up a crate (and optionally an item) in the workspace's
+/// `cargo doc` output first, then the configured docs.rs mirror.
+pub fn exec_docs(
+    workspace: &str,
+    crate_name: &str,
+    item: Option<&str>,
+    offset: Option<usize>,
+    confirm: bool,
+) -> ToolResult {
+    let label = match item {
+        Some(item) => format!("{crate_name}::{item}"),
+        None => crate_name.to_string(),
+    };
+    ui::print_tool_action("docs", &label);
+
+    // rustdoc directory names use underscores
+    let dir_name = crate_name.replace('-', "_");
+    let local_root = Path::new(workspace)
+        .join("target")
+        .join("doc")
+        .join(&dir_name);
+    if local_root.is_dir() {
+        if let Some(page) = find_doc_page(&local_root, item) {
+            if let Ok(html) = std::fs::read_to_string(&page) {
+                ui::print_dim(&format!("    (local: {})", page.display()));
+                let markdown = html_to_markdown(&html);
+                return ToolResult {
+                    success: true,
+                    output: paginate(&markdown, offset.unwrap_or(0), "docs"),
+                };
+            }
+        }
+    }
+
+    let mirror = config::docs_mirror();
+    let url = match item {
+        Some(item) => format!(
+            "{}/{crate_name}/latest/{dir_name}/?search={item}",
+            mirror.trim_end_matches('/')
+        ),
+        None => format!(
+            "{}/{crate_name}/latest/{dir_name}/",
+            mirror.trim_end_matches('/')
+        ),
+    };
+
+    if confirm && !ui::confirm(&format!("Fetch docs from {url}?")) {
+        return ToolResult {
+            success: false,
+            output: "User declined the fetch".into(),
+        };
+    }
+
+    // The configured mirror is trusted even if it isn't on the fetch allowlist
+    let mut allowlist = config::fetch_allowlist();
+    if let Some(host) = url_host(&mirror) {
+        allowlist.push(host);
+    }
+
+    match fetch_markdown(&url, &allowlist) {
+        Ok(markdown) => ToolResult {
+            success: true,
+            output: paginate(&markdown, offset.unwrap_or(0), "docs"),
+        },
+        Err(e) => {
+            ui::print_error(&format!("    {e}"));
+            ToolResult {
+                success: false,
+                output: format!(
+                    "Error: no local docs in target/doc/{dir_name} and mirror lookup failed: {e}. \
+                     Run `cargo doc` to build local docs."
+                ),
+            }
+        }
+    }
+}
+
+/// Find the rustdoc page for `item` (e.g. `HashMap`, `fs::read`) under a crate's
+/// doc directory, or the crate index when no item is given.
+fn find_doc_page(root: &Path, item: Option<&str>) -> Option<PathBuf> {
+    let item = match item {
+        Some(item) => item,
+        None => return Some(root.join("index.html")).filter(|p| p.is_file()),
+    };
+
+    let mut segments: Vec<&str> = item.split("::").filter(|s| !s.is_empty()).collect();
+    let name = segments.pop()?;
+    let dir = segments.iter().fold(root.to_path_buf(), |d, s| d.join(s));
+
+    // Module pages are `<mod>/index.html`, items are `<kind>.<Name>.html`
+    let module_index = dir.join(name).join("index.html");
+    if module_index.is_file() {
+        return Some(module_index);
+    }
+
+    let mut stack = vec![dir];
+    while let Some(d) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&d) else {
+            continue;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if path.is_dir() {
+                stack.push(path);
+                continue;
+            }
+            let file = entry.file_name().to_string_lossy().to_string();
+            if let Some((_, rest)) = file.split_once('.') {
+                if rest == format!("{name}.html") {
+                    return Some(path);
+                }
+            }
+        }
+    }
+    None
+}
+
+// ---------------------------------------------------------------------------
+// HTML → Markdown
+// ---------------------------------------------------------------------------
+
+/// Elements whose whole subtree is dropped — scripts and page chrome
+const SKIP_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "nav", "header", "footer", "aside", "svg", "form", "iframe",
+    "button", "template", "head",
+];
+
+/// Convert HTML to readable Markdown. Keeps headings, paragraphs, lists, links,
+/// code and pre blocks; drops navigation, scripts and styling. Uses the `<main>`
+/// or `<article>` element as the root when the page has one.
+pub fn html_to_markdown(html: &str) -> String {
+    let body = ["<main", "<article"]
+        .iter()
+        .find_map(|tag| {
+            let start = find_ascii_ci(html, tag)?;
+            let close = format!("</{}>", &tag[1..]);
+            let end = rfind_ascii_ci(html, &close)?;
+            (end > start).then(|| &html[start..end])
+        })
+        .unwrap_or(html);
+
+    let mut out = String::new();
+    let mut skip_depth = 0usize;
+    let mut skip_tag = String::new();
+    let mut in_pre = false;
+    let mut link_href: Option<String> = None;
+    let mut link_text_start: Option<usize> = None;
+    let mut list_depth = 0usize;
+
+    let mut rest = body;
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            if skip_depth == 0 {
+                push_text(&mut out, rest, in_pre);
+            }
+            break;
+        };
+        if skip_depth == 0 {
+            push_text(&mut out, &rest[..lt], in_pre);
+        }
+        rest = &rest[lt..];
+
+        // Comments
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|i| &rest[i + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let Some(gt) = rest.find('>') else {
+            break;
+        };
+        let tag_src = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        let closing = tag_src.starts_with('/');
+        let tag_body = tag_src.trim_start_matches('/');
+        let name: String = tag_body
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        if name.is_empty() {
+            continue;
+        }
+
+        // Skipped subtrees: track nesting of the same element only
+        if skip_depth > 0 {
+            if name == skip_tag {
+                if closing {
+                    skip_depth -= 1;
+                } else if !tag_src.ends_with('/') {
+                    skip_depth += 1;
+                }
+            }
+            continue;
+        }
+        if !closing && SKIP_ELEMENTS.contains(&name.as_str()) {
+            if !tag_src.ends_with('/') {
+                skip_depth = 1;
+                skip_tag = name;
+            }
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                ensure_blank_line(&mut out);
+                out.push_str(&"#".repeat(level));
+                out.push(' ');
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => ensure_blank_line(&mut out),
+            ("p" | "div" | "section" | "table" | "dl" | "blockquote", _) => {
+                ensure_blank_line(&mut out)
+            }
+            ("br", _) => out.push('\n'),
+            ("tr" | "dt" | "dd", false) => ensure_newline(&mut out),
+            ("td" | "th", false) => out.push_str(" | "),
+            ("ul" | "ol", false) => {
+                list_depth += 1;
+                ensure_newline(&mut out);
+            }
+            ("ul" | "ol", true) => {
+                list_depth = list_depth.saturating_sub(1);
+                ensure_newline(&mut out);
+            }
+            ("li", false) => {
+                ensure_newline(&mut out);
+                out.push_str(&"  ".repeat(list_depth.saturating_sub(1)));
+                out.push_str("- ");
+            }
+            ("pre", false) => {
+                ensure_blank_line(&mut out);
+                out.push_str("```\n");
+                in_pre = true;
+            }
+            ("pre", true) => {
+                ensure_newline(&mut out);
+                out.push_str("```\n\n");
+                in_pre = false;
+            }
+            ("code", _) if !in_pre => out.push('`'),
+            ("strong" | "b", _) => out.push_str("**"),
+            ("em" | "i", _) => out.push('_'),
+            ("a", false) => {
+                link_href = attr_value(tag_body, "href");
+                link_text_start = Some(out.len());
+                out.push('[');
+            }
+            ("a", true) => {
+                // A stray `</a>` with no matching opener is dropped
+                let Some(start) = link_text_start.take() else {
+                    continue;
+                };
+                let text = out[start + 1..].trim().to_string();
+                match link_href.take() {
+                    Some(href) if !text.is_empty() && !href.starts_with('#') => {
+                        out.truncate(start);
+                        out.push_str(&format!("[{text}]({href})"));
+                    }
+                    _ => {
+                        out.truncate(start);
+                        out.push_str(&text);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Collapse runs of blank lines
+    let mut result = String::new();
+    let mut blank = 0;
+    for line in out.lines() {
+        if line.trim().is_empty() {
+            blank += 1;
+            if blank > 1 {
+                continue;
+            }
+        } else {
+            blank = 0;
+        }
+        result.push_str(line.trim_end());
+        result.push('\n');
+    }
+    result.trim().to_string()
+}
+
+fn push_text(out: &mut String, raw: &str, in_pre: bool) {
+    let text = decode_entities(raw);
+    if in_pre {
+        out.push_str(&text);
+        return;
+    }
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if collapsed.is_empty() {
+        if !text.is_empty() && !out.ends_with([' ', '\n']) {
+            out.push(' ');
+        }
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !out.ends_with([' ', '\n', '[']) {
+        out.push(' ');
+    }
+    out.push_str(&collapsed);
+    if text.ends_with(char::is_whitespace) {
+        out.push(' ');
+    }
+}
+
+fn ensure_newline(out: &mut String) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+fn ensure_blank_line(out: &mut String) {
+    ensure_newline(out);
+    if !out.is_empty() && !out.ends_with("\n\n") {
+        out.push('\n');
+    }
+}
+
+/// Byte offset of the first ASCII-case-insensitive match of `needle` (which
+/// must be ASCII). Works on the original bytes so the offset is valid for
+/// slicing `haystack`, unlike offsets taken from `to_lowercase()`.
+fn find_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    let (hay, pat) = (haystack.as_bytes(), needle.as_bytes());
+    (0..=hay.len().checked_sub(pat.len())?)
+        .find(|&i| hay[i..i + pat.len()].eq_ignore_ascii_case(pat))
+}
+
+/// Like [`find_ascii_ci`] but returns the last match.
+fn rfind_ascii_ci(haystack: &str, needle: &str) -> Option<usize> {
+    let (hay, pat) = (haystack.as_bytes(), needle.as_bytes());
+    (0..=hay.len().checked_sub(pat.len())?)
+        .rev()
+        .find(|&i| hay[i..i + pat.len()].eq_ignore_ascii_case(pat))
+}
+
+fn attr_value(tag_body: &str, attr: &str) -> Option<String> {
+    let pos = find_ascii_ci(tag_body, &format!("{attr}="))?;
+    let rest = &tag_body[pos + attr.len() + 1..];
+    let value = match rest.chars().next()? {
+        q @ ('"' | '\'') => rest[1..].split(q).next()?,
+        _ => rest.split(|c: char| c.is_whitespace() || c == '>').next()?,
+    };
+    Some(decode_entities(value))
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest[..rest.len().min(10)].find(';') else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semi];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|h| u32::from_str_radix(h, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::net::TcpListener;
+
+    /// Serve one canned HTTP response per queued body on a local port.
+    fn serve(responses: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for resp in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(resp.as_bytes());
+            }
+        });
+        format!("http://127.0.0.1:{}", addr.port())
+    }
+
+    fn html_response(body: &str) -> String {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    #[test]
+    fn converts_html_and_strips_navigation() {
+        let html = r#"<html><head><title>x</title><script>var a = 1;</script></head>
+            <body><nav><a href="/home">Home</a></nav>
+            <main><h1>Title</h1><p>Some <b>bold</b> &amp; <a href="https://x.dev/y">a link</a>.</p>
+            <ul><li>one</li><li>two</li></ul><pre><code>fn main() {}
+</code></pre></main><footer>footer text</footer></body></html>"#;
+        let md = html_to_markdown(html);
+        assert!(md.starts_with("# Title"));
+        assert!(md.contains("Some **bold** & [a link](https://x.dev/y)."));
+        assert!(md.contains("- one\n- two"));
+        assert!(md.contains("```\nfn main() {}\n```"));
+        assert!(!md.contains("Home"));
+        assert!(!md.contains("footer text"));
+        assert!(!md.contains("var a"));
+    }
+
+    #[test]
+    fn non_ascii_and_malformed_html_do_not_panic() {
+        // 'İ' lowercases to a longer byte sequence, shifting offsets
+        // computed on a lowercased copy
+        let html = "<p>İİİİ</p><MAIN><p>Straße <A HREF=\"/x\">link</A></p></MAIN>";
+        assert_eq!(html_to_markdown(html), "Straße [link](/x)");
+
+        let md = html_to_markdown("</a>text</a><a href=\"/y\">ok</a><a");
+        assert_eq!(md, "text[ok](/y)");
+        assert_eq!(html_to_markdown("<p>ẞ<a"), "ẞ");
+        assert_eq!(
+            attr_value("a data-İ=1 HREF='/z'", "href").as_deref(),
+            Some("/z")
+        );
+    }
+
+    #[test]
+    fn allowlist_matches_subdomains() {
+        let list = vec!["docs.rs".to_string(), "example.com".to_string()];
+        assert!(host_allowed("docs.rs", &list));
+        assert!(host_allowed("api.example.com", &list));
+        assert!(!host_allowed("evil-example.com", &list));
+        assert!(host_allowed("anything.org", &["*".to_string()]));
+        assert_eq!(
+            url_host("https://user@Docs.rs:443/x?y").as_deref(),
+            Some("docs.rs")
+        );
+    }
+
+    #[test]
+    fn downloads_from_local_server_and_enforces_allowlist() {
+        let base = serve(vec![html_response("<p>hello &lt;world&gt;</p>")]);
+        let allow = vec!["127.0.0.1".to_string()];
+        let (_, body, is_html) = download(&format!("{base}/page"), &allow).unwrap();
+        assert!(is_html);
+        assert_eq!(html_to_markdown(&body), "hello <world>");
+
+        let err = download(&format!("{base}/page"), &["docs.rs".to_string()]).unwrap_err();
+        assert!(err.contains("not on the fetch allowlist"));
+    }
+
+    #[test]
+    fn redirect_to_disallowed_host_is_refused() {
+        let base = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: https://evil.test/x\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        ]);
+        let err = download(&format!("{base}/r"), &["127.0.0.1".to_string()]).unwrap_err();
+        assert!(err.contains("evil.test"));
+    }
+}
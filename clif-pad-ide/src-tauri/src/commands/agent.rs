@@ -4,9 +4,17 @@ use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tauri::{Emitter, Manager};
+use tracing::Instrument;
 use uuid::Uuid;
 
+use crate::commands::budget::{Budget, Usage};
+use crate::commands::checkpoints;
+use crate::commands::conversations::{self, Recorder};
+use crate::commands::edit_review::{self, ProposedEdit};
 use crate::commands::git::get_git_context;
+use crate::commands::lsp;
+use crate::commands::mcp;
+use crate::services::ai_provider::{self, EventStream, StreamEvent};
 
 static AGENT_SESSIONS: std::sync::LazyLock<Arc<Mutex<HashMap<String, tokio::sync::oneshot::Sender<()>>>>> =
     std::sync::LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
@@ -23,13 +31,13 @@ static SESSION_TO_CONV: std::sync::LazyLock<Arc<Mutex<HashMap<String, String>>>>
     std::sync::LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
 
 // Files read during a conversation: conversation_id -> canonical paths.
-// Lives across user turns so `edit_file` does not lose its read-before-edit
+// Lives across user turns so the edit tools do not lose their read-before-edit
 // invariant when the user sends a follow-up message.
 static CONV_READ_FILES: std::sync::LazyLock<Arc<Mutex<HashMap<String, HashSet<PathBuf>>>>> =
     std::sync::LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct TodoItem {
+pub struct TodoItem {
     id: String,
     content: String,
     status: String,
@@ -72,6 +80,11 @@ fn scoped_conversation_id(window_label: &str, conversation_id: Option<&str>) ->
 /// entries to make room rather than letting the maps grow forever.
 const MAX_TRACKED_CONVERSATIONS: usize = 256;
 
+/// How long get_diagnostics waits for a server that may be starting up
+const DIAGNOSTICS_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+/// How long a file edit result waits for diagnostics from a running server
+const POST_EDIT_DIAGNOSTICS_WAIT: std::time::Duration = std::time::Duration::from_secs(3);
+
 /// Make room in a conversation-keyed map without reaching for an LRU
 /// crate. Picks an arbitrary entry whose key is NOT the one we're about
 /// to insert and removes it. This is "good enough" eviction for state
@@ -189,19 +202,6 @@ fn is_valid_todo_status(status: &str) -> bool {
     matches!(status, "pending" | "in_progress" | "completed" | "cancelled")
 }
 
-/// Strip OpenAI-only fields that cause 400 errors on other providers
-fn strip_openai_fields(mut tools: Vec<serde_json::Value>) -> Vec<serde_json::Value> {
-    for tool in &mut tools {
-        if let Some(func) = tool.get_mut("function").and_then(|f| f.as_object_mut()) {
-            func.remove("strict");
-            if let Some(params) = func.get_mut("parameters").and_then(|p| p.as_object_mut()) {
-                params.remove("additionalProperties");
-            }
-        }
-    }
-    tools
-}
-
 /// Tool definitions for OpenAI function-calling format
 fn tool_definitions() -> Vec<serde_json::Value> {
     vec![
@@ -259,6 +259,73 @@ fn tool_definitions() -> Vec<serde_json::Value> {
                 }
             }
         }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "multi_edit",
+                "description": "Apply several old_string → new_string replacements to one file in order, atomically: if any edit fails to match, nothing is written. Read the file first. Use this instead of repeated edit_file calls on the same file. The result contains a unified diff of the change.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "Path to the file to edit. Prefer paths inside the current workspace." },
+                        "edits": {
+                            "type": "array",
+                            "description": "Edits applied in order; each sees the result of the ones before it.",
+                            "items": {
+                                "type": "object",
+                                "additionalProperties": false,
+                                "properties": {
+                                    "old_string": { "type": "string", "description": "Exact text to find and replace." },
+                                    "new_string": { "type": "string", "description": "Replacement text." },
+                                    "replace_all": { "type": ["boolean", "null"], "description": "Set true to replace every match. Defaults to false." }
+                                },
+                                "required": ["old_string", "new_string"]
+                            }
+                        }
+                    },
+                    "required": ["path", "edits"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "insert_at_line",
+                "description": "Insert lines before a 1-based line number, using the numbering from your latest read_file. Use one past the last line to append. Needs no unique match, so it works in repetitive code. The result contains a unified diff of the change.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "Path to the file to edit. Prefer paths inside the current workspace." },
+                        "line": { "type": "integer", "description": "1-based line the new text is inserted before." },
+                        "content": { "type": "string", "description": "Lines to insert." }
+                    },
+                    "required": ["path", "line", "content"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "replace_lines",
+                "description": "Replace an inclusive range of 1-based lines with new content, using the numbering from your latest read_file. Pass empty content to delete the lines. Needs no unique match, so it works in repetitive code. The result contains a unified diff of the change.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "Path to the file to edit. Prefer paths inside the current workspace." },
+                        "start_line": { "type": "integer", "description": "First line to replace (1-based)." },
+                        "end_line": { "type": "integer", "description": "Last line to replace (1-based, inclusive)." },
+                        "content": { "type": "string", "description": "Replacement lines. Empty deletes the range." }
+                    },
+                    "required": ["path", "start_line", "end_line", "content"]
+                }
+            }
+        }),
         json!({
             "type": "function",
             "function": {
@@ -343,6 +410,77 @@ fn tool_definitions() -> Vec<serde_json::Value> {
                 }
             }
         }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "find_references",
+                "description": "List every reference to a symbol using the workspace's language server. Point at one occurrence of the symbol by file, line and name. More precise than `search` for renames and impact analysis.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "File containing an occurrence of the symbol." },
+                        "line": { "type": "integer", "description": "1-based line of that occurrence." },
+                        "symbol": { "type": "string", "description": "The symbol name as it appears on that line." }
+                    },
+                    "required": ["path", "line", "symbol"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "goto_definition",
+                "description": "Resolve where a symbol used in a file is defined, using the workspace's language server. Follows imports and type information, unlike `find_symbol`.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "File containing a use of the symbol." },
+                        "line": { "type": "integer", "description": "1-based line of that use." },
+                        "symbol": { "type": "string", "description": "The symbol name as it appears on that line." }
+                    },
+                    "required": ["path", "line", "symbol"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "rename_symbol",
+                "description": "Rename a symbol across the workspace with the language server and apply the edits to every affected file. Prefer this over repeated edit_file calls for renames.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "File containing an occurrence of the symbol." },
+                        "line": { "type": "integer", "description": "1-based line of that occurrence." },
+                        "symbol": { "type": "string", "description": "The current symbol name as it appears on that line." },
+                        "new_name": { "type": "string", "description": "The new name." }
+                    },
+                    "required": ["path", "line", "symbol", "new_name"]
+                }
+            }
+        }),
+        json!({
+            "type": "function",
+            "function": {
+                "name": "get_diagnostics",
+                "description": "Get the language server's current errors and warnings for a file. Starts the server if needed, so the first call may take a few seconds.",
+                "strict": true,
+                "parameters": {
+                    "type": "object",
+                    "additionalProperties": false,
+                    "properties": {
+                        "path": { "type": "string", "description": "File to check." }
+                    },
+                    "required": ["path"]
+                }
+            }
+        }),
         json!({
             "type": "function",
             "function": {
@@ -422,8 +560,10 @@ fn build_system_prompt(workspace_dir: &str) -> String {
          - Read relevant files before editing them.\n\
          - Prefer list_files, find_file, and search for exploration before using run_command.\n\
          - When you know the name of a function, class, struct, interface, type, enum, or constant, call find_symbol instead of search — it returns ranked file:line definitions from the local index in one hop.\n\
+         - Use goto_definition, find_references and rename_symbol when you need exact, type-aware answers; file edit results include the language server's diagnostics for the file when one is running.\n\
          - For multi-step or ambiguous tasks, call todo_write to plan the work up front and todo_read to recheck progress; skip it for one-shot edits.\n\
          - Use edit_file for small targeted changes and write_file only for new files or full rewrites.\n\
+         - When old_string would be ambiguous or one file needs several changes, use multi_edit, or insert_at_line / replace_lines with line numbers from your latest read_file.\n\
          - After meaningful code changes, run verification commands when feasible.\n\
          - If a tool returns an error, fix the arguments or approach and retry deliberately.\n\
          - Do not call submit until the user's request is complete or you are clearly blocked.\n\
@@ -475,7 +615,7 @@ fn build_runtime_context(workspace_dir: &str, context: Option<&str>) -> String {
     runtime.push_str("## Interaction Mode\n\n");
     runtime.push_str(&format!("Current mode: {}\n", mode.as_str()));
     if mode == AgentMode::Ask {
-        runtime.push_str("Constraint: Ask mode is read-only. Do not call edit_file, write_file, or run_command.\n");
+        runtime.push_str("Constraint: Ask mode is read-only. Do not call edit_file, multi_edit, insert_at_line, replace_lines, rename_symbol, write_file, or run_command.\n");
     } else if mode == AgentMode::Plan {
         runtime.push_str("Constraint: Plan mode is read-only. Explore and produce a plan without editing files or running commands.\n");
     }
@@ -596,6 +736,179 @@ fn mode_from_context(context: Option<&str>) -> AgentMode {
     }
 }
 
+/// Whether the frontend asked for file edits to be reviewed before writing
+fn review_edits_from_context(context: Option<&str>) -> bool {
+    context
+        .and_then(|ctx| serde_json::from_str::<serde_json::Value>(ctx).ok())
+        .and_then(|v| v.get("reviewEdits").and_then(|r| r.as_bool()))
+        .unwrap_or(false)
+}
+
+/// Write a file, or stage it for the review queue when the session has
+/// review mode on
+async fn write_or_stage(
+    session_id: Option<&str>,
+    path: &str,
+    full_path: &Path,
+    content: &str,
+) -> std::io::Result<()> {
+    if let Some(sid) = session_id.filter(|sid| edit_review::is_reviewing(sid)) {
+        let original = tokio::fs::read_to_string(full_path).await.ok();
+        edit_review::stage(
+            sid,
+            ProposedEdit {
+                path: path.to_string(),
+                full_path: full_path.to_path_buf(),
+                original,
+                proposed: content.to_string(),
+            },
+        );
+        return Ok(());
+    }
+    checkpoints::record(session_id, full_path);
+    if let Some(parent) = full_path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(full_path, content).await
+}
+
+/// The text in `content` that `old_string` refers to: the string itself when
+/// it appears verbatim, otherwise a block of lines equal to it up to
+/// whitespace.
+fn locate_old_string(content: &str, old_string: &str) -> Option<String> {
+    if content.contains(old_string) {
+        return Some(old_string.to_string());
+    }
+
+    // Fallback: try whitespace-agnostic matching
+    let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+    let norm_old = normalize(old_string);
+
+    // Simple sliding window over lines to find a matching block
+    let lines: Vec<&str> = content.lines().collect();
+    let old_lines_count = old_string.lines().count().max(1);
+
+    for i in 0..=lines.len().saturating_sub(old_lines_count) {
+        for j in i + 1..=lines.len().min(i + old_lines_count * 2) {
+            let block = lines[i..j].join("\n");
+            if normalize(&block) == norm_old {
+                return Some(block);
+            }
+        }
+    }
+    None
+}
+
+/// Replace `remove` lines starting at 0-based line `start` with `insert`.
+/// Inserted text takes the file's line ending and always ends its last line,
+/// so it never runs into the line after it; a file without a trailing
+/// newline keeps lacking one.
+fn splice_lines(original: &str, start: usize, remove: usize, insert: &str) -> String {
+    let lines: Vec<&str> = original.split_inclusive('\n').collect();
+    let eol = if original.contains("\r\n") { "\r\n" } else { "\n" };
+    let at_end = start + remove >= lines.len();
+
+    let mut out = String::with_capacity(original.len() + insert.len());
+    for line in &lines[..start] {
+        out.push_str(line);
+    }
+    if !insert.is_empty() {
+        if !out.is_empty() && !out.ends_with('\n') {
+            out.push_str(eol);
+        }
+        let normalized = insert.replace("\r\n", "\n");
+        let body = normalized.strip_suffix('\n').unwrap_or(&normalized);
+        for line in body.split('\n') {
+            out.push_str(line);
+            out.push_str(eol);
+        }
+        if at_end && !original.is_empty() && !original.ends_with('\n') {
+            out.truncate(out.len() - eol.len());
+        }
+    }
+    for line in &lines[(start + remove).min(lines.len())..] {
+        out.push_str(line);
+    }
+    out
+}
+
+/// Result for multi_edit, insert_at_line and replace_lines. `diff_preview`
+/// holds a unified diff of the whole change, which the chat renders as a
+/// diff card and the model reads to confirm what it did.
+fn diff_result(tool: &str, path: &str, summary: String, before: &str, after: &str) -> String {
+    let diff = edit_review::unified_diff(path, before, after);
+    let added = diff.lines().filter(|l| l.starts_with('+') && !l.starts_with("+++")).count();
+    let removed = diff.lines().filter(|l| l.starts_with('-') && !l.starts_with("---")).count();
+    json!({
+        "ok": true,
+        "tool": tool,
+        "path": path,
+        "summary": format!("{} (+{} -{})", summary, added, removed),
+        "diff_preview": diff,
+    })
+    .to_string()
+}
+
+/// Checks shared by the edit tools that change an existing file in place:
+/// agent mode, a path inside the workspace, and a prior read_file.
+fn prepare_in_place_edit(
+    tool: &str,
+    path: &str,
+    workspace_dir: &str,
+    session_id: Option<&str>,
+    mode: AgentMode,
+) -> Result<PathBuf, String> {
+    if mode != AgentMode::Agent {
+        return Err(tool_error(
+            "MODE_RESTRICTED",
+            format!("{} is disabled in {} mode. Switch to agent mode to edit files.", tool, mode.as_str()),
+            false,
+        ));
+    }
+    let full_path = ensure_path_in_workspace(path, workspace_dir, true)
+        .map_err(|e| tool_error("PATH_OUTSIDE_WORKSPACE", e, false))?;
+    if !full_path.is_file() {
+        return Err(tool_error(
+            "FILE_NOT_FOUND",
+            format!("{} edits existing files only; use write_file to create '{}'.", tool, path),
+            false,
+        ));
+    }
+    if !has_read_file(session_id, &full_path) {
+        return Err(tool_error(
+            "READ_REQUIRED",
+            format!("Read '{}' before modifying it with {}.", path, tool),
+            true,
+        ));
+    }
+    Ok(full_path)
+}
+
+/// Attach the language server's diagnostics to a successful file edit
+/// result. Only servers that are already running are asked, so an edit never
+/// waits on a cold start; results stay unchanged when nothing is published.
+async fn with_post_edit_diagnostics(result: String, path: &str, workspace_dir: &str) -> String {
+    let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&result) else {
+        return result;
+    };
+    if value.get("ok").and_then(|v| v.as_bool()) != Some(true) {
+        return result;
+    }
+    let Ok(full_path) = ensure_path_in_workspace(path, workspace_dir, false) else {
+        return result;
+    };
+    if !lsp::is_running_for(&full_path) {
+        return result;
+    }
+    match lsp::diagnostics(workspace_dir, &full_path, POST_EDIT_DIAGNOSTICS_WAIT).await {
+        Ok(Some(diagnostics)) => {
+            value["diagnostics"] = diagnostics;
+            value.to_string()
+        }
+        _ => result,
+    }
+}
+
 /// Execute a tool call and return the result
 async fn execute_tool(
     name: &str,
@@ -603,6 +916,31 @@ async fn execute_tool(
     workspace_dir: &str,
     session_id: Option<&str>,
     mode: AgentMode,
+) -> String {
+    let span = tracing::info_span!(
+        "tool_call",
+        tool = name,
+        args_hash = %crate::services::telemetry::args_hash(&args.to_string()),
+        success = tracing::field::Empty,
+    );
+    let result = execute_tool_inner(name, args, workspace_dir, session_id, mode)
+        .instrument(span.clone())
+        .await;
+    // Tools report failure through the `ok` flag of their JSON envelope
+    let ok = serde_json::from_str::<serde_json::Value>(&result)
+        .ok()
+        .and_then(|v| v.get("ok").and_then(|ok| ok.as_bool()))
+        .unwrap_or(true);
+    span.record("success", ok);
+    result
+}
+
+async fn execute_tool_inner(
+    name: &str,
+    args: &serde_json::Value,
+    workspace_dir: &str,
+    session_id: Option<&str>,
+    mode: AgentMode,
 ) -> String {
     match name {
         "read_file" => {
@@ -664,10 +1002,6 @@ async fn execute_tool(
                 Err(e) => return tool_error("PATH_OUTSIDE_WORKSPACE", e, false),
             };
 
-            if let Some(parent) = full_path.parent() {
-                let _ = tokio::fs::create_dir_all(parent).await;
-            }
-
             // Check if file existed before write (for metadata)
             let existed = full_path.exists();
             if existed && !has_read_file(session_id, &full_path) {
@@ -687,7 +1021,7 @@ async fn execute_tool(
             };
             let new_line_count = content.lines().count();
 
-            match tokio::fs::write(&full_path, content).await {
+            match write_or_stage(session_id, path, &full_path, content).await {
                 Ok(()) => {
                     // Build a small preview of the first few lines
                     let preview_lines: Vec<&str> = content.lines().take(8).collect();
@@ -745,46 +1079,19 @@ async fn execute_tool(
 
             match tokio::fs::read_to_string(&full_path).await {
                 Ok(content) => {
-                    let mut match_found = false;
-                    let mut actual_old_string = old_string.to_string();
-
-                    if content.contains(old_string) {
-                        match_found = true;
-                    } else {
-                        // Fallback: try whitespace-agnostic matching
-                        let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
-                        let norm_old = normalize(old_string);
-                        
-                        // Simple sliding window over lines to find a matching block
-                        let lines: Vec<&str> = content.lines().collect();
-                        let old_lines_count = old_string.lines().count().max(1);
-                        
-                        for i in 0..=lines.len().saturating_sub(old_lines_count) {
-                            for j in i + 1..=lines.len().min(i + old_lines_count * 2) {
-                                let block = lines[i..j].join("\n");
-                                if normalize(&block) == norm_old {
-                                    actual_old_string = block;
-                                    match_found = true;
-                                    break;
-                                }
-                            }
-                            if match_found { break; }
-                        }
-                    }
-
-                    if !match_found {
+                    let Some(actual_old_string) = locate_old_string(&content, old_string) else {
                         return tool_error(
                             "OLD_STRING_NOT_FOUND",
                             format!("old_string not found in {}. Make sure you are matching the exact indentation and whitespace.", path),
                             true
                         );
-                    }
+                    };
 
                     let occurrence_count = content.matches(&actual_old_string).count();
                     if occurrence_count > 1 && !replace_all {
                         return tool_error(
                             "MULTIPLE_MATCHES",
-                            "Found multiple matches for old_string. Add more context or set replace_all=true.",
+                            "Found multiple matches for old_string. Add more context, set replace_all=true, or use replace_lines.",
                             true,
                         );
                     }
@@ -808,7 +1115,7 @@ async fn execute_tool(
                     } else {
                         content.replacen(&actual_old_string, new_string, 1)
                     };
-                    match tokio::fs::write(&full_path, &new_content).await {
+                    match write_or_stage(session_id, path, &full_path, &new_content).await {
                         Ok(()) => {
                             json!({
                                 "ok": true,
@@ -842,6 +1149,111 @@ async fn execute_tool(
                 Err(e) => tool_error("READ_FAILED", format!("Error reading file: {}", e), true),
             }
         }
+        "multi_edit" => {
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let edits = args.get("edits").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+            let full_path = match prepare_in_place_edit("multi_edit", path, workspace_dir, session_id, mode) {
+                Ok(path) => path,
+                Err(result) => return result,
+            };
+            let original = match tokio::fs::read_to_string(&full_path).await {
+                Ok(content) => content,
+                Err(e) => return tool_error("READ_FAILED", format!("Error reading file: {}", e), true),
+            };
+
+            // Apply in order against the running result; any failure leaves
+            // the file untouched.
+            let mut updated = original.clone();
+            for (i, edit) in edits.iter().enumerate() {
+                let old_string = edit.get("old_string").and_then(|v| v.as_str()).unwrap_or("");
+                let new_string = edit.get("new_string").and_then(|v| v.as_str()).unwrap_or("");
+                let replace_all = edit.get("replace_all").and_then(|v| v.as_bool()).unwrap_or(false);
+                let Some(actual_old_string) = locate_old_string(&updated, old_string) else {
+                    return tool_error(
+                        "OLD_STRING_NOT_FOUND",
+                        format!(
+                            "edits[{}]: old_string not found in {} after applying the edits before it. No changes were written.",
+                            i, path
+                        ),
+                        true,
+                    );
+                };
+                let occurrences = updated.matches(&actual_old_string).count();
+                if occurrences > 1 && !replace_all {
+                    return tool_error(
+                        "MULTIPLE_MATCHES",
+                        format!(
+                            "edits[{}]: found {} matches for old_string. Add more context, set replace_all=true, or use replace_lines. No changes were written.",
+                            i, occurrences
+                        ),
+                        true,
+                    );
+                }
+                updated = if replace_all {
+                    updated.replace(&actual_old_string, new_string)
+                } else {
+                    updated.replacen(&actual_old_string, new_string, 1)
+                };
+            }
+
+            match write_or_stage(session_id, path, &full_path, &updated).await {
+                Ok(()) => diff_result(
+                    "multi_edit",
+                    path,
+                    format!("Applied {} edits to {}", edits.len(), path),
+                    &original,
+                    &updated,
+                ),
+                Err(e) => tool_error("WRITE_FAILED", format!("Error writing file: {}", e), true),
+            }
+        }
+        "insert_at_line" | "replace_lines" => {
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let content = args.get("content").and_then(|v| v.as_str()).unwrap_or("");
+            let full_path = match prepare_in_place_edit(name, path, workspace_dir, session_id, mode) {
+                Ok(path) => path,
+                Err(result) => return result,
+            };
+            let original = match tokio::fs::read_to_string(&full_path).await {
+                Ok(content) => content,
+                Err(e) => return tool_error("READ_FAILED", format!("Error reading file: {}", e), true),
+            };
+            let total = original.lines().count();
+
+            let (updated, summary) = if name == "insert_at_line" {
+                let line = args.get("line").and_then(|v| v.as_u64()).unwrap_or(1).max(1) as usize;
+                if line > total + 1 {
+                    return tool_error(
+                        "LINE_OUT_OF_RANGE",
+                        format!("line {} is past the end of {} ({} lines). Use line {} to append.", line, path, total, total + 1),
+                        true,
+                    );
+                }
+                (
+                    splice_lines(&original, line - 1, 0, content),
+                    format!("Inserted at line {} of {}", line, path),
+                )
+            } else {
+                let start = args.get("start_line").and_then(|v| v.as_u64()).unwrap_or(1).max(1) as usize;
+                let end = (args.get("end_line").and_then(|v| v.as_u64()).unwrap_or(1) as usize).max(start);
+                if end > total {
+                    return tool_error(
+                        "LINE_OUT_OF_RANGE",
+                        format!("end_line {} is past the end of {} ({} lines).", end, path, total),
+                        true,
+                    );
+                }
+                (
+                    splice_lines(&original, start - 1, end - start + 1, content),
+                    format!("Replaced lines {}-{} of {}", start, end, path),
+                )
+            };
+
+            match write_or_stage(session_id, path, &full_path, &updated).await {
+                Ok(()) => diff_result(name, path, summary, &original, &updated),
+                Err(e) => tool_error("WRITE_FAILED", format!("Error writing file: {}", e), true),
+            }
+        }
         "list_files" => {
             let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
             let full_path = match ensure_path_in_workspace(path, workspace_dir, false) {
@@ -925,6 +1337,7 @@ async fn execute_tool(
             // Use login shell to inherit user's PATH (NVM, Homebrew, etc.)
             // This sources .zprofile/.bash_profile before running the command.
             let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+            let scan = checkpoints::scan_before_command(session_id, workspace_dir);
             let output = tokio::time::timeout(
                 std::time::Duration::from_secs(30),
                 tokio::process::Command::new(&shell)
@@ -934,6 +1347,9 @@ async fn execute_tool(
                     .current_dir(&safe_working_dir)
                     .output()
             ).await;
+            if let Some(before) = scan {
+                checkpoints::record_command_changes(session_id, workspace_dir, before);
+            }
 
             match output {
                 Ok(Ok(out)) => {
@@ -1050,6 +1466,113 @@ async fn execute_tool(
                 ),
             }
         }
+        "find_references" | "goto_definition" => {
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let line = args.get("line").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+            let symbol = args.get("symbol").and_then(|v| v.as_str()).unwrap_or("");
+            let full_path = match ensure_path_in_workspace(path, workspace_dir, false) {
+                Ok(path) => path,
+                Err(e) => return tool_error("PATH_OUTSIDE_WORKSPACE", e, false),
+            };
+            let result = if name == "find_references" {
+                lsp::find_references(workspace_dir, &full_path, line, symbol).await
+            } else {
+                lsp::goto_definition(workspace_dir, &full_path, line, symbol).await
+            };
+            match result {
+                Ok(locations) => tool_success(locations),
+                Err(e) => tool_error("LSP_FAILED", e, true),
+            }
+        }
+        "rename_symbol" => {
+            if mode != AgentMode::Agent {
+                return tool_error(
+                    "MODE_RESTRICTED",
+                    format!("rename_symbol is disabled in {} mode. Switch to agent mode to edit files.", mode.as_str()),
+                    false,
+                );
+            }
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let line = args.get("line").and_then(|v| v.as_u64()).unwrap_or(1) as usize;
+            let symbol = args.get("symbol").and_then(|v| v.as_str()).unwrap_or("");
+            let new_name = args.get("new_name").and_then(|v| v.as_str()).unwrap_or("");
+            let full_path = match ensure_path_in_workspace(path, workspace_dir, false) {
+                Ok(path) => path,
+                Err(e) => return tool_error("PATH_OUTSIDE_WORKSPACE", e, false),
+            };
+            let files = match lsp::rename_edits(workspace_dir, &full_path, line, symbol, new_name).await {
+                Ok(files) => files,
+                Err(e) => return tool_error("LSP_FAILED", e, true),
+            };
+            if files.is_empty() {
+                return tool_error("LSP_FAILED", format!("The language server found nothing to rename for '{}'.", symbol), false);
+            }
+
+            // Compute every file before writing any, so a bad edit or a file
+            // outside the workspace leaves the tree untouched.
+            let mut updates = Vec::with_capacity(files.len());
+            for (file, edits) in &files {
+                let target = match ensure_path_in_workspace(&file.to_string_lossy(), workspace_dir, true) {
+                    Ok(path) => path,
+                    Err(e) => return tool_error("PATH_OUTSIDE_WORKSPACE", e, false),
+                };
+                let content = match tokio::fs::read_to_string(&target).await {
+                    Ok(content) => content,
+                    Err(e) => return tool_error("READ_FAILED", format!("Error reading file: {}", e), true),
+                };
+                let updated = match lsp::apply_text_edits(&content, edits) {
+                    Ok(updated) => updated,
+                    Err(e) => return tool_error("LSP_FAILED", format!("{} in {}", e, file.display()), true),
+                };
+                updates.push((lsp::relative_display(workspace_dir, &target), target, edits.len(), content, updated));
+            }
+
+            // A failed write puts back every file written so far, including
+            // a partial write of the failing one, so the rename is never left
+            // half-applied.
+            let mut changed = Vec::with_capacity(updates.len());
+            for (i, (display, target, edit_count, _, updated)) in updates.iter().enumerate() {
+                if let Err(e) = write_or_stage(session_id, display, target, updated).await {
+                    let mut unrestored = Vec::new();
+                    for (written, target, _, original, _) in &updates[..=i] {
+                        if tokio::fs::write(target, original).await.is_err() {
+                            unrestored.push(written.as_str());
+                        }
+                    }
+                    let outcome = if unrestored.is_empty() {
+                        "No files were changed.".to_string()
+                    } else {
+                        format!("Could not restore: {}.", unrestored.join(", "))
+                    };
+                    return tool_error("WRITE_FAILED", format!("Error writing {}: {}. {}", display, e, outcome), true);
+                }
+                changed.push(json!({ "path": display, "edits": edit_count }));
+            }
+            let total: usize = files.values().map(Vec::len).sum();
+            json!({
+                "ok": true,
+                "tool": "rename_symbol",
+                "summary": format!("Renamed '{}' to '{}' — {} edits in {} files", symbol, new_name, total, changed.len()),
+                "files": changed,
+            })
+            .to_string()
+        }
+        "get_diagnostics" => {
+            let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+            let full_path = match ensure_path_in_workspace(path, workspace_dir, false) {
+                Ok(path) => path,
+                Err(e) => return tool_error("PATH_OUTSIDE_WORKSPACE", e, false),
+            };
+            match lsp::diagnostics(workspace_dir, &full_path, DIAGNOSTICS_WAIT).await {
+                Ok(Some(diagnostics)) => tool_success(diagnostics),
+                Ok(None) => tool_success(json!(format!(
+                    "The language server published no diagnostics for {} within {}s. It may still be indexing; try again shortly.",
+                    path,
+                    DIAGNOSTICS_WAIT.as_secs()
+                ))),
+                Err(e) => tool_error("LSP_FAILED", e, true),
+            }
+        }
         "todo_write" => {
             let Some(sid) = session_id else {
                 return tool_error("SESSION_REQUIRED", "todo_write requires an active agent session.", false);
@@ -1124,6 +1647,19 @@ async fn execute_tool(
             let summary = args.get("summary").and_then(|v| v.as_str()).unwrap_or("Task complete");
             tool_success(json!(format!("Task complete: {}", summary)))
         }
+        _ if mcp::is_mcp_tool(name) => {
+            if mode != AgentMode::Agent && !mcp::is_read_only(workspace_dir, name) {
+                return tool_error(
+                    "MODE_RESTRICTED",
+                    format!("{} is not marked read-only, so it is disabled in {} mode.", name, mode.as_str()),
+                    false,
+                );
+            }
+            match mcp::call_tool(workspace_dir, name, args).await {
+                Ok(output) => tool_success(json!(output)),
+                Err(e) => tool_error("MCP_TOOL_FAILED", e, true),
+            }
+        }
         _ => tool_error("UNKNOWN_TOOL", format!("Unknown tool: {}", name), false),
     }
 }
@@ -1188,7 +1724,7 @@ fn ensure_path_in_workspace(path: &str, workspace_dir: &str, for_write: bool) ->
     }
 }
 
-fn validate_tool_args(name: &str, args: &serde_json::Value) -> Result<(), String> {
+fn validate_tool_args(name: &str, args: &serde_json::Value, workspace_dir: &str) -> Result<(), String> {
     let obj = args
         .as_object()
         .ok_or_else(|| "Tool arguments must be a JSON object".to_string())?;
@@ -1197,14 +1733,21 @@ fn validate_tool_args(name: &str, args: &serde_json::Value) -> Result<(), String
         "read_file" => &["path", "offset", "limit"],
         "write_file" => &["path", "content"],
         "edit_file" => &["path", "old_string", "new_string", "replace_all"],
+        "multi_edit" => &["path", "edits"],
+        "insert_at_line" => &["path", "line", "content"],
+        "replace_lines" => &["path", "start_line", "end_line", "content"],
         "list_files" => &["path"],
         "search" => &["query", "path"],
         "run_command" => &["command", "working_dir"],
         "find_file" => &["name", "dir"],
         "find_symbol" => &["name", "limit"],
+        "find_references" | "goto_definition" => &["path", "line", "symbol"],
+        "rename_symbol" => &["path", "line", "symbol", "new_name"],
+        "get_diagnostics" => &["path"],
         "todo_write" => &["todos", "merge"],
         "todo_read" => &[],
         "submit" => &["summary"],
+        _ if mcp::is_mcp_tool(name) => return mcp::validate_args(workspace_dir, name, args),
         _ => return Err(format!("Unknown tool: {}", name)),
     };
 
@@ -1253,6 +1796,58 @@ fn validate_tool_args(name: &str, args: &serde_json::Value) -> Result<(), String
             }
             Ok(())
         }
+        "multi_edit" => {
+            require_string("path")?;
+            let edits = obj
+                .get("edits")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| "Field 'edits' must be an array".to_string())?;
+            if edits.is_empty() {
+                return Err("Field 'edits' must contain at least one edit".to_string());
+            }
+            for (i, item) in edits.iter().enumerate() {
+                let edit = item
+                    .as_object()
+                    .ok_or_else(|| format!("edits[{}] must be an object", i))?;
+                if let Some(key) = edit
+                    .keys()
+                    .find(|k| !matches!(k.as_str(), "old_string" | "new_string" | "replace_all"))
+                {
+                    return Err(format!("Unexpected argument 'edits[{}].{}'", i, key));
+                }
+                match edit.get("old_string").and_then(|v| v.as_str()) {
+                    Some(old) if !old.is_empty() => {}
+                    Some(_) => return Err(format!("edits[{}].old_string cannot be empty", i)),
+                    None => return Err(format!("edits[{}].old_string must be a string", i)),
+                }
+                if !edit.get("new_string").is_some_and(|v| v.is_string()) {
+                    return Err(format!("edits[{}].new_string must be a string", i));
+                }
+                if let Some(v) = edit.get("replace_all") {
+                    if !v.is_boolean() && !v.is_null() {
+                        return Err(format!("edits[{}].replace_all must be a boolean or null", i));
+                    }
+                }
+            }
+            Ok(())
+        }
+        "insert_at_line" | "replace_lines" => {
+            require_string("path")?;
+            require_string("content")?;
+            let line_field = |field: &str| -> Result<u64, String> {
+                match obj.get(field).and_then(|v| v.as_u64()) {
+                    Some(line) if line >= 1 => Ok(line),
+                    Some(_) => Err(format!("Field '{}' must be 1 or greater", field)),
+                    None => Err(format!("Field '{}' must be a positive integer", field)),
+                }
+            };
+            if name == "insert_at_line" {
+                line_field("line")?;
+            } else if line_field("end_line")? < line_field("start_line")? {
+                return Err("Field 'end_line' must not be less than 'start_line'".to_string());
+            }
+            Ok(())
+        }
         "search" => {
             require_string("query")?;
             require_string("path")
@@ -1284,6 +1879,20 @@ fn validate_tool_args(name: &str, args: &serde_json::Value) -> Result<(), String
             }
             Ok(())
         }
+        "find_references" | "goto_definition" | "rename_symbol" => {
+            require_string("path")?;
+            require_string("symbol")?;
+            match obj.get("line").and_then(|v| v.as_u64()) {
+                Some(line) if line >= 1 => {}
+                Some(_) => return Err("Field 'line' must be 1 or greater".to_string()),
+                None => return Err("Field 'line' must be a positive integer".to_string()),
+            }
+            if name == "rename_symbol" {
+                require_string("new_name")?;
+            }
+            Ok(())
+        }
+        "get_diagnostics" => require_string("path"),
         "todo_write" => {
             let todos = obj
                 .get("todos")
@@ -1563,23 +2172,6 @@ fn build_workspace_snapshot(workspace_dir: &str) -> String {
     lines.join("\n")
 }
 
-/// Get the provider URL
-fn get_provider_url(provider: &str) -> String {
-    match provider {
-        "ollama" => "http://localhost:11434/v1/chat/completions".to_string(),
-        _ => "https://openrouter.ai/api/v1/chat/completions".to_string(),
-    }
-}
-
-/// Load API key from stored keys
-fn load_api_key(provider: &str) -> Option<String> {
-    let home = std::env::var("HOME").ok()?;
-    let keys_path = format!("{}/.clif/api_keys.json", home);
-    let content = std::fs::read_to_string(keys_path).ok()?;
-    let keys: serde_json::Value = serde_json::from_str(&content).ok()?;
-    keys.get(provider).and_then(|k| k.as_str()).map(|s| s.to_string())
-}
-
 #[tauri::command]
 pub async fn agent_chat(
     window: tauri::Window,
@@ -1601,6 +2193,26 @@ pub async fn agent_chat(
     // symmetric with creation.
     let conv_id = scoped_conversation_id(&label, conversation_id.as_deref());
 
+    // The persisted thread owns the history; it also brings back the
+    // read-set and todos when this is the first turn since a restart.
+    let thread_id = conversation_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("default");
+    let (messages, restored, recorder) = if workspace_dir.is_empty() {
+        (messages, None, None)
+    } else {
+        match conversations::resume(&workspace_dir, thread_id, &messages) {
+            Ok(r) => (r.messages, Some((r.read_files, r.todos)), Some(r.recorder)),
+            Err(e) => {
+                log::warn!("conversation persistence disabled for this turn: {}", e);
+                (messages, None, None)
+            }
+        }
+    };
+    let (restored_reads, restored_todos) = restored.unwrap_or_default();
+
     let (cancel_tx, cancel_rx) = tokio::sync::oneshot::channel::<()>();
 
     {
@@ -1624,14 +2236,41 @@ pub async fn agent_chat(
             .lock()
             .map_err(|e| format!("Failed to lock conv read files: {}", e))?;
         evict_one_if_full(&mut reads, &conv_id);
-        reads.entry(conv_id.clone()).or_insert_with(HashSet::new);
+        reads.entry(conv_id.clone()).or_insert(restored_reads);
     }
     {
         let mut todos = CONV_TODOS
             .lock()
             .map_err(|e| format!("Failed to lock conv todos: {}", e))?;
         evict_one_if_full(&mut todos, &conv_id);
-        todos.entry(conv_id.clone()).or_insert_with(Vec::new);
+        todos.entry(conv_id.clone()).or_insert(restored_todos);
+    }
+
+    edit_review::set_review_mode(&session_id, review_edits_from_context(context.as_deref()));
+
+    // Files this turn touches are snapshotted so the turn can be undone
+    if recorder.is_some() {
+        if let Some(user) = messages.last().filter(|m| m.role == "user") {
+            let reads = CONV_READ_FILES
+                .lock()
+                .ok()
+                .and_then(|m| m.get(&conv_id).cloned())
+                .unwrap_or_default();
+            let todos = CONV_TODOS
+                .lock()
+                .ok()
+                .and_then(|m| m.get(&conv_id).cloned())
+                .unwrap_or_default();
+            checkpoints::begin(
+                &session_id,
+                &workspace_dir,
+                thread_id,
+                messages.len() - 1,
+                &user.content,
+                &reads,
+                &todos,
+            );
+        }
     }
 
     // Emit session ID to frontend so it can call agent_stop
@@ -1640,6 +2279,17 @@ pub async fn agent_chat(
     let sid = session_id.clone();
 
     tokio::spawn(async move {
+        let turn_span = tracing::info_span!(
+            "turn",
+            session_id = %sid,
+            model = %model,
+            provider = %provider,
+            rounds = tracing::field::Empty,
+            outcome = tracing::field::Empty,
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
         let result = run_agent_loop(
             app.clone(),
             &label,
@@ -1651,10 +2301,27 @@ pub async fn agent_chat(
             workspace_dir,
             context,
             cancel_rx,
+            recorder.clone(),
         )
+        .instrument(turn_span.clone())
         .await;
 
+        if let Some(recorder) = &recorder {
+            let reads = CONV_READ_FILES
+                .lock()
+                .ok()
+                .and_then(|m| m.get(&conv_id).cloned())
+                .unwrap_or_default();
+            let todos = CONV_TODOS
+                .lock()
+                .ok()
+                .and_then(|m| m.get(&conv_id).cloned())
+                .unwrap_or_default();
+            recorder.state(&reads, &todos);
+        }
+
         if let Err(e) = result {
+            turn_span.record("error", e.as_str());
             let _ = app.emit_to(&label, "agent_error", e);
         }
 
@@ -1664,6 +2331,8 @@ pub async fn agent_chat(
         if let Ok(mut sessions) = AGENT_SESSIONS.lock() {
             sessions.remove(&sid);
         }
+        edit_review::end_session(&sid);
+        checkpoints::end(&sid);
         // Drop only the per-session mapping. Conversation-scoped state
         // (CONV_READ_FILES, CONV_TODOS) intentionally lives until the tab
         // is closed via `agent_clear_conversation`, so the next user turn
@@ -1687,46 +2356,27 @@ async fn run_agent_loop(
     workspace_dir: String,
     context: Option<String>,
     mut cancel_rx: tokio::sync::oneshot::Receiver<()>,
+    recorder: Option<Recorder>,
 ) -> Result<(), String> {
-    let url = get_provider_url(&provider);
-    let key = api_key.or_else(|| load_api_key(&provider));
+    let ai = ai_provider::resolve(&provider, api_key)?;
+    // Messages produced this turn also go to the persisted thread
+    let record = |msg: &serde_json::Value| {
+        if let Some(recorder) = &recorder {
+            recorder.message(msg);
+        }
+    };
 
     let system_prompt = build_system_prompt(&workspace_dir);
     let runtime_context = build_runtime_context(&workspace_dir, context.as_deref());
     let context_files = context_files_from_json(context.as_deref());
     let mode = mode_from_context(context.as_deref());
 
-    // Build conversation from initial messages
-    // For OpenRouter: Use array content with cache_control for 90% cost reduction on cached prompts
-    // For Ollama: Use string content (Ollama doesn't support array content for system messages)
-    let use_caching = provider == "openrouter";
-    
-    let mut conversation: Vec<serde_json::Value> = if use_caching {
-        // Build system prompt as array of text parts for cache_control support
-        let mut system_parts: Vec<serde_json::Value> = vec![json!({
-            "type": "text",
-            "text": system_prompt
-        })];
-
-        // Tag the LAST static block with cache_control for Anthropic/OpenRouter caching
-        // This enables 90% cost reduction on subsequent requests with same prefix
-        if let Some(last_part) = system_parts.last_mut().and_then(|p| p.as_object_mut()) {
-            last_part.insert("cache_control".to_string(), json!({"type": "ephemeral"}));
-        }
-        
-        vec![json!({
-            "role": "system",
-            "content": system_parts
-        })]
-    } else {
-        // Ollama: use simple string format (no caching, but maximum compatibility)
-        let conv = vec![json!({
-            "role": "system",
-            "content": system_prompt,
-        })];
-
-        conv
-    };
+    // Build conversation from initial messages. The static system prompt
+    // goes first so providers can cache it (see `ai_provider`).
+    let mut conversation: Vec<serde_json::Value> = vec![json!({
+        "role": "system",
+        "content": system_prompt,
+    })];
 
     // Add volatile runtime context after static system content to preserve cacheability.
     conversation.push(json!({
@@ -1835,12 +2485,47 @@ async fn run_agent_loop(
         }));
     }
 
-    let raw_tools = tool_definitions();
-    let tools = if provider == "openai" { raw_tools } else { strip_openai_fields(raw_tools) };
+    let mut tools = tool_definitions();
+    if !workspace_dir.is_empty() {
+        // Servers from the repo's .clif/mcp.json stay off until the user
+        // trusts them; ask once per run.
+        let untrusted = mcp::untrusted_servers(&workspace_dir);
+        if !untrusted.is_empty() {
+            let _ = app.emit_to(
+                label,
+                "agent_mcp_trust_required",
+                json!({ "workspace_dir": workspace_dir, "servers": untrusted }),
+            );
+        }
+        tools.extend(mcp::tool_definitions(&workspace_dir).await);
+    }
     let client = reqwest::Client::new();
-    let max_turns = 200; // Safety limit — compaction handles context
-
-    for _turn in 0..max_turns {
+    let settings = super::settings::get_settings().unwrap_or_default();
+    let budget = Budget::resolve(&settings, context.as_deref());
+    let mut usage = Usage::start();
+    let turn_span = tracing::Span::current();
+
+    loop {
+        // Budgets are checked between model calls so a batch of tool results
+        // is never cut off from the assistant message that requested it
+        if let Some(reason) = budget.exhausted(&usage) {
+            let summary = budget.summary(&usage, reason);
+            let _ = app.emit_to(
+                label,
+                "agent_budget_exhausted",
+                json!({
+                    "reason": reason,
+                    "usage": budget.usage_event(&usage, 0, 0, estimate_conversation_tokens(&conversation) as u64),
+                }),
+            );
+            let _ = app.emit_to(label, "agent_stream", summary);
+            let _ = app.emit_to(label, "agent_stream", "[DONE]");
+            turn_span.record("outcome", "budget_exhausted");
+            return Ok(());
+        }
+        let _turn = usage.iterations;
+        usage.iterations += 1;
+        turn_span.record("rounds", usage.iterations);
         // Periodic context refresh
         if _turn > 0 && _turn % 50 == 0 {
             let snapshot = build_workspace_snapshot(&workspace_dir);
@@ -1906,7 +2591,7 @@ async fn run_agent_loop(
             && m.get("content").map(|c| c.is_array()).unwrap_or(false)
         });
 
-        let mut request_body = json!({
+        let request_body = json!({
             "model": model,
             "messages": conversation,
             "tools": tools,
@@ -1915,38 +2600,31 @@ async fn run_agent_loop(
             "stream_options": { "include_usage": true },
         });
 
-        if provider == "openrouter" {
-            if let Some(obj) = request_body.as_object_mut() {
-                obj.insert("transforms".to_string(), json!(["middle-out"]));
-            }
-        }
-
-        let mut req_builder = client
-            .post(&url)
-            .header("Content-Type", "application/json");
-
-        if let Some(ref k) = key {
-            req_builder = req_builder.header("Authorization", format!("Bearer {}", k));
-        }
-
-        if provider == "openrouter" {
-            req_builder = req_builder
-                .header("HTTP-Referer", "https://clif.dev")
-                .header("X-Title", "ClifPad");
-        }
+        let request_span = tracing::info_span!(
+            "model_request",
+            model = %model,
+            provider = %provider,
+            messages = conversation.len(),
+            prompt_tokens = tracing::field::Empty,
+            completion_tokens = tracing::field::Empty,
+            finish_reason = tracing::field::Empty,
+            tool_calls = tracing::field::Empty,
+            error = tracing::field::Empty,
+        );
 
-        let response = req_builder
-            .json(&request_body)
-            .send()
+        let response = ai_provider::send(ai.as_ref(), &client, request_body)
             .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .inspect_err(|e| {
+                request_span.record("error", e.as_str());
+            })?;
 
         if !response.status().is_success() {
             let status = response.status();
+            request_span.record("error", format!("HTTP {}", status).as_str());
             let body = response.text().await.unwrap_or_default();
 
             // Context overflow — compact and retry once
-            if status.as_u16() == 400 && (body.contains("context_length") || body.contains("too many tokens") || body.contains("maximum context")) {
+            if status.as_u16() == 400 && (body.contains("context_length") || body.contains("too many tokens") || body.contains("maximum context") || body.contains("prompt is too long")) {
                 let before = estimate_conversation_tokens(&conversation);
                 compact_conversation(&mut conversation, 20_000);
                 let after = estimate_conversation_tokens(&conversation);
@@ -1961,29 +2639,14 @@ async fn run_agent_loop(
                     }),
                 );
 
-                let mut retry_body = json!({
+                let retry_body = json!({
                     "model": model,
                     "messages": conversation,
                     "tools": tools,
                     "stream": true,
                 });
-                if provider == "openrouter" {
-                    if let Some(obj) = retry_body.as_object_mut() {
-                        obj.insert("transforms".to_string(), json!(["middle-out"]));
-                    }
-                }
 
-                let mut retry_req = client.post(&url).header("Content-Type", "application/json");
-                if let Some(ref k) = key {
-                    retry_req = retry_req.header("Authorization", format!("Bearer {}", k));
-                }
-                if provider == "openrouter" {
-                    retry_req = retry_req
-                        .header("HTTP-Referer", "https://clif.dev")
-                        .header("X-Title", "ClifPad");
-                }
-
-                let retry_response = retry_req.json(&retry_body).send().await
+                let retry_response = ai_provider::send(ai.as_ref(), &client, retry_body).await
                     .map_err(|e| format!("Retry failed: {}", e))?;
 
                 if !retry_response.status().is_success() {
@@ -2001,110 +2664,78 @@ async fn run_agent_loop(
         }
 
         // Parse the streaming response
-        let mut stream = response.bytes_stream();
-        use futures::StreamExt;
-
-        let mut buffer = String::new();
+        let mut events = EventStream::new(ai.clone(), response);
         let mut assistant_content = String::new();
         let mut tool_calls_map: HashMap<usize, (String, String, String)> = HashMap::new();
         let mut finish_reason = String::new();
         let mut turn_prompt_tokens: u64 = 0;
         let mut turn_completion_tokens: u64 = 0;
 
-        while let Some(chunk_result) = stream.next().await {
+        while let Some(batch) = events.next().await {
             // Check cancellation between chunks
             if cancel_rx.try_recv().is_ok() {
                 return Ok(());
             }
 
-            match chunk_result {
-                Ok(chunk) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    buffer.push_str(&chunk_str);
-
-                    while let Some(newline_pos) = buffer.find('\n') {
-                        let line = buffer[..newline_pos].trim().to_string();
-                        buffer = buffer[newline_pos + 1..].to_string();
-
-                        if line.is_empty() || !line.starts_with("data: ") {
-                            continue;
+            let batch = batch.inspect_err(|e| {
+                request_span.record("error", e.as_str());
+            })?;
+            for event in batch {
+                match event {
+                    StreamEvent::Usage { prompt_tokens, completion_tokens } => {
+                        if let Some(pt) = prompt_tokens {
+                            turn_prompt_tokens = pt;
                         }
-
-                        let data = &line[6..];
-                        if data == "[DONE]" {
-                            break;
+                        if let Some(ct) = completion_tokens {
+                            turn_completion_tokens = ct;
                         }
-
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(data) {
-                            // Extract usage from final chunk
-                            if let Some(usage) = parsed.get("usage") {
-                                if let Some(pt) = usage.get("prompt_tokens").and_then(|v| v.as_u64()) {
-                                    turn_prompt_tokens = pt;
-                                }
-                                if let Some(ct) = usage.get("completion_tokens").and_then(|v| v.as_u64()) {
-                                    turn_completion_tokens = ct;
-                                }
-                            }
-
-                            if let Some(choices) = parsed.get("choices").and_then(|c| c.as_array()) {
-                                if let Some(choice) = choices.first() {
-                                    if let Some(fr) = choice.get("finish_reason").and_then(|v| v.as_str()) {
-                                        finish_reason = fr.to_string();
-                                    }
-
-                                    if let Some(delta) = choice.get("delta") {
-                                        if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                            assistant_content.push_str(content);
-                                            let _ = app.emit_to(label, "agent_stream", content);
-                                        }
-
-                                        if let Some(tcs) = delta.get("tool_calls").and_then(|t| t.as_array()) {
-                                            for tc in tcs {
-                                                let index = tc.get("index").and_then(|i| i.as_u64()).unwrap_or(0) as usize;
-                                                let entry = tool_calls_map.entry(index).or_insert_with(|| {
-                                                    (String::new(), String::new(), String::new())
-                                                });
-
-                                                if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
-                                                    entry.0 = id.to_string();
-                                                }
-                                                if let Some(func) = tc.get("function") {
-                                                    if let Some(name) = func.get("name").and_then(|v| v.as_str()) {
-                                                        entry.1 = name.to_string();
-                                                        // INSTANT FEEDBACK: Emit tool name immediately for UI responsiveness
-                                                        // This fires before arguments finish streaming, giving ~1-2s faster perceived speed
-                                                        let _ = app.emit_to(label, "agent_tool_start", json!({
-                                                            "name": name,
-                                                            "index": index
-                                                        }));
-                                                    }
-                                                    if let Some(args) = func.get("arguments").and_then(|v| v.as_str()) {
-                                                        entry.2.push_str(args);
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    }
+                    StreamEvent::Finish(reason) => finish_reason = reason,
+                    StreamEvent::Text(content) => {
+                        assistant_content.push_str(&content);
+                        let _ = app.emit_to(label, "agent_stream", content);
+                    }
+                    StreamEvent::ToolCall { index, id, name, arguments } => {
+                        let entry = tool_calls_map.entry(index).or_insert_with(|| {
+                            (String::new(), String::new(), String::new())
+                        });
+                        if let Some(id) = id {
+                            entry.0 = id;
+                        }
+                        if let Some(name) = name {
+                            // INSTANT FEEDBACK: Emit tool name immediately for UI responsiveness
+                            // This fires before arguments finish streaming, giving ~1-2s faster perceived speed
+                            let _ = app.emit_to(label, "agent_tool_start", json!({
+                                "name": name,
+                                "index": index
+                            }));
+                            entry.1 = name;
                         }
+                        entry.2.push_str(&arguments);
                     }
-                }
-                Err(e) => {
-                    return Err(format!("Stream read error: {}", e));
+                    StreamEvent::Reasoning(_) | StreamEvent::Done => {}
                 }
             }
         }
 
-        // Emit usage for this turn
-        if turn_prompt_tokens > 0 || turn_completion_tokens > 0 {
-            let estimated_ctx = estimate_conversation_tokens(&conversation) as u64;
-            let _ = app.emit_to(label, "agent_usage", json!({
-                "prompt_tokens": turn_prompt_tokens,
-                "completion_tokens": turn_completion_tokens,
-                "estimated_context": estimated_ctx,
-            }));
-        }
+        request_span.record("prompt_tokens", turn_prompt_tokens);
+        request_span.record("completion_tokens", turn_completion_tokens);
+        request_span.record("finish_reason", finish_reason.as_str());
+        request_span.record("tool_calls", tool_calls_map.len());
+        drop(request_span);
+        usage.prompt_tokens += turn_prompt_tokens;
+        usage.completion_tokens += turn_completion_tokens;
+        usage.tool_calls += tool_calls_map.len() as u32;
+        turn_span.record("prompt_tokens", usage.prompt_tokens);
+        turn_span.record("completion_tokens", usage.completion_tokens);
+
+        // Emit usage for this turn, with run totals against the budget
+        let estimated_ctx = estimate_conversation_tokens(&conversation) as u64;
+        let _ = app.emit_to(
+            label,
+            "agent_usage",
+            budget.usage_event(&usage, turn_prompt_tokens, turn_completion_tokens, estimated_ctx),
+        );
 
         // If the model returned nothing at all (empty content, no tool calls) —
         // most likely a vision-incapable model silently ignored the image.
@@ -2116,6 +2747,7 @@ async fn run_agent_loop(
             };
             let _ = app.emit_to(label, "agent_stream", hint);
             let _ = app.emit_to(label, "agent_stream", "[DONE]");
+            turn_span.record("outcome", "empty");
             return Ok(());
         }
 
@@ -2131,11 +2763,13 @@ async fn run_agent_loop(
                 || assistant_content.contains("I'll edit")
                 || assistant_content.contains("I'll search");
 
-            if narrating && _turn < max_turns - 1 {
-                conversation.push(json!({
+            if narrating && usage.iterations < budget.max_iterations {
+                let narration = json!({
                     "role": "assistant",
                     "content": &assistant_content,
-                }));
+                });
+                record(&narration);
+                conversation.push(narration);
                 conversation.push(json!({
                     "role": "system",
                     "content": "You described using tools but did not actually call them. \
@@ -2144,7 +2778,9 @@ async fn run_agent_loop(
                 continue;
             }
 
+            record(&json!({ "role": "assistant", "content": &assistant_content }));
             let _ = app.emit_to(label, "agent_stream", "[DONE]");
+            turn_span.record("outcome", "answered");
             return Ok(());
         }
 
@@ -2174,15 +2810,24 @@ async fn run_agent_loop(
         if !assistant_content.is_empty() {
             assistant_msg["content"] = json!(assistant_content);
         }
+        record(&assistant_msg);
         conversation.push(assistant_msg);
 
         // ── Parallel read-only tool execution ────────────────────────────────
-        // Read-only tools (read_file, search, list_files, find_file) are safe to
+        // Read-only tools (read_file, search, list_files, find_file, LSP lookups) are safe to
         // run concurrently — they never mutate files or state. When the LLM emits
         // a batch of such calls we run them in parallel then collect results in
         // original order. Write/run tools still execute sequentially to avoid
         // race conditions and to preserve the cancel/approval flow.
-        const READONLY_TOOLS: &[&str] = &["read_file", "search", "list_files", "find_file"];
+        const READONLY_TOOLS: &[&str] = &[
+            "read_file",
+            "search",
+            "list_files",
+            "find_file",
+            "find_references",
+            "goto_definition",
+            "get_diagnostics",
+        ];
 
         // Pre-parse and validate all args first so we can handle errors uniformly.
         // Produces Vec<(idx, id, name, args_str, parsed_args_or_err)>
@@ -2283,11 +2928,13 @@ async fn run_agent_loop(
                 } else {
                     result
                 };
-                conversation.push(json!({
+                let tool_msg = json!({
                     "role": "tool",
                     "tool_call_id": call.id,
                     "content": context_result,
-                }));
+                });
+                record(&tool_msg);
+                conversation.push(tool_msg);
             }
         }
 
@@ -2315,16 +2962,18 @@ async fn run_agent_loop(
                         "agent_tool_result",
                         json!({ "tool_call_id": call.id, "result": &result }),
                     );
-                    conversation.push(json!({
+                    let tool_msg = json!({
                         "role": "tool",
                         "tool_call_id": call.id,
                         "content": result,
-                    }));
+                    });
+                    record(&tool_msg);
+                    conversation.push(tool_msg);
                     continue;
                 }
             };
 
-            if let Err(e) = validate_tool_args(&call.name, &args) {
+            if let Err(e) = validate_tool_args(&call.name, &args, &workspace_dir) {
                 let result = tool_error("INVALID_TOOL_ARGUMENTS", e, true);
                 let _ = app.emit_to(
                     label,
@@ -2336,11 +2985,13 @@ async fn run_agent_loop(
                     "agent_tool_result",
                     json!({ "tool_call_id": call.id, "result": &result }),
                 );
-                conversation.push(json!({
+                let tool_msg = json!({
                     "role": "tool",
                     "tool_call_id": call.id,
                     "content": result,
-                }));
+                });
+                record(&tool_msg);
+                conversation.push(tool_msg);
                 continue;
             }
 
@@ -2351,6 +3002,12 @@ async fn run_agent_loop(
                     .get("summary")
                     .and_then(|v| v.as_str())
                     .unwrap_or("Task complete.");
+                record(&json!({
+                    "role": "tool",
+                    "tool_call_id": call.id,
+                    "content": tool_success(json!({ "submitted": true })),
+                }));
+                record(&json!({ "role": "assistant", "content": summary }));
                 let _ = app.emit_to(label, "agent_stream", summary);
                 let _ = app.emit_to(label, "agent_stream", "[DONE]");
                 return Ok(());
@@ -2359,14 +3016,19 @@ async fn run_agent_loop(
             let tool_status = match call.name.as_str() {
                 "read_file" => "Reading file...",
                 "write_file" => "Writing file...",
-                "edit_file" => "Editing file...",
+                "edit_file" | "multi_edit" | "insert_at_line" | "replace_lines" => "Editing file...",
                 "list_files" => "Exploring files...",
                 "search" => "Searching codebase...",
                 "find_file" => "Finding file...",
                 "find_symbol" => "Looking up symbol...",
+                "find_references" => "Finding references...",
+                "goto_definition" => "Finding definition...",
+                "rename_symbol" => "Renaming symbol...",
+                "get_diagnostics" => "Checking diagnostics...",
                 "todo_write" => "Updating task list...",
                 "todo_read" => "Reading task list...",
                 "run_command" => "Running command...",
+                name if mcp::is_mcp_tool(name) => "Calling MCP tool...",
                 _ => "Working...",
             };
             let _ = app.emit_to(label, "agent_status", tool_status);
@@ -2378,10 +3040,18 @@ async fn run_agent_loop(
                 json!({ "id": call.id, "name": call.name, "arguments": call.args_str }),
             );
 
-            // For run_command: request user approval before executing.
+            // For run_command and MCP tools: request user approval before executing.
             // Emit approval request, wait for frontend response (or cancel).
-            let result = if call.name == "run_command" {
-                let command_preview = args.get("command").and_then(|v| v.as_str()).unwrap_or("").to_string();
+            let needs_approval = call.name == "run_command"
+                || (mcp::is_mcp_tool(&call.name) && mcp::needs_approval(&workspace_dir, &call.name));
+            // Outcome of an edit review, when the call went through one
+            let mut reviewed: Option<Vec<edit_review::FileOutcome>> = None;
+            let result = if needs_approval {
+                let command_preview = if call.name == "run_command" {
+                    args.get("command").and_then(|v| v.as_str()).unwrap_or("").to_string()
+                } else {
+                    mcp::approval_preview(&workspace_dir, &call.name, &args)
+                };
 
                 let (approval_tx, approval_rx) = tokio::sync::oneshot::channel::<bool>();
                 {
@@ -2428,20 +3098,86 @@ async fn run_agent_loop(
                         }
                     }
                 }
+            } else if matches!(
+                call.name.as_str(),
+                "write_file" | "edit_file" | "multi_edit" | "insert_at_line" | "replace_lines" | "rename_symbol"
+            )
+                && edit_review::is_reviewing(session_id)
+            {
+                // Review mode: the tool only stages its edit. Show the diff and
+                // wait for a decision on every hunk before touching the file.
+                let result = execute_tool(&call.name, &args, &workspace_dir, Some(session_id), mode).await;
+                let staged = edit_review::take_staged(session_id);
+                if staged.is_empty() {
+                    reviewed = Some(Vec::new());
+                    result
+                } else {
+                    let (review, done_rx) = edit_review::open(session_id, &call.id, staged);
+                    let _ = app.emit_to(label, "agent_status", "Waiting for edit review...");
+                    let _ = app.emit_to(label, "agent_edit_review", review);
+
+                    tokio::select! {
+                        _ = done_rx => {}
+                        _ = async {
+                            loop {
+                                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+                                if cancel_rx.try_recv().is_ok() { break; }
+                            }
+                        } => {
+                            edit_review::end_session(session_id);
+                            let _ = app.emit_to(label, "agent_stream", "\n*[Stopped by user]*\n");
+                            let _ = app.emit_to(label, "agent_stream", "[DONE]");
+                            return Ok(());
+                        }
+                    }
+                    let outcomes = edit_review::finish(session_id);
+                    let result = edit_review::tool_result(&result, &outcomes);
+                    reviewed = Some(outcomes);
+                    result
+                }
             } else {
                 execute_tool(&call.name, &args, &workspace_dir, Some(session_id), mode).await
             };
 
             // Notify frontend of file changes so open tabs, git status, and file tree update
             // without relying solely on the OS file watcher (which can be delayed on macOS).
-            if matches!(call.name.as_str(), "write_file" | "edit_file") {
+            // A reviewed edit only touched the files it actually wrote.
+            if let Some(outcomes) = &reviewed {
+                for outcome in outcomes.iter().filter(|o| o.written) {
+                    let abs_path = outcome.full_path.to_string_lossy().to_string();
+                    let _ = app.emit_to(label, "file-changed", json!({ "path": abs_path, "kind": "modify" }));
+                }
+            } else if matches!(
+                call.name.as_str(),
+                "write_file" | "edit_file" | "multi_edit" | "insert_at_line" | "replace_lines"
+            ) {
                 if let Some(path_str) = args.get("path").and_then(|v| v.as_str()) {
                     let full_path = std::path::Path::new(&workspace_dir).join(path_str);
                     let abs_path = full_path.to_string_lossy().to_string();
                     let _ = app.emit_to(label, "file-changed", json!({ "path": abs_path, "kind": "modify" }));
                 }
+            } else if call.name == "rename_symbol" {
+                let parsed = serde_json::from_str::<serde_json::Value>(&result).unwrap_or_default();
+                for file in parsed["files"].as_array().into_iter().flatten() {
+                    if let Some(path_str) = file["path"].as_str() {
+                        let full_path = std::path::Path::new(&workspace_dir).join(path_str);
+                        let abs_path = full_path.to_string_lossy().to_string();
+                        let _ = app.emit_to(label, "file-changed", json!({ "path": abs_path, "kind": "modify" }));
+                    }
+                }
             }
 
+            // Let the model see what its edit broke without another turn
+            let result = if matches!(
+                call.name.as_str(),
+                "edit_file" | "multi_edit" | "insert_at_line" | "replace_lines"
+            ) {
+                let path = args.get("path").and_then(|v| v.as_str()).unwrap_or("");
+                with_post_edit_diagnostics(result, path, &workspace_dir).await
+            } else {
+                result
+            };
+
             // Emit full result to frontend (UI can scroll)
             let _ = app.emit_to(
                 label,
@@ -2465,22 +3201,18 @@ async fn run_agent_loop(
                 result
             };
 
-            conversation.push(json!({
+            let tool_msg = json!({
                 "role": "tool",
                 "tool_call_id": call.id,
                 "content": context_result,
-            }));
+            });
+            record(&tool_msg);
+            conversation.push(tool_msg);
         }
 
         // Reset for next iteration
         assistant_content = String::new();
     }
-
-    // Safety limit reached (should rarely happen with compaction)
-    let _ = app.emit_to(label, "agent_stream", "\n\n*Paused — send a message to continue.*");
-    let _ = app.emit_to(label, "agent_stream", "[DONE]");
-
-    Ok(())
 }
 
 #[tauri::command]
@@ -2532,8 +3264,7 @@ pub async fn clif_init_project(
 ) -> Result<(), String> {
     let app = window.app_handle().clone();
     let label = window.label().to_string();
-    let url = get_provider_url(&provider);
-    let key = api_key.or_else(|| load_api_key(&provider));
+    let ai = ai_provider::resolve(&provider, api_key)?;
 
     let clif_dir = std::path::Path::new(&workspace_dir).join(".clif");
     let _ = std::fs::create_dir_all(&clif_dir);
@@ -2562,9 +3293,7 @@ pub async fn clif_init_project(
         json!({ "role": "user", "content": "Analyze this project and write .clif/CLIF.md now." }),
     ];
 
-    let raw_tools = tool_definitions();
-    let tools = if provider == "openai" { raw_tools } else { strip_openai_fields(raw_tools) };
-    let client = reqwest::Client::new();
+    let tools = tool_definitions();
     let (cancel_tx, mut cancel_rx) = tokio::sync::oneshot::channel::<()>();
     let session_id = uuid::Uuid::new_v4().to_string();
 
@@ -2581,8 +3310,18 @@ pub async fn clif_init_project(
     let max_turns = 15;
     let mut step = 0usize;
     let start_time = std::time::Instant::now();
+    // Same token, cost and time limits as an agent run; the turn cap stays fixed
+    let settings = super::settings::get_settings().unwrap_or_default();
+    let budget = Budget {
+        max_iterations: max_turns as u32,
+        ..Budget::resolve(&settings, None)
+    };
+    let mut usage = Usage::start();
 
     for _turn in 0..max_turns {
+        if budget.exhausted(&usage).is_some() {
+            break;
+        }
         if cancel_rx.try_recv().is_ok() {
             let _ = app.emit_to(&label, "clif_init_done", json!({ "success": false, "message": "Cancelled" }));
             return Ok(());
@@ -2595,25 +3334,14 @@ pub async fn clif_init_project(
             "stream": false,
         });
 
-        let mut req = client.post(&url).header("Content-Type", "application/json");
-        if let Some(ref k) = key {
-            req = req.header("Authorization", format!("Bearer {}", k));
-        }
-        if provider == "openrouter" {
-            req = req.header("HTTP-Referer", "https://clif.dev").header("X-Title", "ClifPad");
-        }
-
-        let resp = req.json(&body).send().await.map_err(|e| format!("Request failed: {}", e))?;
-        if !resp.status().is_success() {
-            let err = resp.text().await.unwrap_or_default();
-            return Err(format!("API error: {}", err));
-        }
-
-        let resp_json: serde_json::Value = resp.json().await.map_err(|e| e.to_string())?;
-        let finish_reason = resp_json.pointer("/choices/0/finish_reason").and_then(|v| v.as_str()).unwrap_or("").to_string();
-        let content = resp_json.pointer("/choices/0/message/content").and_then(|v| v.as_str()).unwrap_or("").to_string();
-
-        let tool_calls_raw = resp_json.pointer("/choices/0/message/tool_calls").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+        let reply = ai_provider::complete(ai.as_ref(), body).await?;
+        usage.iterations += 1;
+        usage.prompt_tokens += reply.prompt_tokens;
+        usage.completion_tokens += reply.completion_tokens;
+        usage.tool_calls += reply.tool_calls.len() as u32;
+        let finish_reason = reply.finish_reason;
+        let content = reply.content;
+        let tool_calls_raw = reply.tool_calls;
 
         let assistant_msg = if tool_calls_raw.is_empty() {
             json!({ "role": "assistant", "content": content })
@@ -2643,7 +3371,7 @@ pub async fn clif_init_project(
                 }
             };
 
-            if let Err(e) = validate_tool_args(&name, &args) {
+            if let Err(e) = validate_tool_args(&name, &args, &workspace_dir) {
                 let result = tool_error("INVALID_TOOL_ARGUMENTS", e, true);
                 conversation.push(json!({ "role": "tool", "tool_call_id": id, "content": result }));
                 continue;
@@ -2692,6 +3420,7 @@ pub async fn clif_init_project(
                     "message": summary,
                     "path": clif_path.to_string_lossy(),
                     "elapsed_secs": start_time.elapsed().as_secs(),
+                    "usage": budget.usage_event(&usage, reply.prompt_tokens, reply.completion_tokens, 0),
                 }));
                 let mut sessions = AGENT_SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
                 sessions.remove(&session_id);
@@ -2708,6 +3437,8 @@ pub async fn clif_init_project(
         "success": clif_path.exists(),
         "message": "Analysis complete",
         "path": clif_path.to_string_lossy(),
+        "usage": budget.usage_event(&usage, 0, 0, 0),
+        "budget_exhausted": budget.exhausted(&usage),
     }));
     let mut sessions = AGENT_SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
     sessions.remove(&session_id);
@@ -2917,4 +3648,28 @@ mod tests {
         assert_eq!(map.len(), MAX_TRACKED_CONVERSATIONS - 1);
         assert!(!map.contains_key(&keep), "must not evict the incoming key");
     }
+
+    #[test]
+    fn splice_lines_inserts_replaces_and_deletes() {
+        let text = "a\nb\nc\n";
+        assert_eq!(splice_lines(text, 1, 0, "x"), "a\nx\nb\nc\n");
+        assert_eq!(splice_lines(text, 3, 0, "x\ny\n"), "a\nb\nc\nx\ny\n");
+        assert_eq!(splice_lines(text, 0, 2, "z"), "z\nc\n");
+        assert_eq!(splice_lines(text, 1, 1, ""), "a\nc\n");
+        // CRLF files keep CRLF; a missing final newline stays missing
+        assert_eq!(splice_lines("a\r\nb", 1, 1, "x\ny"), "a\r\nx\r\ny");
+        assert_eq!(splice_lines("a\nb", 2, 0, "c"), "a\nb\nc");
+        assert_eq!(splice_lines("", 0, 0, "x"), "x\n");
+    }
+
+    #[test]
+    fn locate_old_string_falls_back_to_whitespace_insensitive_lines() {
+        let content = "fn a() {\n    let x = 1;\n}\n";
+        assert_eq!(locate_old_string(content, "let x = 1;").as_deref(), Some("let x = 1;"));
+        assert_eq!(
+            locate_old_string(content, "let   x = 1;").as_deref(),
+            Some("    let x = 1;")
+        );
+        assert_eq!(locate_old_string(content, "let y = 2;"), None);
+    }
 }
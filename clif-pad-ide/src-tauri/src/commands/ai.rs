@@ -1,13 +1,14 @@
+use crate::services::ai_provider::{self, EventStream, StreamEvent};
+use crate::services::secret_store;
 use serde_json::json;
-use std::fs;
-use std::path::PathBuf;
 use tauri::{Emitter, Manager};
 
-#[derive(serde::Deserialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct ChatMessage {
     pub role: String,
     pub content: String,
     /// Optional base64 data URLs for image attachments (vision models)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub images: Option<Vec<String>>,
     /// OpenAI-format tool calls attached to an assistant turn. When set, the
     /// agent loop preserves them as `assistant.tool_calls` so the model
@@ -26,47 +27,7 @@ pub struct ChatMessage {
     pub name: Option<String>,
 }
 
-#[derive(serde::Serialize)]
-pub struct ModelInfo {
-    pub id: String,
-    pub name: String,
-    pub provider: String,
-}
-
-/// Get the path to the API keys storage file
-fn get_keys_file_path() -> Result<PathBuf, String> {
-    let home = dirs_next().ok_or_else(|| "Could not determine home directory".to_string())?;
-    let config_dir = home.join(".clif");
-    if !config_dir.exists() {
-        fs::create_dir_all(&config_dir)
-            .map_err(|e| format!("Failed to create config directory: {}", e))?;
-    }
-    Ok(config_dir.join("api_keys.json"))
-}
-
-/// Helper to get the home directory cross-platform
-fn dirs_next() -> Option<PathBuf> {
-    #[cfg(target_os = "macos")]
-    {
-        std::env::var("HOME").ok().map(PathBuf::from)
-    }
-    #[cfg(target_os = "windows")]
-    {
-        std::env::var("USERPROFILE").ok().map(PathBuf::from)
-    }
-    #[cfg(target_os = "linux")]
-    {
-        std::env::var("HOME").ok().map(PathBuf::from)
-    }
-}
-
-/// Get the base URL for a given provider
-fn get_provider_url(provider: &str) -> String {
-    match provider {
-        "ollama" => "http://localhost:11434/v1/chat/completions".to_string(),
-        "openrouter" | _ => "https://openrouter.ai/api/v1/chat/completions".to_string(),
-    }
-}
+pub use crate::services::ai_provider::ModelInfo;
 
 #[tauri::command]
 pub async fn ai_chat(
@@ -78,7 +39,7 @@ pub async fn ai_chat(
 ) -> Result<(), String> {
     let app = window.app_handle().clone();
     let label = window.label().to_string();
-    let url = get_provider_url(&provider);
+    let provider = ai_provider::resolve(&provider, api_key)?;
 
     // Build the messages array for the API
     let api_messages: Vec<serde_json::Value> = messages
@@ -91,45 +52,19 @@ pub async fn ai_chat(
         })
         .collect();
 
-    let mut request_body = json!({
+    let request_body = json!({
         "model": model,
         "messages": api_messages,
         "stream": true,
     });
 
-    // For OpenRouter, add extra headers via the body if needed
-    if provider == "openrouter" {
-        if let Some(obj) = request_body.as_object_mut() {
-            obj.insert(
-                "transforms".to_string(),
-                json!(["middle-out"]),
-            );
-        }
-    }
-
-    let client = reqwest::Client::new();
-    let mut req_builder = client
-        .post(&url)
-        .header("Content-Type", "application/json");
-
-    // Add authorization header if API key is provided
-    if let Some(ref key) = api_key {
-        req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
-    }
-
-    // Add OpenRouter-specific headers
-    if provider == "openrouter" {
-        req_builder = req_builder
-            .header("HTTP-Referer", "https://clif.dev")
-            .header("X-Title", "Clif");
-    }
-
     // Spawn the streaming task so we don't block the command
     tokio::spawn(async move {
-        let response = match req_builder.json(&request_body).send().await {
+        let client = reqwest::Client::new();
+        let response = match ai_provider::send(provider.as_ref(), &client, request_body).await {
             Ok(resp) => resp,
             Err(e) => {
-                let _ = app.emit_to(&label, "ai_stream_error", format!("Request failed: {}", e));
+                let _ = app.emit_to(&label, "ai_stream_error", e);
                 return;
             }
         };
@@ -146,63 +81,26 @@ pub async fn ai_chat(
         }
 
         // Read the streaming response
-        let mut stream = response.bytes_stream();
-        use futures::StreamExt;
-
-        let mut buffer = String::new();
-
-        while let Some(chunk_result) = stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    eprintln!("AI Review - Raw chunk: {} bytes", chunk_str.len());
-                    buffer.push_str(&chunk_str);
-
-                    // Process complete SSE lines from the buffer
-                    while let Some(newline_pos) = buffer.find('\n') {
-                        let line = buffer[..newline_pos].trim().to_string();
-                        buffer = buffer[newline_pos + 1..].to_string();
-
-                        if line.is_empty() {
-                            continue;
-                        }
-
-                        if line.starts_with("data: ") {
-                            let data = &line[6..];
-
-                            if data == "[DONE]" {
-                                let _ = app.emit_to(&label, "ai_stream", "[DONE]");
-                                return;
-                            }
-
-                            // Parse the JSON chunk
-                            if let Ok(parsed) =
-                                serde_json::from_str::<serde_json::Value>(data)
-                            {
-                                // Extract delta content from the streaming response
-                                if let Some(choices) = parsed.get("choices") {
-                                    if let Some(first_choice) = choices.get(0) {
-                                        if let Some(delta) = first_choice.get("delta") {
-                                            if let Some(content) =
-                                                delta.get("content").and_then(|c| c.as_str())
-                                            {
-                                                let _ = app.emit_to(&label, "ai_stream", content);
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+        let mut events = EventStream::new(provider, response);
+        while let Some(batch) = events.next().await {
+            let batch = match batch {
+                Ok(batch) => batch,
                 Err(e) => {
-                    let _ = app.emit_to(
-                        &label,
-                        "ai_stream_error",
-                        format!("Stream read error: {}", e),
-                    );
+                    let _ = app.emit_to(&label, "ai_stream_error", e);
                     return;
                 }
+            };
+            for event in batch {
+                match event {
+                    StreamEvent::Text(content) => {
+                        let _ = app.emit_to(&label, "ai_stream", content);
+                    }
+                    StreamEvent::Done => {
+                        let _ = app.emit_to(&label, "ai_stream", "[DONE]");
+                        return;
+                    }
+                    _ => {}
+                }
             }
         }
 
@@ -220,7 +118,7 @@ pub async fn ai_complete(
     api_key: Option<String>,
     provider: String,
 ) -> Result<String, String> {
-    let url = get_provider_url(&provider);
+    let provider = ai_provider::resolve(&provider, api_key)?;
 
     let request_body = json!({
         "model": model,
@@ -239,48 +137,8 @@ pub async fn ai_complete(
         "stream": false,
     });
 
-    let client = reqwest::Client::new();
-    let mut req_builder = client
-        .post(&url)
-        .header("Content-Type", "application/json");
-
-    if let Some(ref key) = api_key {
-        req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
-    }
-
-    if provider == "openrouter" {
-        req_builder = req_builder
-            .header("HTTP-Referer", "https://clif.dev")
-            .header("X-Title", "Clif");
-    }
-
-    let response = req_builder
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("API error {}: {}", status, body));
-    }
-
-    let body: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-    let completion = body
-        .get("choices")
-        .and_then(|c| c.get(0))
-        .and_then(|c| c.get("message"))
-        .and_then(|m| m.get("content"))
-        .and_then(|c| c.as_str())
-        .unwrap_or("")
-        .to_string();
-
-    Ok(completion)
+    let reply = ai_provider::complete(provider.as_ref(), request_body).await?;
+    Ok(reply.content)
 }
 
 #[tauri::command]
@@ -288,139 +146,34 @@ pub async fn get_models(
     provider: String,
     api_key: Option<String>,
 ) -> Result<Vec<ModelInfo>, String> {
-    let client = reqwest::Client::new();
-
-    match provider.as_str() {
-        "ollama" => {
-            // Fetch from Ollama's local API
-            let response = client
-                .get("http://localhost:11434/api/tags")
-                .send()
-                .await
-                .map_err(|e| format!("Failed to connect to Ollama: {}", e))?;
-
-            if !response.status().is_success() {
-                return Err("Failed to fetch Ollama models. Is Ollama running?".to_string());
-            }
-
-            let body: serde_json::Value = response
-                .json()
-                .await
-                .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
-
-            let models = body
-                .get("models")
-                .and_then(|m| m.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|m| {
-                            let name = m.get("name")?.as_str()?.to_string();
-                            Some(ModelInfo {
-                                id: name.clone(),
-                                name: name.clone(),
-                                provider: "ollama".to_string(),
-                            })
-                        })
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            Ok(models)
-        }
-        "openrouter" | _ => {
-            // Fetch from OpenRouter API
-            let mut req_builder = client.get("https://openrouter.ai/api/v1/models");
-
-            if let Some(ref key) = api_key {
-                req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
-            }
-
-            let response = req_builder
-                .send()
-                .await
-                .map_err(|e| format!("Failed to fetch models: {}", e))?;
-
-            if !response.status().is_success() {
-                return Err("Failed to fetch OpenRouter models".to_string());
-            }
-
-            let body: serde_json::Value = response
-                .json()
-                .await
-                .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-            let models = body
-                .get("data")
-                .and_then(|d| d.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|m| {
-                            let id = m.get("id")?.as_str()?.to_string();
-                            let name = m
-                                .get("name")
-                                .and_then(|n| n.as_str())
-                                .unwrap_or(&id)
-                                .to_string();
-                            Some(ModelInfo {
-                                id,
-                                name,
-                                provider: "openrouter".to_string(),
-                            })
-                        })
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            Ok(models)
-        }
-    }
+    let provider = ai_provider::resolve(&provider, api_key)?;
+    ai_provider::list_models(provider.as_ref()).await
 }
 
 #[tauri::command]
 pub async fn set_api_key(provider: String, key: String) -> Result<(), String> {
-    let keys_path = get_keys_file_path()?;
-
-    // Read existing keys or create new object
-    let mut keys: serde_json::Value = if keys_path.exists() {
-        let content =
-            fs::read_to_string(&keys_path).map_err(|e| format!("Failed to read keys file: {}", e))?;
-        serde_json::from_str(&content).unwrap_or_else(|_| json!({}))
-    } else {
-        json!({})
-    };
-
-    // Set the key for the provider
-    if let Some(obj) = keys.as_object_mut() {
-        obj.insert(provider, json!(key));
-    }
-
-    // Write back
-    let content =
-        serde_json::to_string_pretty(&keys).map_err(|e| format!("Failed to serialize keys: {}", e))?;
-    fs::write(&keys_path, content).map_err(|e| format!("Failed to write keys file: {}", e))?;
-
-    Ok(())
+    tokio::task::spawn_blocking(move || secret_store::set(&provider, &key))
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
 pub async fn get_api_key(provider: String) -> Result<Option<String>, String> {
-    let keys_path = get_keys_file_path()?;
-
-    if !keys_path.exists() {
-        return Ok(None);
-    }
-
-    let content =
-        fs::read_to_string(&keys_path).map_err(|e| format!("Failed to read keys file: {}", e))?;
-    let keys: serde_json::Value =
-        serde_json::from_str(&content).map_err(|e| format!("Failed to parse keys file: {}", e))?;
-
-    let key = keys
-        .get(&provider)
-        .and_then(|k| k.as_str())
-        .map(|s| s.to_string());
+    tokio::task::spawn_blocking(move || secret_store::get(&provider))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    Ok(key)
+/// Unlock the encrypted key file for this session. Only needed when no OS
+/// credential store is available and `CLIF_KEYS_PASSPHRASE` isn't set.
+#[tauri::command]
+pub async fn unlock_api_keys(passphrase: String) -> Result<(), String> {
+    tokio::task::spawn_blocking(move || {
+        secret_store::unlock(&passphrase)?;
+        secret_store::migrate_legacy().map(|_| ())
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
@@ -431,7 +184,7 @@ pub async fn generate_commit_message(
     api_key: Option<String>,
     provider: String,
 ) -> Result<String, String> {
-    let url = get_provider_url(&provider);
+    let provider = ai_provider::resolve(&provider, api_key)?;
 
     let files_list = staged_files.join(", ");
     let prompt = format!(
@@ -457,62 +210,20 @@ Respond with ONLY the commit message, nothing else."#,
         "content": prompt,
     })];
 
-    let mut request_body = json!({
+    let request_body = json!({
         "model": model,
         "messages": api_messages,
         "stream": false,
         "max_tokens": 100,
     });
 
-    if provider == "openrouter" {
-        if let Some(obj) = request_body.as_object_mut() {
-            obj.insert(
-                "transforms".to_string(),
-                json!(["middle-out"]),
-            );
-        }
-    }
-
-    let client = reqwest::Client::new();
-    let mut req_builder = client
-        .post(&url)
-        .header("Content-Type", "application/json");
-
-    if let Some(ref key) = api_key {
-        if provider == "openrouter" {
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", key))
-                .header("HTTP-Referer", "https://clifcode.io")
-                .header("X-Title", "ClifPad");
-        } else {
-            req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
-        }
-    }
-
-    let response = req_builder
-        .json(&request_body)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to send request: {}", e))?;
-
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
-        return Err(format!("API error {}: {}", status, body));
-    }
-
-    let json: serde_json::Value = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
-
-    let content = json["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("chore: update files")
-        .trim()
-        .to_string();
-
-    Ok(content)
+    let reply = ai_provider::complete(provider.as_ref(), request_body).await?;
+    let content = reply.content.trim();
+    Ok(if content.is_empty() {
+        "chore: update files".to_string()
+    } else {
+        content.to_string()
+    })
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -543,7 +254,7 @@ pub async fn ai_review_code(
 ) -> Result<(), String> {
     let app = window.app_handle().clone();
     let label = window.label().to_string();
-    let url = get_provider_url(&provider);
+    let provider = ai_provider::resolve(&provider, api_key)?;
 
     // Truncate diff if too long for faster processing
     let truncated_diff = if diff.len() > 6000 {
@@ -571,44 +282,20 @@ Only real issues. Be brief."#,
         "content": prompt,
     })];
 
-    let mut request_body = json!({
+    let request_body = json!({
         "model": model,
         "messages": api_messages,
         "stream": true,
         "max_tokens": 500,
     });
 
-    if provider == "openrouter" {
-        if let Some(obj) = request_body.as_object_mut() {
-            obj.insert(
-                "transforms".to_string(),
-                json!(["middle-out"]),
-            );
-        }
-    }
-
-    let client = reqwest::Client::new();
-    let mut req_builder = client
-        .post(&url)
-        .header("Content-Type", "application/json");
-
-    if let Some(ref key) = api_key {
-        if provider == "openrouter" {
-            req_builder = req_builder
-                .header("Authorization", format!("Bearer {}", key))
-                .header("HTTP-Referer", "https://clifcode.io")
-                .header("X-Title", "ClifPad");
-        } else {
-            req_builder = req_builder.header("Authorization", format!("Bearer {}", key));
-        }
-    }
-
     // Spawn streaming task
     tokio::spawn(async move {
-        let response = match req_builder.json(&request_body).send().await {
+        let client = reqwest::Client::new();
+        let response = match ai_provider::send(provider.as_ref(), &client, request_body).await {
             Ok(resp) => resp,
             Err(e) => {
-                let _ = app.emit_to(&label, "code_review_error", format!("Request failed: {}", e));
+                let _ = app.emit_to(&label, "code_review_error", e);
                 return;
             }
         };
@@ -624,60 +311,30 @@ Only real issues. Be brief."#,
         let _ = app.emit_to(&label, "code_review_start", staged_files.clone());
 
         // Stream the response
-        let mut stream = response.bytes_stream();
-        use futures::StreamExt;
-
-        let mut buffer = String::new();
+        let mut events = EventStream::new(provider, response);
         let mut full_content = String::new();
 
-        while let Some(chunk_result) = stream.next().await {
-            match chunk_result {
-                Ok(chunk) => {
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    eprintln!("AI Review - Raw chunk received: {} bytes", chunk_str.len());
-                    buffer.push_str(&chunk_str);
-
-                    while let Some(newline_pos) = buffer.find('\n') {
-                        let line = buffer[..newline_pos].trim().to_string();
-                        buffer = buffer[newline_pos + 1..].to_string();
-
-                        if line.is_empty() || !line.starts_with("data: ") {
-                            continue;
-                        }
-
-                        let data = &line[6..];
-
-                        if data == "[DONE]" {
-                            let _ = app.emit_to(&label, "code_review_done", &full_content);
-                            return;
-                        }
-
-                        if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(data) {
-                            // Log the parsed structure for debugging
-                            eprintln!("Parsed JSON: {}", serde_json::to_string(&parsed).unwrap_or_default());
-                            
-                            // Check for content in both "content" and "reasoning" fields
-                            // Some models (like GLM, DeepSeek) use "reasoning" instead of "content"
-                            let content = parsed["choices"][0]["delta"]["content"].as_str()
-                                .filter(|s| !s.is_empty())
-                                .or_else(|| parsed["choices"][0]["delta"]["reasoning"].as_str())
-                                .unwrap_or("");
-                            
-                            if !content.is_empty() {
-                                eprintln!("Code review stream content: {} bytes", content.len());
-                                full_content.push_str(content);
-                                let _ = app.emit_to(&label, "code_review_stream", content);
-                            }
-                        } else {
-                            // Log parsing failures for debugging
-                            eprintln!("Failed to parse SSE data: {}", data);
-                        }
-                    }
-                }
+        while let Some(batch) = events.next().await {
+            let batch = match batch {
+                Ok(batch) => batch,
                 Err(e) => {
-                    let _ = app.emit_to(&label, "code_review_error", format!("Stream error: {}", e));
+                    let _ = app.emit_to(&label, "code_review_error", e);
                     return;
                 }
+            };
+            for event in batch {
+                match event {
+                    // Some models (like GLM, DeepSeek) stream "reasoning" instead of "content"
+                    StreamEvent::Text(content) | StreamEvent::Reasoning(content) => {
+                        full_content.push_str(&content);
+                        let _ = app.emit_to(&label, "code_review_stream", content);
+                    }
+                    StreamEvent::Done => {
+                        let _ = app.emit_to(&label, "code_review_done", &full_content);
+                        return;
+                    }
+                    _ => {}
+                }
             }
         }
 
@@ -0,0 +1,315 @@
+//! Per-run budgets for the IDE agent.
+//!
+//! A run may be capped on model iterations, wall-clock time, tokens and
+//! dollar cost. Limits come from `~/.clif/settings.json` (`agentMaxIterations`,
+//! `agentMaxSeconds`, `agentMaxTokens`, `agentMaxCostUsd`) and can be
+//! overridden per request through the `budget` object of the `context` JSON.
+//! The agent loop reports a [`Usage`] snapshot as `agent_usage` after every
+//! model call and stops with a summary once [`Budget::exhausted`] fires.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+
+/// Iteration cap when nothing is configured. Compaction handles context, so
+/// this only guards against a model that never stops calling tools.
+const DEFAULT_MAX_ITERATIONS: u32 = 200;
+
+/// USD per token when the frontend does not know the model's pricing. Matches
+/// the $3 / $15 per million estimate shown in the chat footer.
+const DEFAULT_PROMPT_PRICE: f64 = 3.0 / 1_000_000.0;
+const DEFAULT_COMPLETION_PRICE: f64 = 15.0 / 1_000_000.0;
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Budget {
+    pub max_iterations: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_cost_usd: Option<f64>,
+    /// USD per prompt token
+    #[serde(skip)]
+    pub prompt_price: f64,
+    /// USD per completion token
+    #[serde(skip)]
+    pub completion_price: f64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_seconds: None,
+            max_tokens: None,
+            max_cost_usd: None,
+            prompt_price: DEFAULT_PROMPT_PRICE,
+            completion_price: DEFAULT_COMPLETION_PRICE,
+        }
+    }
+}
+
+/// Which limit ended the run
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Exhausted {
+    Iterations,
+    Time,
+    Tokens,
+    Cost,
+}
+
+impl Exhausted {
+    fn describe(self) -> &'static str {
+        match self {
+            Exhausted::Iterations => "iteration limit",
+            Exhausted::Time => "time limit",
+            Exhausted::Tokens => "token budget",
+            Exhausted::Cost => "cost budget",
+        }
+    }
+}
+
+/// Running totals for one agent run
+#[derive(Clone, Debug)]
+pub(crate) struct Usage {
+    started: Instant,
+    pub iterations: u32,
+    pub tool_calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    pub fn start() -> Self {
+        Usage {
+            started: Instant::now(),
+            iterations: 0,
+            tool_calls: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// A positive number from a JSON value, accepting numeric strings
+fn positive(value: Option<&Value>) -> Option<f64> {
+    let n = match value? {
+        Value::Number(n) => n.as_f64()?,
+        Value::String(s) => s.trim().parse::<f64>().ok()?,
+        _ => return None,
+    };
+    (n.is_finite() && n > 0.0).then_some(n)
+}
+
+impl Budget {
+    /// Settings defaults, overridden by the request's `context.budget`.
+    /// `context.pricing` carries the model's per-token prices as the
+    /// OpenRouter model list reports them.
+    pub fn resolve(settings: &Value, context: Option<&str>) -> Self {
+        let mut budget = Budget::default();
+        budget.apply(
+            positive(settings.get("agentMaxIterations")),
+            positive(settings.get("agentMaxSeconds")),
+            positive(settings.get("agentMaxTokens")),
+            positive(settings.get("agentMaxCostUsd")),
+        );
+
+        let parsed = context.and_then(|ctx| serde_json::from_str::<Value>(ctx).ok());
+        if let Some(overrides) = parsed.as_ref().and_then(|v| v.get("budget")) {
+            budget.apply(
+                positive(overrides.get("maxIterations")),
+                positive(overrides.get("maxSeconds")),
+                positive(overrides.get("maxTokens")),
+                positive(overrides.get("maxCostUsd")),
+            );
+        }
+        if let Some(pricing) = parsed.as_ref().and_then(|v| v.get("pricing")) {
+            // Free models report "0", which `positive` rejects on purpose —
+            // only replace the estimate when both prices are usable.
+            let prompt = pricing.get("prompt").and_then(|p| match p {
+                Value::String(s) => s.trim().parse::<f64>().ok(),
+                other => other.as_f64(),
+            });
+            let completion = pricing.get("completion").and_then(|p| match p {
+                Value::String(s) => s.trim().parse::<f64>().ok(),
+                other => other.as_f64(),
+            });
+            if let (Some(p), Some(c)) = (prompt, completion) {
+                if p >= 0.0 && c >= 0.0 {
+                    budget.prompt_price = p;
+                    budget.completion_price = c;
+                }
+            }
+        }
+        budget
+    }
+
+    fn apply(
+        &mut self,
+        iterations: Option<f64>,
+        seconds: Option<f64>,
+        tokens: Option<f64>,
+        cost: Option<f64>,
+    ) {
+        if let Some(n) = iterations {
+            self.max_iterations = n.max(1.0) as u32;
+        }
+        if let Some(n) = seconds {
+            self.max_seconds = Some(n.ceil() as u64);
+        }
+        if let Some(n) = tokens {
+            self.max_tokens = Some(n as u64);
+        }
+        if let Some(n) = cost {
+            self.max_cost_usd = Some(n);
+        }
+    }
+
+    pub fn cost_usd(&self, usage: &Usage) -> f64 {
+        usage.prompt_tokens as f64 * self.prompt_price
+            + usage.completion_tokens as f64 * self.completion_price
+    }
+
+    /// The first limit `usage` has reached, if any
+    pub fn exhausted(&self, usage: &Usage) -> Option<Exhausted> {
+        if usage.iterations >= self.max_iterations {
+            return Some(Exhausted::Iterations);
+        }
+        if self
+            .max_seconds
+            .is_some_and(|s| usage.elapsed() >= Duration::from_secs(s))
+        {
+            return Some(Exhausted::Time);
+        }
+        if self.max_tokens.is_some_and(|t| usage.total_tokens() >= t) {
+            return Some(Exhausted::Tokens);
+        }
+        if self.max_cost_usd.is_some_and(|c| self.cost_usd(usage) >= c) {
+            return Some(Exhausted::Cost);
+        }
+        None
+    }
+
+    /// Payload for the `agent_usage` event. The per-call token counts keep
+    /// the fields the frontend already accumulates; the rest are run totals.
+    pub fn usage_event(
+        &self,
+        usage: &Usage,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+        estimated_context: u64,
+    ) -> Value {
+        serde_json::json!({
+            "prompt_tokens": prompt_tokens,
+            "completion_tokens": completion_tokens,
+            "estimated_context": estimated_context,
+            "iterations": usage.iterations,
+            "tool_calls": usage.tool_calls,
+            "elapsed_ms": usage.elapsed().as_millis() as u64,
+            "total_tokens": usage.total_tokens(),
+            "cost_usd": self.cost_usd(usage),
+            "budget": self,
+        })
+    }
+
+    /// Markdown note shown to the user when a run stops on a budget
+    pub fn summary(&self, usage: &Usage, reason: Exhausted) -> String {
+        let limit = match reason {
+            Exhausted::Iterations => format!("{} iterations", self.max_iterations),
+            Exhausted::Time => format!("{}s", self.max_seconds.unwrap_or_default()),
+            Exhausted::Tokens => format!("{} tokens", self.max_tokens.unwrap_or_default()),
+            Exhausted::Cost => format!("${:.2}", self.max_cost_usd.unwrap_or_default()),
+        };
+        format!(
+            "\n\n*Stopped: {} reached ({}). Used {} iterations, {} tool calls, {} tokens (~${:.2}) in {}s. Send a message to continue.*",
+            reason.describe(),
+            limit,
+            usage.iterations,
+            usage.tool_calls,
+            usage.total_tokens(),
+            self.cost_usd(usage),
+            usage.elapsed().as_secs(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn resolve_defaults_without_settings_or_context() {
+        let budget = Budget::resolve(&json!({}), None);
+        assert_eq!(budget, Budget::default());
+        assert_eq!(budget.max_iterations, DEFAULT_MAX_ITERATIONS);
+    }
+
+    #[test]
+    fn context_overrides_settings() {
+        let settings = json!({ "agentMaxIterations": 50, "agentMaxCostUsd": 2.0 });
+        let context = json!({ "budget": { "maxIterations": 5, "maxSeconds": "90" } }).to_string();
+        let budget = Budget::resolve(&settings, Some(&context));
+        assert_eq!(budget.max_iterations, 5);
+        assert_eq!(budget.max_seconds, Some(90));
+        assert_eq!(budget.max_cost_usd, Some(2.0));
+        assert_eq!(budget.max_tokens, None);
+    }
+
+    #[test]
+    fn non_positive_limits_are_ignored() {
+        let settings = json!({ "agentMaxTokens": 0, "agentMaxIterations": -3 });
+        let budget = Budget::resolve(&settings, None);
+        assert_eq!(budget.max_tokens, None);
+        assert_eq!(budget.max_iterations, DEFAULT_MAX_ITERATIONS);
+    }
+
+    #[test]
+    fn pricing_from_context_drives_cost() {
+        let context = json!({ "pricing": { "prompt": "0.000001", "completion": "0.000002" } }).to_string();
+        let budget = Budget::resolve(&json!({}), Some(&context));
+        let mut usage = Usage::start();
+        usage.prompt_tokens = 1_000_000;
+        usage.completion_tokens = 500_000;
+        assert!((budget.cost_usd(&usage) - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn exhausted_reports_first_limit_hit() {
+        let budget = Budget {
+            max_iterations: 10,
+            max_tokens: Some(1_000),
+            max_cost_usd: Some(0.01),
+            ..Budget::default()
+        };
+        let mut usage = Usage::start();
+        assert_eq!(budget.exhausted(&usage), None);
+
+        usage.prompt_tokens = 1_200;
+        assert_eq!(budget.exhausted(&usage), Some(Exhausted::Tokens));
+
+        usage.iterations = 10;
+        assert_eq!(budget.exhausted(&usage), Some(Exhausted::Iterations));
+    }
+
+    #[test]
+    fn cost_limit_uses_default_pricing() {
+        let budget = Budget {
+            max_cost_usd: Some(0.5),
+            ..Budget::default()
+        };
+        let mut usage = Usage::start();
+        usage.completion_tokens = 40_000; // $0.60 at $15/M
+        assert_eq!(budget.exhausted(&usage), Some(Exhausted::Cost));
+    }
+}
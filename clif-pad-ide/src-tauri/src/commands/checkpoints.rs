@@ -0,0 +1,628 @@
+//! Per-turn checkpoints of the files the IDE agent touches.
+//!
+//! `agent_chat` opens a checkpoint at the start of every user turn. The first
+//! time the turn writes, creates or removes a file, its previous bytes (or
+//! the fact that it did not exist) are copied to
+//! `~/.clif/checkpoints/<workspace-hash>/<thread id>/<checkpoint id>/`.
+//! Restoring a checkpoint puts those files back, undoing that turn and every
+//! later one, and rewinds the thread to the messages before the turn. Only
+//! recorded files are touched, so this works without git and never reverts
+//! edits the agent didn't make.
+//!
+//! `run_command` can't say which files it will touch, so the workspace file
+//! list is compared before and after each command. Files a command creates are
+//! recorded like any other and removed on restore. Existing files a command
+//! modifies or deletes are only noted as unrestorable: their old bytes were
+//! never copied. The scan skips dependency and build directories
+//! (`node_modules`, `target`, ...) and `.clif`, where agent worktrees live,
+//! and gives up on workspaces over `SCAN_MAX_FILES` files.
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::Emitter;
+
+use super::agent::TodoItem;
+use super::conversations;
+
+const LABEL_CHARS: usize = 80;
+const SCAN_MAX_FILES: usize = 20_000;
+const SCAN_SKIP_DIRS: &[&str] = &[
+    ".git",
+    // Agent worktrees and other IDE state
+    ".clif",
+    "node_modules",
+    "target",
+    "dist",
+    ".next",
+    "__pycache__",
+    ".venv",
+    "build",
+    "out",
+];
+
+// Checkpoint of the running turn: session_id -> checkpoint
+static ACTIVE: LazyLock<Mutex<HashMap<String, Checkpoint>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct FileSnapshot {
+    path: PathBuf,
+    /// `false` when the turn created the file; restoring removes it
+    existed: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Manifest {
+    id: String,
+    created_at: u64,
+    /// Thread length before the turn's user message
+    message_count: usize,
+    label: String,
+    read_files: Vec<PathBuf>,
+    todos: Vec<TodoItem>,
+    /// Blob `i` under `blobs/` holds the old bytes of `files[i]`
+    files: Vec<FileSnapshot>,
+    /// Existing files a shell command changed or deleted; not snapshotted
+    #[serde(default)]
+    unrestorable: Vec<PathBuf>,
+}
+
+/// Checkpoint being filled by a running turn. Nothing is written until the
+/// turn first modifies a file, so read-only turns leave no checkpoint.
+pub(crate) struct Checkpoint {
+    /// The thread's checkpoint directory
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CheckpointSummary {
+    pub id: String,
+    pub created_at: u64,
+    pub message_count: usize,
+    /// Start of the user message that began the turn
+    pub label: String,
+    pub files: Vec<String>,
+    pub unrestorable: Vec<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RestoredCheckpoint {
+    pub message_count: usize,
+    pub restored: Vec<String>,
+    pub removed: Vec<String>,
+    /// Files commands changed in the undone turns that could not be put back
+    pub unrestorable: Vec<String>,
+}
+
+/// Paths `restore` rewrote, removed, and could not restore
+#[derive(Debug, PartialEq)]
+struct Restored {
+    restored: Vec<PathBuf>,
+    removed: Vec<PathBuf>,
+    unrestorable: Vec<PathBuf>,
+}
+
+/// Size and modification time of every workspace file, taken before a command
+pub(crate) struct FileScan(HashMap<PathBuf, (u64, Option<SystemTime>)>);
+
+impl Checkpoint {
+    fn record(&mut self, path: &Path) -> Result<(), String> {
+        if self.manifest.files.iter().any(|f| f.path == path) {
+            return Ok(());
+        }
+        let blobs = self.dir.join(&self.manifest.id).join("blobs");
+        fs::create_dir_all(&blobs).map_err(|e| format!("Failed to create checkpoint: {}", e))?;
+        let existed = match fs::read(path) {
+            Ok(bytes) => {
+                let blob = blobs.join(self.manifest.files.len().to_string());
+                fs::write(&blob, bytes).map_err(|e| format!("Failed to save checkpoint: {}", e))?;
+                true
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+            Err(e) => return Err(format!("Failed to snapshot {}: {}", path.display(), e)),
+        };
+        self.manifest.files.push(FileSnapshot {
+            path: path.to_path_buf(),
+            existed,
+        });
+        write_manifest(&self.dir, &self.manifest)
+    }
+
+    /// Record a file that did not exist before the turn but is there now
+    fn record_created(&mut self, path: &Path) -> Result<(), String> {
+        if self.manifest.files.iter().any(|f| f.path == path) {
+            return Ok(());
+        }
+        fs::create_dir_all(self.dir.join(&self.manifest.id))
+            .map_err(|e| format!("Failed to create checkpoint: {}", e))?;
+        self.manifest.files.push(FileSnapshot {
+            path: path.to_path_buf(),
+            existed: false,
+        });
+        write_manifest(&self.dir, &self.manifest)
+    }
+
+    /// Apply the difference between two scans of the workspace
+    fn record_scan_changes(&mut self, before: &FileScan, after: &FileScan) -> Result<(), String> {
+        let mut created: Vec<&PathBuf> = after
+            .0
+            .keys()
+            .filter(|p| !before.0.contains_key(*p))
+            .collect();
+        created.sort();
+        for path in created {
+            self.record_created(path)?;
+        }
+
+        let mut changed: Vec<&PathBuf> = before
+            .0
+            .iter()
+            .filter(|(p, meta)| after.0.get(*p) != Some(meta))
+            .map(|(p, _)| p)
+            .collect();
+        changed.sort();
+        let mut noted = false;
+        for path in changed {
+            // Snapshotted earlier in the turn: restore already covers it
+            if self.manifest.files.iter().any(|f| &f.path == path)
+                || self.manifest.unrestorable.contains(path)
+            {
+                continue;
+            }
+            self.manifest.unrestorable.push(path.clone());
+            noted = true;
+        }
+        if noted {
+            fs::create_dir_all(self.dir.join(&self.manifest.id))
+                .map_err(|e| format!("Failed to create checkpoint: {}", e))?;
+            write_manifest(&self.dir, &self.manifest)?;
+        }
+        Ok(())
+    }
+}
+
+/// Open the checkpoint for a new turn of `conversation_id`
+pub(crate) fn begin(
+    session_id: &str,
+    workspace_dir: &str,
+    conversation_id: &str,
+    message_count: usize,
+    user_message: &str,
+    read_files: &HashSet<PathBuf>,
+    todos: &[TodoItem],
+) {
+    let dir = match thread_dir(workspace_dir, conversation_id) {
+        Ok(dir) => dir,
+        Err(e) => {
+            log::warn!("checkpoints disabled for this turn: {}", e);
+            return;
+        }
+    };
+    let created_at = now_ms();
+    let mut read_files: Vec<PathBuf> = read_files.iter().cloned().collect();
+    read_files.sort();
+    let checkpoint = Checkpoint {
+        dir,
+        manifest: Manifest {
+            id: format!(
+                "{}-{}",
+                created_at,
+                &uuid::Uuid::new_v4().simple().to_string()[..6]
+            ),
+            created_at,
+            message_count,
+            label: label_from(user_message),
+            read_files,
+            todos: todos.to_vec(),
+            files: Vec::new(),
+            unrestorable: Vec::new(),
+        },
+    };
+    if let Ok(mut active) = ACTIVE.lock() {
+        active.insert(session_id.to_string(), checkpoint);
+    }
+}
+
+/// Snapshot `path` before the session's turn first modifies it
+pub(crate) fn record(session_id: Option<&str>, path: &Path) {
+    let Some(sid) = session_id else { return };
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(checkpoint) = active.get_mut(sid) {
+        if let Err(e) = checkpoint.record(path) {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+/// Scan the workspace before a shell command, if the session's turn has a
+/// checkpoint open
+pub(crate) fn scan_before_command(
+    session_id: Option<&str>,
+    workspace_dir: &str,
+) -> Option<FileScan> {
+    let sid = session_id?;
+    let open = ACTIVE
+        .lock()
+        .map(|active| active.contains_key(sid))
+        .unwrap_or(false);
+    if !open {
+        return None;
+    }
+    scan(Path::new(workspace_dir))
+}
+
+/// Record the files a shell command created, changed or deleted since `before`
+pub(crate) fn record_command_changes(
+    session_id: Option<&str>,
+    workspace_dir: &str,
+    before: FileScan,
+) {
+    let Some(sid) = session_id else { return };
+    let Some(after) = scan(Path::new(workspace_dir)) else {
+        return;
+    };
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(checkpoint) = active.get_mut(sid) {
+        if let Err(e) = checkpoint.record_scan_changes(&before, &after) {
+            log::warn!("{}", e);
+        }
+    }
+}
+
+/// Every file under `root` outside `SCAN_SKIP_DIRS`; `None` past `SCAN_MAX_FILES`
+fn scan(root: &Path) -> Option<FileScan> {
+    let mut files = HashMap::new();
+    for entry in walkdir::WalkDir::new(root)
+        .follow_links(false)
+        .into_iter()
+        .filter_entry(|e| {
+            !(e.depth() > 0
+                && e.file_type().is_dir()
+                && SCAN_SKIP_DIRS.contains(&e.file_name().to_string_lossy().as_ref()))
+        })
+    {
+        let Ok(entry) = entry else { continue };
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let Ok(meta) = entry.metadata() else { continue };
+        if files.len() >= SCAN_MAX_FILES {
+            log::warn!(
+                "workspace has over {} files; shell command changes are not checkpointed",
+                SCAN_MAX_FILES
+            );
+            return None;
+        }
+        files.insert(entry.into_path(), (meta.len(), meta.modified().ok()));
+    }
+    Some(FileScan(files))
+}
+
+pub(crate) fn end(session_id: &str) {
+    if let Ok(mut active) = ACTIVE.lock() {
+        active.remove(session_id);
+    }
+}
+
+/// Checkpoints of a thread, newest first
+#[tauri::command]
+pub fn agent_list_checkpoints(
+    workspace_dir: String,
+    conversation_id: String,
+) -> Result<Vec<CheckpointSummary>, String> {
+    let dir = thread_dir(&workspace_dir, &conversation_id)?;
+    let root = Path::new(&workspace_dir);
+    Ok(list(&dir)
+        .into_iter()
+        .rev()
+        .map(|m| CheckpointSummary {
+            files: m.files.iter().map(|f| display(root, &f.path)).collect(),
+            unrestorable: m.unrestorable.iter().map(|p| display(root, p)).collect(),
+            id: m.id,
+            created_at: m.created_at,
+            message_count: m.message_count,
+            label: m.label,
+        })
+        .collect())
+}
+
+/// Undo the checkpoint's turn and every later one: put recorded files back,
+/// remove files those turns created, and rewind the thread
+#[tauri::command]
+pub fn agent_restore_checkpoint(
+    window: tauri::Window,
+    workspace_dir: String,
+    conversation_id: String,
+    checkpoint_id: String,
+) -> Result<RestoredCheckpoint, String> {
+    let dir = thread_dir(&workspace_dir, &conversation_id)?;
+    let running = ACTIVE
+        .lock()
+        .map(|active| active.values().any(|c| c.dir == dir))
+        .unwrap_or(false);
+    if running {
+        return Err("Stop the agent before restoring a checkpoint".into());
+    }
+
+    let (manifest, paths) = restore(&dir, &checkpoint_id)?;
+    let read_files: HashSet<PathBuf> = manifest.read_files.iter().cloned().collect();
+    conversations::rewind(
+        &workspace_dir,
+        &conversation_id,
+        manifest.message_count,
+        &read_files,
+        &manifest.todos,
+    )?;
+    // Drop the in-memory read-set and todos so the next turn picks up the
+    // rewound ones from the thread
+    super::agent::agent_clear_conversation(window.clone(), conversation_id)?;
+
+    for (changed, kind) in [(&paths.restored, "modify"), (&paths.removed, "remove")] {
+        for path in changed {
+            let _ = window.emit(
+                "file-changed",
+                json!({ "path": path.to_string_lossy(), "kind": kind }),
+            );
+        }
+    }
+    let root = Path::new(&workspace_dir);
+    Ok(RestoredCheckpoint {
+        message_count: manifest.message_count,
+        restored: paths.restored.iter().map(|p| display(root, p)).collect(),
+        removed: paths.removed.iter().map(|p| display(root, p)).collect(),
+        unrestorable: paths
+            .unrestorable
+            .iter()
+            .map(|p| display(root, p))
+            .collect(),
+    })
+}
+
+/// Oldest first
+fn list(dir: &Path) -> Vec<Manifest> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut manifests: Vec<Manifest> = entries
+        .flatten()
+        .filter_map(|e| fs::read_to_string(e.path().join("manifest.json")).ok())
+        .filter_map(|raw| serde_json::from_str(&raw).ok())
+        .collect();
+    manifests.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+    manifests
+}
+
+/// Restore `checkpoint_id` and delete it along with every later checkpoint.
+/// Returns its manifest and the paths rewritten, removed, and left as they are.
+fn restore(dir: &Path, checkpoint_id: &str) -> Result<(Manifest, Restored), String> {
+    let all = list(dir);
+    let start = all
+        .iter()
+        .position(|m| m.id == checkpoint_id)
+        .ok_or_else(|| format!("No checkpoint '{}'", checkpoint_id))?;
+    let undone = &all[start..];
+
+    // A file recorded by several turns goes back to its oldest snapshot
+    let mut oldest: HashMap<&Path, (&Manifest, usize)> = HashMap::new();
+    for manifest in undone.iter().rev() {
+        for (i, file) in manifest.files.iter().enumerate() {
+            oldest.insert(&file.path, (manifest, i));
+        }
+    }
+
+    // Changed by a command without a snapshot from an earlier undone turn
+    let mut unrestorable: Vec<PathBuf> = undone
+        .iter()
+        .flat_map(|m| m.unrestorable.iter())
+        .filter(|p| !oldest.contains_key(p.as_path()))
+        .cloned()
+        .collect();
+    unrestorable.sort();
+    unrestorable.dedup();
+
+    let mut restored = Vec::new();
+    let mut removed = Vec::new();
+    for (path, (manifest, i)) in oldest {
+        if manifest.files[i].existed {
+            let blob = dir.join(&manifest.id).join("blobs").join(i.to_string());
+            let bytes =
+                fs::read(&blob).map_err(|e| format!("Checkpoint is missing data: {}", e))?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::write(path, bytes)
+                .map_err(|e| format!("Failed to restore {}: {}", path.display(), e))?;
+            restored.push(path.to_path_buf());
+        } else {
+            match fs::remove_file(path) {
+                Ok(()) => removed.push(path.to_path_buf()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to remove {}: {}", path.display(), e)),
+            }
+        }
+    }
+    restored.sort();
+    removed.sort();
+
+    for manifest in undone {
+        let _ = fs::remove_dir_all(dir.join(&manifest.id));
+    }
+    Ok((
+        undone[0].clone(),
+        Restored {
+            restored,
+            removed,
+            unrestorable,
+        },
+    ))
+}
+
+fn write_manifest(dir: &Path, manifest: &Manifest) -> Result<(), String> {
+    let cp_dir = dir.join(&manifest.id);
+    let raw = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+    let tmp = cp_dir.join("manifest.json.tmp");
+    fs::write(&tmp, raw).map_err(|e| format!("Failed to save checkpoint: {}", e))?;
+    fs::rename(&tmp, cp_dir.join("manifest.json"))
+        .map_err(|e| format!("Failed to save checkpoint: {}", e))
+}
+
+fn thread_dir(workspace_dir: &str, conversation_id: &str) -> Result<PathBuf, String> {
+    if workspace_dir.trim().is_empty() {
+        return Err("No workspace open".into());
+    }
+    conversations::validate_id(conversation_id)?;
+    let home = super::settings::get_home_dir().ok_or("Could not determine home directory")?;
+    let key = format!("{:x}", super::settings::md5_hash(workspace_dir));
+    Ok(home
+        .join(".clif")
+        .join("checkpoints")
+        .join(key)
+        .join(conversation_id))
+}
+
+fn display(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn label_from(message: &str) -> String {
+    let flat = message.split_whitespace().collect::<Vec<_>>().join(" ");
+    match flat.char_indices().nth(LABEL_CHARS) {
+        Some((cut, _)) => format!("{}...", &flat[..cut]),
+        None => flat,
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(dir: &Path, id: &str, created_at: u64, message_count: usize) -> Checkpoint {
+        Checkpoint {
+            dir: dir.to_path_buf(),
+            manifest: Manifest {
+                id: id.into(),
+                created_at,
+                message_count,
+                label: format!("turn {}", id),
+                read_files: Vec::new(),
+                todos: Vec::new(),
+                files: Vec::new(),
+                unrestorable: Vec::new(),
+            },
+        }
+    }
+
+    #[test]
+    fn restore_undoes_later_turns_and_leaves_other_files_alone() {
+        let base = std::env::temp_dir().join(format!("clif-cp-{}", uuid::Uuid::new_v4()));
+        let (work, store) = (base.join("work"), base.join("store"));
+        fs::create_dir_all(&work).unwrap();
+        let (a, b, other) = (
+            work.join("a.txt"),
+            work.join("new/b.txt"),
+            work.join("other.txt"),
+        );
+        fs::write(&a, "v1").unwrap();
+        fs::write(&other, "mine").unwrap();
+
+        // Turn 1 edits a.txt
+        let mut first = checkpoint(&store, "1", 1, 0);
+        first.record(&a).unwrap();
+        fs::write(&a, "v2").unwrap();
+        // Turn 2 edits a.txt again and creates new/b.txt
+        let mut second = checkpoint(&store, "2", 2, 3);
+        second.record(&a).unwrap();
+        second.record(&a).unwrap();
+        fs::create_dir_all(b.parent().unwrap()).unwrap();
+        second.record(&b).unwrap();
+        fs::write(&a, "v3").unwrap();
+        fs::write(&b, "created").unwrap();
+        assert_eq!(list(&store).len(), 2);
+        assert_eq!(list(&store)[1].files.len(), 2);
+
+        // Undo turn 2 only
+        let (manifest, paths) = restore(&store, "2").unwrap();
+        assert_eq!(manifest.message_count, 3);
+        assert_eq!(
+            paths,
+            Restored {
+                restored: vec![a.clone()],
+                removed: vec![b.clone()],
+                unrestorable: Vec::new(),
+            }
+        );
+        assert_eq!(fs::read_to_string(&a).unwrap(), "v2");
+        assert_eq!(list(&store).len(), 1);
+
+        // Undo turn 1: back to the original
+        fs::write(&a, "v4").unwrap();
+        restore(&store, "1").unwrap();
+        assert_eq!(fs::read_to_string(&a).unwrap(), "v1");
+        assert_eq!(fs::read_to_string(&other).unwrap(), "mine");
+        assert!(list(&store).is_empty());
+        assert!(restore(&store, "1").is_err());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn shell_command_changes_are_recorded_from_workspace_scans() {
+        let base = std::env::temp_dir().join(format!("clif-cp-{}", uuid::Uuid::new_v4()));
+        let (work, store) = (base.join("work"), base.join("store"));
+        fs::create_dir_all(work.join("node_modules")).unwrap();
+        let (edited, kept, doomed, created, dep) = (
+            work.join("edited.txt"),
+            work.join("kept.txt"),
+            work.join("doomed.txt"),
+            work.join("gen/out.txt"),
+            work.join("node_modules/dep.js"),
+        );
+        for path in [&edited, &kept, &doomed] {
+            fs::write(path, "before").unwrap();
+        }
+
+        let mut turn = checkpoint(&store, "1", 1, 0);
+        // The agent edits one file itself, then a command rewrites it too
+        turn.record(&edited).unwrap();
+        let before = scan(&work).unwrap();
+        fs::write(&edited, "by the command").unwrap();
+        fs::write(&kept, "changed by the command").unwrap();
+        fs::remove_file(&doomed).unwrap();
+        fs::create_dir_all(created.parent().unwrap()).unwrap();
+        fs::write(&created, "new").unwrap();
+        fs::write(&dep, "ignored").unwrap();
+        fs::create_dir_all(work.join(".clif/worktrees/agent-1")).unwrap();
+        fs::write(work.join(".clif/worktrees/agent-1/a.txt"), "checkout").unwrap();
+        turn.record_scan_changes(&before, &scan(&work).unwrap())
+            .unwrap();
+
+        let (_, paths) = restore(&store, "1").unwrap();
+        assert_eq!(
+            paths,
+            Restored {
+                restored: vec![edited.clone()],
+                removed: vec![created.clone()],
+                unrestorable: vec![doomed.clone(), kept.clone()],
+            }
+        );
+        assert_eq!(fs::read_to_string(&edited).unwrap(), "before");
+        assert!(!created.exists());
+        assert!(dep.exists());
+        let _ = fs::remove_dir_all(&base);
+    }
+}
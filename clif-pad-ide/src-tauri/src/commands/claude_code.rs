@@ -98,9 +98,21 @@ async fn run_claude_process(
 ) -> Result<(), String> {
     let claude_bin = resolve_claude_path();
 
-    let mut child = Command::new(&claude_bin)
-        .arg("-p")
-        .arg(task)
+    let mut cmd = Command::new(&claude_bin);
+    cmd.arg("-p").arg(task);
+    // Give Claude ClifPad's index and review results as MCP tools. Both
+    // flags take several values, so they go after the prompt.
+    match super::mcp::server::endpoint(working_dir).await {
+        Ok(endpoint) => {
+            cmd.arg("--allowedTools")
+                .arg("mcp__clifpad")
+                .arg("--mcp-config")
+                .arg(endpoint.config.to_string());
+        }
+        Err(e) => log::warn!("starting claude without ClifPad MCP tools: {}", e),
+    }
+
+    let mut child = cmd
         .current_dir(working_dir)
         .env("TERM", "dumb")
         .env("NO_COLOR", "1")
@@ -0,0 +1,726 @@
+//! Agent conversations owned by the backend.
+//!
+//! Each chat thread is an append-only JSONL log under
+//! `~/.clif/conversations/<workspace-hash>/<id>.jsonl`. `agent_chat` records
+//! every user, assistant and tool message as it happens plus a snapshot of
+//! the read-set and todo list at the end of each turn, so a thread resumes
+//! after a restart with the same history and `edit_file` invariants.
+//!
+//! The thread id is the frontend tab id (`default`, `chat-…`, `pr-123`).
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::agent::TodoItem;
+use super::ai::ChatMessage;
+
+/// Serializes appends so concurrent turns never interleave partial lines
+static APPEND_LOCK: Mutex<()> = Mutex::new(());
+
+const TITLE_CHARS: usize = 48;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Created {
+        title: String,
+        workspace: String,
+        created_at: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        forked_from: Option<String>,
+    },
+    Message {
+        at: u64,
+        message: ChatMessage,
+    },
+    Renamed {
+        at: u64,
+        title: String,
+    },
+    /// Read-set and todos at the end of a turn; the last one wins
+    State {
+        at: u64,
+        read_files: Vec<PathBuf>,
+        todos: Vec<TodoItem>,
+    },
+    /// A checkpoint restore dropped every message after the first
+    /// `message_count`
+    Rewound {
+        at: u64,
+        message_count: usize,
+    },
+}
+
+/// A thread rebuilt from its log
+struct Conversation {
+    id: String,
+    title: String,
+    created_at: u64,
+    updated_at: u64,
+    forked_from: Option<String>,
+    messages: Vec<ChatMessage>,
+    read_files: HashSet<PathBuf>,
+    todos: Vec<TodoItem>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub message_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ConversationDetail {
+    #[serde(flatten)]
+    pub summary: ConversationSummary,
+    pub messages: Vec<ChatMessage>,
+    pub todos: Vec<TodoItem>,
+}
+
+/// Appends to one thread's log. Write failures are logged, never fatal —
+/// losing persistence must not abort a running agent turn.
+#[derive(Clone)]
+pub(crate) struct Recorder {
+    path: PathBuf,
+}
+
+impl Recorder {
+    /// Record an OpenAI-format message from the agent loop
+    pub(crate) fn message(&self, wire: &Value) {
+        self.append(&[Entry::Message {
+            at: now_ms(),
+            message: from_wire(wire),
+        }]);
+    }
+
+    pub(crate) fn state(&self, read_files: &HashSet<PathBuf>, todos: &[TodoItem]) {
+        let mut read_files: Vec<PathBuf> = read_files.iter().cloned().collect();
+        read_files.sort();
+        self.append(&[Entry::State {
+            at: now_ms(),
+            read_files,
+            todos: todos.to_vec(),
+        }]);
+    }
+
+    fn append(&self, entries: &[Entry]) {
+        if let Err(e) = append(&self.path, entries) {
+            log::warn!("failed to record conversation: {}", e);
+        }
+    }
+}
+
+/// What `agent_chat` needs to continue a thread
+pub(crate) struct Resumed {
+    pub messages: Vec<ChatMessage>,
+    pub read_files: HashSet<PathBuf>,
+    pub todos: Vec<TodoItem>,
+    pub recorder: Recorder,
+}
+
+/// Open a thread for a new user turn. The frontend still sends its full
+/// message list: a new thread is seeded with all of it (which also imports
+/// tabs from before threads were persisted), an existing thread only takes
+/// the trailing user message and keeps its own history.
+pub(crate) fn resume(
+    workspace_dir: &str,
+    conversation_id: &str,
+    incoming: &[ChatMessage],
+) -> Result<Resumed, String> {
+    let path = thread_path(&workspace_dir_path(workspace_dir)?, conversation_id)?;
+    let existing = load(&path)?.filter(|c| !c.messages.is_empty());
+    let (mut history, read_files, todos, new) = match existing {
+        Some(c) => {
+            let new: Vec<ChatMessage> = incoming
+                .last()
+                .filter(|m| m.role == "user")
+                .cloned()
+                .into_iter()
+                .collect();
+            (c.messages, c.read_files, c.todos, new)
+        }
+        None => {
+            if !path.exists() {
+                append(
+                    &path,
+                    &[Entry::Created {
+                        title: title_from(incoming),
+                        workspace: workspace_dir.to_string(),
+                        created_at: now_ms(),
+                        forked_from: None,
+                    }],
+                )?;
+            }
+            (Vec::new(), HashSet::new(), Vec::new(), incoming.to_vec())
+        }
+    };
+    let at = now_ms();
+    let entries: Vec<Entry> = new
+        .iter()
+        .map(|m| Entry::Message {
+            at,
+            message: m.clone(),
+        })
+        .collect();
+    append(&path, &entries)?;
+    history.extend(new);
+    Ok(Resumed {
+        messages: history,
+        read_files,
+        todos,
+        recorder: Recorder { path },
+    })
+}
+
+/// Roll a thread back to its first `message_count` messages, with the
+/// read-set and todos it had at that point
+pub(crate) fn rewind(
+    workspace_dir: &str,
+    conversation_id: &str,
+    message_count: usize,
+    read_files: &HashSet<PathBuf>,
+    todos: &[TodoItem],
+) -> Result<(), String> {
+    let path = thread_path(&workspace_dir_path(workspace_dir)?, conversation_id)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut read_files: Vec<PathBuf> = read_files.iter().cloned().collect();
+    read_files.sort();
+    let at = now_ms();
+    append(
+        &path,
+        &[
+            Entry::Rewound { at, message_count },
+            Entry::State {
+                at,
+                read_files,
+                todos: todos.to_vec(),
+            },
+        ],
+    )
+}
+
+/// List the workspace's threads, most recently active first
+#[tauri::command]
+pub fn agent_list_conversations(workspace_dir: String) -> Result<Vec<ConversationSummary>, String> {
+    list(&workspace_dir_path(&workspace_dir)?)
+}
+
+/// Full history and todos of one thread, or `None` if it doesn't exist
+#[tauri::command]
+pub fn agent_load_conversation(
+    workspace_dir: String,
+    conversation_id: String,
+) -> Result<Option<ConversationDetail>, String> {
+    let path = thread_path(&workspace_dir_path(&workspace_dir)?, &conversation_id)?;
+    Ok(load(&path)?.map(|c| ConversationDetail {
+        summary: summary(&c),
+        messages: c.messages,
+        todos: c.todos,
+    }))
+}
+
+#[tauri::command]
+pub fn agent_rename_conversation(
+    workspace_dir: String,
+    conversation_id: String,
+    title: String,
+) -> Result<(), String> {
+    let path = thread_path(&workspace_dir_path(&workspace_dir)?, &conversation_id)?;
+    if !path.exists() {
+        return Err(format!("No conversation '{}'", conversation_id));
+    }
+    let title = title.trim();
+    if title.is_empty() {
+        return Err("Title must not be empty".into());
+    }
+    append(
+        &path,
+        &[Entry::Renamed {
+            at: now_ms(),
+            title: title.to_string(),
+        }],
+    )
+}
+
+/// Delete a thread's log and drop this window's in-memory state for it
+#[tauri::command]
+pub fn agent_delete_conversation(
+    window: tauri::Window,
+    workspace_dir: String,
+    conversation_id: String,
+) -> Result<(), String> {
+    let path = thread_path(&workspace_dir_path(&workspace_dir)?, &conversation_id)?;
+    match fs::remove_file(&path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(format!("Failed to delete conversation: {}", e)),
+    }
+    super::agent::agent_clear_conversation(window, conversation_id)
+}
+
+/// Copy a thread into a new one, optionally keeping only the first
+/// `message_count` messages. Returns the new thread.
+#[tauri::command]
+pub fn agent_fork_conversation(
+    workspace_dir: String,
+    conversation_id: String,
+    message_count: Option<usize>,
+    title: Option<String>,
+) -> Result<ConversationSummary, String> {
+    let dir = workspace_dir_path(&workspace_dir)?;
+    let new_id = format!("chat-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    fork(
+        &dir,
+        &workspace_dir,
+        &conversation_id,
+        &new_id,
+        message_count,
+        title,
+    )
+}
+
+fn fork(
+    dir: &Path,
+    workspace_dir: &str,
+    source_id: &str,
+    new_id: &str,
+    message_count: Option<usize>,
+    title: Option<String>,
+) -> Result<ConversationSummary, String> {
+    let source = load(&thread_path(dir, source_id)?)?
+        .ok_or_else(|| format!("No conversation '{}'", source_id))?;
+    let keep = message_count
+        .unwrap_or(source.messages.len())
+        .min(source.messages.len());
+    let title = title
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| format!("{} (fork)", source.title));
+
+    let at = now_ms();
+    let mut entries = vec![Entry::Created {
+        title,
+        workspace: workspace_dir.to_string(),
+        created_at: at,
+        forked_from: Some(source_id.to_string()),
+    }];
+    entries.extend(source.messages[..keep].iter().map(|m| Entry::Message {
+        at,
+        message: m.clone(),
+    }));
+    let mut read_files: Vec<PathBuf> = source.read_files.into_iter().collect();
+    read_files.sort();
+    entries.push(Entry::State {
+        at,
+        read_files,
+        todos: source.todos,
+    });
+
+    let path = thread_path(dir, new_id)?;
+    append(&path, &entries)?;
+    load(&path)?
+        .map(|c| summary(&c))
+        .ok_or_else(|| "Failed to create fork".to_string())
+}
+
+fn list(dir: &Path) -> Result<Vec<ConversationSummary>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to list conversations: {}", e)),
+    };
+    let mut out: Vec<ConversationSummary> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "jsonl"))
+        .filter_map(|p| load(&p).ok().flatten())
+        .map(|c| summary(&c))
+        .collect();
+    out.sort_by_key(|c| std::cmp::Reverse(c.updated_at));
+    Ok(out)
+}
+
+/// Replay a log. Lines that don't parse are logged and skipped: a crash
+/// mid-append leaves a torn line, which stays in the middle of the file once
+/// later entries are appended after it.
+fn load(path: &Path) -> Result<Option<Conversation>, String> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Failed to read conversation: {}", e)),
+    };
+    let id = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut conv = Conversation {
+        id,
+        title: String::new(),
+        created_at: 0,
+        updated_at: 0,
+        forked_from: None,
+        messages: Vec::new(),
+        read_files: HashSet::new(),
+        todos: Vec::new(),
+    };
+    for (number, line) in std::io::BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read conversation: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!(
+                    "{}:{}: skipping unreadable entry: {}",
+                    path.display(),
+                    number + 1,
+                    e
+                );
+                continue;
+            }
+        };
+        match entry {
+            Entry::Created {
+                title,
+                created_at,
+                forked_from,
+                ..
+            } => {
+                conv.title = title;
+                conv.created_at = created_at;
+                conv.updated_at = created_at;
+                conv.forked_from = forked_from;
+            }
+            Entry::Message { at, message } => {
+                conv.messages.push(message);
+                conv.updated_at = at;
+            }
+            Entry::Renamed { at, title } => {
+                conv.title = title;
+                conv.updated_at = at;
+            }
+            Entry::State {
+                at,
+                read_files,
+                todos,
+            } => {
+                conv.read_files = read_files.into_iter().collect();
+                conv.todos = todos;
+                conv.updated_at = at;
+            }
+            Entry::Rewound { at, message_count } => {
+                conv.messages.truncate(message_count);
+                conv.updated_at = at;
+            }
+        }
+    }
+    close_dangling_tool_calls(&mut conv.messages);
+    Ok(Some(conv))
+}
+
+/// A turn stopped between a tool call and its result leaves a call without
+/// an answer, which providers reject. Answer those calls as cancelled.
+fn close_dangling_tool_calls(messages: &mut Vec<ChatMessage>) {
+    let mut i = 0;
+    while i < messages.len() {
+        let call_ids: Vec<String> = messages[i]
+            .tool_calls
+            .iter()
+            .flatten()
+            .filter_map(|c| c.get("id").and_then(|id| id.as_str()).map(String::from))
+            .collect();
+        i += 1;
+        if call_ids.is_empty() {
+            continue;
+        }
+        let mut answered = HashSet::new();
+        while i < messages.len() && messages[i].role == "tool" {
+            if let Some(id) = &messages[i].tool_call_id {
+                answered.insert(id.clone());
+            }
+            i += 1;
+        }
+        for id in call_ids.into_iter().filter(|id| !answered.contains(id)) {
+            messages.insert(
+                i,
+                ChatMessage {
+                    role: "tool".into(),
+                    content: "Cancelled: the turn was stopped before this tool ran.".into(),
+                    images: None,
+                    tool_calls: None,
+                    tool_call_id: Some(id),
+                    name: None,
+                },
+            );
+            i += 1;
+        }
+    }
+}
+
+fn append(path: &Path, entries: &[Entry]) -> Result<(), String> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut buf = String::new();
+    for entry in entries {
+        buf.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+        buf.push('\n');
+    }
+    let _guard = APPEND_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create conversations dir: {}", e))?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open conversation: {}", e))?;
+    // After a crash mid-append the last line is torn; start on a fresh line
+    // so the torn fragment doesn't swallow the first new entry.
+    if !ends_with_newline(&mut file).map_err(|e| format!("Failed to read conversation: {}", e))? {
+        buf.insert(0, '\n');
+    }
+    file.write_all(buf.as_bytes())
+        .map_err(|e| format!("Failed to write conversation: {}", e))
+}
+
+/// Whether the file is empty or its last byte is a newline
+fn ends_with_newline(file: &mut fs::File) -> std::io::Result<bool> {
+    if file.metadata()?.len() == 0 {
+        return Ok(true);
+    }
+    let mut last = [0u8; 1];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] == b'\n')
+}
+
+fn summary(c: &Conversation) -> ConversationSummary {
+    ConversationSummary {
+        id: c.id.clone(),
+        title: c.title.clone(),
+        created_at: c.created_at,
+        updated_at: c.updated_at,
+        message_count: c.messages.len(),
+        forked_from: c.forked_from.clone(),
+    }
+}
+
+/// Lenient conversion from the loop's OpenAI-format JSON
+fn from_wire(v: &Value) -> ChatMessage {
+    let str_field = |key: &str| v.get(key).and_then(|s| s.as_str()).map(String::from);
+    ChatMessage {
+        role: str_field("role").unwrap_or_default(),
+        content: str_field("content").unwrap_or_default(),
+        images: None,
+        tool_calls: v
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .filter(|t| !t.is_empty())
+            .cloned(),
+        tool_call_id: str_field("tool_call_id"),
+        name: str_field("name"),
+    }
+}
+
+fn title_from(messages: &[ChatMessage]) -> String {
+    let first = messages
+        .iter()
+        .find(|m| m.role == "user")
+        .map(|m| m.content.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default();
+    if first.is_empty() {
+        return "New chat".into();
+    }
+    match first.char_indices().nth(TITLE_CHARS) {
+        Some((cut, _)) => format!("{}...", &first[..cut]),
+        None => first,
+    }
+}
+
+fn workspace_dir_path(workspace_dir: &str) -> Result<PathBuf, String> {
+    if workspace_dir.trim().is_empty() {
+        return Err("No workspace open".into());
+    }
+    let home = super::settings::get_home_dir().ok_or("Could not determine home directory")?;
+    let key = format!("{:x}", super::settings::md5_hash(workspace_dir));
+    Ok(home.join(".clif").join("conversations").join(key))
+}
+
+/// Thread ids become file names, so only allow a safe alphabet
+pub(crate) fn validate_id(conversation_id: &str) -> Result<(), String> {
+    let valid = !conversation_id.is_empty()
+        && conversation_id.len() <= 128
+        && conversation_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        return Err(format!("Invalid conversation id '{}'", conversation_id));
+    }
+    Ok(())
+}
+
+fn thread_path(dir: &Path, conversation_id: &str) -> Result<PathBuf, String> {
+    validate_id(conversation_id)?;
+    Ok(dir.join(format!("{}.jsonl", conversation_id)))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("clif-conv-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn user(content: &str) -> ChatMessage {
+        from_wire(&json!({ "role": "user", "content": content }))
+    }
+
+    #[test]
+    fn log_replays_messages_state_and_renames() {
+        let dir = temp_dir();
+        let path = thread_path(&dir, "default").unwrap();
+        let rec = Recorder { path: path.clone() };
+        append(
+            &path,
+            &[Entry::Created {
+                title: "Fix the build".into(),
+                workspace: "/w".into(),
+                created_at: 1,
+                forked_from: None,
+            }],
+        )
+        .unwrap();
+        rec.message(&json!({ "role": "user", "content": "fix the build" }));
+        rec.message(&json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{ "id": "c1", "type": "function",
+                "function": { "name": "read_file", "arguments": "{}" } }],
+        }));
+        rec.message(&json!({ "role": "tool", "tool_call_id": "c1", "content": "ok" }));
+        let todo: TodoItem =
+            serde_json::from_value(json!({ "id": "1", "content": "x", "status": "pending" }))
+                .unwrap();
+        rec.state(&HashSet::from([PathBuf::from("/w/a.rs")]), &[todo]);
+        rec.append(&[Entry::Renamed {
+            at: 2,
+            title: "Build".into(),
+        }]);
+        // Torn trailing line from a crash mid-append
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"type\":\"mess")
+            .unwrap();
+        assert_eq!(load(&path).unwrap().unwrap().messages.len(), 3);
+        // The next append starts a fresh line instead of gluing onto the tear
+        rec.message(&json!({ "role": "user", "content": "after the crash" }));
+
+        let conv = load(&path).unwrap().unwrap();
+        assert_eq!(conv.title, "Build");
+        assert_eq!(conv.messages.len(), 4);
+        assert_eq!(conv.messages[3].content, "after the crash");
+        assert_eq!(conv.messages[1].tool_calls.as_ref().unwrap().len(), 1);
+        assert!(conv.read_files.contains(Path::new("/w/a.rs")));
+        assert_eq!(conv.todos.len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn stopped_tool_calls_are_answered_on_replay() {
+        let mut messages = vec![
+            user("go"),
+            from_wire(&json!({
+                "role": "assistant",
+                "tool_calls": [
+                    { "id": "a", "type": "function", "function": { "name": "x", "arguments": "{}" } },
+                    { "id": "b", "type": "function", "function": { "name": "y", "arguments": "{}" } },
+                ],
+            })),
+            from_wire(&json!({ "role": "tool", "tool_call_id": "a", "content": "done" })),
+            user("continue"),
+        ];
+        close_dangling_tool_calls(&mut messages);
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["user", "assistant", "tool", "tool", "user"]);
+        assert_eq!(messages[3].tool_call_id.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn fork_copies_a_prefix_into_a_new_thread() {
+        let dir = temp_dir();
+        let path = thread_path(&dir, "default").unwrap();
+        append(
+            &path,
+            &[Entry::Created {
+                title: "Main".into(),
+                workspace: "/w".into(),
+                created_at: 1,
+                forked_from: None,
+            }],
+        )
+        .unwrap();
+        let rec = Recorder { path };
+        for text in ["one", "two", "three"] {
+            rec.message(&json!({ "role": "user", "content": text }));
+        }
+
+        let forked = fork(&dir, "/w", "default", "chat-1", Some(2), None).unwrap();
+        assert_eq!(forked.title, "Main (fork)");
+        assert_eq!(forked.message_count, 2);
+        assert_eq!(forked.forked_from.as_deref(), Some("default"));
+
+        let listed = list(&dir).unwrap();
+        assert_eq!(listed.len(), 2);
+        assert!(thread_path(&dir, "../etc").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rewound_threads_drop_later_messages() {
+        let dir = temp_dir();
+        let rec = Recorder {
+            path: thread_path(&dir, "default").unwrap(),
+        };
+        for text in ["one", "two", "three"] {
+            rec.message(&json!({ "role": "user", "content": text }));
+        }
+        rec.append(&[Entry::Rewound {
+            at: 5,
+            message_count: 1,
+        }]);
+        rec.message(&json!({ "role": "user", "content": "again" }));
+
+        let conv = load(&rec.path).unwrap().unwrap();
+        let contents: Vec<&str> = conv.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, ["one", "again"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
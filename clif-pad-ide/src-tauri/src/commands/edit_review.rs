@@ -0,0 +1,521 @@
+//! Edit review queue for the IDE agent.
+//!
+//! With review mode on, `write_file` and `edit_file` stage their result here
+//! instead of writing to disk. The agent loop then opens a review: it splits
+//! each staged file into unified-diff hunks, emits `agent_edit_review`, and
+//! waits until the user has accepted or rejected every hunk through
+//! `agent_review_accept` / `agent_review_reject`. Accepted hunks are applied
+//! to the original text and written atomically; the tool result tells the
+//! model which hunks were rejected.
+
+use serde::Serialize;
+use serde_json::json;
+use similar::{DiffOp, TextDiff};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{LazyLock, Mutex};
+
+/// Unchanged lines shown around each hunk
+const CONTEXT_LINES: usize = 3;
+
+// Sessions running with review mode on
+static REVIEW_SESSIONS: LazyLock<Mutex<HashSet<String>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+// Edits staged by the current tool call: session_id -> proposals
+static STAGED: LazyLock<Mutex<HashMap<String, Vec<ProposedEdit>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Reviews waiting on the user: session_id -> change set
+static PENDING: LazyLock<Mutex<HashMap<String, PendingReview>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub(crate) struct ProposedEdit {
+    /// Path as the model gave it, for display
+    pub path: String,
+    pub full_path: PathBuf,
+    /// `None` when the edit creates the file
+    pub original: Option<String>,
+    pub proposed: String,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Hunk {
+    pub index: usize,
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    /// Unified diff body including the `@@` header
+    pub diff: String,
+}
+
+struct FileReview {
+    edit: ProposedEdit,
+    hunks: Vec<Hunk>,
+    decisions: Vec<Option<bool>>,
+}
+
+struct PendingReview {
+    files: Vec<FileReview>,
+    done: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+/// What happened to one file once its review closed
+#[derive(Serialize, Debug)]
+pub(crate) struct FileOutcome {
+    pub path: String,
+    #[serde(skip)]
+    pub full_path: PathBuf,
+    pub accepted: Vec<usize>,
+    pub rejected: Vec<Hunk>,
+    pub written: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub(crate) fn set_review_mode(session_id: &str, enabled: bool) {
+    let mut sessions = REVIEW_SESSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    if enabled {
+        sessions.insert(session_id.to_string());
+    } else {
+        sessions.remove(session_id);
+    }
+}
+
+pub(crate) fn is_reviewing(session_id: &str) -> bool {
+    REVIEW_SESSIONS
+        .lock()
+        .map(|s| s.contains(session_id))
+        .unwrap_or(false)
+}
+
+/// Drop everything held for a finished or cancelled session
+pub(crate) fn end_session(session_id: &str) {
+    set_review_mode(session_id, false);
+    if let Ok(mut staged) = STAGED.lock() {
+        staged.remove(session_id);
+    }
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.remove(session_id);
+    }
+}
+
+pub(crate) fn stage(session_id: &str, edit: ProposedEdit) {
+    let mut staged = STAGED.lock().unwrap_or_else(|e| e.into_inner());
+    staged.entry(session_id.to_string()).or_default().push(edit);
+}
+
+pub(crate) fn take_staged(session_id: &str) -> Vec<ProposedEdit> {
+    STAGED
+        .lock()
+        .ok()
+        .and_then(|mut s| s.remove(session_id))
+        .unwrap_or_default()
+}
+
+/// Register a change set for review. Returns the `agent_edit_review`
+/// payload and a receiver that fires once every hunk has a decision.
+/// A file whose proposal changes nothing has no hunks to decide.
+pub(crate) fn open(
+    session_id: &str,
+    tool_call_id: &str,
+    edits: Vec<ProposedEdit>,
+) -> (serde_json::Value, tokio::sync::oneshot::Receiver<()>) {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let files: Vec<FileReview> = edits
+        .into_iter()
+        .map(|edit| {
+            let hunks = hunks(edit.original.as_deref().unwrap_or(""), &edit.proposed);
+            let decisions = vec![None; hunks.len()];
+            FileReview {
+                edit,
+                hunks,
+                decisions,
+            }
+        })
+        .collect();
+    let payload = json!({
+        "session_id": session_id,
+        "tool_call_id": tool_call_id,
+        "files": files.iter().map(|f| json!({
+            "path": f.edit.path,
+            "created": f.edit.original.is_none(),
+            "hunks": f.hunks,
+        })).collect::<Vec<_>>(),
+    });
+    let mut review = PendingReview {
+        files,
+        done: Some(tx),
+    };
+    notify_if_decided(&mut review);
+    PENDING
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(session_id.to_string(), review);
+    (payload, rx)
+}
+
+/// Apply accepted hunks and close the review
+pub(crate) fn finish(session_id: &str) -> Vec<FileOutcome> {
+    let Some(review) = PENDING.lock().ok().and_then(|mut p| p.remove(session_id)) else {
+        return Vec::new();
+    };
+    review
+        .files
+        .into_iter()
+        .map(|file| apply_file(session_id, file))
+        .collect()
+}
+
+/// Accept (`accept = true`) or reject one hunk, or every hunk of `path`
+/// when `hunk` is `None`
+pub(crate) fn decide(
+    session_id: &str,
+    path: &str,
+    hunk: Option<usize>,
+    accept: bool,
+) -> Result<(), String> {
+    let mut pending = PENDING.lock().map_err(|e| e.to_string())?;
+    let review = pending
+        .get_mut(session_id)
+        .ok_or_else(|| "No edit review pending for this session".to_string())?;
+    let file = review
+        .files
+        .iter_mut()
+        .find(|f| f.edit.path == path)
+        .ok_or_else(|| format!("'{}' is not part of the pending review", path))?;
+    match hunk {
+        Some(i) => {
+            let slot = file
+                .decisions
+                .get_mut(i)
+                .ok_or_else(|| format!("'{}' has no hunk {}", path, i))?;
+            *slot = Some(accept);
+        }
+        None => file.decisions.iter_mut().for_each(|d| *d = Some(accept)),
+    }
+    notify_if_decided(review);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn agent_review_accept(
+    session_id: String,
+    path: String,
+    hunk: Option<usize>,
+) -> Result<(), String> {
+    decide(&session_id, &path, hunk, true)
+}
+
+#[tauri::command]
+pub fn agent_review_reject(
+    session_id: String,
+    path: String,
+    hunk: Option<usize>,
+) -> Result<(), String> {
+    decide(&session_id, &path, hunk, false)
+}
+
+/// Tool result for a reviewed edit. A failed write or a fully rejected edit
+/// becomes an error so the model doesn't build on it; a partial one lists
+/// what was dropped.
+pub(crate) fn tool_result(result: &str, outcomes: &[FileOutcome]) -> String {
+    if let Some(failed) = outcomes.iter().find_map(|o| o.error.as_deref()) {
+        return json!({
+            "ok": false,
+            "error_code": "WRITE_FAILED",
+            "message": format!("Error writing reviewed edit: {}", failed),
+            "retryable": true,
+        })
+        .to_string();
+    }
+    let rejected_all = !outcomes.is_empty()
+        && outcomes
+            .iter()
+            .all(|o| o.accepted.is_empty() && !o.rejected.is_empty());
+    if rejected_all {
+        let paths: Vec<&str> = outcomes.iter().map(|o| o.path.as_str()).collect();
+        return json!({
+            "ok": false,
+            "error_code": "EDIT_REJECTED",
+            "message": format!(
+                "The user reviewed and rejected this edit to {}. The file is unchanged. \
+                 Ask or take a different approach instead of retrying the same change.",
+                paths.join(", ")
+            ),
+            "retryable": false,
+        })
+        .to_string();
+    }
+    let mut value: serde_json::Value =
+        serde_json::from_str(result).unwrap_or_else(|_| json!({ "ok": true, "result": result }));
+    if outcomes.iter().any(|o| !o.rejected.is_empty()) {
+        let note = outcomes
+            .iter()
+            .filter(|o| !o.rejected.is_empty())
+            .map(|o| {
+                let hunks: Vec<String> = o.rejected.iter().map(|h| h.diff.clone()).collect();
+                format!(
+                    "Rejected in {} (left unchanged):\n{}",
+                    o.path,
+                    hunks.join("")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        value["review"] = json!({
+            "outcome": "partially_accepted",
+            "files": outcomes,
+            "note": note,
+        });
+    } else {
+        value["review"] = json!({ "outcome": "accepted" });
+    }
+    value.to_string()
+}
+
+fn notify_if_decided(review: &mut PendingReview) {
+    let decided = review
+        .files
+        .iter()
+        .all(|f| f.decisions.iter().all(|d| d.is_some()));
+    if decided {
+        if let Some(tx) = review.done.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+fn apply_file(session_id: &str, file: FileReview) -> FileOutcome {
+    let accepted: Vec<bool> = file.decisions.iter().map(|d| *d == Some(true)).collect();
+    let mut outcome = FileOutcome {
+        path: file.edit.path.clone(),
+        full_path: file.edit.full_path.clone(),
+        accepted: (0..accepted.len()).filter(|&i| accepted[i]).collect(),
+        rejected: file
+            .hunks
+            .iter()
+            .filter(|h| !accepted[h.index])
+            .cloned()
+            .collect(),
+        written: false,
+        error: None,
+    };
+    // A new file with nothing accepted is simply never created
+    if outcome.accepted.is_empty() {
+        return outcome;
+    }
+    // The hunks were cut against the text seen when the edit was staged; if
+    // the file has moved on since, applying them would clobber that change.
+    let current = std::fs::read_to_string(&file.edit.full_path).ok();
+    if current != file.edit.original {
+        outcome.rejected = file.hunks;
+        outcome.accepted.clear();
+        outcome.error = Some(format!(
+            "{} changed on disk while the edit was in review, so none of it was applied. \
+             Read the file again and redo the edit.",
+            file.edit.path
+        ));
+        return outcome;
+    }
+    let original = file.edit.original.as_deref().unwrap_or("");
+    let content = apply_hunks(original, &file.edit.proposed, &accepted);
+    super::checkpoints::record(Some(session_id), &file.edit.full_path);
+    match write_atomic(&file.edit.full_path, &content) {
+        Ok(()) => outcome.written = true,
+        Err(e) => outcome.error = Some(e),
+    }
+    outcome
+}
+
+fn hunk_groups<'a>(diff: &TextDiff<'a, 'a, 'a, str>) -> Vec<Vec<DiffOp>> {
+    diff.grouped_ops(CONTEXT_LINES)
+        .into_iter()
+        .filter(|g| g.iter().any(|op| !matches!(op, DiffOp::Equal { .. })))
+        .collect()
+}
+
+/// Split a change into unified-diff hunks
+pub(crate) fn hunks(old: &str, new: &str) -> Vec<Hunk> {
+    let diff = TextDiff::from_lines(old, new);
+    hunk_groups(&diff)
+        .iter()
+        .enumerate()
+        .map(|(index, group)| {
+            let (first, last) = (&group[0], &group[group.len() - 1]);
+            let old_range = first.old_range().start..last.old_range().end;
+            let new_range = first.new_range().start..last.new_range().end;
+            let mut body = format!(
+                "@@ -{},{} +{},{} @@\n",
+                old_range.start + 1,
+                old_range.len(),
+                new_range.start + 1,
+                new_range.len()
+            );
+            for op in group {
+                for change in diff.iter_changes(op) {
+                    let sign = match change.tag() {
+                        similar::ChangeTag::Equal => ' ',
+                        similar::ChangeTag::Delete => '-',
+                        similar::ChangeTag::Insert => '+',
+                    };
+                    body.push(sign);
+                    body.push_str(change.value().trim_end_matches(['\n', '\r']));
+                    body.push('\n');
+                }
+            }
+            Hunk {
+                index,
+                old_start: old_range.start + 1,
+                old_lines: old_range.len(),
+                new_start: new_range.start + 1,
+                new_lines: new_range.len(),
+                diff: body,
+            }
+        })
+        .collect()
+}
+
+/// Full unified diff of a change, with `a/` and `b/` file headers
+pub(crate) fn unified_diff(path: &str, old: &str, new: &str) -> String {
+    let mut out = format!("--- a/{}\n+++ b/{}\n", path, path);
+    for hunk in hunks(old, new) {
+        out.push_str(&hunk.diff);
+    }
+    out
+}
+
+/// Rebuild the file from `old`, taking the new side only for accepted hunks
+pub(crate) fn apply_hunks(old: &str, new: &str, accepted: &[bool]) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+    let mut out = String::with_capacity(new.len().max(old.len()));
+    let mut pos = 0;
+    for (i, group) in hunk_groups(&diff).iter().enumerate() {
+        let start = group[0].old_range().start;
+        old_lines[pos..start].iter().for_each(|l| out.push_str(l));
+        for op in group {
+            if accepted.get(i).copied().unwrap_or(false) {
+                new_lines[op.new_range()]
+                    .iter()
+                    .for_each(|l| out.push_str(l));
+            } else {
+                old_lines[op.old_range()]
+                    .iter()
+                    .for_each(|l| out.push_str(l));
+            }
+        }
+        pos = group[group.len() - 1].old_range().end;
+    }
+    old_lines[pos..].iter().for_each(|l| out.push_str(l));
+    out
+}
+
+/// Write via a temp file in the same directory and rename over the target
+fn write_atomic(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{}.clif-review.tmp", file_name));
+    std::fs::write(&tmp, content).map_err(|e| e.to_string())?;
+    if let Ok(meta) = std::fs::metadata(path) {
+        let _ = std::fs::set_permissions(&tmp, meta.permissions());
+    }
+    std::fs::rename(&tmp, path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        e.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+    const NEW: &str = "A\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nM\n";
+
+    #[test]
+    fn distant_changes_become_separate_hunks() {
+        let hunks = hunks(OLD, NEW);
+        assert_eq!(hunks.len(), 2);
+        assert!(hunks[0].diff.starts_with("@@ -1,4 +1,4 @@\n-a\n+A\n b\n"));
+        assert!(hunks[1].diff.contains("-m\n+M\n"));
+    }
+
+    #[test]
+    fn unified_diff_has_headers_and_every_hunk() {
+        let diff = unified_diff("src/x.txt", OLD, NEW);
+        assert!(diff.starts_with("--- a/src/x.txt\n+++ b/src/x.txt\n@@ -1,4 +1,4 @@\n"));
+        assert_eq!(diff.matches("@@ -").count(), 2);
+        assert_eq!(unified_diff("x", OLD, OLD), "--- a/x\n+++ b/x\n");
+    }
+
+    #[test]
+    fn only_accepted_hunks_are_applied() {
+        assert_eq!(apply_hunks(OLD, NEW, &[true, true]), NEW);
+        assert_eq!(apply_hunks(OLD, NEW, &[false, false]), OLD);
+        assert_eq!(
+            apply_hunks(OLD, NEW, &[false, true]),
+            OLD.replace("m\n", "M\n")
+        );
+    }
+
+    #[test]
+    fn review_completes_when_every_hunk_is_decided() {
+        let sid = "review-test";
+        let edit = ProposedEdit {
+            path: "src/x.txt".into(),
+            full_path: std::env::temp_dir()
+                .join(format!("clif-review-{}.txt", uuid::Uuid::new_v4())),
+            original: None,
+            proposed: "hello\n".into(),
+        };
+        let (payload, mut rx) = open(sid, "call-1", vec![edit]);
+        assert_eq!(payload["files"][0]["created"], true);
+        assert!(rx.try_recv().is_err());
+
+        decide(sid, "src/x.txt", None, false).unwrap();
+        assert!(rx.try_recv().is_ok());
+        let outcomes = finish(sid);
+        assert!(!outcomes[0].written);
+        let result = tool_result(r#"{"ok":true}"#, &outcomes);
+        assert!(result.contains("EDIT_REJECTED"));
+        assert!(decide(sid, "src/x.txt", None, true).is_err());
+    }
+
+    #[test]
+    fn file_changed_during_review_is_left_alone() {
+        let sid = "review-stale-test";
+        let full_path =
+            std::env::temp_dir().join(format!("clif-review-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&full_path, OLD).unwrap();
+        let edit = ProposedEdit {
+            path: "src/x.txt".into(),
+            full_path: full_path.clone(),
+            original: Some(OLD.into()),
+            proposed: NEW.into(),
+        };
+        let (_, mut rx) = open(sid, "call-1", vec![edit]);
+        std::fs::write(&full_path, "edited by hand\n").unwrap();
+        decide(sid, "src/x.txt", None, true).unwrap();
+        assert!(rx.try_recv().is_ok());
+
+        let outcomes = finish(sid);
+        assert!(!outcomes[0].written);
+        assert!(outcomes[0].accepted.is_empty());
+        assert_eq!(outcomes[0].rejected.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(&full_path).unwrap(),
+            "edited by hand\n"
+        );
+        let result = tool_result(r#"{"ok":true}"#, &outcomes);
+        assert!(result.contains("changed on disk"));
+        let _ = std::fs::remove_file(&full_path);
+    }
+}
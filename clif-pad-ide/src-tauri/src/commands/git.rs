@@ -321,7 +321,7 @@ pub fn git_ahead_behind(path: String) -> Result<(usize, usize), String> {
     }
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, Debug, Default)]
 pub struct GitDiffStat {
     pub files_changed: usize,
     pub insertions: usize,
@@ -344,38 +344,36 @@ pub fn git_diff_stat(path: String) -> Result<GitDiffStat, String> {
         .output()
         .map_err(|e| format!("Failed to run git diff --cached: {}", e))?;
 
-    let mut files_changed = 0usize;
-    let mut insertions = 0usize;
-    let mut deletions = 0usize;
-
+    let mut total = GitDiffStat::default();
     for output in [&unstaged, &staged] {
-        let text = String::from_utf8_lossy(&output.stdout);
-        let text = text.trim();
-        if text.is_empty() {
-            continue;
-        }
-        // Parse: " 3 files changed, 10 insertions(+), 2 deletions(-)"
-        for part in text.split(',') {
-            let part = part.trim();
-            if let Some(num_str) = part.split_whitespace().next() {
-                if let Ok(num) = num_str.parse::<usize>() {
-                    if part.contains("file") {
-                        files_changed += num;
-                    } else if part.contains("insertion") {
-                        insertions += num;
-                    } else if part.contains("deletion") {
-                        deletions += num;
-                    }
+        let stat = parse_shortstat(&String::from_utf8_lossy(&output.stdout));
+        total.files_changed += stat.files_changed;
+        total.insertions += stat.insertions;
+        total.deletions += stat.deletions;
+    }
+
+    Ok(total)
+}
+
+/// Parse `git diff --shortstat` output:
+/// " 3 files changed, 10 insertions(+), 2 deletions(-)"
+pub(crate) fn parse_shortstat(text: &str) -> GitDiffStat {
+    let mut stat = GitDiffStat::default();
+    for part in text.trim().split(',') {
+        let part = part.trim();
+        if let Some(num_str) = part.split_whitespace().next() {
+            if let Ok(num) = num_str.parse::<usize>() {
+                if part.contains("file") {
+                    stat.files_changed += num;
+                } else if part.contains("insertion") {
+                    stat.insertions += num;
+                } else if part.contains("deletion") {
+                    stat.deletions += num;
                 }
             }
         }
     }
-
-    Ok(GitDiffStat {
-        files_changed,
-        insertions,
-        deletions,
-    })
+    stat
 }
 
 #[tauri::command]
@@ -1,10 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use serde_json::Value;
-use tauri::{AppHandle, Emitter, State};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::{oneshot, watch};
 
 // ─── Types ───────────────────────────────────────────────────────────────────
 
@@ -12,11 +16,45 @@ use tauri::{AppHandle, Emitter, State};
 struct LspProcess {
     child: Child,
     stdin: ChildStdin,
+    /// Flips to true once the initialize handshake has completed
+    ready: watch::Receiver<bool>,
+    /// Documents the backend itself opened, with their current version
+    documents: HashMap<String, i32>,
+    /// Documents the editor opened through `lsp_send`. The editor owns their
+    /// server-side text, so the backend never syncs them.
+    editor_documents: HashSet<String>,
 }
 
 #[derive(Default)]
 pub struct LspState {
     processes: Mutex<HashMap<String, LspProcess>>,
+    /// Backend-issued requests waiting for their response, by id, with the
+    /// server they went to
+    pending: Mutex<HashMap<String, (String, oneshot::Sender<Value>)>>,
+    /// Latest publishDiagnostics per document URI, tagged with the value of
+    /// `published` when it arrived
+    diagnostics: Mutex<HashMap<String, (u64, Vec<Value>)>>,
+    published: AtomicU64,
+    next_id: AtomicU64,
+}
+
+/// Handle used by backend callers (the agent) that have no Tauri `State`.
+static APP: OnceLock<AppHandle> = OnceLock::new();
+
+/// Request ids the backend uses. The editor numbers its own requests, so a
+/// string prefix keeps the two apart on the shared connection.
+const ID_PREFIX: &str = "clif-";
+const INIT_ID: &str = "clif-init";
+
+const INIT_TIMEOUT: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+/// Quiet period after a publish before diagnostics count as settled
+const DIAGNOSTICS_SETTLE: Duration = Duration::from_millis(300);
+const MAX_LOCATIONS: usize = 100;
+
+/// Remember the app handle so backend code can reach the managed `LspState`.
+pub fn init(app: &AppHandle) {
+    let _ = APP.set(app.clone());
 }
 
 // ─── Helpers ─────────────────────────────────────────────────────────────────
@@ -52,7 +90,13 @@ fn encode_message(msg: &Value) -> Vec<u8> {
 }
 
 /// Spawn a reader thread that forwards server → client messages as Tauri events.
-fn spawn_reader(language: String, stdout: ChildStdout, app: AppHandle) {
+fn spawn_reader(
+    language: String,
+    pid: u32,
+    stdout: ChildStdout,
+    app: AppHandle,
+    ready: watch::Sender<bool>,
+) {
     std::thread::spawn(move || {
         let mut reader = BufReader::new(stdout);
         loop {
@@ -87,33 +131,138 @@ fn spawn_reader(language: String, stdout: ChildStdout, app: AppHandle) {
             }
 
             if let Ok(text) = String::from_utf8(body) {
+                if let Ok(msg) = serde_json::from_str::<Value>(&text) {
+                    if !route_message(&language, &msg, &app, &ready) {
+                        continue;
+                    }
+                }
                 // Emit to frontend as "lsp-message" with the language as prefix
                 let event = format!("lsp-message-{}", language);
                 let _ = app.emit(&event, text);
             }
         }
+        server_exited(&app.state::<LspState>(), &language, pid);
     });
 }
 
-// ─── Tauri Commands ──────────────────────────────────────────────────────────
+/// Forget a server whose stdout closed: fail its pending requests right away
+/// instead of letting them run into the timeout, and drop the process so the
+/// next call starts a fresh one.
+fn server_exited(state: &LspState, language: &str, pid: u32) {
+    let mut processes = state.processes.lock().unwrap();
+    match processes.get(language).map(|p| p.child.id()) {
+        Some(current) if current == pid => {
+            if let Some(mut process) = processes.remove(language) {
+                let _ = process.child.try_wait();
+            }
+        }
+        // A restarted server already took its place; the requests pending
+        // now are the new server's
+        Some(_) => return,
+        None => {}
+    }
+    drop(processes);
+    // Dropping the senders wakes the waiters with an "exited" error
+    state
+        .pending
+        .lock()
+        .unwrap()
+        .retain(|_, (server, _)| server != language);
+}
 
-/// Start a language server for the given language and workspace root.
-/// Returns the list of capabilities reported by the server after initialize.
-#[tauri::command]
-pub async fn lsp_start(
-    language: String,
-    workspace_root: String,
-    app: AppHandle,
-    state: State<'_, LspState>,
-) -> Result<String, String> {
+/// Handle the parts of a server message the backend owns: the initialize
+/// reply, replies to backend requests, server → client requests, and the
+/// diagnostics cache. Returns false when the message is not for the editor.
+fn route_message(
+    language: &str,
+    msg: &Value,
+    app: &AppHandle,
+    ready: &watch::Sender<bool>,
+) -> bool {
+    let state = app.state::<LspState>();
+    let method = msg.get("method").and_then(|m| m.as_str());
+
+    if let Some(id) = msg.get("id").and_then(|id| id.as_str()) {
+        if method.is_none() && id.starts_with(ID_PREFIX) {
+            if id == INIT_ID {
+                if let Some(err) = msg.get("error") {
+                    log::warn!("{} language server failed to initialize: {}", language, err);
+                } else {
+                    let initialized =
+                        json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} });
+                    let _ = write_to(&state, language, &initialized);
+                    let _ = ready.send(true);
+                }
+            } else if let Some((_, waiter)) = state.pending.lock().unwrap().remove(id) {
+                let _ = waiter.send(msg.clone());
+            }
+            return false;
+        }
+    }
+
+    match method {
+        Some("textDocument/publishDiagnostics") => {
+            if let Some(uri) = msg["params"]["uri"].as_str() {
+                let items = msg["params"]["diagnostics"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default();
+                let seq = state.published.fetch_add(1, Ordering::SeqCst) + 1;
+                state
+                    .diagnostics
+                    .lock()
+                    .unwrap()
+                    .insert(uri.to_string(), (seq, items));
+            }
+        }
+        // Server → client requests (configuration, capability registration,
+        // progress tokens). Nothing answered these before, which leaves some
+        // servers waiting; empty results are enough for all of them.
+        Some(method) if msg.get("id").is_some() => {
+            let result = if method == "workspace/configuration" {
+                let items = msg["params"]["items"]
+                    .as_array()
+                    .map_or(0, |items| items.len());
+                Value::Array(vec![Value::Null; items])
+            } else {
+                Value::Null
+            };
+            let reply = json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result });
+            let _ = write_to(&state, language, &reply);
+        }
+        _ => {}
+    }
+    true
+}
+
+/// Write one message to the running server for `language`.
+fn write_to(state: &LspState, language: &str, msg: &Value) -> Result<(), String> {
+    let mut processes = state.processes.lock().unwrap();
+    let process = processes
+        .get_mut(language)
+        .ok_or_else(|| format!("No LSP running for '{}'", language))?;
+    process
+        .stdin
+        .write_all(&encode_message(msg))
+        .map_err(|e| format!("LSP write error: {}", e))
+}
+
+/// Spawn the server for `language` and send `initialize`. Returns false when
+/// it was already running.
+fn start_process(
+    state: &LspState,
+    app: &AppHandle,
+    language: &str,
+    workspace_root: &str,
+) -> Result<bool, String> {
     let mut processes = state.processes.lock().unwrap();
 
     // Already running?
-    if processes.contains_key(&language) {
-        return Ok("already_running".to_string());
+    if processes.contains_key(language) {
+        return Ok(false);
     }
 
-    let (bin, args) = server_command(&language)
+    let (bin, args) = server_command(language)
         .ok_or_else(|| format!("No language server configured for '{}'", language))?;
 
     if !server_available(bin) {
@@ -135,18 +284,30 @@ pub async fn lsp_start(
     let stdin = child.stdin.take().unwrap();
 
     // Spawn background reader thread
-    spawn_reader(language.clone(), stdout, app.clone());
+    let (ready_tx, ready_rx) = watch::channel(false);
+    spawn_reader(
+        language.to_string(),
+        child.id(),
+        stdout,
+        app.clone(),
+        ready_tx,
+    );
 
-    // Send LSP initialize request
-    let init_msg = serde_json::json!({
+    // Send LSP initialize request. The reader answers the reply with
+    // `initialized`, so the editor and the agent both find a ready server.
+    let root_uri = path_to_uri(Path::new(workspace_root));
+    let init_msg = json!({
         "jsonrpc": "2.0",
-        "id": 1,
+        "id": INIT_ID,
         "method": "initialize",
         "params": {
             "processId": std::process::id(),
-            "rootUri": format!("file://{}", workspace_root),
+            "rootUri": root_uri,
             "capabilities": {
                 "textDocument": {
+                    "synchronization": {
+                        "didSave": true
+                    },
                     "completion": {
                         "completionItem": {
                             "snippetSupport": true,
@@ -156,7 +317,9 @@ pub async fn lsp_start(
                     "hover": {
                         "contentFormat": ["plaintext", "markdown"]
                     },
-                    "definition": {},
+                    "definition": {
+                        "linkSupport": true
+                    },
                     "references": {},
                     "documentSymbol": {},
                     "publishDiagnostics": {
@@ -172,12 +335,15 @@ pub async fn lsp_start(
                 },
                 "workspace": {
                     "workspaceFolders": true,
+                    "workspaceEdit": {
+                        "documentChanges": true
+                    },
                     "symbol": {}
                 }
             },
             "workspaceFolders": [{
-                "uri": format!("file://{}", workspace_root),
-                "name": std::path::Path::new(&workspace_root)
+                "uri": root_uri,
+                "name": Path::new(workspace_root)
                     .file_name()
                     .and_then(|n| n.to_str())
                     .unwrap_or("workspace")
@@ -185,15 +351,65 @@ pub async fn lsp_start(
         }
     });
 
-    let mut process = LspProcess { child, stdin };
+    let mut process = LspProcess {
+        child,
+        stdin,
+        ready: ready_rx,
+        documents: HashMap::new(),
+        editor_documents: HashSet::new(),
+    };
     let encoded = encode_message(&init_msg);
     process
         .stdin
         .write_all(&encoded)
         .map_err(|e| format!("Failed to write to LSP stdin: {}", e))?;
 
-    processes.insert(language.clone(), process);
-    Ok("started".to_string())
+    processes.insert(language.to_string(), process);
+    Ok(true)
+}
+
+/// Wait for the initialize handshake of a started server.
+async fn wait_ready(state: &LspState, language: &str) -> Result<(), String> {
+    let mut ready = state
+        .processes
+        .lock()
+        .unwrap()
+        .get(language)
+        .map(|process| process.ready.clone())
+        .ok_or_else(|| format!("No LSP running for '{}'", language))?;
+    let outcome = tokio::time::timeout(INIT_TIMEOUT, ready.wait_for(|ready| *ready)).await;
+    match outcome {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(_)) => Err(format!(
+            "Language server for {} exited during startup",
+            language
+        )),
+        Err(_) => Err(format!(
+            "Language server for {} did not finish initializing",
+            language
+        )),
+    }
+}
+
+// ─── Tauri Commands ──────────────────────────────────────────────────────────
+
+/// Start a language server for the given language and workspace root.
+/// Resolves once the server has finished the initialize handshake.
+#[tauri::command]
+pub async fn lsp_start(
+    language: String,
+    workspace_root: String,
+    app: AppHandle,
+    state: State<'_, LspState>,
+) -> Result<String, String> {
+    let started = start_process(&state, &app, &language, &workspace_root)?;
+    wait_ready(&state, &language).await?;
+    Ok(if started {
+        "started"
+    } else {
+        "already_running"
+    }
+    .to_string())
 }
 
 /// Send a JSON-RPC message to the language server for the given language.
@@ -211,15 +427,42 @@ pub async fn lsp_send(
     let msg: Value = serde_json::from_str(&message)
         .map_err(|e| format!("Invalid JSON-RPC message: {}", e))?;
 
-    let encoded = encode_message(&msg);
-    process
-        .stdin
-        .write_all(&encoded)
-        .map_err(|e| format!("LSP write error: {}", e))?;
+    let mut outgoing = Vec::new();
+    let uri = msg["params"]["textDocument"]["uri"].as_str();
+    match (msg.get("method").and_then(|m| m.as_str()), uri) {
+        (Some("textDocument/didOpen"), Some(uri)) => {
+            // The agent may have opened this document already; a second
+            // didOpen would leave the server with two conflicting copies.
+            if process.documents.remove(uri).is_some() {
+                outgoing.push(did_close(uri));
+            }
+            process.editor_documents.insert(uri.to_string());
+        }
+        (Some("textDocument/didClose"), Some(uri)) => {
+            process.editor_documents.remove(uri);
+        }
+        _ => {}
+    }
+    outgoing.push(msg);
+
+    for msg in outgoing {
+        process
+            .stdin
+            .write_all(&encode_message(&msg))
+            .map_err(|e| format!("LSP write error: {}", e))?;
+    }
 
     Ok(())
 }
 
+fn did_close(uri: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didClose",
+        "params": { "textDocument": { "uri": uri } }
+    })
+}
+
 /// Stop the language server for the given language.
 #[tauri::command]
 pub async fn lsp_stop(
@@ -252,3 +495,619 @@ pub async fn lsp_check_servers() -> Result<HashMap<String, bool>, String> {
     }
     Ok(result)
 }
+
+// ─── Agent Requests ──────────────────────────────────────────────────────────
+//
+// The agent talks to the same server processes as the editor, issuing its own
+// requests on the shared connection and matching replies by id.
+
+/// Editor language id for a file, mirroring `EXT_TO_LANGUAGE` in `lib/lsp.ts`
+/// so the agent and the editor share one server per language.
+pub(crate) fn language_for_path(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    let language = match ext.as_str() {
+        "ts" => "typescript",
+        "tsx" => "typescriptreact",
+        "js" => "javascript",
+        "jsx" => "javascriptreact",
+        "rs" => "rust",
+        "py" => "python",
+        "go" => "go",
+        "css" => "css",
+        "scss" => "scss",
+        "less" => "less",
+        "html" => "html",
+        "json" => "json",
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// `file://` URI for a path, percent-encoded the way Monaco encodes it.
+pub(crate) fn path_to_uri(path: &Path) -> String {
+    let raw = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !raw.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in raw.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+pub(crate) fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%' && i + 2 < encoded.len() {
+            let hex = std::str::from_utf8(&encoded[i + 1..i + 3]).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    let decoded = String::from_utf8(bytes).ok()?;
+    // "/C:/dir" on Windows
+    let is_drive = decoded.len() > 2 && decoded.as_bytes()[2] == b':';
+    Some(PathBuf::from(if is_drive {
+        &decoded[1..]
+    } else {
+        decoded.as_str()
+    }))
+}
+
+/// LSP position (0-based line, UTF-16 character) of `symbol` on the 1-based
+/// `line`. Prefers a whole-word match so `id` does not land inside `idx`.
+pub(crate) fn symbol_position(text: &str, line: usize, symbol: &str) -> Result<Value, String> {
+    let line_text = line
+        .checked_sub(1)
+        .and_then(|index| text.lines().nth(index))
+        .ok_or_else(|| format!("Line {} is out of range", line))?;
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let whole_word = line_text.match_indices(symbol).find(|(start, _)| {
+        let before = line_text[..*start].chars().next_back();
+        let after = line_text[start + symbol.len()..].chars().next();
+        !before.is_some_and(is_word) && !after.is_some_and(is_word)
+    });
+    let (start, _) = whole_word
+        .or_else(|| line_text.match_indices(symbol).next())
+        .ok_or_else(|| format!("'{}' does not appear on line {}", symbol, line))?;
+    let character: usize = line_text[..start].chars().map(char::len_utf16).sum();
+    Ok(json!({ "line": line - 1, "character": character }))
+}
+
+/// Byte offset of an LSP position. Characters past the end of a line clamp
+/// to the line end, as the spec requires.
+fn offset_at(text: &str, position: &Value) -> Option<usize> {
+    let line = position.get("line")?.as_u64()? as usize;
+    let character = position.get("character")?.as_u64()? as usize;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+    let line_end = text[line_start..]
+        .find('\n')
+        .map_or(text.len(), |i| line_start + i);
+    let line_end = if text[..line_end].ends_with('\r') {
+        line_end - 1
+    } else {
+        line_end
+    };
+
+    let mut units = 0;
+    for (i, c) in text[line_start..line_end].char_indices() {
+        if units >= character {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(line_end)
+}
+
+/// Apply LSP `TextEdit`s computed against `text`.
+pub(crate) fn apply_text_edits(text: &str, edits: &[Value]) -> Result<String, String> {
+    let mut ranges = Vec::with_capacity(edits.len());
+    for edit in edits {
+        let range = edit.get("range").ok_or("Text edit is missing its range")?;
+        let start = range.get("start").and_then(|p| offset_at(text, p));
+        let end = range.get("end").and_then(|p| offset_at(text, p));
+        let (Some(start), Some(end)) = (start, end) else {
+            return Err("Text edit range is outside the file".to_string());
+        };
+        if end < start {
+            return Err("Text edit range ends before it starts".to_string());
+        }
+        let new_text = edit.get("newText").and_then(|t| t.as_str()).unwrap_or("");
+        ranges.push((start, end, new_text));
+    }
+    // Stable sort keeps inserts at the same offset in the order given
+    ranges.sort_by_key(|(start, end, _)| (*start, *end));
+    if ranges.windows(2).any(|pair| pair[1].0 < pair[0].1) {
+        return Err("Text edits overlap".to_string());
+    }
+
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end, new_text) in ranges {
+        out.push_str(&text[cursor..start]);
+        out.push_str(new_text);
+        cursor = end;
+    }
+    out.push_str(&text[cursor..]);
+    Ok(out)
+}
+
+/// Text edits per file from a `WorkspaceEdit`, accepting both `changes` and
+/// `documentChanges`. File create/rename/delete operations are refused.
+pub(crate) fn workspace_edit_files(edit: &Value) -> Result<BTreeMap<PathBuf, Vec<Value>>, String> {
+    let mut files: BTreeMap<PathBuf, Vec<Value>> = BTreeMap::new();
+    let mut add = |uri: &str, edits: &[Value]| -> Result<(), String> {
+        let path = uri_to_path(uri).ok_or_else(|| format!("Unsupported document URI '{}'", uri))?;
+        files.entry(path).or_default().extend(edits.iter().cloned());
+        Ok(())
+    };
+
+    if let Some(changes) = edit.get("documentChanges").and_then(|c| c.as_array()) {
+        for change in changes {
+            if let Some(kind) = change.get("kind").and_then(|k| k.as_str()) {
+                return Err(format!(
+                    "The rename needs a file {} operation, which the agent does not apply",
+                    kind
+                ));
+            }
+            let uri = change["textDocument"]["uri"].as_str().unwrap_or("");
+            let edits = change
+                .get("edits")
+                .and_then(|e| e.as_array())
+                .cloned()
+                .unwrap_or_default();
+            add(uri, &edits)?;
+        }
+    } else if let Some(changes) = edit.get("changes").and_then(|c| c.as_object()) {
+        for (uri, edits) in changes {
+            add(uri, edits.as_array().map(Vec::as_slice).unwrap_or_default())?;
+        }
+    }
+    Ok(files)
+}
+
+fn app_state() -> Result<(&'static AppHandle, State<'static, LspState>), String> {
+    let app = APP.get().ok_or("Language servers are not available")?;
+    Ok((app, app.state::<LspState>()))
+}
+
+/// Whether a server for the file's language is already running.
+pub(crate) fn is_running_for(path: &Path) -> bool {
+    let (Some(language), Ok((_, state))) = (language_for_path(path), app_state()) else {
+        return false;
+    };
+    let running = state.processes.lock().unwrap().contains_key(language);
+    running
+}
+
+/// Start the file's server if needed and push its on-disk text, so requests
+/// see what the agent just wrote. Documents open in the editor are left
+/// alone: the editor already syncs them and picks up the agent's write from
+/// disk. Returns the server key, URI and text.
+async fn sync_document(
+    workspace_root: &str,
+    path: &Path,
+) -> Result<(&'static str, String, String), String> {
+    let language = language_for_path(path)
+        .ok_or_else(|| format!("No language server configured for '{}'", path.display()))?;
+    let (app, state) = app_state()?;
+    start_process(&state, app, language, workspace_root)?;
+    wait_ready(&state, language).await?;
+
+    let text = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("Error reading file: {}", e))?;
+    let uri = path_to_uri(path);
+
+    let mut processes = state.processes.lock().unwrap();
+    let process = processes
+        .get_mut(language)
+        .ok_or_else(|| format!("Language server for {} exited", language))?;
+    if process.editor_documents.contains(&uri) {
+        return Ok((language, uri, text));
+    }
+    let sync = match process.documents.get_mut(&uri) {
+        Some(version) => {
+            *version += 1;
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": uri, "version": *version },
+                    "contentChanges": [{ "text": text }]
+                }
+            })
+        }
+        None => {
+            process.documents.insert(uri.clone(), 1);
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": { "uri": uri, "languageId": language, "version": 1, "text": text }
+                }
+            })
+        }
+    };
+    // didSave as well: some servers (rust-analyzer) only run their full
+    // checks on save, and the file is already on disk.
+    let saved = json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didSave",
+        "params": { "textDocument": { "uri": uri } }
+    });
+    for msg in [sync, saved] {
+        process
+            .stdin
+            .write_all(&encode_message(&msg))
+            .map_err(|e| format!("LSP write error: {}", e))?;
+    }
+    Ok((language, uri, text))
+}
+
+/// Send a request and wait for the matching reply's `result`.
+async fn request(language: &str, method: &str, params: Value) -> Result<Value, String> {
+    let (_, state) = app_state()?;
+    let id = format!(
+        "{}{}",
+        ID_PREFIX,
+        state.next_id.fetch_add(1, Ordering::Relaxed)
+    );
+    let (tx, rx) = oneshot::channel();
+    state
+        .pending
+        .lock()
+        .unwrap()
+        .insert(id.clone(), (language.to_string(), tx));
+
+    let msg = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    if let Err(e) = write_to(&state, language, &msg) {
+        state.pending.lock().unwrap().remove(&id);
+        return Err(e);
+    }
+
+    let reply = match tokio::time::timeout(REQUEST_TIMEOUT, rx).await {
+        Ok(Ok(reply)) => reply,
+        Ok(Err(_)) => return Err(format!("Language server for {} exited", language)),
+        Err(_) => {
+            state.pending.lock().unwrap().remove(&id);
+            return Err(format!(
+                "{} timed out after {}s",
+                method,
+                REQUEST_TIMEOUT.as_secs()
+            ));
+        }
+    };
+    if let Some(error) = reply.get("error") {
+        let message = error
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!("{} failed: {}", method, message));
+    }
+    Ok(reply.get("result").cloned().unwrap_or(Value::Null))
+}
+
+/// Path relative to the workspace, which may be given un-canonicalized.
+pub(crate) fn relative_display(workspace_root: &str, path: &Path) -> String {
+    let canonical = std::fs::canonicalize(workspace_root).ok();
+    path.strip_prefix(workspace_root)
+        .or_else(|e| {
+            canonical
+                .as_deref()
+                .map_or(Err(e), |root| path.strip_prefix(root))
+        })
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Compact `{path, line, column, text}` entries for a `Location`,
+/// `Location[]` or `LocationLink[]` result.
+fn locations_json(workspace_root: &str, result: &Value) -> Value {
+    let items = match result {
+        Value::Array(items) => items.clone(),
+        Value::Null => Vec::new(),
+        single => vec![single.clone()],
+    };
+
+    let mut sources: HashMap<PathBuf, Option<String>> = HashMap::new();
+    let mut locations = Vec::new();
+    for item in items.iter().take(MAX_LOCATIONS) {
+        let uri = item
+            .get("uri")
+            .or_else(|| item.get("targetUri"))
+            .and_then(|u| u.as_str());
+        let range = item
+            .get("range")
+            .or_else(|| item.get("targetSelectionRange"));
+        let (Some(path), Some(start)) = (uri.and_then(uri_to_path), range.map(|r| &r["start"]))
+        else {
+            continue;
+        };
+        let line = start["line"].as_u64().unwrap_or(0) as usize;
+        let character = start["character"].as_u64().unwrap_or(0);
+        let source = sources
+            .entry(path.clone())
+            .or_insert_with(|| std::fs::read_to_string(&path).ok());
+        let text = source
+            .as_deref()
+            .and_then(|s| s.lines().nth(line))
+            .map(|l| l.trim().to_string())
+            .unwrap_or_default();
+        locations.push(json!({
+            "path": relative_display(workspace_root, &path),
+            "line": line + 1,
+            "column": character + 1,
+            "text": text,
+        }));
+    }
+
+    json!({
+        "count": items.len(),
+        "truncated": items.len() > MAX_LOCATIONS,
+        "locations": locations,
+    })
+}
+
+fn severity_name(severity: Option<u64>) -> &'static str {
+    match severity {
+        Some(1) => "error",
+        Some(2) => "warning",
+        Some(3) => "info",
+        Some(4) => "hint",
+        _ => "error",
+    }
+}
+
+/// Diagnostics as `{line, column, severity, message, source, code}` with
+/// 1-based positions, errors first.
+pub(crate) fn diagnostics_json(items: &[Value]) -> Value {
+    let mut entries: Vec<(u64, u64, Value)> = items
+        .iter()
+        .map(|d| {
+            let severity = d.get("severity").and_then(|s| s.as_u64());
+            let line = d["range"]["start"]["line"].as_u64().unwrap_or(0);
+            let entry = json!({
+                "line": line + 1,
+                "column": d["range"]["start"]["character"].as_u64().unwrap_or(0) + 1,
+                "end_line": d["range"]["end"]["line"].as_u64().unwrap_or(line) + 1,
+                "severity": severity_name(severity),
+                "message": d.get("message").and_then(|m| m.as_str()).unwrap_or(""),
+                "source": d.get("source").cloned().unwrap_or(Value::Null),
+                "code": d.get("code").cloned().unwrap_or(Value::Null),
+            });
+            (severity.unwrap_or(1), line, entry)
+        })
+        .collect();
+    entries.sort_by_key(|(severity, line, _)| (*severity, *line));
+    Value::Array(entries.into_iter().map(|(_, _, entry)| entry).collect())
+}
+
+pub(crate) async fn find_references(
+    workspace_root: &str,
+    path: &Path,
+    line: usize,
+    symbol: &str,
+) -> Result<Value, String> {
+    let (language, uri, text) = sync_document(workspace_root, path).await?;
+    let position = symbol_position(&text, line, symbol)?;
+    let result = request(
+        language,
+        "textDocument/references",
+        json!({
+            "textDocument": { "uri": uri },
+            "position": position,
+            "context": { "includeDeclaration": true }
+        }),
+    )
+    .await?;
+    Ok(locations_json(workspace_root, &result))
+}
+
+pub(crate) async fn goto_definition(
+    workspace_root: &str,
+    path: &Path,
+    line: usize,
+    symbol: &str,
+) -> Result<Value, String> {
+    let (language, uri, text) = sync_document(workspace_root, path).await?;
+    let position = symbol_position(&text, line, symbol)?;
+    let result = request(
+        language,
+        "textDocument/definition",
+        json!({ "textDocument": { "uri": uri }, "position": position }),
+    )
+    .await?;
+    Ok(locations_json(workspace_root, &result))
+}
+
+/// Ask the server to rename a symbol and return its edits per file. The
+/// caller applies them so the writes go through review and checkpoints.
+pub(crate) async fn rename_edits(
+    workspace_root: &str,
+    path: &Path,
+    line: usize,
+    symbol: &str,
+    new_name: &str,
+) -> Result<BTreeMap<PathBuf, Vec<Value>>, String> {
+    let (language, uri, text) = sync_document(workspace_root, path).await?;
+    let position = symbol_position(&text, line, symbol)?;
+    let result = request(
+        language,
+        "textDocument/rename",
+        json!({ "textDocument": { "uri": uri }, "position": position, "newName": new_name }),
+    )
+    .await?;
+    if result.is_null() {
+        return Err(format!(
+            "The language server cannot rename '{}' there",
+            symbol
+        ));
+    }
+    workspace_edit_files(&result)
+}
+
+/// Push the file's current text and wait up to `wait` for the server to
+/// publish diagnostics for it. `Ok(None)` means nothing new was published in
+/// time — typically a server that is still indexing.
+pub(crate) async fn diagnostics(
+    workspace_root: &str,
+    path: &Path,
+    wait: Duration,
+) -> Result<Option<Value>, String> {
+    let (_, state) = app_state()?;
+    let before = state.published.load(Ordering::SeqCst);
+    let (_, uri, _) = sync_document(workspace_root, path).await?;
+
+    let deadline = Instant::now() + wait;
+    let mut seen = before;
+    let mut settled_at = None;
+    loop {
+        let latest = state
+            .diagnostics
+            .lock()
+            .unwrap()
+            .get(&uri)
+            .map(|(seq, _)| *seq);
+        if let Some(seq) = latest.filter(|seq| *seq > seen) {
+            // Servers often publish twice (syntax, then semantics); wait
+            // for a quiet period before reporting.
+            seen = seq;
+            settled_at = Some(Instant::now() + DIAGNOSTICS_SETTLE);
+        }
+        let now = Instant::now();
+        if settled_at.is_some_and(|t| now >= t) || now >= deadline {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    if seen == before {
+        return Ok(None);
+    }
+    let items = state
+        .diagnostics
+        .lock()
+        .unwrap()
+        .get(&uri)
+        .map(|(_, items)| items.clone())
+        .unwrap_or_default();
+    Ok(Some(diagnostics_json(&items)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uri_round_trips_paths_with_spaces_and_unicode() {
+        let path = Path::new("/home/me/my project/héllo.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/me/my%20project/h%C3%A9llo.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn symbol_position_prefers_whole_words_and_counts_utf16() {
+        let text = "fn main() {\n    let idx = id(\"é😀\") + id;\n}\n";
+        assert_eq!(
+            symbol_position(text, 2, "id").unwrap(),
+            json!({ "line": 1, "character": 14 })
+        );
+        assert_eq!(
+            symbol_position(text, 2, "idx").unwrap(),
+            json!({ "line": 1, "character": 8 })
+        );
+        // é is one UTF-16 unit, the emoji two
+        let text = "let s = \"é😀\"; value\n";
+        assert_eq!(
+            symbol_position(text, 1, "value").unwrap(),
+            json!({ "line": 0, "character": 15 })
+        );
+        assert!(symbol_position(text, 1, "missing").is_err());
+        assert!(symbol_position(text, 9, "value").is_err());
+    }
+
+    #[test]
+    fn apply_text_edits_handles_several_edits_and_rejects_overlap() {
+        let text = "let foo = 1;\r\nprint(foo);\nfoo\n";
+        let edit = |l1: u64, c1: u64, l2: u64, c2: u64, t: &str| json!({ "range": { "start": { "line": l1, "character": c1 }, "end": { "line": l2, "character": c2 } }, "newText": t });
+        let edits = vec![
+            edit(2, 0, 2, 3, "bar"),
+            edit(0, 4, 0, 7, "bar"),
+            edit(1, 6, 1, 9, "bar"),
+        ];
+        assert_eq!(
+            apply_text_edits(text, &edits).unwrap(),
+            "let bar = 1;\r\nprint(bar);\nbar\n"
+        );
+
+        // Past the end of a line clamps before the \r
+        let clamped = vec![edit(0, 12, 0, 99, " // x")];
+        assert_eq!(
+            apply_text_edits(text, &clamped).unwrap(),
+            "let foo = 1; // x\r\nprint(foo);\nfoo\n"
+        );
+
+        let overlapping = vec![edit(0, 0, 0, 5, "a"), edit(0, 3, 0, 7, "b")];
+        assert!(apply_text_edits(text, &overlapping).is_err());
+    }
+
+    #[test]
+    fn workspace_edit_files_reads_both_shapes() {
+        let edit = json!({ "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 1 } }, "newText": "x" });
+        let changes =
+            json!({ "changes": { "file:///ws/a.ts": [edit], "file:///ws/b.ts": [edit, edit] } });
+        let files = workspace_edit_files(&changes).unwrap();
+        assert_eq!(files[Path::new("/ws/a.ts")].len(), 1);
+        assert_eq!(files[Path::new("/ws/b.ts")].len(), 2);
+
+        let document_changes = json!({ "documentChanges": [
+            { "textDocument": { "uri": "file:///ws/a.ts", "version": 3 }, "edits": [edit] }
+        ] });
+        assert_eq!(workspace_edit_files(&document_changes).unwrap().len(), 1);
+
+        let with_rename = json!({ "documentChanges": [
+            { "kind": "rename", "oldUri": "file:///ws/a.ts", "newUri": "file:///ws/c.ts" }
+        ] });
+        assert!(workspace_edit_files(&with_rename).is_err());
+    }
+
+    #[test]
+    fn exited_server_fails_only_its_own_pending_requests() {
+        let state = LspState::default();
+        let (rust_tx, mut rust_rx) = oneshot::channel();
+        let (go_tx, mut go_rx) = oneshot::channel();
+        state.pending.lock().unwrap().extend([
+            ("clif-1".to_string(), ("rust".to_string(), rust_tx)),
+            ("clif-2".to_string(), ("go".to_string(), go_tx)),
+        ]);
+
+        server_exited(&state, "rust", 42);
+        assert!(matches!(
+            rust_rx.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        ));
+        assert!(matches!(
+            go_rx.try_recv(),
+            Err(oneshot::error::TryRecvError::Empty)
+        ));
+    }
+}
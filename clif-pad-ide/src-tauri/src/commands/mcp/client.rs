@@ -0,0 +1,356 @@
+//! JSON-RPC client for one MCP server, over stdio or streamable HTTP.
+//!
+//! Requests are serialized per connection: the agent runs non-read-only tools
+//! one at a time anyway, and it keeps the stdio transport to a simple
+//! write-a-line, read-until-matching-id exchange.
+
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+use super::{ServerConfig, PROTOCOL_VERSION};
+
+/// Covers `initialize` on servers launched through `npx`, which may first
+/// download the package
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub(crate) struct McpTool {
+    pub name: String,
+    pub description: Option<String>,
+    pub input_schema: Value,
+    /// The server's `readOnlyHint` annotation
+    pub read_only: bool,
+}
+
+struct StdioPipe {
+    // Held so the process is killed when the connection is dropped
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: Lines<BufReader<ChildStdout>>,
+}
+
+struct HttpEndpoint {
+    http: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+    /// `Mcp-Session-Id` handed out by the server at initialization
+    session_id: Mutex<Option<String>>,
+}
+
+enum Transport {
+    Stdio(Box<tokio::sync::Mutex<StdioPipe>>),
+    Http(HttpEndpoint),
+}
+
+pub(crate) struct McpClient {
+    transport: Transport,
+    next_id: AtomicU64,
+    tools: Vec<McpTool>,
+}
+
+impl McpClient {
+    /// Start or reach the server, run the initialize handshake and fetch its tools
+    pub async fn connect(config: &ServerConfig, workspace_dir: &str) -> Result<Self, String> {
+        let transport = match (&config.url, &config.command) {
+            (Some(url), _) => Transport::Http(HttpEndpoint {
+                http: reqwest::Client::new(),
+                url: url.clone(),
+                headers: config.headers.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                session_id: Mutex::new(None),
+            }),
+            (None, Some(command)) => {
+                Transport::Stdio(Box::new(tokio::sync::Mutex::new(spawn(config, command, workspace_dir)?)))
+            }
+            (None, None) => return Err("needs either a `command` or a `url`".to_string()),
+        };
+        let mut client = McpClient {
+            transport,
+            next_id: AtomicU64::new(1),
+            tools: Vec::new(),
+        };
+
+        let handshake = async {
+            client
+                .request(
+                    "initialize",
+                    json!({
+                        "protocolVersion": PROTOCOL_VERSION,
+                        "capabilities": {},
+                        "clientInfo": { "name": "clifpad", "version": env!("CARGO_PKG_VERSION") },
+                    }),
+                )
+                .await?;
+            client.notify("notifications/initialized").await?;
+            client.list_tools().await
+        };
+        let tools = tokio::time::timeout(CONNECT_TIMEOUT, handshake)
+            .await
+            .map_err(|_| format!("no answer within {}s", CONNECT_TIMEOUT.as_secs()))??;
+        client.tools = tools;
+        Ok(client)
+    }
+
+    pub fn tools(&self) -> &[McpTool] {
+        &self.tools
+    }
+
+    /// The raw `tools/call` result
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<Value, String> {
+        self.request("tools/call", json!({ "name": name, "arguments": arguments }))
+            .await
+    }
+
+    async fn list_tools(&self) -> Result<Vec<McpTool>, String> {
+        let mut tools = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(c) => json!({ "cursor": c }),
+                None => json!({}),
+            };
+            let page = self.request("tools/list", params).await?;
+            for tool in page.get("tools").and_then(|t| t.as_array()).into_iter().flatten() {
+                let Some(name) = tool.get("name").and_then(|n| n.as_str()) else {
+                    continue;
+                };
+                tools.push(McpTool {
+                    name: name.to_string(),
+                    description: tool.get("description").and_then(|d| d.as_str()).map(String::from),
+                    input_schema: tool.get("inputSchema").cloned().unwrap_or_else(|| json!({})),
+                    read_only: tool
+                        .pointer("/annotations/readOnlyHint")
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false),
+                });
+            }
+            cursor = page.get("nextCursor").and_then(|c| c.as_str()).map(String::from);
+            if cursor.is_none() {
+                return Ok(tools);
+            }
+        }
+    }
+
+    async fn request(&self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        let response = tokio::time::timeout(REQUEST_TIMEOUT, self.exchange(&message, Some(id)))
+            .await
+            .map_err(|_| format!("{} timed out", method))??;
+        if let Some(error) = response.get("error") {
+            return Err(format!(
+                "{} failed: {} ({})",
+                method,
+                error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error"),
+                error.get("code").and_then(|c| c.as_i64()).unwrap_or_default(),
+            ));
+        }
+        Ok(response.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    async fn notify(&self, method: &str) -> Result<(), String> {
+        let message = json!({ "jsonrpc": "2.0", "method": method });
+        self.exchange(&message, None).await.map(|_| ())
+    }
+
+    /// Send one message; for requests, wait for the response with `id`
+    async fn exchange(&self, message: &Value, id: Option<u64>) -> Result<Value, String> {
+        match &self.transport {
+            Transport::Stdio(pipe) => {
+                let mut pipe = pipe.lock().await;
+                write_line(&mut pipe.stdin, message).await?;
+                let Some(id) = id else { return Ok(Value::Null) };
+                loop {
+                    let line = pipe
+                        .stdout
+                        .next_line()
+                        .await
+                        .map_err(|e| format!("read failed: {}", e))?
+                        .ok_or("server exited")?;
+                    // Some servers log to stdout; anything that is not JSON is noise
+                    let Ok(incoming) = serde_json::from_str::<Value>(&line) else {
+                        continue;
+                    };
+                    if is_response_to(&incoming, id) {
+                        return Ok(incoming);
+                    }
+                    if let Some(reply) = reply_to_server_request(&incoming) {
+                        write_line(&mut pipe.stdin, &reply).await?;
+                    }
+                }
+            }
+            Transport::Http(endpoint) => endpoint.post(message, id).await,
+        }
+    }
+}
+
+impl HttpEndpoint {
+    async fn post(&self, message: &Value, id: Option<u64>) -> Result<Value, String> {
+        let mut req = self
+            .http
+            .post(&self.url)
+            .header("Accept", "application/json, text/event-stream")
+            .header("MCP-Protocol-Version", PROTOCOL_VERSION)
+            .json(message);
+        for (key, value) in &self.headers {
+            req = req.header(key, value);
+        }
+        let session = self.session_id.lock().ok().and_then(|s| s.clone());
+        if let Some(session) = &session {
+            req = req.header("Mcp-Session-Id", session);
+        }
+
+        let response = req.send().await.map_err(|e| format!("request failed: {}", e))?;
+        if let Some(session) = response
+            .headers()
+            .get("mcp-session-id")
+            .and_then(|v| v.to_str().ok())
+        {
+            if let Ok(mut slot) = self.session_id.lock() {
+                *slot = Some(session.to_string());
+            }
+        }
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("HTTP {}: {}", status, body.trim()));
+        }
+        let Some(id) = id else { return Ok(Value::Null) };
+
+        let is_stream = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with("text/event-stream"));
+        let body = response.text().await.map_err(|e| format!("read failed: {}", e))?;
+        let messages = if is_stream {
+            sse_messages(&body)
+        } else {
+            match serde_json::from_str::<Value>(&body).map_err(|e| format!("invalid JSON: {}", e))? {
+                Value::Array(batch) => batch,
+                single => vec![single],
+            }
+        };
+        messages
+            .into_iter()
+            .find(|m| is_response_to(m, id))
+            .ok_or_else(|| "server sent no response".to_string())
+    }
+}
+
+/// Launch a stdio server through the user's login shell, like `run_command`,
+/// so `npx`/`uvx` resolve with the same PATH as in a terminal
+fn spawn(config: &ServerConfig, command: &str, workspace_dir: &str) -> Result<StdioPipe, String> {
+    let cwd = match &config.cwd {
+        Some(dir) => Path::new(workspace_dir).join(dir),
+        None => Path::new(workspace_dir).to_path_buf(),
+    };
+    #[cfg(unix)]
+    let mut cmd = {
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+        let mut cmd = Command::new(shell);
+        cmd.arg("-l").arg("-c").arg("exec \"$0\" \"$@\"").arg(command);
+        cmd
+    };
+    #[cfg(not(unix))]
+    let mut cmd = Command::new(command);
+    cmd.args(&config.args)
+        .envs(&config.env)
+        .current_dir(cwd)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .kill_on_drop(true);
+
+    let mut child = cmd
+        .spawn()
+        .map_err(|e| format!("failed to start '{}': {}", command, e))?;
+    let stdin = child.stdin.take().ok_or("failed to capture stdin")?;
+    let stdout = child.stdout.take().ok_or("failed to capture stdout")?;
+    Ok(StdioPipe {
+        _child: child,
+        stdin,
+        stdout: BufReader::new(stdout).lines(),
+    })
+}
+
+async fn write_line(stdin: &mut ChildStdin, message: &Value) -> Result<(), String> {
+    let mut line = message.to_string();
+    line.push('\n');
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| format!("write failed: {}", e))?;
+    stdin.flush().await.map_err(|e| format!("write failed: {}", e))
+}
+
+fn is_response_to(message: &Value, id: u64) -> bool {
+    message.get("id").and_then(|v| v.as_u64()) == Some(id)
+        && (message.get("result").is_some() || message.get("error").is_some())
+}
+
+/// Servers may ask the client things mid-call. We answer `ping` and turn
+/// down everything else (sampling, roots, elicitation) so the server is not
+/// left waiting.
+fn reply_to_server_request(message: &Value) -> Option<Value> {
+    let id = message.get("id")?;
+    let method = message.get("method")?.as_str()?;
+    Some(if method == "ping" {
+        json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+    } else {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": -32601, "message": format!("ClifPad does not support {}", method) },
+        })
+    })
+}
+
+/// JSON payloads of the `data:` fields in an SSE body
+fn sse_messages(body: &str) -> Vec<Value> {
+    let mut messages = Vec::new();
+    for event in body.replace("\r\n", "\n").split("\n\n") {
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|d| d.strip_prefix(' ').unwrap_or(d))
+            .collect();
+        if data.is_empty() {
+            continue;
+        }
+        if let Ok(value) = serde_json::from_str::<Value>(&data.join("\n")) {
+            messages.push(value);
+        }
+    }
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_messages_joins_multiline_data_and_skips_other_fields() {
+        let body = "event: message\r\nid: 1\r\ndata: {\"jsonrpc\":\"2.0\",\r\ndata: \"id\":7,\"result\":{}}\r\n\r\n: keep-alive\n\ndata: not json\n\n";
+        let messages = sse_messages(body);
+        assert_eq!(messages.len(), 1);
+        assert!(is_response_to(&messages[0], 7));
+        assert!(!is_response_to(&messages[0], 8));
+    }
+
+    #[test]
+    fn server_requests_get_an_answer() {
+        let ping = reply_to_server_request(&json!({ "jsonrpc": "2.0", "id": 3, "method": "ping" })).unwrap();
+        assert_eq!(ping["result"], json!({}));
+        let sampling =
+            reply_to_server_request(&json!({ "jsonrpc": "2.0", "id": "a", "method": "sampling/createMessage" }))
+                .unwrap();
+        assert_eq!(sampling["error"]["code"], -32601);
+        assert!(reply_to_server_request(&json!({ "jsonrpc": "2.0", "method": "notifications/progress" })).is_none());
+    }
+}
@@ -0,0 +1,697 @@
+//! Model Context Protocol support for the IDE agent.
+//!
+//! Client side: servers listed under `mcpServers` in `~/.clif/settings.json`
+//! or in the workspace's `.clif/mcp.json` (workspace entries win) are
+//! connected lazily, per workspace, over stdio or streamable HTTP. Their
+//! tools are offered to the agent as `mcp__<server>__<tool>` and go through
+//! the same argument validation, `tool_error` envelope and approval prompt
+//! as the built-in tools.
+//!
+//! `.clif/mcp.json` comes with the repository, so nothing in it is spawned
+//! until the user trusts that exact file content for the workspace, and its
+//! `autoApprove` lists are ignored — only user settings can skip approval.
+//!
+//! Server side: [`server`] exposes the codebase index and PR review results
+//! to external agents such as the Claude CLI.
+
+mod client;
+pub mod server;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+use client::{McpClient, McpTool};
+
+/// Protocol revision we speak, both as client and server
+pub(crate) const PROTOCOL_VERSION: &str = "2025-06-18";
+
+/// How long a single `tools/call` may take before the agent gives up
+const CALL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Prefix of agent tool names that route to an MCP server
+const TOOL_PREFIX: &str = "mcp__";
+
+/// OpenAI rejects function names longer than this
+const MAX_TOOL_NAME: usize = 64;
+
+/// One entry of `mcpServers`, in the format Claude and Cursor use
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ServerConfig {
+    /// Executable for a stdio server
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Working directory, relative to the workspace. Defaults to the workspace.
+    #[serde(default)]
+    pub cwd: Option<String>,
+    /// Endpoint of a streamable HTTP server
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub disabled: bool,
+    /// Tools that run without an approval prompt; `"*"` trusts the whole server
+    #[serde(default)]
+    pub auto_approve: Vec<String>,
+}
+
+impl ServerConfig {
+    fn transport(&self) -> &'static str {
+        if self.url.is_some() {
+            "http"
+        } else {
+            "stdio"
+        }
+    }
+
+    fn auto_approves(&self, tool: &str) -> bool {
+        self.auto_approve.iter().any(|t| t == "*" || t == tool)
+    }
+}
+
+struct Connection {
+    config: ServerConfig,
+    client: Result<Arc<McpClient>, String>,
+}
+
+// Live connections: workspace_dir -> server name -> connection. A failed
+// connect is cached too, so a dead server costs one timeout rather than one
+// per agent turn; `mcp_reconnect` or a config change retries it.
+static CONNECTIONS: LazyLock<tokio::sync::Mutex<HashMap<String, BTreeMap<String, Connection>>>> =
+    LazyLock::new(|| tokio::sync::Mutex::new(HashMap::new()));
+
+/// An MCP tool as the agent sees it
+#[derive(Clone, Debug)]
+struct RegisteredTool {
+    server: String,
+    tool: McpTool,
+    auto_approve: bool,
+}
+
+// workspace_dir -> agent tool name -> MCP tool. Each workspace's entries are
+// replaced whenever its tool definitions are built, so a server name shared
+// by two windows never lends one workspace's auto-approval to the other.
+// Validation and approval checks run synchronously off this map.
+static TOOLS: LazyLock<Mutex<HashMap<String, HashMap<String, RegisteredTool>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Merge `mcpServers` from settings and from a trusted `.clif/mcp.json`.
+/// Entries that do not parse are skipped with a warning rather than failing
+/// the rest. `autoApprove` is only honoured from settings.
+fn merge_configs(settings: &Value, workspace_file: Option<&Value>) -> BTreeMap<String, ServerConfig> {
+    let mut servers = BTreeMap::new();
+    for (source, from_workspace) in [(Some(settings), false), (workspace_file, true)] {
+        let Some(entries) = source.and_then(|s| s.get("mcpServers")).and_then(|v| v.as_object()) else {
+            continue;
+        };
+        for (name, entry) in entries {
+            match serde_json::from_value::<ServerConfig>(entry.clone()) {
+                Ok(mut config) => {
+                    if from_workspace {
+                        config.auto_approve.clear();
+                    }
+                    servers.insert(name.clone(), config);
+                }
+                Err(e) => log::warn!("ignoring MCP server '{}': {}", name, e),
+            }
+        }
+    }
+    servers.retain(|_, config| !config.disabled);
+    servers
+}
+
+/// The `mcpServers` object of the workspace's `.clif/mcp.json`, if any
+fn workspace_servers(workspace_dir: &str) -> Option<Value> {
+    let raw = std::fs::read_to_string(Path::new(workspace_dir).join(".clif").join("mcp.json")).ok()?;
+    match serde_json::from_str::<Value>(&raw) {
+        Ok(v) => v.get("mcpServers").filter(|s| s.as_object().is_some_and(|o| !o.is_empty())).cloned(),
+        Err(e) => {
+            log::warn!("ignoring .clif/mcp.json: {}", e);
+            None
+        }
+    }
+}
+
+fn trust_path() -> Option<std::path::PathBuf> {
+    Some(super::settings::get_home_dir()?.join(".clif").join("mcp_trust.json"))
+}
+
+/// Workspace -> the `mcpServers` content the user trusted for it
+fn trust_store() -> serde_json::Map<String, Value> {
+    trust_path()
+        .and_then(|p| std::fs::read_to_string(p).ok())
+        .and_then(|raw| serde_json::from_str::<Value>(&raw).ok())
+        .and_then(|v| v.as_object().cloned())
+        .unwrap_or_default()
+}
+
+/// Trust is tied to the exact server entries the user saw, so a pull that
+/// edits `.clif/mcp.json` asks again.
+fn is_trusted(store: &serde_json::Map<String, Value>, workspace_dir: &str, servers: &Value) -> bool {
+    store.get(workspace_dir) == Some(servers)
+}
+
+/// Workspace-defined servers waiting for the user's trust, with what each
+/// would run
+pub(crate) fn untrusted_servers(workspace_dir: &str) -> Vec<Value> {
+    let Some(servers) = workspace_servers(workspace_dir) else {
+        return Vec::new();
+    };
+    if is_trusted(&trust_store(), workspace_dir, &servers) {
+        return Vec::new();
+    }
+    servers
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(name, entry)| {
+            let runs = match entry.get("url").and_then(|u| u.as_str()) {
+                Some(url) => url.to_string(),
+                None => std::iter::once(entry.get("command").and_then(|c| c.as_str()).unwrap_or(""))
+                    .chain(
+                        entry.get("args").and_then(|a| a.as_array()).into_iter().flatten().filter_map(|a| a.as_str()),
+                    )
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            json!({ "name": name, "runs": runs })
+        })
+        .collect()
+}
+
+fn load_configs(workspace_dir: &str) -> BTreeMap<String, ServerConfig> {
+    let settings = super::settings::get_settings().unwrap_or_default();
+    let trusted = workspace_servers(workspace_dir)
+        .filter(|servers| is_trusted(&trust_store(), workspace_dir, servers))
+        .map(|servers| json!({ "mcpServers": servers }));
+    merge_configs(&settings, trusted.as_ref())
+}
+
+/// Connect every configured server for the workspace, reusing connections
+/// whose config has not changed and dropping ones no longer configured.
+/// New servers are connected without holding the lock, so one slow server
+/// doesn't stall every other window's agent.
+async fn connections(workspace_dir: &str) -> Vec<(String, ServerConfig, Result<Arc<McpClient>, String>)> {
+    let configs = load_configs(workspace_dir);
+    let missing: Vec<(String, ServerConfig)> = {
+        let mut all = CONNECTIONS.lock().await;
+        let live = all.entry(workspace_dir.to_string()).or_default();
+        live.retain(|name, conn| configs.get(name) == Some(&conn.config));
+        configs
+            .iter()
+            .filter(|(name, _)| !live.contains_key(*name))
+            .map(|(name, config)| (name.clone(), config.clone()))
+            .collect()
+    };
+
+    let mut connected = Vec::new();
+    for (name, config) in missing {
+        let client = McpClient::connect(&config, workspace_dir).await.map(Arc::new);
+        if let Err(e) = &client {
+            log::warn!("MCP server '{}' unavailable: {}", name, e);
+        }
+        connected.push((name, Connection { config, client }));
+    }
+
+    let mut all = CONNECTIONS.lock().await;
+    let live = all.entry(workspace_dir.to_string()).or_default();
+    for (name, conn) in connected {
+        // A concurrent call may have connected it first; keep that one
+        live.entry(name).or_insert(conn);
+    }
+    live.iter()
+        .filter(|(name, _)| configs.contains_key(*name))
+        .map(|(name, conn)| (name.clone(), conn.config.clone(), conn.client.clone()))
+        .collect()
+}
+
+/// `mcp__<server>__<tool>`, limited to the characters and length that
+/// function-calling APIs accept
+fn qualified_name(server: &str, tool: &str) -> String {
+    let clean = |s: &str| -> String {
+        s.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+            .collect()
+    };
+    let mut name = format!("{}{}__{}", TOOL_PREFIX, clean(server), clean(tool));
+    name.truncate(MAX_TOOL_NAME);
+    name
+}
+
+/// Agent tool names for `(server, tool)` pairs. Pairs whose cleaned names
+/// collide all get a short hash of the original names, so none of them
+/// shadows another.
+fn qualified_names(pairs: &[(&str, &str)]) -> Vec<String> {
+    let names: Vec<String> = pairs.iter().map(|(server, tool)| qualified_name(server, tool)).collect();
+    names
+        .iter()
+        .zip(pairs)
+        .map(|(name, (server, tool))| {
+            if names.iter().filter(|n| *n == name).count() == 1 {
+                return name.clone();
+            }
+            let hash = super::settings::md5_hash(&format!("{}\0{}", server, tool));
+            let suffix = format!("_{:08x}", hash as u32);
+            let mut name = name.clone();
+            name.truncate(MAX_TOOL_NAME - suffix.len());
+            name + &suffix
+        })
+        .collect()
+}
+
+pub(crate) fn is_mcp_tool(name: &str) -> bool {
+    name.starts_with(TOOL_PREFIX)
+}
+
+/// OpenAI-format definitions for every tool of every connected server
+pub(crate) async fn tool_definitions(workspace_dir: &str) -> Vec<Value> {
+    let mut definitions = Vec::new();
+    let mut registered = HashMap::new();
+    let servers: Vec<(String, ServerConfig, Arc<McpClient>)> = connections(workspace_dir)
+        .await
+        .into_iter()
+        .filter_map(|(server, config, client)| Some((server, config, client.ok()?)))
+        .collect();
+    let offered: Vec<(&str, &ServerConfig, &McpTool)> = servers
+        .iter()
+        .flat_map(|(server, config, client)| client.tools().iter().map(move |tool| (server.as_str(), config, tool)))
+        .collect();
+    let pairs: Vec<(&str, &str)> = offered.iter().map(|(server, _, tool)| (*server, tool.name.as_str())).collect();
+    let names = qualified_names(&pairs);
+    for (name, (server, config, tool)) in names.into_iter().zip(offered) {
+        if registered.contains_key(&name) {
+            log::warn!("MCP server '{}' lists tool '{}' more than once; using the first", server, tool.name);
+            continue;
+        }
+        let mut parameters = tool.input_schema.clone();
+        if !parameters.is_object() {
+            parameters = json!({});
+        }
+        parameters["type"] = json!("object");
+        if parameters.get("properties").is_none() {
+            parameters["properties"] = json!({});
+        }
+        definitions.push(json!({
+            "type": "function",
+            "function": {
+                "name": name,
+                "description": format!(
+                    "[MCP server '{}'] {}",
+                    server,
+                    tool.description.as_deref().unwrap_or(&tool.name)
+                ),
+                "parameters": parameters,
+            }
+        }));
+        registered.insert(
+            name,
+            RegisteredTool {
+                server: server.to_string(),
+                tool: tool.clone(),
+                auto_approve: config.auto_approves(&tool.name),
+            },
+        );
+    }
+    if let Ok(mut tools) = TOOLS.lock() {
+        tools.insert(workspace_dir.to_string(), registered);
+    }
+    definitions
+}
+
+fn registered(workspace_dir: &str, name: &str) -> Option<RegisteredTool> {
+    TOOLS
+        .lock()
+        .ok()
+        .and_then(|tools| tools.get(workspace_dir)?.get(name).cloned())
+}
+
+/// Whether `value` matches a JSON Schema `type` keyword
+fn matches_type(value: &Value, ty: &Value) -> bool {
+    let one = |t: &str| match t {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    match ty {
+        Value::String(t) => one(t),
+        Value::Array(types) => types.iter().filter_map(|t| t.as_str()).any(one),
+        _ => true,
+    }
+}
+
+/// Check arguments against the top level of the tool's input schema:
+/// required fields, declared types and `additionalProperties: false`
+fn validate_against_schema(tool: &str, schema: &Value, args: &Value) -> Result<(), String> {
+    let obj = args
+        .as_object()
+        .ok_or_else(|| "Tool arguments must be a JSON object".to_string())?;
+    let properties = schema.get("properties").and_then(|p| p.as_object());
+
+    if let Some(required) = schema.get("required").and_then(|r| r.as_array()) {
+        for field in required.iter().filter_map(|f| f.as_str()) {
+            if !obj.contains_key(field) {
+                return Err(format!("Missing required field '{}'", field));
+            }
+        }
+    }
+    for (key, value) in obj {
+        match properties.and_then(|p| p.get(key)) {
+            Some(prop) => {
+                if let Some(ty) = prop.get("type") {
+                    if !matches_type(value, ty) {
+                        return Err(format!("Field '{}' must be of type {}", key, ty));
+                    }
+                }
+            }
+            None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => {
+                return Err(format!("Unexpected argument '{}' for tool '{}'", key, tool));
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_args(workspace_dir: &str, name: &str, args: &Value) -> Result<(), String> {
+    let tool = registered(workspace_dir, name).ok_or_else(|| format!("Unknown tool: {}", name))?;
+    validate_against_schema(name, &tool.tool.input_schema, args)
+}
+
+/// Read-only tools stay available in Ask and Plan mode. This trusts the
+/// server's `readOnlyHint` annotation.
+pub(crate) fn is_read_only(workspace_dir: &str, name: &str) -> bool {
+    registered(workspace_dir, name).is_some_and(|t| t.tool.read_only)
+}
+
+/// Whether the user must approve a call first. Everything needs approval
+/// unless the server config lists the tool under `autoApprove`.
+pub(crate) fn needs_approval(workspace_dir: &str, name: &str) -> bool {
+    registered(workspace_dir, name).is_none_or(|t| !t.auto_approve)
+}
+
+/// What the approval card shows for a call
+pub(crate) fn approval_preview(workspace_dir: &str, name: &str, args: &Value) -> String {
+    let mut arguments = args.to_string();
+    if arguments.len() > 400 {
+        let mut end = 400;
+        while !arguments.is_char_boundary(end) {
+            end -= 1;
+        }
+        arguments.truncate(end);
+        arguments.push('…');
+    }
+    match registered(workspace_dir, name) {
+        Some(t) => format!("MCP {} › {} {}", t.server, t.tool.name, arguments),
+        None => format!("{} {}", name, arguments),
+    }
+}
+
+/// Run a tool call. `Ok` carries the text the model sees; `Err` covers both
+/// transport failures and results the server flagged with `isError`.
+pub(crate) async fn call_tool(workspace_dir: &str, name: &str, args: &Value) -> Result<String, String> {
+    let tool = registered(workspace_dir, name).ok_or_else(|| format!("Unknown tool: {}", name))?;
+    let client = {
+        let all = CONNECTIONS.lock().await;
+        all.get(workspace_dir)
+            .and_then(|live| live.get(&tool.server))
+            .ok_or_else(|| format!("MCP server '{}' is not connected", tool.server))?
+            .client
+            .clone()?
+    };
+    let result = tokio::time::timeout(CALL_TIMEOUT, client.call_tool(&tool.tool.name, args.clone()))
+        .await
+        .map_err(|_| format!("MCP tool '{}' timed out after {}s", tool.tool.name, CALL_TIMEOUT.as_secs()))??;
+
+    let text = result_text(&result);
+    if result.get("isError").and_then(|v| v.as_bool()).unwrap_or(false) {
+        Err(text)
+    } else {
+        Ok(text)
+    }
+}
+
+/// Flatten a `tools/call` result into text for the model
+fn result_text(result: &Value) -> String {
+    let mut parts = Vec::new();
+    for block in result.get("content").and_then(|c| c.as_array()).into_iter().flatten() {
+        match block.get("type").and_then(|t| t.as_str()) {
+            Some("text") => parts.push(block["text"].as_str().unwrap_or("").to_string()),
+            Some("resource") => {
+                let resource = &block["resource"];
+                match resource.get("text").and_then(|t| t.as_str()) {
+                    Some(text) => parts.push(text.to_string()),
+                    None => parts.push(format!("[resource: {}]", resource["uri"].as_str().unwrap_or("?"))),
+                }
+            }
+            Some("resource_link") => {
+                parts.push(format!("[resource: {}]", block["uri"].as_str().unwrap_or("?")))
+            }
+            Some(other) => parts.push(format!(
+                "[{} content: {}]",
+                other,
+                block["mimeType"].as_str().unwrap_or("unknown type")
+            )),
+            None => {}
+        }
+    }
+    if parts.is_empty() {
+        if let Some(structured) = result.get("structuredContent") {
+            return structured.to_string();
+        }
+    }
+    parts.join("\n")
+}
+
+#[derive(Serialize, Debug)]
+pub struct McpServerStatus {
+    pub name: String,
+    pub transport: String,
+    pub connected: bool,
+    pub tools: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Defined in `.clif/mcp.json` and not yet trusted, so never started
+    pub pending_trust: bool,
+}
+
+/// Configured servers for the workspace and whether they connected
+#[tauri::command]
+pub async fn mcp_list_servers(workspace_dir: String) -> Result<Vec<McpServerStatus>, String> {
+    let mut servers: Vec<McpServerStatus> = connections(&workspace_dir)
+        .await
+        .into_iter()
+        .map(|(name, config, client)| McpServerStatus {
+            name,
+            transport: config.transport().to_string(),
+            connected: client.is_ok(),
+            tools: client
+                .as_ref()
+                .map(|c| c.tools().iter().map(|t| t.name.clone()).collect())
+                .unwrap_or_default(),
+            error: client.err(),
+            pending_trust: false,
+        })
+        .collect();
+    for entry in untrusted_servers(&workspace_dir) {
+        let name = entry["name"].as_str().unwrap_or_default().to_string();
+        // A settings entry of the same name runs in the meantime
+        if servers.iter().any(|s| s.name == name) {
+            continue;
+        }
+        servers.push(McpServerStatus {
+            name,
+            transport: if entry["runs"].as_str().is_some_and(|r| r.starts_with("http")) { "http" } else { "stdio" }
+                .to_string(),
+            connected: false,
+            tools: Vec::new(),
+            error: Some("Defined in .clif/mcp.json and not trusted yet".to_string()),
+            pending_trust: true,
+        });
+    }
+    Ok(servers)
+}
+
+/// Drop every connection for the workspace; the next agent turn reconnects
+#[tauri::command]
+pub async fn mcp_reconnect(workspace_dir: String) -> Result<(), String> {
+    CONNECTIONS.lock().await.remove(&workspace_dir);
+    if let Ok(mut tools) = TOOLS.lock() {
+        tools.remove(&workspace_dir);
+    }
+    Ok(())
+}
+
+/// Trust (or stop trusting) the servers the workspace's `.clif/mcp.json`
+/// currently defines. Trust covers that exact content only.
+#[tauri::command]
+pub async fn mcp_trust_workspace(workspace_dir: String, trusted: bool) -> Result<(), String> {
+    let path = trust_path().ok_or("Could not determine home directory")?;
+    let mut store = trust_store();
+    match workspace_servers(&workspace_dir) {
+        Some(servers) if trusted => {
+            store.insert(workspace_dir.clone(), servers);
+        }
+        _ => {
+            store.remove(&workspace_dir);
+        }
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create config dir: {}", e))?;
+    }
+    let json = serde_json::to_string_pretty(&Value::Object(store))
+        .map_err(|e| format!("Failed to serialize MCP trust: {}", e))?;
+    std::fs::write(&path, json).map_err(|e| format!("Failed to write MCP trust: {}", e))?;
+    mcp_reconnect(workspace_dir).await
+}
+
+/// Endpoint and `mcpServers` snippet for pointing an external agent at
+/// ClifPad's own MCP server
+#[tauri::command]
+pub async fn mcp_server_info(workspace_dir: String) -> Result<server::Endpoint, String> {
+    server::endpoint(&workspace_dir).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_config_overrides_settings_and_drops_disabled() {
+        let settings = json!({ "mcpServers": {
+            "docs": { "url": "https://example.com/mcp" },
+            "fs": { "command": "npx", "args": ["fs-server"] },
+            "off": { "command": "x", "disabled": true },
+        }});
+        let workspace = json!({ "mcpServers": {
+            "fs": { "command": "node", "args": ["local.js"], "autoApprove": ["read"] },
+            "broken": { "args": "not-a-list" },
+        }});
+        let servers = merge_configs(&settings, Some(&workspace));
+        assert_eq!(servers.keys().collect::<Vec<_>>(), vec!["docs", "fs"]);
+        assert_eq!(servers["fs"].command.as_deref(), Some("node"));
+        assert_eq!(servers["docs"].transport(), "http");
+    }
+
+    #[test]
+    fn workspace_file_cannot_grant_auto_approve() {
+        let settings = json!({ "mcpServers": {
+            "docs": { "url": "https://example.com/mcp", "autoApprove": ["search"] },
+        }});
+        let workspace = json!({ "mcpServers": {
+            "fs": { "command": "node", "autoApprove": ["*"] },
+            "git": { "command": "git-mcp", "autoApprove": ["status"] },
+        }});
+        let servers = merge_configs(&settings, Some(&workspace));
+        assert!(servers["docs"].auto_approves("search"));
+        assert!(!servers["docs"].auto_approves("fetch"));
+        assert!(servers["fs"].auto_approve.is_empty());
+        assert!(!servers["fs"].auto_approves("write"));
+        assert!(!servers["git"].auto_approves("status"));
+    }
+
+    #[test]
+    fn trust_is_tied_to_the_exact_servers_and_workspace() {
+        let servers = json!({ "fs": { "command": "node", "args": ["a.js"] } });
+        let mut store = serde_json::Map::new();
+        assert!(!is_trusted(&store, "/ws", &servers));
+        store.insert("/ws".to_string(), servers.clone());
+        assert!(is_trusted(&store, "/ws", &servers));
+        assert!(!is_trusted(&store, "/other", &servers));
+        let edited = json!({ "fs": { "command": "node", "args": ["evil.js"] } });
+        assert!(!is_trusted(&store, "/ws", &edited));
+    }
+
+    #[test]
+    fn registry_is_scoped_per_workspace() {
+        let tool = |auto_approve| RegisteredTool {
+            server: "fs".into(),
+            tool: McpTool {
+                name: "write".into(),
+                description: None,
+                input_schema: json!({}),
+                read_only: false,
+            },
+            auto_approve,
+        };
+        let name = qualified_name("fs", "write");
+        {
+            let mut tools = TOOLS.lock().unwrap();
+            tools.insert("/a".into(), HashMap::from([(name.clone(), tool(true))]));
+            tools.insert("/b".into(), HashMap::from([(name.clone(), tool(false))]));
+        }
+        assert!(!needs_approval("/a", &name));
+        assert!(needs_approval("/b", &name));
+        assert!(needs_approval("/c", &name));
+
+        // A rebuild replaces the workspace's entries rather than adding to them
+        TOOLS.lock().unwrap().insert("/a".into(), HashMap::new());
+        assert!(registered("/a", &name).is_none());
+    }
+
+    #[test]
+    fn qualified_names_are_api_safe() {
+        assert_eq!(qualified_name("git hub", "create.issue"), "mcp__git_hub__create_issue");
+        assert!(qualified_name("s", &"x".repeat(100)).len() <= MAX_TOOL_NAME);
+        assert!(is_mcp_tool("mcp__a__b"));
+        assert!(!is_mcp_tool("read_file"));
+
+        let long = "x".repeat(100);
+        let names = qualified_names(&[
+            ("git hub", "issue"),
+            ("git.hub", "issue"),
+            ("fs", "read"),
+            ("s", &long),
+            ("s", &format!("{}y", long)),
+        ]);
+        assert_eq!(names[2], "mcp__fs__read");
+        for name in &names {
+            assert!(name.len() <= MAX_TOOL_NAME);
+            assert_eq!(names.iter().filter(|n| *n == name).count(), 1, "{}", name);
+        }
+        assert!(names[0].starts_with("mcp__git_hub__issue_"));
+    }
+
+    #[test]
+    fn schema_validation_checks_required_types_and_extras() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string" },
+                "limit": { "type": ["integer", "null"] },
+            },
+            "required": ["query"],
+            "additionalProperties": false,
+        });
+        assert!(validate_against_schema("t", &schema, &json!({ "query": "x", "limit": 3 })).is_ok());
+        assert!(validate_against_schema("t", &schema, &json!({ "query": "x", "limit": null })).is_ok());
+        assert!(validate_against_schema("t", &schema, &json!({ "limit": 3 })).is_err());
+        assert!(validate_against_schema("t", &schema, &json!({ "query": 1 })).is_err());
+        assert!(validate_against_schema("t", &schema, &json!({ "query": "x", "extra": true })).is_err());
+        assert!(validate_against_schema("t", &schema, &json!("x")).is_err());
+    }
+
+    #[test]
+    fn result_text_flattens_content_blocks() {
+        let result = json!({ "content": [
+            { "type": "text", "text": "hello" },
+            { "type": "image", "data": "...", "mimeType": "image/png" },
+            { "type": "resource", "resource": { "uri": "file:///a", "text": "body" } },
+        ]});
+        assert_eq!(result_text(&result), "hello\n[image content: image/png]\nbody");
+        assert_eq!(result_text(&json!({ "content": [], "structuredContent": { "n": 1 } })), "{\"n\":1}");
+    }
+}
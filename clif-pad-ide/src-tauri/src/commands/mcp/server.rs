@@ -0,0 +1,442 @@
+//! ClifPad as an MCP server.
+//!
+//! Serves the codebase index (`index_find_symbol`, `index_search`) and cached
+//! PR review results over streamable HTTP on loopback. The listener starts on
+//! first use and is shared by all windows; each workspace gets its own bearer
+//! token, and a token only ever sees the workspace it was issued for. The
+//! server is stateless, so it answers every POST with plain JSON and never
+//! opens an SSE stream.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{LazyLock, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::PROTOCOL_VERSION;
+use crate::commands::{indexer, review, settings};
+
+/// Older revisions we still answer to; the wire format we use is unchanged
+const SUPPORTED_VERSIONS: &[&str] = &[PROTOCOL_VERSION, "2025-03-26", "2024-11-05"];
+
+/// Requests larger than this are refused
+const MAX_BODY: usize = 1024 * 1024;
+
+static LISTENER: tokio::sync::OnceCell<Result<SocketAddr, String>> = tokio::sync::OnceCell::const_new();
+
+// Bearer token -> workspace_dir
+static TOKENS: LazyLock<Mutex<HashMap<String, String>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Endpoint {
+    pub url: String,
+    pub token: String,
+    /// Ready-made `mcpServers` entry for Claude, Cursor and friends
+    pub config: Value,
+}
+
+/// Start the server if needed and return the endpoint for `workspace_dir`
+pub(crate) async fn endpoint(workspace_dir: &str) -> Result<Endpoint, String> {
+    let addr = LISTENER.get_or_init(start).await.clone()?;
+    let token = {
+        let mut tokens = TOKENS.lock().map_err(|e| e.to_string())?;
+        match tokens.iter().find(|(_, ws)| ws.as_str() == workspace_dir) {
+            Some((token, _)) => token.clone(),
+            None => {
+                let token = uuid::Uuid::new_v4().simple().to_string();
+                tokens.insert(token.clone(), workspace_dir.to_string());
+                token
+            }
+        }
+    };
+    let url = format!("http://{}/mcp", addr);
+    let config = json!({
+        "mcpServers": {
+            "clifpad": {
+                "type": "http",
+                "url": url,
+                "headers": { "Authorization": format!("Bearer {}", token) },
+            }
+        }
+    });
+    Ok(Endpoint { url, token, config })
+}
+
+/// Bind to loopback; `mcpServerPort` in settings pins the port, otherwise
+/// the OS picks one
+async fn start() -> Result<SocketAddr, String> {
+    let port = settings::get_settings()
+        .ok()
+        .and_then(|s| s.get("mcpServerPort").and_then(|p| p.as_u64()))
+        .and_then(|p| u16::try_from(p).ok())
+        .unwrap_or(0);
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .map_err(|e| format!("MCP server could not bind 127.0.0.1:{}: {}", port, e))?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    log::info!("MCP server listening on {}", addr);
+
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream).await {
+                            log::debug!("MCP connection closed: {}", e);
+                        }
+                    });
+                }
+                Err(e) => log::warn!("MCP accept failed: {}", e),
+            }
+        }
+    });
+    Ok(addr)
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    body: Option<Value>,
+}
+
+impl Response {
+    fn empty(status: &'static str) -> Self {
+        Response { status, body: None }
+    }
+}
+
+/// Minimal HTTP/1.1 keep-alive loop: read a request, answer it, repeat
+async fn serve_connection(stream: TcpStream) -> std::io::Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    loop {
+        let Some(request) = read_request(&mut reader).await? else {
+            return Ok(());
+        };
+        let close = request
+            .headers
+            .get("connection")
+            .is_some_and(|v| v.eq_ignore_ascii_case("close"));
+        let response = route(request).await;
+
+        let body = response.body.map(|b| b.to_string()).unwrap_or_default();
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\n",
+            response.status,
+            body.len()
+        );
+        if !body.is_empty() {
+            head.push_str("Content-Type: application/json\r\n");
+        }
+        if response.status.starts_with("405") {
+            head.push_str("Allow: POST\r\n");
+        }
+        head.push_str("\r\n");
+        write.write_all(head.as_bytes()).await?;
+        write.write_all(body.as_bytes()).await?;
+        write.flush().await?;
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+async fn read_request<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> std::io::Result<Option<Request>> {
+    let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string());
+
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().ok_or_else(|| invalid("empty request line"))?.to_string();
+    let target = parts.next().ok_or_else(|| invalid("missing request target"))?;
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Err(invalid("connection closed inside headers"));
+        }
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = trimmed.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(invalid("request body too large"));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(Request { method, path, headers, body }))
+}
+
+async fn route(request: Request) -> Response {
+    if request.path != "/mcp" {
+        return Response::empty("404 Not Found");
+    }
+    // Browsers always send Origin; refuse pages other than local ones
+    // (DNS rebinding protection, as the transport spec asks)
+    if let Some(origin) = request.headers.get("origin") {
+        let host = origin
+            .split_once("://")
+            .map_or("", |(_, rest)| rest)
+            .split([':', '/'])
+            .next()
+            .unwrap_or("");
+        let local = matches!(host, "127.0.0.1" | "localhost");
+        if !local {
+            return Response::empty("403 Forbidden");
+        }
+    }
+    let workspace = request
+        .headers
+        .get("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .and_then(|token| TOKENS.lock().ok()?.get(token.trim()).cloned());
+    let Some(workspace) = workspace else {
+        return Response::empty("401 Unauthorized");
+    };
+    if request.method != "POST" {
+        return Response::empty("405 Method Not Allowed");
+    }
+
+    let Ok(message) = serde_json::from_slice::<Value>(&request.body) else {
+        return Response {
+            status: "400 Bad Request",
+            body: Some(rpc_error(Value::Null, -32700, "Parse error")),
+        };
+    };
+    match handle(&workspace, &message).await {
+        Some(reply) => Response { status: "200 OK", body: Some(reply) },
+        // Notifications and responses get no body
+        None => Response::empty("202 Accepted"),
+    }
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Answer one JSON-RPC message. `None` for notifications.
+pub(crate) async fn handle(workspace_dir: &str, message: &Value) -> Option<Value> {
+    let id = message.get("id")?.clone();
+    let method = message.get("method").and_then(|m| m.as_str())?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = match method {
+        "initialize" => {
+            let requested = params.get("protocolVersion").and_then(|v| v.as_str()).unwrap_or("");
+            let version = if SUPPORTED_VERSIONS.contains(&requested) {
+                requested
+            } else {
+                PROTOCOL_VERSION
+            };
+            json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": { "listChanged": false } },
+                "serverInfo": { "name": "clifpad", "version": env!("CARGO_PKG_VERSION") },
+                "instructions": "ClifPad's view of the open workspace: a symbol and BM25 file index, and the results of PR reviews run in ClifPad.",
+            })
+        }
+        "ping" => json!({}),
+        "tools/list" => json!({ "tools": tool_list() }),
+        "tools/call" => {
+            let Some(name) = params.get("name").and_then(|n| n.as_str()) else {
+                return Some(rpc_error(id, -32602, "tools/call needs a tool name"));
+            };
+            let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            match call_tool(workspace_dir, name, &args).await {
+                Ok(result) => json!({
+                    "content": [{ "type": "text", "text": result.to_string() }],
+                    "structuredContent": result,
+                    "isError": false,
+                }),
+                Err(e) => json!({
+                    "content": [{ "type": "text", "text": e }],
+                    "isError": true,
+                }),
+            }
+        }
+        _ => return Some(rpc_error(id, -32601, &format!("Method not found: {}", method))),
+    };
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn tool_list() -> Vec<Value> {
+    let read_only = json!({ "readOnlyHint": true, "openWorldHint": false });
+    let query_schema = |what: &str| {
+        json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "description": what },
+                "limit": { "type": "integer", "description": "Max results. Defaults to 20." },
+            },
+            "required": ["query"],
+        })
+    };
+    vec![
+        json!({
+            "name": "index_find_symbol",
+            "description": "Find function, class, struct, type and constant definitions by name (case-insensitive, exact > prefix > substring). Returns file, line and kind for each hit.",
+            "inputSchema": query_schema("Symbol name or part of it"),
+            "annotations": read_only,
+        }),
+        json!({
+            "name": "index_search",
+            "description": "Rank workspace files for a free-text query with BM25, boosted by how recently each file was touched.",
+            "inputSchema": query_schema("Words to search for"),
+            "annotations": read_only,
+        }),
+        json!({
+            "name": "review_list",
+            "description": "List the PR reviews ClifPad has run in this workspace: PR number, summary, risk score and finding counts.",
+            "inputSchema": { "type": "object", "properties": {} },
+            "annotations": read_only,
+        }),
+        json!({
+            "name": "review_get",
+            "description": "Full result of ClifPad's review of one PR, including every finding with its path, lines, severity and suggested patch.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "pr_number": { "type": "integer", "description": "Pull request number" } },
+                "required": ["pr_number"],
+            },
+            "annotations": read_only,
+        }),
+    ]
+}
+
+async fn call_tool(workspace_dir: &str, name: &str, args: &Value) -> Result<Value, String> {
+    let query = || {
+        args.get("query")
+            .and_then(|q| q.as_str())
+            .map(String::from)
+            .ok_or_else(|| "Missing required field 'query'".to_string())
+    };
+    let limit = args.get("limit").and_then(|l| l.as_u64()).map(|l| l.min(200) as u32);
+    let ws = workspace_dir.to_string();
+
+    match name {
+        "index_find_symbol" => {
+            let query = query()?;
+            let hits = tokio::task::spawn_blocking(move || indexer::index_find_symbol(ws, query, limit))
+                .await
+                .map_err(|e| e.to_string())??;
+            serde_json::to_value(hits).map_err(|e| e.to_string())
+        }
+        "index_search" => {
+            let query = query()?;
+            let hits = tokio::task::spawn_blocking(move || indexer::index_search(ws, query, limit))
+                .await
+                .map_err(|e| e.to_string())??;
+            serde_json::to_value(hits).map_err(|e| e.to_string())
+        }
+        "review_list" => {
+            let reviews = review::pr_review_list(ws).await?;
+            let summaries: Vec<Value> = reviews
+                .iter()
+                .map(|r| {
+                    let open = r.findings.iter().filter(|f| !f.dismissed).count();
+                    json!({
+                        "pr_number": r.pr_number,
+                        "generated_at": r.generated_at,
+                        "summary": r.summary,
+                        "risk_score": r.risk_score,
+                        "findings": r.findings.len(),
+                        "open_findings": open,
+                    })
+                })
+                .collect();
+            Ok(json!(summaries))
+        }
+        "review_get" => {
+            let pr_number = args
+                .get("pr_number")
+                .and_then(|n| n.as_i64())
+                .ok_or("Missing required field 'pr_number'")?;
+            match review::pr_review_get(ws, pr_number).await? {
+                Some(result) => serde_json::to_value(result).map_err(|e| e.to_string()),
+                None => Err(format!("No ClifPad review found for PR #{}", pr_number)),
+            }
+        }
+        _ => Err(format!("Unknown tool: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn initialize_negotiates_version_and_notifications_get_no_reply() {
+        let reply = handle(
+            "/tmp/ws",
+            &json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "protocolVersion": "2025-03-26" } }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply["result"]["protocolVersion"], "2025-03-26");
+        assert!(reply["result"]["capabilities"]["tools"].is_object());
+
+        let reply = handle(
+            "/tmp/ws",
+            &json!({ "jsonrpc": "2.0", "id": 2, "method": "initialize", "params": { "protocolVersion": "1999-01-01" } }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(reply["result"]["protocolVersion"], PROTOCOL_VERSION);
+
+        assert!(handle("/tmp/ws", &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn lists_tools_and_rejects_unknown_methods() {
+        let reply = handle("/tmp/ws", &json!({ "jsonrpc": "2.0", "id": "a", "method": "tools/list" }))
+            .await
+            .unwrap();
+        let names: Vec<&str> = reply["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|t| t["name"].as_str())
+            .collect();
+        assert_eq!(names, ["index_find_symbol", "index_search", "review_list", "review_get"]);
+
+        let reply = handle("/tmp/ws", &json!({ "jsonrpc": "2.0", "id": 3, "method": "resources/list" }))
+            .await
+            .unwrap();
+        assert_eq!(reply["error"]["code"], -32601);
+        assert_eq!(reply["id"], 3);
+    }
+
+    #[tokio::test]
+    async fn read_request_parses_head_and_body() {
+        let raw = b"POST /mcp?x=1 HTTP/1.1\r\nHost: 127.0.0.1\r\nContent-Length: 2\r\nAuthorization: Bearer t\r\n\r\n{}";
+        let mut reader = BufReader::new(&raw[..]);
+        let request = read_request(&mut reader).await.unwrap().unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/mcp"));
+        assert_eq!(request.headers["authorization"], "Bearer t");
+        assert_eq!(request.body, b"{}");
+        assert!(read_request(&mut reader).await.unwrap().is_none());
+    }
+}
@@ -1,11 +1,16 @@
 pub mod agent;
 pub mod ai;
+pub mod budget;
+pub mod checkpoints;
 pub mod claude_code;
+pub mod conversations;
+pub mod edit_review;
 pub mod fs;
 pub mod gh;
 pub mod git;
 pub mod indexer;
 pub mod lsp;
+pub mod mcp;
 pub mod pty;
 pub mod review;
 pub mod search;
@@ -13,3 +18,4 @@ pub mod security;
 pub mod settings;
 pub mod sync;
 pub mod window;
+pub mod worktree;
@@ -3,6 +3,7 @@ use std::path::PathBuf;
 use tokio::process::Command;
 
 use crate::commands::gh::{augmented_path, gh_command, gh_std_command};
+use crate::commands::worktree;
 
 /// Context returned from fetch_pr. Minimal surface that any review run needs.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,14 +55,8 @@ impl GhCliDriver {
         }
     }
 
-    fn worktree_root(&self) -> PathBuf {
-        PathBuf::from(&self.workspace_dir)
-            .join(".clif")
-            .join("worktrees")
-    }
-
     fn worktree_path(&self, number: i64) -> PathBuf {
-        self.worktree_root().join(format!("pr-{}", number))
+        worktree::worktree_root(&self.workspace_dir).join(format!("pr-{}", number))
     }
 }
 
@@ -136,8 +131,7 @@ impl ReviewDriver for GhCliDriver {
     }
 
     fn checkout_worktree(&self, number: i64) -> Result<PathBuf, String> {
-        let root = self.worktree_root();
-        std::fs::create_dir_all(&root).map_err(|e| format!("mkdir worktrees: {}", e))?;
+        worktree::ensure_root(&self.workspace_dir)?;
         let target = self.worktree_path(number);
 
         // If already present, reuse (cheap idempotency).
@@ -159,19 +153,7 @@ impl ReviewDriver for GhCliDriver {
             .current_dir(&self.workspace_dir)
             .output();
 
-        let out = std::process::Command::new("git")
-            .args([
-                "worktree",
-                "add",
-                target.to_string_lossy().as_ref(),
-                &branch,
-            ])
-            .env("PATH", augmented_path())
-            .current_dir(&self.workspace_dir)
-            .output()
-            .map_err(|e| format!("git worktree add failed to start: {}", e))?;
-
-        if !out.status.success() {
+        if let Err(add_err) = worktree::add(&self.workspace_dir, &target, &branch, None) {
             // Fallback: try via gh pr checkout within a detached clone
             if let Ok(mut cmd) = gh_std_command() {
                 let fallback = cmd
@@ -187,11 +169,7 @@ impl ReviewDriver for GhCliDriver {
                     .current_dir(&target)
                     .output();
                 if fallback.is_err() {
-                    return Err(format!(
-                        "git worktree add failed: {}. Tried spec {}",
-                        String::from_utf8_lossy(&out.stderr),
-                        ref_spec
-                    ));
+                    return Err(format!("{}. Tried spec {}", add_err, ref_spec));
                 }
             }
         }
@@ -200,20 +178,7 @@ impl ReviewDriver for GhCliDriver {
     }
 
     fn cleanup_worktree(&self, number: i64) {
-        let target = self.worktree_path(number);
-        if !target.exists() {
-            return;
-        }
-        let _ = std::process::Command::new("git")
-            .args([
-                "worktree",
-                "remove",
-                "--force",
-                target.to_string_lossy().as_ref(),
-            ])
-            .env("PATH", augmented_path())
-            .current_dir(&self.workspace_dir)
-            .output();
+        worktree::remove(&self.workspace_dir, &self.worktree_path(number));
     }
 
     fn post_review(
@@ -2,6 +2,8 @@ use serde_json::json;
 use std::collections::HashMap;
 use std::path::Path;
 
+use crate::services::ai_provider;
+
 use super::driver::{PrContext, ReviewDriver};
 use super::rules::{path_instructions_for, path_is_excluded, Profile, ReviewConfig};
 use super::schema::{
@@ -394,11 +396,7 @@ fn chrono_like_now() -> String {
 }
 
 async fn call_llm(settings: &ReviewSettings, prompt: &str) -> Result<String, String> {
-    let url = match settings.provider.as_str() {
-        "ollama" => "http://localhost:11434/v1/chat/completions".to_string(),
-        _ => "https://openrouter.ai/api/v1/chat/completions".to_string(),
-    };
-    let client = reqwest::Client::new();
+    let provider = ai_provider::resolve(&settings.provider, settings.api_key.clone())?;
     let body = json!({
         "model": settings.model,
         "messages": [
@@ -409,35 +407,11 @@ async fn call_llm(settings: &ReviewSettings, prompt: &str) -> Result<String, Str
         "response_format": { "type": "json_object" }
     });
 
-    let mut req = client.post(&url).header("Content-Type", "application/json");
-    if let Some(key) = &settings.api_key {
-        req = req.header("Authorization", format!("Bearer {}", key));
-    }
-    if settings.provider == "openrouter" {
-        req = req
-            .header("HTTP-Referer", "https://clif.dev")
-            .header("X-Title", "ClifPad Review");
-    }
-
-    let resp = req
-        .json(&body)
-        .send()
-        .await
-        .map_err(|e| format!("request error: {}", e))?;
-    if !resp.status().is_success() {
-        let status = resp.status();
-        let txt = resp.text().await.unwrap_or_default();
-        return Err(format!("{}: {}", status, txt));
+    let reply = ai_provider::complete(provider.as_ref(), body).await?;
+    if reply.content.is_empty() {
+        return Err("model returned no content".to_string());
     }
-    let json_resp: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| format!("decode error: {}", e))?;
-    let content = json_resp
-        .pointer("/choices/0/message/content")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| "missing choices[0].message.content".to_string())?;
-    Ok(content.to_string())
+    Ok(reply.content)
 }
 
 pub fn cache_path(workspace_dir: &str, pr_number: i64) -> std::path::PathBuf {
@@ -17,6 +17,8 @@ use std::sync::{Arc, Mutex};
 use serde_json::json;
 use tauri::{AppHandle, Emitter};
 
+use crate::services::ai_provider;
+
 use audit::{list_entries, AuditEntry};
 use auto_comment::{draft_comment, list_pending, PendingComment};
 use consolidate::{apply_consolidation, plan_consolidation, ConsolidationPlan, ConsolidationResult};
@@ -37,14 +39,6 @@ static RUNNING_REVIEWS: std::sync::LazyLock<
 static CACHED_PLANS: std::sync::LazyLock<Arc<Mutex<HashMap<String, PolishPlan>>>> =
     std::sync::LazyLock::new(|| Arc::new(Mutex::new(HashMap::new())));
 
-fn load_api_key_for(provider: &str) -> Option<String> {
-    let home = std::env::var("HOME").ok()?;
-    let keys_path = format!("{}/.clif/api_keys.json", home);
-    let content = std::fs::read_to_string(keys_path).ok()?;
-    let keys: serde_json::Value = serde_json::from_str(&content).ok()?;
-    keys.get(provider).and_then(|k| k.as_str()).map(|s| s.to_string())
-}
-
 #[tauri::command]
 pub async fn pr_fetch_diff(workspace_dir: String, pr_number: i64) -> Result<String, String> {
     fetch_pr_diff_async(&workspace_dir, pr_number).await
@@ -62,9 +56,12 @@ pub async fn pr_review_run(
     let provider = provider.unwrap_or_else(|| "openrouter".to_string());
     let resolved_model = model.unwrap_or_else(|| match provider.as_str() {
         "ollama" => "qwen2.5-coder".to_string(),
+        "anthropic" => "claude-sonnet-4-20250514".to_string(),
+        "openai" | "azure" => "gpt-4o".to_string(),
+        "gemini" => "gemini-2.5-flash".to_string(),
         _ => "anthropic/claude-sonnet-4".to_string(),
     });
-    let resolved_key = api_key.or_else(|| load_api_key_for(&provider));
+    let resolved_key = api_key.or_else(|| ai_provider::load_api_key(&provider));
 
     let settings = ReviewSettings {
         model: resolved_model,
@@ -13,7 +13,7 @@ fn get_settings_path() -> Result<PathBuf, String> {
 }
 
 /// Helper to get the home directory cross-platform
-fn get_home_dir() -> Option<PathBuf> {
+pub(crate) fn get_home_dir() -> Option<PathBuf> {
     #[cfg(target_os = "macos")]
     {
         std::env::var("HOME").ok().map(PathBuf::from)
@@ -102,7 +102,7 @@ pub fn load_agent_history(workspace_dir: String) -> Option<serde_json::Value> {
 }
 
 /// Simple deterministic hash for workspace path → filename
-fn md5_hash(s: &str) -> u64 {
+pub(crate) fn md5_hash(s: &str) -> u64 {
     let mut hash: u64 = 14695981039346656037;
     for byte in s.bytes() {
         hash ^= byte as u64;
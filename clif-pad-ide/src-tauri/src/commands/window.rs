@@ -1,7 +1,32 @@
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+/// What a new window should do once its frontend is up
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct WindowLaunch {
+    pub project_path: Option<String>,
+    /// First message for the agent, e.g. a race task
+    pub agent_task: Option<String>,
+    pub agent_model: Option<String>,
+}
+
+// Launch requests waiting for their window: label -> launch
+static PENDING_LAUNCHES: LazyLock<Mutex<HashMap<String, WindowLaunch>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 #[tauri::command]
-pub async fn create_window(app: tauri::AppHandle) -> Result<String, String> {
+pub async fn create_window(
+    app: tauri::AppHandle,
+    launch: Option<WindowLaunch>,
+) -> Result<String, String> {
     let label = format!("window-{}", &uuid::Uuid::new_v4().to_string()[..8]);
 
+    if let Some(launch) = launch {
+        if let Ok(mut pending) = PENDING_LAUNCHES.lock() {
+            pending.insert(label.clone(), launch);
+        }
+    }
+
     tauri::WebviewWindowBuilder::new(
         &app,
         &label,
@@ -10,7 +35,18 @@ pub async fn create_window(app: tauri::AppHandle) -> Result<String, String> {
     .title("Clif")
     .inner_size(1400.0, 900.0)
     .build()
-    .map_err(|e| format!("Failed to create window: {}", e))?;
+    .map_err(|e| {
+        if let Ok(mut pending) = PENDING_LAUNCHES.lock() {
+            pending.remove(&label);
+        }
+        format!("Failed to create window: {}", e)
+    })?;
 
     Ok(label)
 }
+
+/// Hand the calling window its launch request, once
+#[tauri::command]
+pub fn take_window_launch(window: tauri::Window) -> Option<WindowLaunch> {
+    PENDING_LAUNCHES.lock().ok()?.remove(window.label())
+}
@@ -0,0 +1,377 @@
+//! Git worktrees under `.clif/worktrees/`.
+//!
+//! The helpers at the top are shared with the PR review driver. The
+//! `agent_worktree_*` commands give each agent session its own checkout on a
+//! `clif/agent/<id>` branch so several sessions can race on the same task
+//! without touching each other's files. A winner is merged or cherry-picked
+//! back into the workspace and its race is cleaned up.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::commands::gh::augmented_path;
+use crate::commands::git::{parse_shortstat, GitDiffStat};
+
+/// Directory holding every ClifPad-managed worktree of a workspace
+pub(crate) fn worktree_root(workspace_dir: &str) -> PathBuf {
+    PathBuf::from(workspace_dir).join(".clif").join("worktrees")
+}
+
+/// Create the worktree root, ignored so the main checkout never lists it
+pub(crate) fn ensure_root(workspace_dir: &str) -> Result<PathBuf, String> {
+    let root = worktree_root(workspace_dir);
+    std::fs::create_dir_all(&root).map_err(|e| format!("mkdir worktrees: {}", e))?;
+    let ignore = root.join(".gitignore");
+    if !ignore.exists() {
+        let _ = std::fs::write(&ignore, "*\n");
+    }
+    Ok(root)
+}
+
+/// Run git in `dir`, returning stdout or stderr as the error
+pub(crate) fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let out = Command::new("git")
+        .args(args)
+        .env("PATH", augmented_path())
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("git {} failed to start: {}", args[0], e))?;
+    if !out.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+/// `git worktree add`. With `start` a new `branch` is created at that
+/// commit; without it the existing `branch` is checked out.
+pub(crate) fn add(
+    workspace_dir: &str,
+    target: &Path,
+    branch: &str,
+    start: Option<&str>,
+) -> Result<(), String> {
+    let target = target.to_string_lossy();
+    let mut args = vec!["worktree", "add"];
+    match start {
+        Some(start) => args.extend(["-b", branch, target.as_ref(), start]),
+        None => args.extend([target.as_ref(), branch]),
+    }
+    git(Path::new(workspace_dir), &args).map(|_| ())
+}
+
+/// Best-effort `git worktree remove --force`
+pub(crate) fn remove(workspace_dir: &str, target: &Path) {
+    if !target.exists() {
+        return;
+    }
+    let _ = git(
+        Path::new(workspace_dir),
+        &[
+            "worktree",
+            "remove",
+            "--force",
+            target.to_string_lossy().as_ref(),
+        ],
+    );
+}
+
+/// One agent session's isolated checkout
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentWorktree {
+    pub id: String,
+    /// Shared by the sessions started together on one task
+    pub race_id: Option<String>,
+    /// Usually the model the session runs
+    pub label: String,
+    pub path: String,
+    pub branch: String,
+    /// Commit the worktree was created from
+    pub base: String,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentWorktreeStatus {
+    #[serde(flatten)]
+    pub worktree: AgentWorktree,
+    /// Everything the session changed since `base`, committed or not
+    pub stat: GitDiffStat,
+    pub commits: usize,
+}
+
+fn meta_path(workspace_dir: &str, id: &str) -> PathBuf {
+    worktree_root(workspace_dir).join(format!("{}.json", id))
+}
+
+fn load(workspace_dir: &str, id: &str) -> Result<AgentWorktree, String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(format!("Invalid worktree id: {}", id));
+    }
+    let raw = std::fs::read_to_string(meta_path(workspace_dir, id))
+        .map_err(|_| format!("Unknown agent worktree: {}", id))?;
+    serde_json::from_str(&raw).map_err(|e| format!("Corrupt worktree metadata: {}", e))
+}
+
+fn list(workspace_dir: &str) -> Vec<AgentWorktree> {
+    let Ok(entries) = std::fs::read_dir(worktree_root(workspace_dir)) else {
+        return Vec::new();
+    };
+    let mut worktrees: Vec<AgentWorktree> = entries
+        .flatten()
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|e| std::fs::read_to_string(e.path()).ok())
+        .filter_map(|raw| serde_json::from_str(&raw).ok())
+        .collect();
+    worktrees.sort_by_key(|w| w.created_at);
+    worktrees
+}
+
+/// Diff a worktree against its base, read-only: the agent may be running
+/// git in the worktree, so its index is never touched. Untracked files are
+/// diffed against /dev/null and appended after the tracked changes.
+fn diff_against_base(wt: &AgentWorktree, extra: &[&str]) -> Result<String, String> {
+    let dir = Path::new(&wt.path);
+    let mut args = vec!["diff"];
+    args.extend_from_slice(extra);
+    args.push(&wt.base);
+    let mut out = git(dir, &args)?;
+
+    let untracked = git(dir, &["ls-files", "--others", "--exclude-standard", "-z"])?;
+    for file in untracked.split('\0').filter(|f| !f.is_empty()) {
+        let mut args = vec!["diff", "--no-index"];
+        args.extend_from_slice(extra);
+        args.extend(["--", "/dev/null", file]);
+        out.push_str(&diff_no_index(dir, &args)?);
+    }
+    Ok(out)
+}
+
+/// `git diff --no-index` exits 1 when the sides differ, which is the point
+fn diff_no_index(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let out = Command::new("git")
+        .args(args)
+        .env("PATH", augmented_path())
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("git diff failed to start: {}", e))?;
+    match out.status.code() {
+        Some(0 | 1) => Ok(String::from_utf8_lossy(&out.stdout).to_string()),
+        _ => Err(format!(
+            "git diff failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )),
+    }
+}
+
+/// Sum the `--shortstat` lines of [`diff_against_base`]
+fn sum_shortstats(text: &str) -> GitDiffStat {
+    let mut total = GitDiffStat::default();
+    for stat in text.lines().map(parse_shortstat) {
+        total.files_changed += stat.files_changed;
+        total.insertions += stat.insertions;
+        total.deletions += stat.deletions;
+    }
+    total
+}
+
+fn discard(workspace_dir: &str, wt: &AgentWorktree) {
+    remove(workspace_dir, Path::new(&wt.path));
+    let _ = git(Path::new(workspace_dir), &["branch", "-D", &wt.branch]);
+    let _ = std::fs::remove_file(meta_path(workspace_dir, &wt.id));
+}
+
+/// Create a worktree on a fresh branch at the workspace's HEAD
+#[tauri::command]
+pub fn agent_worktree_create(
+    workspace_dir: String,
+    label: Option<String>,
+    race_id: Option<String>,
+) -> Result<AgentWorktree, String> {
+    let base = git(Path::new(&workspace_dir), &["rev-parse", "HEAD"])
+        .map_err(|_| "Agent worktrees need a git repository with at least one commit".to_string())?
+        .trim()
+        .to_string();
+    let root = ensure_root(&workspace_dir)?;
+    let id = format!("agent-{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let target = root.join(&id);
+    let branch = format!("clif/agent/{}", id);
+    add(&workspace_dir, &target, &branch, Some(&base))?;
+
+    let worktree = AgentWorktree {
+        label: label.unwrap_or_else(|| id.clone()),
+        id,
+        race_id,
+        path: target.to_string_lossy().to_string(),
+        branch,
+        base,
+        created_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+    let meta = serde_json::to_string_pretty(&worktree).map_err(|e| e.to_string())?;
+    if let Err(e) = std::fs::write(meta_path(&workspace_dir, &worktree.id), meta) {
+        discard(&workspace_dir, &worktree);
+        return Err(format!("Failed to write worktree metadata: {}", e));
+    }
+    Ok(worktree)
+}
+
+/// Agent worktrees of this workspace with their diff stats
+#[tauri::command]
+pub fn agent_worktree_list(workspace_dir: String) -> Vec<AgentWorktreeStatus> {
+    list(&workspace_dir)
+        .into_iter()
+        .map(|wt| {
+            let stat = diff_against_base(&wt, &["--shortstat"])
+                .map(|s| sum_shortstats(&s))
+                .unwrap_or_default();
+            let commits = git(
+                Path::new(&wt.path),
+                &["rev-list", "--count", &format!("{}..HEAD", wt.base)],
+            )
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0);
+            AgentWorktreeStatus {
+                worktree: wt,
+                stat,
+                commits,
+            }
+        })
+        .collect()
+}
+
+/// Full diff of a worktree against the commit it started from
+#[tauri::command]
+pub fn agent_worktree_diff(workspace_dir: String, id: String) -> Result<String, String> {
+    diff_against_base(&load(&workspace_dir, &id)?, &[])
+}
+
+/// Bring a worktree's changes back into the workspace with `strategy`
+/// `"merge"` or `"cherry-pick"`, then remove it and every other worktree of
+/// its race.
+#[tauri::command]
+pub fn agent_worktree_apply(
+    workspace_dir: String,
+    id: String,
+    strategy: String,
+    message: Option<String>,
+) -> Result<String, String> {
+    let wt = load(&workspace_dir, &id)?;
+    let dir = Path::new(&wt.path);
+
+    // Commit whatever the agent left uncommitted on its branch
+    git(dir, &["add", "--all"])?;
+    if git(dir, &["diff", "--cached", "--quiet"]).is_err() {
+        let message = message.unwrap_or_else(|| format!("Agent changes ({})", wt.label));
+        git(dir, &["commit", "-m", &message])?;
+    }
+    let range = format!("{}..{}", wt.base, wt.branch);
+    let commits: usize = git(dir, &["rev-list", "--count", &range])?
+        .trim()
+        .parse()
+        .unwrap_or(0);
+    if commits == 0 {
+        return Err(format!("{} has no changes to apply", wt.label));
+    }
+
+    let workspace = Path::new(&workspace_dir);
+    let output = match strategy.as_str() {
+        "merge" => {
+            git(workspace, &["merge", "--no-ff", "--no-edit", &wt.branch]).inspect_err(|_| {
+                let _ = git(workspace, &["merge", "--abort"]);
+            })?
+        }
+        "cherry-pick" => git(workspace, &["cherry-pick", &range]).inspect_err(|_| {
+            let _ = git(workspace, &["cherry-pick", "--abort"]);
+        })?,
+        other => return Err(format!("Unknown apply strategy: {}", other)),
+    };
+
+    for other in list(&workspace_dir) {
+        if other.id == wt.id || (wt.race_id.is_some() && other.race_id == wt.race_id) {
+            discard(&workspace_dir, &other);
+        }
+    }
+    Ok(output)
+}
+
+/// Remove a worktree and its branch without applying anything
+#[tauri::command]
+pub fn agent_worktree_remove(workspace_dir: String, id: String) -> Result<(), String> {
+    let wt = load(&workspace_dir, &id)?;
+    discard(&workspace_dir, &wt);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_all(dir: &Path, message: &str) {
+        git(dir, &["add", "--all"]).unwrap();
+        git(
+            dir,
+            &[
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@t",
+                "commit",
+                "-q",
+                "-m",
+                message,
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn diff_includes_new_files_without_touching_the_index() {
+        let dir = std::env::temp_dir().join(format!("clif-wt-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        git(&dir, &["init", "-q"]).unwrap();
+        std::fs::write(dir.join("a.txt"), "one\ntwo\n").unwrap();
+        std::fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        commit_all(&dir, "init");
+        let base = git(&dir, &["rev-parse", "HEAD"])
+            .unwrap()
+            .trim()
+            .to_string();
+
+        std::fs::write(dir.join("a.txt"), "one\n2\n").unwrap();
+        std::fs::write(dir.join("b.txt"), "new\nfile\nhere\n").unwrap();
+        std::fs::write(dir.join("debug.log"), "ignored\n").unwrap();
+        let wt = AgentWorktree {
+            id: "agent-1".into(),
+            race_id: None,
+            label: "agent-1".into(),
+            path: dir.to_string_lossy().to_string(),
+            branch: "main".into(),
+            base,
+            created_at: 0,
+        };
+
+        let diff = diff_against_base(&wt, &[]).unwrap();
+        assert!(diff.contains("-two\n+2\n"), "{diff}");
+        assert!(diff.contains("+++ b/b.txt"), "{diff}");
+        assert!(diff.contains("+here"), "{diff}");
+        assert!(!diff.contains("debug.log"), "{diff}");
+
+        let stat = sum_shortstats(&diff_against_base(&wt, &["--shortstat"]).unwrap());
+        assert_eq!(
+            (stat.files_changed, stat.insertions, stat.deletions),
+            (2, 4, 1)
+        );
+        // Nothing was staged, not even intent-to-add entries
+        assert_eq!(git(&dir, &["diff", "--cached", "--name-only"]).unwrap(), "");
+        assert_eq!(git(&dir, &["ls-files"]).unwrap(), ".gitignore\na.txt\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
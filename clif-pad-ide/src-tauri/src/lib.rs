@@ -59,6 +59,10 @@ fn build_menu(app: &tauri::AppHandle) -> Result<tauri::menu::Menu<tauri::Wry>, t
 
 pub fn run() {
     env_logger::init();
+    let _trace_guard = services::telemetry::init().unwrap_or_else(|e| {
+        log::warn!("tracing disabled: {}", e);
+        None
+    });
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
@@ -68,6 +72,15 @@ pub fn run() {
         .manage(LspState::default())
         .manage(WatcherState::new())
         .setup(|app| {
+            commands::lsp::init(app.handle());
+            // Move plaintext api_keys.json into the credential store
+            tauri::async_runtime::spawn_blocking(|| {
+                match services::secret_store::migrate_legacy() {
+                    Ok(0) => {}
+                    Ok(n) => log::info!("migrated {} API key(s) out of api_keys.json", n),
+                    Err(e) => log::warn!("API key migration deferred: {}", e),
+                }
+            });
             let menu = build_menu(app.handle())?;
             app.set_menu(menu)?;
             Ok(())
@@ -77,7 +90,7 @@ pub fn run() {
                 "new_window" => {
                     let app = app.clone();
                     tauri::async_runtime::spawn(async move {
-                        let _ = commands::window::create_window(app).await;
+                        let _ = commands::window::create_window(app, None).await;
                     });
                 }
                 "about_clif" => {
@@ -120,6 +133,7 @@ pub fn run() {
             commands::ai::get_models,
             commands::ai::set_api_key,
             commands::ai::get_api_key,
+            commands::ai::unlock_api_keys,
             commands::ai::generate_commit_message,
             commands::ai::ai_review_code,
             commands::git::git_status,
@@ -155,11 +169,30 @@ pub fn run() {
             commands::pty::pty_resize,
             commands::pty::pty_kill,
             commands::window::create_window,
+            commands::window::take_window_launch,
             commands::agent::agent_chat,
             commands::agent::agent_stop,
             commands::agent::agent_approve_command,
             commands::agent::agent_clear_todos,
             commands::agent::agent_clear_conversation,
+            commands::conversations::agent_list_conversations,
+            commands::conversations::agent_load_conversation,
+            commands::conversations::agent_rename_conversation,
+            commands::conversations::agent_delete_conversation,
+            commands::conversations::agent_fork_conversation,
+            commands::checkpoints::agent_list_checkpoints,
+            commands::checkpoints::agent_restore_checkpoint,
+            commands::edit_review::agent_review_accept,
+            commands::edit_review::agent_review_reject,
+            commands::worktree::agent_worktree_create,
+            commands::worktree::agent_worktree_list,
+            commands::worktree::agent_worktree_diff,
+            commands::worktree::agent_worktree_apply,
+            commands::worktree::agent_worktree_remove,
+            commands::mcp::mcp_list_servers,
+            commands::mcp::mcp_reconnect,
+            commands::mcp::mcp_server_info,
+            commands::mcp::mcp_trust_workspace,
             commands::indexer::index_build,
             commands::indexer::index_status,
             commands::indexer::index_find_symbol,
@@ -1,30 +1,1009 @@
-// AI provider abstraction - will be expanded in Phase 2
+//! AI providers — base URL, auth headers, request shaping, streaming and model
+//! listing for every model API ClifPad talks to.
+//!
+//! Commands build request bodies in OpenAI chat format (`model`, `messages`,
+//! `tools`, `stream`, …) and hand them to [`send`] or [`complete`]. The
+//! provider rewrites the body for its own API, and [`EventStream`] /
+//! [`ChatReply`] turn the response back into OpenAI terms, so callers never
+//! branch on the provider name.
+//!
+//! Providers: `openrouter`, `openai`, `anthropic`, `gemini`, `azure`,
+//! `ollama` and `custom` (any OpenAI-compatible URL). `azure` and `custom`
+//! read their URL from `"aiBaseUrl"` in ~/.clif/settings.json.
 
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const OPENROUTER_REFERER: &str = "https://clif.dev";
+const OPENROUTER_TITLE: &str = "ClifPad";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const AZURE_API_VERSION: &str = "2024-10-21";
+/// Anthropic requires `max_tokens`; used when the caller doesn't set one
+const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 8192;
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ModelInfo {
+    pub id: String,
+    pub name: String,
+    pub provider: String,
+}
+
+/// One piece of a streamed reply, in OpenAI terms
 #[derive(Debug, Clone, PartialEq)]
-pub enum Provider {
-    OpenRouter,
-    Ollama,
+pub enum StreamEvent {
+    Text(String),
+    /// Reasoning tokens (DeepSeek, GLM, Anthropic thinking)
+    Reasoning(String),
+    /// A tool call delta. `id` and `name` come with the first delta of each
+    /// call; `arguments` is appended across deltas.
+    ToolCall {
+        index: usize,
+        id: Option<String>,
+        name: Option<String>,
+        arguments: String,
+    },
+    Usage {
+        prompt_tokens: Option<u64>,
+        completion_tokens: Option<u64>,
+    },
+    /// `stop`, `tool_calls` or `length`
+    Finish(String),
+    Done,
+}
+
+/// A complete (non-streaming) reply
+#[derive(Debug, Clone, Default)]
+pub struct ChatReply {
+    pub content: String,
+    /// OpenAI wire shape: `{id, type: "function", function: {name, arguments}}`
+    pub tool_calls: Vec<Value>,
+    pub finish_reason: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Per-stream parser state
+#[derive(Debug, Default)]
+pub struct StreamState {
+    /// Tool calls seen so far, for providers that omit `index`
+    tool_calls: usize,
+}
+
+pub trait AiProvider: Send + Sync {
+    /// Name as stored in settings and `api_keys.json`
+    fn id(&self) -> &str;
+    fn chat_url(&self, model: &str) -> String;
+    /// Add auth and provider-specific headers
+    fn authorize(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder;
+    /// Rewrite an OpenAI-format request body for this API
+    fn shape_request(&self, body: Value) -> Value;
+    /// Parse one SSE `data:` payload
+    fn parse_stream_data(&self, data: &str, state: &mut StreamState, events: &mut Vec<StreamEvent>);
+    fn parse_response(&self, body: &Value) -> ChatReply;
+    fn models_url(&self) -> Option<String>;
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo>;
+}
+
+/// The provider named `provider`. Falls back to the stored key when `api_key`
+/// is `None`; unknown names get OpenRouter, as before.
+pub fn resolve(provider: &str, api_key: Option<String>) -> Result<Arc<dyn AiProvider>, String> {
+    let key = api_key
+        .filter(|k| !k.trim().is_empty())
+        .or_else(|| load_api_key(provider));
+    let settings = crate::commands::settings::get_settings().unwrap_or_default();
+    let setting = |name: &str| {
+        settings
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(|v| v.trim().trim_end_matches('/').to_string())
+            .filter(|v| !v.is_empty())
+    };
+    let compatible = |id: &'static str, base_url: &str| OpenAiCompatible {
+        id,
+        base_url: base_url.to_string(),
+        key: key.clone(),
+        models_url: Some(format!("{}/models", base_url)),
+        openrouter: false,
+        strict_tools: false,
+    };
+
+    let provider: Arc<dyn AiProvider> = match provider {
+        "openai" => Arc::new(OpenAiCompatible {
+            strict_tools: true,
+            ..compatible("openai", "https://api.openai.com/v1")
+        }),
+        "gemini" => Arc::new(compatible(
+            "gemini",
+            "https://generativelanguage.googleapis.com/v1beta/openai",
+        )),
+        "ollama" => Arc::new(OpenAiCompatible {
+            models_url: Some("http://localhost:11434/api/tags".to_string()),
+            ..compatible("ollama", "http://localhost:11434/v1")
+        }),
+        "custom" => {
+            let base_url = setting("aiBaseUrl")
+                .ok_or("Custom provider needs a base URL — set it in the agent settings")?;
+            Arc::new(compatible("custom", &base_url))
+        }
+        "azure" => Arc::new(Azure {
+            endpoint: setting("aiBaseUrl")
+                .ok_or("Azure OpenAI needs an endpoint URL — set it in the agent settings")?,
+            api_version: setting("azureApiVersion").unwrap_or_else(|| AZURE_API_VERSION.into()),
+            key,
+        }),
+        "anthropic" => Arc::new(Anthropic { key }),
+        _ => Arc::new(OpenAiCompatible {
+            openrouter: true,
+            ..compatible("openrouter", "https://openrouter.ai/api/v1")
+        }),
+    };
+    Ok(provider)
+}
+
+/// Key saved with `set_api_key`
+pub fn load_api_key(provider: &str) -> Option<String> {
+    super::secret_store::get(provider).unwrap_or_else(|e| {
+        log::warn!("API key for {} unavailable: {}", provider, e);
+        None
+    })
+}
+
+/// POST a chat request. Only transport failures are errors — callers check
+/// the status so they can react to specific API errors.
+pub async fn send(
+    provider: &dyn AiProvider,
+    client: &reqwest::Client,
+    body: Value,
+) -> Result<reqwest::Response, String> {
+    let model = body
+        .get("model")
+        .and_then(|m| m.as_str())
+        .unwrap_or("")
+        .to_string();
+    let req = client
+        .post(provider.chat_url(&model))
+        .header("Content-Type", "application/json");
+    provider
+        .authorize(req)
+        .json(&provider.shape_request(body))
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))
+}
+
+/// Send a non-streaming request and parse the reply
+pub async fn complete(provider: &dyn AiProvider, mut body: Value) -> Result<ChatReply, String> {
+    body["stream"] = json!(false);
+    let response = send(provider, &reqwest::Client::new(), body).await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(format!("API error {}: {}", status, text));
+    }
+    let json: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    Ok(provider.parse_response(&json))
+}
+
+pub async fn list_models(provider: &dyn AiProvider) -> Result<Vec<ModelInfo>, String> {
+    let url = provider.models_url().ok_or_else(|| {
+        format!(
+            "{} can't list models — enter the model or deployment name",
+            provider.id()
+        )
+    })?;
+    let response = provider
+        .authorize(reqwest::Client::new().get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch models from {}: {}", provider.id(), e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Failed to fetch {} models ({})",
+            provider.id(),
+            response.status()
+        ));
+    }
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+    Ok(provider.parse_models(&body))
+}
+
+/// Server-sent events from a streaming response, parsed by the provider
+pub struct EventStream {
+    provider: Arc<dyn AiProvider>,
+    bytes: BoxStream<'static, reqwest::Result<Vec<u8>>>,
+    buffer: Vec<u8>,
+    state: StreamState,
+    done: bool,
+}
+
+impl EventStream {
+    pub fn new(provider: Arc<dyn AiProvider>, response: reqwest::Response) -> Self {
+        EventStream {
+            provider,
+            bytes: response
+                .bytes_stream()
+                .map(|r| r.map(|b| b.to_vec()))
+                .boxed(),
+            buffer: Vec::new(),
+            state: StreamState::default(),
+            done: false,
+        }
+    }
+
+    /// Events from the next network chunk; `None` once the stream ends.
+    /// The last batch ends with [`StreamEvent::Done`].
+    pub async fn next(&mut self) -> Option<Result<Vec<StreamEvent>, String>> {
+        if self.done {
+            return None;
+        }
+        let chunk = match self.bytes.next().await {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => {
+                self.done = true;
+                return Some(Err(format!("Stream read error: {}", e)));
+            }
+            None => {
+                self.done = true;
+                return Some(Ok(vec![StreamEvent::Done]));
+            }
+        };
+        // Split on whole lines only, so multi-byte characters cut across
+        // chunks decode intact
+        self.buffer.extend_from_slice(&chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim();
+            if data == "[DONE]" {
+                events.push(StreamEvent::Done);
+            } else {
+                self.provider
+                    .parse_stream_data(data, &mut self.state, &mut events);
+            }
+            if events.last() == Some(&StreamEvent::Done) {
+                self.done = true;
+                break;
+            }
+        }
+        Some(Ok(events))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// OpenAI-compatible: OpenRouter, OpenAI, Gemini, Ollama, custom URLs
+// ---------------------------------------------------------------------------
+
+struct OpenAiCompatible {
+    id: &'static str,
+    base_url: String,
+    key: Option<String>,
+    models_url: Option<String>,
+    /// Referer/title headers, `middle-out` transforms and prompt caching
+    openrouter: bool,
+    /// Keep `strict` / `additionalProperties` in tool schemas; other
+    /// providers answer them with a 400
+    strict_tools: bool,
+}
+
+impl AiProvider for OpenAiCompatible {
+    fn id(&self) -> &str {
+        self.id
+    }
+
+    fn chat_url(&self, _model: &str) -> String {
+        format!("{}/chat/completions", self.base_url)
+    }
+
+    fn authorize(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(key) = &self.key {
+            req = req.header("Authorization", format!("Bearer {}", key));
+        }
+        if self.openrouter {
+            req = req
+                .header("HTTP-Referer", OPENROUTER_REFERER)
+                .header("X-Title", OPENROUTER_TITLE);
+        }
+        req
+    }
+
+    fn shape_request(&self, mut body: Value) -> Value {
+        if !self.strict_tools {
+            strip_strict_schema(&mut body);
+        }
+        if self.openrouter {
+            body["transforms"] = json!(["middle-out"]);
+            cache_first_system_message(&mut body);
+        }
+        body
+    }
+
+    fn parse_stream_data(
+        &self,
+        data: &str,
+        state: &mut StreamState,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        let Ok(chunk) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            events.push(StreamEvent::Usage {
+                prompt_tokens: usage.get("prompt_tokens").and_then(|v| v.as_u64()),
+                completion_tokens: usage.get("completion_tokens").and_then(|v| v.as_u64()),
+            });
+        }
+        let Some(choice) = chunk.pointer("/choices/0") else {
+            return;
+        };
+        if let Some(delta) = choice.get("delta") {
+            if let Some(text) = delta.get("content").and_then(|c| c.as_str()) {
+                if !text.is_empty() {
+                    events.push(StreamEvent::Text(text.to_string()));
+                }
+            }
+            if let Some(text) = delta.get("reasoning").and_then(|c| c.as_str()) {
+                if !text.is_empty() {
+                    events.push(StreamEvent::Reasoning(text.to_string()));
+                }
+            }
+            for tc in delta
+                .get("tool_calls")
+                .and_then(|t| t.as_array())
+                .into_iter()
+                .flatten()
+            {
+                let id = tc.get("id").and_then(|v| v.as_str()).map(String::from);
+                // Gemini sends each call whole and without an index
+                let index = match tc.get("index").and_then(|i| i.as_u64()) {
+                    Some(i) => i as usize,
+                    None if id.is_some() => state.tool_calls,
+                    None => state.tool_calls.saturating_sub(1),
+                };
+                state.tool_calls = state.tool_calls.max(index + 1);
+                events.push(StreamEvent::ToolCall {
+                    index,
+                    id,
+                    name: tc
+                        .pointer("/function/name")
+                        .and_then(|v| v.as_str())
+                        .map(String::from),
+                    arguments: tc
+                        .pointer("/function/arguments")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or("")
+                        .to_string(),
+                });
+            }
+        }
+        if let Some(reason) = choice.get("finish_reason").and_then(|v| v.as_str()) {
+            events.push(StreamEvent::Finish(
+                tool_finish_reason(reason, state.tool_calls > 0).to_string(),
+            ));
+        }
+    }
+
+    fn parse_response(&self, body: &Value) -> ChatReply {
+        let tool_calls: Vec<Value> = body
+            .pointer("/choices/0/message/tool_calls")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let finish_reason = body
+            .pointer("/choices/0/finish_reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or("");
+        ChatReply {
+            finish_reason: tool_finish_reason(finish_reason, !tool_calls.is_empty()).to_string(),
+            tool_calls,
+            content: body
+                .pointer("/choices/0/message/content")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            prompt_tokens: body
+                .pointer("/usage/prompt_tokens")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0),
+            completion_tokens: body
+                .pointer("/usage/completion_tokens")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(0),
+        }
+    }
+
+    fn models_url(&self) -> Option<String> {
+        self.models_url.clone()
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        // OpenAI-style `data`, or Ollama's native `/api/tags` `models`
+        let entries = body
+            .get("data")
+            .or_else(|| body.get("models"))
+            .and_then(|d| d.as_array());
+        entries
+            .into_iter()
+            .flatten()
+            .filter_map(|m| {
+                let id = m.get("id").or_else(|| m.get("name"))?.as_str()?;
+                // Gemini lists `models/gemini-2.5-flash`
+                let id = id.strip_prefix("models/").unwrap_or(id).to_string();
+                let name = m
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .filter(|n| !n.starts_with("models/"))
+                    .unwrap_or(&id)
+                    .to_string();
+                Some(ModelInfo {
+                    id,
+                    name,
+                    provider: self.id.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Gemini and some local servers finish a tool-calling reply with `stop`
+fn tool_finish_reason(reason: &str, has_tool_calls: bool) -> &str {
+    match reason {
+        "stop" if has_tool_calls => "tool_calls",
+        other => other,
+    }
+}
+
+/// Remove `strict` and `additionalProperties` from tool definitions
+fn strip_strict_schema(body: &mut Value) {
+    for tool in body
+        .get_mut("tools")
+        .and_then(|t| t.as_array_mut())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(func) = tool.get_mut("function").and_then(|f| f.as_object_mut()) {
+            func.remove("strict");
+            if let Some(params) = func.get_mut("parameters").and_then(|p| p.as_object_mut()) {
+                params.remove("additionalProperties");
+            }
+        }
+    }
+}
+
+/// Turn the first system message into a text part tagged with
+/// `cache_control`, so Anthropic and Gemini models behind OpenRouter cache the
+/// static prompt prefix
+fn cache_first_system_message(body: &mut Value) {
+    let Some(system) = body
+        .get_mut("messages")
+        .and_then(|m| m.as_array_mut())
+        .and_then(|m| m.first_mut())
+        .filter(|m| m["role"] == "system")
+    else {
+        return;
+    };
+    if let Some(text) = system["content"].as_str().map(String::from) {
+        system["content"] = json!([{
+            "type": "text",
+            "text": text,
+            "cache_control": {"type": "ephemeral"},
+        }]);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Azure OpenAI
+// ---------------------------------------------------------------------------
+
+/// OpenAI API behind an Azure resource; the model name is the deployment
+struct Azure {
+    endpoint: String,
+    api_version: String,
+    key: Option<String>,
 }
 
-impl Provider {
-    pub fn from_str(s: &str) -> Self {
-        match s.to_lowercase().as_str() {
-            "ollama" => Provider::Ollama,
-            _ => Provider::OpenRouter,
+impl AiProvider for Azure {
+    fn id(&self) -> &str {
+        "azure"
+    }
+
+    fn chat_url(&self, model: &str) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            self.endpoint, model, self.api_version
+        )
+    }
+
+    fn authorize(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.key {
+            Some(key) => req.header("api-key", key),
+            None => req,
+        }
+    }
+
+    fn shape_request(&self, mut body: Value) -> Value {
+        strip_strict_schema(&mut body);
+        body
+    }
+
+    fn parse_stream_data(
+        &self,
+        data: &str,
+        state: &mut StreamState,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        openai_format().parse_stream_data(data, state, events)
+    }
+
+    fn parse_response(&self, body: &Value) -> ChatReply {
+        openai_format().parse_response(body)
+    }
+
+    /// Deployments aren't listed by the data-plane API
+    fn models_url(&self) -> Option<String> {
+        None
+    }
+
+    fn parse_models(&self, _body: &Value) -> Vec<ModelInfo> {
+        Vec::new()
+    }
+}
+
+/// Response parsing shared with OpenAI-format APIs
+fn openai_format() -> OpenAiCompatible {
+    OpenAiCompatible {
+        id: "azure",
+        base_url: String::new(),
+        key: None,
+        models_url: None,
+        openrouter: false,
+        strict_tools: false,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Anthropic Messages API
+// ---------------------------------------------------------------------------
+
+struct Anthropic {
+    key: Option<String>,
+}
+
+impl AiProvider for Anthropic {
+    fn id(&self) -> &str {
+        "anthropic"
+    }
+
+    fn chat_url(&self, _model: &str) -> String {
+        "https://api.anthropic.com/v1/messages".to_string()
+    }
+
+    fn authorize(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(key) = &self.key {
+            req = req.header("x-api-key", key);
+        }
+        req.header("anthropic-version", ANTHROPIC_VERSION)
+    }
+
+    fn shape_request(&self, body: Value) -> Value {
+        let messages = body["messages"].as_array().cloned().unwrap_or_default();
+        let (system, messages) = anthropic_messages(&messages);
+        let mut out = json!({
+            "model": body["model"],
+            "messages": messages,
+            "max_tokens": body
+                .get("max_tokens")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(ANTHROPIC_DEFAULT_MAX_TOKENS),
+            "stream": body.get("stream").and_then(|v| v.as_bool()).unwrap_or(false),
+        });
+        if !system.is_empty() {
+            out["system"] = Value::Array(system);
+        }
+        if let Some(t) = body.get("temperature") {
+            out["temperature"] = t.clone();
+        }
+        let tools: Vec<Value> = body
+            .get("tools")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|t| {
+                let f = t.get("function")?;
+                let mut schema = f
+                    .get("parameters")
+                    .cloned()
+                    .unwrap_or_else(|| json!({"type": "object"}));
+                if let Some(obj) = schema.as_object_mut() {
+                    obj.remove("additionalProperties");
+                }
+                Some(json!({
+                    "name": f.get("name")?,
+                    "description": f.get("description").cloned().unwrap_or(json!("")),
+                    "input_schema": schema,
+                }))
+            })
+            .collect();
+        if !tools.is_empty() {
+            out["tools"] = Value::Array(tools);
+            match body.get("tool_choice").and_then(|c| c.as_str()) {
+                Some("none") => out["tool_choice"] = json!({"type": "none"}),
+                Some("required") => out["tool_choice"] = json!({"type": "any"}),
+                _ => {}
+            }
         }
+        out
     }
 
-    pub fn base_url(&self) -> &str {
-        match self {
-            Provider::OpenRouter => "https://openrouter.ai/api/v1",
-            Provider::Ollama => "http://localhost:11434/v1",
+    fn parse_stream_data(
+        &self,
+        data: &str,
+        _state: &mut StreamState,
+        events: &mut Vec<StreamEvent>,
+    ) {
+        let Ok(event) = serde_json::from_str::<Value>(data) else {
+            return;
+        };
+        match event["type"].as_str().unwrap_or("") {
+            "message_start" => {
+                let usage = &event["message"]["usage"];
+                events.push(StreamEvent::Usage {
+                    prompt_tokens: Some(anthropic_prompt_tokens(usage)),
+                    completion_tokens: None,
+                });
+            }
+            "content_block_start" => {
+                let block = &event["content_block"];
+                if block["type"] == "tool_use" {
+                    events.push(StreamEvent::ToolCall {
+                        index: event["index"].as_u64().unwrap_or(0) as usize,
+                        id: block["id"].as_str().map(String::from),
+                        name: block["name"].as_str().map(String::from),
+                        arguments: String::new(),
+                    });
+                }
+            }
+            "content_block_delta" => {
+                let delta = &event["delta"];
+                match delta["type"].as_str().unwrap_or("") {
+                    "text_delta" => {
+                        if let Some(text) = delta["text"].as_str() {
+                            events.push(StreamEvent::Text(text.to_string()));
+                        }
+                    }
+                    "thinking_delta" => {
+                        if let Some(text) = delta["thinking"].as_str() {
+                            events.push(StreamEvent::Reasoning(text.to_string()));
+                        }
+                    }
+                    "input_json_delta" => events.push(StreamEvent::ToolCall {
+                        index: event["index"].as_u64().unwrap_or(0) as usize,
+                        id: None,
+                        name: None,
+                        arguments: delta["partial_json"].as_str().unwrap_or("").to_string(),
+                    }),
+                    _ => {}
+                }
+            }
+            "message_delta" => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    events.push(StreamEvent::Finish(
+                        anthropic_finish_reason(reason).to_string(),
+                    ));
+                }
+                if let Some(out) = event["usage"]["output_tokens"].as_u64() {
+                    events.push(StreamEvent::Usage {
+                        prompt_tokens: None,
+                        completion_tokens: Some(out),
+                    });
+                }
+            }
+            "message_stop" => events.push(StreamEvent::Done),
+            "error" => {
+                let message = event["error"]["message"].as_str().unwrap_or("stream error");
+                events.push(StreamEvent::Text(format!(
+                    "\n[Anthropic error: {}]",
+                    message
+                )));
+                events.push(StreamEvent::Done);
+            }
+            _ => {}
         }
     }
 
-    pub fn name(&self) -> &str {
-        match self {
-            Provider::OpenRouter => "openrouter",
-            Provider::Ollama => "ollama",
+    fn parse_response(&self, body: &Value) -> ChatReply {
+        let mut reply = ChatReply {
+            finish_reason: anthropic_finish_reason(body["stop_reason"].as_str().unwrap_or(""))
+                .to_string(),
+            prompt_tokens: anthropic_prompt_tokens(&body["usage"]),
+            completion_tokens: body["usage"]["output_tokens"].as_u64().unwrap_or(0),
+            ..ChatReply::default()
+        };
+        for block in body["content"].as_array().into_iter().flatten() {
+            match block["type"].as_str().unwrap_or("") {
+                "text" => reply.content.push_str(block["text"].as_str().unwrap_or("")),
+                "tool_use" => reply.tool_calls.push(json!({
+                    "id": block["id"],
+                    "type": "function",
+                    "function": {
+                        "name": block["name"],
+                        "arguments": block["input"].to_string(),
+                    },
+                })),
+                _ => {}
+            }
+        }
+        reply
+    }
+
+    fn models_url(&self) -> Option<String> {
+        Some("https://api.anthropic.com/v1/models?limit=1000".to_string())
+    }
+
+    fn parse_models(&self, body: &Value) -> Vec<ModelInfo> {
+        body["data"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|m| {
+                let id = m["id"].as_str()?.to_string();
+                Some(ModelInfo {
+                    name: m["display_name"].as_str().unwrap_or(&id).to_string(),
+                    id,
+                    provider: "anthropic".to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Input tokens including cache reads and writes
+fn anthropic_prompt_tokens(usage: &Value) -> u64 {
+    [
+        "input_tokens",
+        "cache_read_input_tokens",
+        "cache_creation_input_tokens",
+    ]
+    .iter()
+    .filter_map(|k| usage[*k].as_u64())
+    .sum()
+}
+
+fn anthropic_finish_reason(stop_reason: &str) -> &str {
+    match stop_reason {
+        "tool_use" => "tool_calls",
+        "max_tokens" => "length",
+        "end_turn" | "stop_sequence" => "stop",
+        other => other,
+    }
+}
+
+/// Split OpenAI-format messages into Anthropic `system` blocks and
+/// alternating user/assistant messages.
+///
+/// Leading system messages become `system` blocks, the first tagged with
+/// `cache_control`; later ones become user text. Tool calls become `tool_use`
+/// blocks and tool results `tool_result` blocks, and consecutive messages of
+/// the same role are merged since the API requires strict alternation.
+fn anthropic_messages(messages: &[Value]) -> (Vec<Value>, Vec<Value>) {
+    let mut system: Vec<Value> = Vec::new();
+    let mut out: Vec<Value> = Vec::new();
+
+    for msg in messages {
+        let role = msg["role"].as_str().unwrap_or("user");
+        let (role, blocks) = match role {
+            "system" if out.is_empty() => {
+                system.extend(anthropic_blocks(&msg["content"]));
+                continue;
+            }
+            "system" => ("user", anthropic_blocks(&msg["content"])),
+            "assistant" => {
+                let mut blocks = anthropic_blocks(&msg["content"]);
+                for tc in msg["tool_calls"].as_array().into_iter().flatten() {
+                    let args = tc["function"]["arguments"].as_str().unwrap_or("{}");
+                    blocks.push(json!({
+                        "type": "tool_use",
+                        "id": tc["id"],
+                        "name": tc["function"]["name"],
+                        "input": serde_json::from_str::<Value>(args).unwrap_or_else(|_| json!({})),
+                    }));
+                }
+                ("assistant", blocks)
+            }
+            "tool" => (
+                "user",
+                vec![json!({
+                    "type": "tool_result",
+                    "tool_use_id": msg["tool_call_id"],
+                    "content": content_text(&msg["content"]),
+                })],
+            ),
+            _ => ("user", anthropic_blocks(&msg["content"])),
+        };
+        if blocks.is_empty() {
+            continue;
+        }
+        match out.last_mut() {
+            Some(prev) if prev["role"] == role => {
+                if let Some(existing) = prev["content"].as_array_mut() {
+                    existing.extend(blocks);
+                }
+            }
+            _ => out.push(json!({"role": role, "content": blocks})),
         }
     }
+
+    if let Some(first) = system.first_mut() {
+        first["cache_control"] = json!({"type": "ephemeral"});
+    }
+    (system, out)
+}
+
+/// Content blocks for a string or OpenAI parts array; empty text is dropped
+/// since the API rejects empty text blocks
+fn anthropic_blocks(content: &Value) -> Vec<Value> {
+    if let Some(text) = content.as_str() {
+        return if text.is_empty() {
+            Vec::new()
+        } else {
+            vec![json!({"type": "text", "text": text})]
+        };
+    }
+    content
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|part| match part["type"].as_str()? {
+            "text" => {
+                let text = part["text"].as_str().filter(|t| !t.is_empty())?;
+                Some(json!({"type": "text", "text": text}))
+            }
+            "image_url" => {
+                let url = part["image_url"]["url"].as_str()?;
+                let source = match url
+                    .strip_prefix("data:")
+                    .and_then(|rest| rest.split_once(";base64,"))
+                {
+                    Some((media_type, data)) => {
+                        json!({"type": "base64", "media_type": media_type, "data": data})
+                    }
+                    None => json!({"type": "url", "url": url}),
+                };
+                Some(json!({"type": "image", "source": source}))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Text of a string or parts-array content
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anthropic_request_moves_system_and_pairs_tool_results() {
+        let body = json!({
+            "model": "claude-sonnet-4-5",
+            "stream": true,
+            "messages": [
+                {"role": "system", "content": "static prompt"},
+                {"role": "system", "content": "runtime context"},
+                {"role": "user", "content": "read both"},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "t1", "type": "function", "function": {"name": "read_file", "arguments": "{\"path\":\"a\"}"}},
+                    {"id": "t2", "type": "function", "function": {"name": "read_file", "arguments": "{\"path\":\"b\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "t1", "content": "A"},
+                {"role": "tool", "tool_call_id": "t2", "content": "B"},
+                {"role": "system", "content": "refreshed tree"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "read_file", "description": "Read", "strict": true,
+                "parameters": {"type": "object", "additionalProperties": false}
+            }}],
+            "tool_choice": "auto"
+        });
+        let shaped = Anthropic { key: None }.shape_request(body);
+        assert_eq!(shaped["system"].as_array().unwrap().len(), 2);
+        assert_eq!(shaped["system"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(shaped["max_tokens"], ANTHROPIC_DEFAULT_MAX_TOKENS);
+        let messages = shaped["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"][1]["input"], json!({"path": "b"}));
+        // Both results and the later system note share one user turn
+        let results = messages[2]["content"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[1]["tool_use_id"], "t2");
+        assert_eq!(results[2]["text"], "refreshed tree");
+        assert!(shaped["tools"][0]["input_schema"]
+            .get("additionalProperties")
+            .is_none());
+        assert!(shaped.get("tool_choice").is_none());
+    }
+
+    #[test]
+    fn anthropic_stream_maps_to_openai_events() {
+        let p = Anthropic { key: None };
+        let mut state = StreamState::default();
+        let mut events = Vec::new();
+        for data in [
+            r#"{"type":"message_start","message":{"usage":{"input_tokens":10,"cache_read_input_tokens":90}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hi"}}"#,
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"tu_1","name":"search"}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"q\":"}}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use"},"usage":{"output_tokens":7}}"#,
+            r#"{"type":"message_stop"}"#,
+        ] {
+            p.parse_stream_data(data, &mut state, &mut events);
+        }
+        assert_eq!(
+            events,
+            vec![
+                StreamEvent::Usage {
+                    prompt_tokens: Some(100),
+                    completion_tokens: None
+                },
+                StreamEvent::Text("Hi".into()),
+                StreamEvent::ToolCall {
+                    index: 1,
+                    id: Some("tu_1".into()),
+                    name: Some("search".into()),
+                    arguments: String::new()
+                },
+                StreamEvent::ToolCall {
+                    index: 1,
+                    id: None,
+                    name: None,
+                    arguments: "{\"q\":".into()
+                },
+                StreamEvent::Finish("tool_calls".into()),
+                StreamEvent::Usage {
+                    prompt_tokens: None,
+                    completion_tokens: Some(7)
+                },
+                StreamEvent::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn openrouter_request_caches_system_prompt() {
+        let p = OpenAiCompatible {
+            openrouter: true,
+            ..openai_format()
+        };
+        let shaped = p.shape_request(json!({
+            "messages": [{"role": "system", "content": "static"}, {"role": "user", "content": "hi"}],
+        }));
+        assert_eq!(shaped["transforms"], json!(["middle-out"]));
+        assert_eq!(
+            shaped["messages"][0]["content"][0]["cache_control"]["type"],
+            "ephemeral"
+        );
+        assert_eq!(shaped["messages"][1]["content"], "hi");
+    }
 }
@@ -1,2 +1,4 @@
 pub mod ai_provider;
 pub mod file_watcher;
+pub mod secret_store;
+pub mod telemetry;
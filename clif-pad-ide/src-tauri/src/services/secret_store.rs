@@ -0,0 +1,392 @@
+//! API key storage.
+//!
+//! Keys live in the OS credential store — Keychain on macOS, Credential
+//! Manager on Windows, Secret Service (GNOME Keyring, KWallet) on Linux.
+//! When no credential store is reachable, e.g. a headless Linux box without a
+//! Secret Service on D-Bus, keys go to `~/.clif/api_keys.enc` instead,
+//! encrypted with a key derived from a passphrase. The passphrase comes from
+//! `CLIF_KEYS_PASSPHRASE` or is set for the session with [`unlock`].
+//!
+//! `CLIF_SECRET_STORE=file` forces the encrypted file even when a credential
+//! store is available.
+//!
+//! Older versions kept keys in plaintext `~/.clif/api_keys.json`.
+//! [`migrate_legacy`] moves them into the store on launch and deletes the
+//! file once every key has been stored; until then it is still read.
+
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Service name for credential store entries; the account is the provider
+const SERVICE: &str = "com.clif.app";
+const PASSPHRASE_ENV: &str = "CLIF_KEYS_PASSPHRASE";
+const STORE_ENV: &str = "CLIF_SECRET_STORE";
+const SALT_LEN: usize = 16;
+
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+static KEYCHAIN_AVAILABLE: OnceLock<bool> = OnceLock::new();
+
+/// Look up the stored key for a provider
+pub fn get(provider: &str) -> Result<Option<String>, String> {
+    lookup(backend(), legacy_keys(), provider)
+}
+
+fn lookup(
+    backend: Result<Backend, String>,
+    legacy: Option<Map<String, Value>>,
+    provider: &str,
+) -> Result<Option<String>, String> {
+    let stored = backend.and_then(|backend| match backend {
+        Backend::Keychain => keychain_get(provider),
+        Backend::File(file) => file.load().map(|keys| string_at(&keys, provider)),
+    });
+    match stored {
+        Ok(Some(key)) => Ok(Some(key)),
+        // Not migrated yet, or no store is usable (locked file, no Secret
+        // Service): the plaintext file is still the source of truth for
+        // whatever it holds.
+        other => match legacy.and_then(|keys| string_at(&keys, provider)) {
+            Some(key) => Ok(Some(key)),
+            None => other,
+        },
+    }
+}
+
+/// Store the key for a provider. An empty key removes it.
+pub fn set(provider: &str, key: &str) -> Result<(), String> {
+    match backend()? {
+        Backend::Keychain => keychain_set(provider, key),
+        Backend::File(file) => {
+            let mut keys = file.load()?;
+            if key.is_empty() {
+                keys.remove(provider);
+            } else {
+                keys.insert(provider.to_string(), Value::String(key.to_string()));
+            }
+            file.save(&keys)
+        }
+    }?;
+    forget_legacy(provider)
+}
+
+/// Set the passphrase for the encrypted file for the rest of the session.
+/// Fails if it doesn't open an existing file, so a typo can't fork the store.
+pub fn unlock(passphrase: &str) -> Result<(), String> {
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into());
+    }
+    let file = EncryptedFile {
+        path: clif_dir()?.join("api_keys.enc"),
+        passphrase: passphrase.to_string(),
+    };
+    file.load()?;
+    *PASSPHRASE.lock().unwrap() = Some(passphrase.to_string());
+    Ok(())
+}
+
+/// Move keys from plaintext `api_keys.json` into the store. Returns how many
+/// were moved; the file is deleted once none are left.
+pub fn migrate_legacy() -> Result<usize, String> {
+    let Some(keys) = legacy_keys() else {
+        return Ok(0);
+    };
+    let mut moved = 0;
+    for (provider, key) in &keys {
+        if let Some(key) = key.as_str() {
+            set(provider, key)?;
+            moved += 1;
+        }
+    }
+    let path = clif_dir()?.join("api_keys.json");
+    if path.exists() {
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    Ok(moved)
+}
+
+enum Backend {
+    Keychain,
+    File(EncryptedFile),
+}
+
+fn backend() -> Result<Backend, String> {
+    let forced_file = std::env::var(STORE_ENV).is_ok_and(|v| v == "file");
+    if !forced_file && *KEYCHAIN_AVAILABLE.get_or_init(probe_keychain) {
+        return Ok(Backend::Keychain);
+    }
+    let passphrase = PASSPHRASE
+        .lock()
+        .unwrap()
+        .clone()
+        .or_else(|| std::env::var(PASSPHRASE_ENV).ok().filter(|p| !p.is_empty()))
+        .ok_or_else(|| {
+            format!(
+                "No OS credential store available and API keys are locked. \
+                 Set {} or unlock them in Settings.",
+                PASSPHRASE_ENV
+            )
+        })?;
+    Ok(Backend::File(EncryptedFile {
+        path: clif_dir()?.join("api_keys.enc"),
+        passphrase,
+    }))
+}
+
+/// A missing entry means the store answered; anything else means there is
+/// no usable credential store in this session.
+fn probe_keychain() -> bool {
+    let probe = keyring::Entry::new(SERVICE, "__probe__").and_then(|entry| entry.get_password());
+    match probe {
+        Ok(_) | Err(keyring::Error::NoEntry) => true,
+        Err(e) => {
+            log::warn!(
+                "OS credential store unavailable, using encrypted file: {}",
+                e
+            );
+            false
+        }
+    }
+}
+
+fn keychain_get(provider: &str) -> Result<Option<String>, String> {
+    let entry = keyring::Entry::new(SERVICE, provider)
+        .map_err(|e| format!("Credential store error: {}", e))?;
+    match entry.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read API key: {}", e)),
+    }
+}
+
+fn keychain_set(provider: &str, key: &str) -> Result<(), String> {
+    let entry = keyring::Entry::new(SERVICE, provider)
+        .map_err(|e| format!("Credential store error: {}", e))?;
+    let result = if key.is_empty() {
+        entry.delete_credential()
+    } else {
+        entry.set_password(key)
+    };
+    match result {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(format!("Failed to store API key: {}", e)),
+    }
+}
+
+/// Provider → key map sealed with ChaCha20-Poly1305 under an Argon2id key.
+/// Salt and nonce are fresh on every write.
+struct EncryptedFile {
+    path: PathBuf,
+    passphrase: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Sealed {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFile {
+    fn load(&self) -> Result<Map<String, Value>, String> {
+        let content = match std::fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Map::new()),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        };
+        let sealed: Sealed = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", self.path.display(), e))?;
+        let corrupt = || format!("{} is corrupted", self.path.display());
+        let salt = from_hex(&sealed.salt).ok_or_else(corrupt)?;
+        let nonce = from_hex(&sealed.nonce).ok_or_else(corrupt)?;
+        let ciphertext = from_hex(&sealed.ciphertext).ok_or_else(corrupt)?;
+        if nonce.len() != 12 {
+            return Err(corrupt());
+        }
+        let cipher = ChaCha20Poly1305::new(&derive_key(&self.passphrase, &salt)?);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "Wrong passphrase for API keys".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|_| corrupt())
+    }
+
+    fn save(&self, keys: &Map<String, Value>) -> Result<(), String> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let cipher = ChaCha20Poly1305::new(&derive_key(&self.passphrase, &salt)?);
+        let plaintext = serde_json::to_vec(keys).map_err(|e| e.to_string())?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| "Failed to encrypt API keys".to_string())?;
+        let sealed = Sealed {
+            version: 1,
+            salt: to_hex(&salt),
+            nonce: to_hex(&nonce),
+            ciphertext: to_hex(&ciphertext),
+        };
+        let content = serde_json::to_string_pretty(&sealed).map_err(|e| e.to_string())?;
+        write_private(&self.path, &content)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Write a file readable only by the current user
+fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create config directory: {}", e))?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content)
+        .map_err(|e| format!("Failed to write {}: {}", tmp.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600));
+    }
+    std::fs::rename(&tmp, path).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn clif_dir() -> Result<PathBuf, String> {
+    std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".clif"))
+        .map_err(|_| "Could not determine home directory".to_string())
+}
+
+fn legacy_keys() -> Option<Map<String, Value>> {
+    let content = std::fs::read_to_string(clif_dir().ok()?.join("api_keys.json")).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Drop a provider from the plaintext file once the store holds its key
+fn forget_legacy(provider: &str) -> Result<(), String> {
+    let Some(mut keys) = legacy_keys() else {
+        return Ok(());
+    };
+    if keys.remove(provider).is_none() {
+        return Ok(());
+    }
+    let path = clif_dir()?.join("api_keys.json");
+    if keys.is_empty() {
+        return std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e));
+    }
+    let content = serde_json::to_string_pretty(&keys).map_err(|e| e.to_string())?;
+    write_private(&path, &content)
+}
+
+fn string_at(keys: &Map<String, Value>, provider: &str) -> Option<String> {
+    keys.get(provider)
+        .and_then(|k| k.as_str())
+        .map(|s| s.to_string())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("clif-secrets-{}", uuid::Uuid::new_v4()));
+        dir.join(name)
+    }
+
+    #[test]
+    fn encrypted_file_round_trips_and_rejects_wrong_passphrase() {
+        let path = temp_file("api_keys.enc");
+        let file = EncryptedFile {
+            path: path.clone(),
+            passphrase: "correct horse".into(),
+        };
+        assert!(file.load().unwrap().is_empty());
+
+        let mut keys = Map::new();
+        keys.insert("openrouter".into(), Value::String("sk-or-secret".into()));
+        file.save(&keys).unwrap();
+
+        let on_disk = std::fs::read_to_string(&path).unwrap();
+        assert!(!on_disk.contains("sk-or-secret"));
+        assert_eq!(
+            string_at(&file.load().unwrap(), "openrouter").as_deref(),
+            Some("sk-or-secret")
+        );
+
+        let wrong = EncryptedFile {
+            path: path.clone(),
+            passphrase: "battery staple".into(),
+        };
+        assert_eq!(wrong.load().unwrap_err(), "Wrong passphrase for API keys");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn legacy_keys_are_read_when_no_store_is_usable() {
+        let locked = || Err::<Backend, String>("API keys are locked".into());
+        let mut legacy = Map::new();
+        legacy.insert("openrouter".into(), Value::String("sk-or-legacy".into()));
+
+        assert_eq!(
+            lookup(locked(), Some(legacy.clone()), "openrouter"),
+            Ok(Some("sk-or-legacy".into()))
+        );
+        // Providers the legacy file doesn't hold still report the store error
+        assert_eq!(
+            lookup(locked(), Some(legacy), "anthropic"),
+            Err("API keys are locked".into())
+        );
+        assert_eq!(
+            lookup(locked(), None, "openrouter"),
+            Err("API keys are locked".into())
+        );
+
+        let path = temp_file("api_keys.enc");
+        let file = EncryptedFile {
+            path: path.clone(),
+            passphrase: "correct horse".into(),
+        };
+        let mut stored = Map::new();
+        stored.insert("openrouter".into(), Value::String("sk-or-new".into()));
+        file.save(&stored).unwrap();
+        assert_eq!(
+            lookup(Ok(Backend::File(file)), None, "openrouter"),
+            Ok(Some("sk-or-new".into()))
+        );
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn hex_round_trips() {
+        let bytes = [0u8, 1, 0xab, 0xff];
+        assert_eq!(from_hex(&to_hex(&bytes)).unwrap(), bytes);
+        assert!(from_hex("abc").is_none());
+        assert!(from_hex("zz").is_none());
+    }
+}
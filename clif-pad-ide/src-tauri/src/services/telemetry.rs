@@ -0,0 +1,39 @@
+//! Trace export for the agent — turns, model requests and tool calls as spans.
+//!
+//! The span layer, trace file and OTLP exporter live in `clif-telemetry`,
+//! shared with `clifcode`, so traces from the IDE and the TUI have the same
+//! format. This module picks the destinations:
+//!   - a JSON-lines trace file: `CLIFPAD_TRACE_FILE` or `"traceFile"` in
+//!     ~/.clif/settings.json
+//!   - an OTLP/HTTP collector: `OTEL_EXPORTER_OTLP_ENDPOINT` or
+//!     `"otlpEndpoint"` in settings. `OTEL_EXPORTER_OTLP_HEADERS` adds headers.
+
+use clif_telemetry::{Service, TraceConfig};
+use std::path::PathBuf;
+
+pub use clif_telemetry::{args_hash, TraceGuard};
+
+const SERVICE: Service = Service {
+    name: "clifpad",
+    version: env!("CARGO_PKG_VERSION"),
+};
+
+/// Install the trace layer for the configured destinations. Returns `None`
+/// when there are none.
+pub fn init() -> Result<Option<TraceGuard>, String> {
+    let settings = crate::commands::settings::get_settings().unwrap_or_default();
+    let setting = |key: &str| {
+        settings
+            .get(key)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.trim().is_empty())
+            .map(String::from)
+    };
+    let file = std::env::var("CLIFPAD_TRACE_FILE")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .or_else(|| setting("traceFile"))
+        .map(PathBuf::from);
+    let config = TraceConfig::from_env(file, setting("otlpEndpoint"));
+    clif_telemetry::init(SERVICE, &config)
+}
@@ -11,6 +11,8 @@ import { loadSettings, settings } from "./stores/settingsStore";
 import { registerKeybinding, initKeybindings } from "./lib/keybindings";
 import { saveActiveFile, projectRoot, openProject, openBrowser, togglePreview } from "./stores/fileStore";
 import { initGit } from "./stores/gitStore";
+import { setLaunchTask } from "./stores/agentStore";
+import { takeWindowLaunch } from "./lib/tauri";
 import { configureMonaco } from "./lib/monaco-setup";
 import { loadGoogleFont, applyUiFont } from "./lib/fonts";
 import { createTerminalTab } from "./stores/terminalStore";
@@ -215,6 +217,17 @@ function handleSidebarResize(e: MouseEvent) {
     // Listen for "About ClifPad" from the system menu
     const { listen } = await import("@tauri-apps/api/event");
     listen("show-about", () => setShowAbout(true));
+
+    // Windows opened for an agent race arrive with a worktree and a task
+    const launch = await takeWindowLaunch().catch(() => null);
+    if (launch?.project_path) {
+      await openProject(launch.project_path);
+      await initGit();
+      if (launch.agent_task) {
+        setLaunchTask({ content: launch.agent_task, model: launch.agent_model ?? undefined });
+      }
+      setAgentVisible(true);
+    }
   });
 
   return (
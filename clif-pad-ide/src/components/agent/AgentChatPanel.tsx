@@ -6,6 +6,7 @@ import {
   agentStreaming,
   agentTokens,
   agentStatus,
+  agentRunUsage,
   agentTabs,
   activeAgentTab,
   sendAgentMessage,
@@ -17,6 +18,8 @@ import {
   initAgentListeners,
   restoreAgentHistory,
   queuedMessages,
+  launchTask,
+  setLaunchTask,
 } from "../../stores/agentStore";
 import { invoke } from "@tauri-apps/api/core";
 import { produce } from "solid-js/store";
@@ -28,9 +31,9 @@ import { prs, reviewResults } from "../../stores/reviewsStore";
 import { classifications } from "../../stores/classificationStore";
 import { settings, updateSettings } from "../../stores/settingsStore";
 import { fontSize } from "../../stores/uiStore";
-import { getApiKey, setApiKey as saveApiKey, agentApproveCommand, clifProjectInitialized, clifReadContext, clifInitProject, getModels } from "../../lib/tauri";
+import { getApiKey, setApiKey as saveApiKey, unlockApiKeys, agentApproveCommand, clifProjectInitialized, clifReadContext, clifInitProject, getModels } from "../../lib/tauri";
 import ChatMessage from "./ChatMessage";
-import type { AgentContext } from "../../types/agent";
+import type { AgentContext, PendingEditReview } from "../../types/agent";
 
 // Extracted sub-components
 import { SparkleIcon, SendIcon, StopIcon, KeyIcon, GearIcon } from "./icons";
@@ -46,6 +49,9 @@ import AgentTabs from "./AgentTabs";
 import InitProjectBanner from "./InitProjectBanner";
 import ProviderModelSelector from "./ProviderModelSelector";
 import EmptyState from "./EmptyState";
+import EditReview from "./EditReview";
+import RacePanel from "./RacePanel";
+import CheckpointPanel from "./CheckpointPanel";
 
 type SessionTodo = {
   id: string;
@@ -86,9 +92,14 @@ const AgentChatPanel: Component = () => {
   const [visibleCount, setVisibleCount] = createSignal(BATCH_SIZE); // how many messages to show from the end
   const [contextFiles, setContextFiles] = createSignal<string[]>([]);
   const [initialized, setInitialized] = createSignal(false);
+  const [historyRestored, setHistoryRestored] = createSignal(false);
   const [hasApiKey, setHasApiKey] = createSignal<boolean | null>(null);
+  // No OS credential store and no passphrase yet: the key input asks for one
+  const [keysLocked, setKeysLocked] = createSignal(false);
   const [apiKeyInput, setApiKeyInput] = createSignal("");
   const [showSettings, setShowSettings] = createSignal(false);
+  const [showRace, setShowRace] = createSignal(false);
+  const [showCheckpoints, setShowCheckpoints] = createSignal(false);
   const [savingKey, setSavingKey] = createSignal(false);
   const [modelDropdownOpen, setModelDropdownOpen] = createSignal(false);
   const [openRouterModels, setOpenRouterModels] = createSignal<OpenRouterModel[]>([]);
@@ -98,6 +109,7 @@ const AgentChatPanel: Component = () => {
   const [modelSort, setModelSort] = createSignal<"name" | "price-asc" | "price-desc" | "ctx">("name");
   const [modelProviderFilter, setModelProviderFilter] = createSignal("all");
   const [pendingCommand, setPendingCommand] = createSignal<{ sessionId: string; command: string; toolCallId: string } | null>(null);
+  const [pendingEdits, setPendingEdits] = createSignal<PendingEditReview | null>(null);
   const [clifInitializing, setClifInitializing] = createSignal(false);
   const [clifInitProgress, setClifInitProgress] = createSignal<{ step: number; total: number; message: string; elapsed_secs: number }>({ step: 0, total: 15, message: "", elapsed_secs: 0 });
   const [clifExists, setClifExists] = createSignal<boolean | null>(null); // null = checking
@@ -183,6 +195,7 @@ const AgentChatPanel: Component = () => {
     if (projectRoot()) {
       await restoreAgentHistory(projectRoot()!);
     }
+    setHistoryRestored(true);
 
     // Listen for run_command approval requests from the agent
     const { getCurrentWebviewWindow } = await import("@tauri-apps/api/webviewWindow");
@@ -198,6 +211,11 @@ const AgentChatPanel: Component = () => {
       }
     );
 
+    // Listen for staged edits waiting on review (review mode only)
+    const unlistenReview = await appWindow.listen<PendingEditReview>("agent_edit_review", (event) => {
+      if (event.payload.files.some((f) => f.hunks.length > 0)) setPendingEdits(event.payload);
+    });
+
     // Listen for init progress and completion
     const unlistenProgress = await appWindow.listen<{ step: number; total: number; message: string; elapsed_secs: number }>("clif_init_progress", (event) => {
       setClifInitProgress(event.payload);
@@ -210,7 +228,7 @@ const AgentChatPanel: Component = () => {
       }
     });
 
-    onCleanup(() => { unlisten(); unlistenProgress(); unlistenDone(); });
+    onCleanup(() => { unlisten(); unlistenReview(); unlistenProgress(); unlistenDone(); });
 
     // Check if CLIF.md exists for current project
     if (projectRoot()) {
@@ -253,6 +271,19 @@ const AgentChatPanel: Component = () => {
     }
   }
 
+  // Send the task this window was launched with once the chat is ready
+  createEffect(() => {
+    const task = launchTask();
+    if (!task || !historyRestored() || !projectRoot()) return;
+    setLaunchTask(null);
+    sendAgentMessage(task.content, buildContext(task.model), task.model);
+  });
+
+  // A review left open by a stopped session can no longer be answered
+  createEffect(() => {
+    if (!agentStreaming()) setPendingEdits(null);
+  });
+
   // Call when provider changes to Ollama
   createEffect(() => {
     if (settings().aiProvider === "ollama") {
@@ -269,8 +300,10 @@ const AgentChatPanel: Component = () => {
     }
     try {
       const key = await getApiKey(provider);
+      setKeysLocked(false);
       setHasApiKey(!!key);
-    } catch {
+    } catch (e) {
+      setKeysLocked(String(e).includes("locked"));
       setHasApiKey(false);
     }
   }
@@ -280,6 +313,12 @@ const AgentChatPanel: Component = () => {
     if (!key) return;
     setSavingKey(true);
     try {
+      if (keysLocked()) {
+        await unlockApiKeys(key);
+        setApiKeyInput("");
+        await checkApiKey();
+        return;
+      }
       await saveApiKey(settings().aiProvider, key);
       setHasApiKey(true);
       setApiKeyInput("");
@@ -484,7 +523,7 @@ const AgentChatPanel: Component = () => {
     el.style.height = Math.min(el.scrollHeight, 150) + "px";
   }
 
-  function buildContext(): AgentContext | undefined {
+  function buildContext(model: string = settings().aiModel): AgentContext | undefined {
     const ctx: AgentContext = {};
     const af = activeFile();
     if (af) ctx.activeFile = af.path;
@@ -493,6 +532,10 @@ const AgentChatPanel: Component = () => {
     const files = contextFiles();
     if (files.length > 0) ctx.files = files;
     ctx.agentMode = agentMode();
+    if (settings().agentReviewEdits) ctx.reviewEdits = true;
+    // Lets the backend price the run for its cost budget
+    const pricing = openRouterModels().find((m) => m.id === model)?.pricing;
+    if (pricing) ctx.pricing = pricing;
 
     const tabId = activeAgentTab();
     if (tabId && tabId.startsWith("pr-")) {
@@ -719,6 +762,51 @@ const AgentChatPanel: Component = () => {
           handleProviderChange={handleProviderChange}
         />
 
+        <Show when={projectRoot()}>
+          <button
+            class="flex items-center justify-center shrink-0 rounded-full transition-colors"
+            style={{
+              width: "22px",
+              height: "22px",
+              border: "1px solid var(--border-default)",
+              background: showCheckpoints() ? "var(--bg-active)" : "var(--bg-hover)",
+              color: showCheckpoints() ? "var(--accent-primary)" : "var(--text-muted)",
+              cursor: "pointer",
+            }}
+            onClick={() => setShowCheckpoints(!showCheckpoints())}
+            title="Checkpoints — restore files and chat to before an earlier message"
+          >
+            <svg width="11" height="11" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2.5" stroke-linecap="round" stroke-linejoin="round">
+              <path d="M3 12a9 9 0 1 0 3-6.7L3 8" />
+              <polyline points="3 3 3 8 8 8" />
+              <polyline points="12 7 12 12 15 15" />
+            </svg>
+          </button>
+        </Show>
+
+        <Show when={projectRoot()}>
+          <button
+            class="flex items-center justify-center shrink-0 rounded-full transition-colors"
+            style={{
+              width: "22px",
+              height: "22px",
+              border: "1px solid var(--border-default)",
+              background: showRace() ? "var(--bg-active)" : "var(--bg-hover)",
+              color: showRace() ? "var(--accent-primary)" : "var(--text-muted)",
+              cursor: "pointer",
+            }}
+            onClick={() => setShowRace(!showRace())}
+            title="Race agents — run a task on several models in separate git worktrees"
+          >
+            <svg width="11" height="11" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2.5" stroke-linecap="round" stroke-linejoin="round">
+              <line x1="6" y1="3" x2="6" y2="15" />
+              <circle cx="18" cy="6" r="3" />
+              <circle cx="6" cy="18" r="3" />
+              <path d="M18 9a9 9 0 0 1-9 9" />
+            </svg>
+          </button>
+        </Show>
+
         <Show when={projectRoot() && settings().aiProvider !== "ollama"}>
           <button
             class="flex items-center justify-center shrink-0 rounded-full transition-colors"
@@ -769,6 +857,14 @@ const AgentChatPanel: Component = () => {
         </Show>
       </div>
 
+      <Show when={showRace() && projectRoot()}>
+        {(root) => <RacePanel projectRoot={root()} currentModel={settings().aiModel} />}
+      </Show>
+
+      <Show when={showCheckpoints() && projectRoot()}>
+        {(root) => <CheckpointPanel projectRoot={root()} conversationId={activeAgentTab()} />}
+      </Show>
+
       {/* API key input (toggled by key icon) */}
       <Show when={showSettings() && settings().aiProvider !== "ollama"}>
         <div
@@ -784,7 +880,7 @@ const AgentChatPanel: Component = () => {
               border: "1px solid var(--border-muted)",
               "font-size": "11px",
             }}
-            placeholder={settings().aiProvider === "openrouter" ? "sk-or-..." : "API key"}
+            placeholder={keysLocked() ? "Passphrase to unlock API keys" : settings().aiProvider === "openrouter" ? "sk-or-..." : "API key"}
             value={apiKeyInput()}
             onInput={(e) => setApiKeyInput(e.currentTarget.value)}
             onKeyDown={(e) => {
@@ -868,6 +964,9 @@ const AgentChatPanel: Component = () => {
           <For each={visibleMessages()}>
             {(msg) => <ChatMessage message={msg} pendingCommand={pendingCommand} onApprove={async (sid, approved) => { setPendingCommand(null); await agentApproveCommand(sid, approved); }} />}
           </For>
+          <Show when={agentStreaming() && pendingEdits()}>
+            {(review) => <EditReview review={review()} onDone={() => setPendingEdits(null)} />}
+          </Show>
           <Show when={agentStreaming() && agentStatus()}>
             <div
               class="flex items-center gap-2 px-4 py-2"
@@ -1589,10 +1688,42 @@ const AgentChatPanel: Component = () => {
               </button>
             </Show>
 
+            <Show when={agentMode() === "agent"}>
+              <button
+                class="flex items-center gap-1 px-1.5 py-0.5 rounded-md transition-colors"
+                style={{
+                  color: settings().agentReviewEdits ? "var(--accent-green)" : "var(--text-muted)",
+                  background: settings().agentReviewEdits ? "color-mix(in srgb, var(--accent-green) 12%, transparent)" : "transparent",
+                  border: `1px solid ${settings().agentReviewEdits ? "color-mix(in srgb, var(--accent-green) 30%, transparent)" : "transparent"}`,
+                  cursor: "pointer",
+                  "font-size": "11px",
+                  "font-weight": "500",
+                }}
+                onClick={() => updateSettings({ agentReviewEdits: !settings().agentReviewEdits })}
+                title={settings().agentReviewEdits ? "Edits wait for your review before they are written" : "Review each edit before it is written"}
+              >
+                <svg width="12" height="12" viewBox="0 0 24 24" fill="none" stroke="currentColor" stroke-width="2.5" stroke-linecap="round" stroke-linejoin="round">
+                  <path d="M9 11l3 3L22 4"/>
+                  <path d="M21 12v7a2 2 0 0 1-2 2H5a2 2 0 0 1-2-2V5a2 2 0 0 1 2-2h11"/>
+                </svg>
+                <span>Review</span>
+              </button>
+            </Show>
+
             <span
               title={(() => {
                 const t = agentTokens();
-                return `Prompt: ${t.prompt.toLocaleString()} · Completion: ${t.completion.toLocaleString()} · Context: ${t.context.toLocaleString()}`;
+                const tokens = `Prompt: ${t.prompt.toLocaleString()} · Completion: ${t.completion.toLocaleString()} · Context: ${t.context.toLocaleString()}`;
+                const run = agentRunUsage();
+                if (!run) return tokens;
+                const b = run.budget;
+                const parts = [
+                  `${run.iterations}/${b.max_iterations} iterations`,
+                  `${Math.round(run.elapsed_ms / 1000)}s${b.max_seconds ? `/${b.max_seconds}s` : ""}`,
+                  `${run.total_tokens.toLocaleString()}${b.max_tokens ? `/${b.max_tokens.toLocaleString()}` : ""} tokens`,
+                  `$${run.cost_usd.toFixed(2)}${b.max_cost_usd ? `/$${b.max_cost_usd.toFixed(2)}` : ""}`,
+                ];
+                return `${tokens}\nLast run: ${parts.join(" · ")}`;
               })()}
               style={{ "font-family": "var(--font-mono, monospace)", opacity: 0.75, "font-size": "11px" }}
             >
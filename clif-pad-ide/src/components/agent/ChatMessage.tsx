@@ -62,6 +62,10 @@ marked.setOptions({ async: false, breaks: true, gfm: true });
 
 // ── Feature #15: Diff Viewer ──────────────────────────────────────────────────
 // Parses unified diff format and renders +/- lines with colour highlighting.
+
+/** Tools whose results carry a `diff_preview` */
+const DIFF_TOOLS = new Set(["edit_file", "multi_edit", "insert_at_line", "replace_lines"]);
+
 const DiffViewer: Component<{ diff: string }> = (props) => {
   const lines = () => props.diff.split("\n");
   return (
@@ -264,6 +268,12 @@ const ToolCallCard: Component<{
       }
       case "write_file": return (args.path as string || "").split("/").pop() || "";
       case "edit_file": return (args.path as string || "").split("/").pop() || "";
+      case "multi_edit": {
+        const edits = Array.isArray(args.edits) ? args.edits.length : 0;
+        return `${(args.path as string || "").split("/").pop()} (${edits} edit${edits === 1 ? "" : "s"})`;
+      }
+      case "insert_at_line": return `${(args.path as string || "").split("/").pop()}:${args.line ?? ""}`;
+      case "replace_lines": return `${(args.path as string || "").split("/").pop()}:${args.start_line ?? ""}-${args.end_line ?? ""}`;
       case "search": return `"${args.query || ""}"${args.path ? ` in ${(args.path as string).split("/").pop()}` : ""}`;
       case "find_file": return (args.name as string) || "";
       case "find_symbol": {
@@ -271,6 +281,12 @@ const ToolCallCard: Component<{
         const limit = typeof args.limit === "number" ? args.limit : null;
         return limit ? `${name} (top ${limit})` : name;
       }
+      case "find_references":
+      case "goto_definition":
+        return `${args.symbol || ""} in ${((args.path as string) || "").split("/").pop()}:${args.line ?? ""}`;
+      case "rename_symbol":
+        return `${args.symbol || ""} → ${args.new_name || ""}`;
+      case "get_diagnostics": return (args.path as string || "").split("/").pop() || "";
       case "list_files": {
         const p = (args.path as string) || ".";
         return p.split("/").pop() || p;
@@ -439,9 +455,9 @@ const ToolCallCard: Component<{
                 position: "relative",
               }}
             >
-              {/* Feature #15: Show coloured diff for edit_file results */}
+              {/* Feature #15: Show coloured diff for edit tool results */}
               <Show
-                when={props.message.toolName === "edit_file" && (() => {
+                when={DIFF_TOOLS.has(props.message.toolName ?? "") && (() => {
                   try { return JSON.parse(toolCall()!.result ?? "{}").diff_preview; } catch { return null; }
                 })()}
                 fallback={
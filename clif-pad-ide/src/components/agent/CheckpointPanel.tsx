@@ -0,0 +1,120 @@
+// Agent checkpoints — files as they were before each turn, restorable in one click
+import { Component, For, Show, createEffect, createSignal, on } from "solid-js";
+import { listAgentCheckpoints, type CheckpointSummary } from "../../lib/tauri";
+import { agentStreaming, restoreCheckpoint } from "../../stores/agentStore";
+
+interface CheckpointPanelProps {
+  projectRoot: string;
+  conversationId: string;
+}
+
+const timeAgo = (ms: number) => {
+  const diff = Math.max(0, Math.floor((Date.now() - ms) / 1000));
+  if (diff < 60) return "just now";
+  if (diff < 3600) return `${Math.floor(diff / 60)}m ago`;
+  if (diff < 86400) return `${Math.floor(diff / 3600)}h ago`;
+  return `${Math.floor(diff / 86400)}d ago`;
+};
+
+const CheckpointPanel: Component<CheckpointPanelProps> = (props) => {
+  const [checkpoints, setCheckpoints] = createSignal<CheckpointSummary[]>([]);
+  const [busy, setBusy] = createSignal(false);
+  const [notice, setNotice] = createSignal<string | null>(null);
+  const [error, setError] = createSignal<string | null>(null);
+
+  async function refresh() {
+    try {
+      setCheckpoints(await listAgentCheckpoints(props.projectRoot, props.conversationId));
+    } catch (e) {
+      setError(String(e));
+    }
+  }
+
+  // A finished turn may have added a checkpoint; a tab switch shows another thread's
+  createEffect(on([() => props.conversationId, agentStreaming], ([, streaming]) => {
+    if (!streaming) refresh();
+  }));
+
+  async function restore(cp: CheckpointSummary) {
+    const shellNote = cp.unrestorable.length
+      ? `\n\nChanges commands made to existing files can't be undone: ${cp.unrestorable.join(", ")}`
+      : "";
+    const ok = window.confirm(
+      `Restore to before "${cp.label}"?\n\nFiles the agent changed since then are put back and later messages are removed.${shellNote}`,
+    );
+    if (!ok) return;
+    setBusy(true);
+    setError(null);
+    setNotice(null);
+    try {
+      const result = await restoreCheckpoint(cp.id);
+      if (result) {
+        const n = result.restored.length + result.removed.length;
+        const kept = result.unrestorable.length;
+        setNotice(
+          `Restored ${n} file${n === 1 ? "" : "s"}` +
+            (kept ? ` · ${kept} changed by commands left as is` : ""),
+        );
+      }
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setBusy(false);
+      await refresh();
+    }
+  }
+
+  return (
+    <div
+      class="shrink-0 px-2 py-2 flex flex-col gap-1.5"
+      style={{ "border-bottom": "1px solid var(--border-default)", "max-height": "40%", overflow: "auto" }}
+    >
+      <Show when={error()}>
+        <span style={{ "font-size": "10.5px", color: "var(--accent-red)" }}>{error()}</span>
+      </Show>
+      <Show when={notice()}>
+        <span style={{ "font-size": "10.5px", color: "var(--accent-green)" }}>{notice()}</span>
+      </Show>
+      <Show
+        when={checkpoints().length > 0}
+        fallback={
+          <span style={{ "font-size": "11px", color: "var(--text-muted)" }}>
+            No checkpoints yet — one is taken before each message you send.
+          </span>
+        }
+      >
+        <For each={checkpoints()}>
+          {(cp) => (
+            <div class="flex items-center gap-2" style={{ "font-size": "11px" }}>
+              <span class="flex-1 truncate" style={{ color: "var(--text-primary)" }} title={cp.label}>
+                {cp.label}
+              </span>
+              <span
+                style={{ color: cp.unrestorable.length ? "var(--accent-yellow)" : "var(--text-muted)" }}
+                title={[...cp.files, ...cp.unrestorable.map((f) => `${f} (changed by a command)`)].join("\n")}
+              >
+                {cp.files.length}f · {timeAgo(cp.created_at)}
+              </span>
+              <button
+                class="rounded-md px-2 py-0.5 transition-colors"
+                style={{
+                  background: "var(--bg-hover)",
+                  color: "var(--text-secondary)",
+                  border: "1px solid var(--border-muted)",
+                  cursor: busy() || agentStreaming() ? "default" : "pointer",
+                  "font-size": "10.5px",
+                }}
+                disabled={busy() || agentStreaming()}
+                onClick={() => restore(cp)}
+              >
+                Restore
+              </button>
+            </div>
+          )}
+        </For>
+      </Show>
+    </div>
+  );
+};
+
+export default CheckpointPanel;
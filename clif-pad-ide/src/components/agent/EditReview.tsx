@@ -0,0 +1,127 @@
+// Review card for agent edits staged in review mode — accept/reject per file or per hunk
+import { Component, For, Show, createSignal } from "solid-js";
+import { fontSize } from "../../stores/uiStore";
+import { agentReviewAccept, agentReviewReject } from "../../lib/tauri";
+import type { PendingEditReview } from "../../types/agent";
+
+interface EditReviewProps {
+  review: PendingEditReview;
+  /** Called once every hunk has a decision */
+  onDone: () => void;
+}
+
+const lineColor = (line: string) => {
+  if (line.startsWith("@@")) return "var(--accent-blue)";
+  if (line.startsWith("+")) return "var(--accent-green)";
+  if (line.startsWith("-")) return "var(--accent-red)";
+  return "var(--text-muted)";
+};
+
+const EditReview: Component<EditReviewProps> = (props) => {
+  // "path#hunk" -> accepted?
+  const [decisions, setDecisions] = createSignal<Record<string, boolean>>({});
+  const [error, setError] = createSignal<string | null>(null);
+
+  const key = (path: string, hunk: number) => `${path}#${hunk}`;
+  const totalHunks = () => props.review.files.reduce((n, f) => n + f.hunks.length, 0);
+
+  async function decide(path: string, accept: boolean, hunk?: number) {
+    setError(null);
+    try {
+      const sid = props.review.session_id;
+      await (accept ? agentReviewAccept(sid, path, hunk) : agentReviewReject(sid, path, hunk));
+    } catch (e) {
+      setError(String(e));
+      return;
+    }
+    const file = props.review.files.find((f) => f.path === path);
+    const indices = hunk === undefined ? (file?.hunks.map((h) => h.index) ?? []) : [hunk];
+    const next = { ...decisions() };
+    for (const i of indices) next[key(path, i)] = accept;
+    setDecisions(next);
+    if (Object.keys(next).length >= totalHunks()) props.onDone();
+  }
+
+  const decisionButton = (label: string, accept: boolean, onClick: () => void) => (
+    <button
+      class="rounded-md px-2 py-0.5 text-xs font-semibold transition-colors"
+      style={{
+        background: accept ? "var(--accent-primary)" : "color-mix(in srgb, var(--accent-red) 12%, transparent)",
+        color: accept ? "var(--accent-text)" : "var(--accent-red)",
+        border: accept ? "none" : "1px solid color-mix(in srgb, var(--accent-red) 25%, transparent)",
+        cursor: "pointer",
+      }}
+      onClick={onClick}
+    >
+      {label}
+    </button>
+  );
+
+  return (
+    <div
+      class="mx-3 my-2 rounded-lg overflow-hidden"
+      style={{ border: "1px solid var(--accent-primary)", background: "var(--bg-base)" }}
+    >
+      <div class="px-3 py-2 text-xs font-semibold" style={{ color: "var(--text-primary)" }}>
+        Review proposed edits
+      </div>
+      <For each={props.review.files}>
+        {(file) => (
+          <div style={{ "border-top": "1px solid var(--border-muted)" }}>
+            <div class="flex items-center gap-2 px-3 py-1.5">
+              <span
+                class="flex-1 truncate"
+                style={{ "font-family": "var(--font-mono, monospace)", "font-size": `${fontSize() - 2}px`, color: "var(--text-secondary)" }}
+              >
+                {file.path}
+                {file.created ? " (new)" : ""}
+              </span>
+              {decisionButton("Accept all", true, () => decide(file.path, true))}
+              {decisionButton("Reject all", false, () => decide(file.path, false))}
+            </div>
+            <For each={file.hunks}>
+              {(hunk) => {
+                const decided = () => decisions()[key(file.path, hunk.index)];
+                return (
+                  <div class="px-3 pb-2" style={{ opacity: decided() === undefined ? "1" : "0.5" }}>
+                    <pre
+                      class="rounded-md px-2 py-1 overflow-x-auto"
+                      style={{
+                        margin: "0",
+                        background: "var(--bg-tertiary)",
+                        "font-size": `${fontSize() - 3}px`,
+                        "font-family": "var(--font-mono, monospace)",
+                      }}
+                    >
+                      <For each={hunk.diff.replace(/\n$/, "").split("\n")}>
+                        {(line) => <div style={{ color: lineColor(line) }}>{line || " "}</div>}
+                      </For>
+                    </pre>
+                    <div class="flex items-center gap-2 mt-1">
+                      <Show
+                        when={decided() === undefined}
+                        fallback={
+                          <span class="text-xs" style={{ color: decided() ? "var(--accent-green)" : "var(--accent-red)" }}>
+                            {decided() ? "Accepted" : "Rejected"}
+                          </span>
+                        }
+                      >
+                        {decisionButton("Accept", true, () => decide(file.path, true, hunk.index))}
+                        {decisionButton("Reject", false, () => decide(file.path, false, hunk.index))}
+                      </Show>
+                    </div>
+                  </div>
+                );
+              }}
+            </For>
+          </div>
+        )}
+      </For>
+      <Show when={error()}>
+        <div class="px-3 py-1.5 text-xs" style={{ color: "var(--accent-red)" }}>{error()}</div>
+      </Show>
+    </div>
+  );
+};
+
+export default EditReview;
@@ -26,6 +26,17 @@ interface ModelBrowserProps {
 const ModelBrowser: Component<ModelBrowserProps> = (props) => {
   const [hoveredModel, setHoveredModel] = createSignal<string | null>(null);
 
+  const directModels = () => {
+    const q = props.modelSearch().trim();
+    const popular = (POPULAR_MODELS[settings().aiProvider] || []).filter(
+      (m) => !q || m.value.toLowerCase().includes(q.toLowerCase()) || m.label.toLowerCase().includes(q.toLowerCase()),
+    );
+    if (q && !popular.some((m) => m.value === q)) {
+      return [{ value: q, label: `Use "${q}"` }, ...popular];
+    }
+    return popular;
+  };
+
   return (
     <div
       style={{
@@ -325,6 +336,31 @@ const ModelBrowser: Component<ModelBrowserProps> = (props) => {
             }}
           </For>
         </Show>
+
+        {/* Direct providers: suggested models, or any id typed into the search box */}
+        <Show when={settings().aiProvider !== "openrouter" && settings().aiProvider !== "ollama"}>
+          <For each={directModels()}>
+            {(m) => {
+              const isActive = () => settings().aiModel === m.value;
+              return (
+                <button
+                  class="flex items-center justify-between w-full rounded-lg px-3 py-2.5 transition-colors"
+                  style={{
+                    background: isActive() ? "color-mix(in srgb, var(--accent-primary) 10%, transparent)" : "transparent",
+                    border: isActive() ? "1px solid color-mix(in srgb, var(--accent-primary) 25%, transparent)" : "1px solid transparent",
+                    cursor: "pointer", "text-align": "left", "margin-bottom": "2px",
+                  }}
+                  onMouseEnter={(e) => { if (!isActive()) (e.currentTarget as HTMLElement).style.background = "var(--bg-hover)"; }}
+                  onMouseLeave={(e) => { if (!isActive()) (e.currentTarget as HTMLElement).style.background = "transparent"; }}
+                  onClick={() => { props.handleModelChange(m.value); props.setModelDropdownOpen(false); props.setModelSearch(""); }}
+                >
+                  <span style={{ "font-size": "13px", "font-weight": "500", color: isActive() ? "var(--accent-primary)" : "var(--text-primary)" }}>{m.label}</span>
+                  <span style={{ "font-size": "11px", "font-family": "var(--font-mono, monospace)", color: "var(--text-muted)" }}>{m.value}</span>
+                </button>
+              );
+            }}
+          </For>
+        </Show>
       </div>
 
       {/* Footer count */}
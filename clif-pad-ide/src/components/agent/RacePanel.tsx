@@ -0,0 +1,199 @@
+// Agent race panel — run one task on several models, each in its own git worktree and window
+import { Component, For, Show, createSignal, onCleanup, onMount } from "solid-js";
+import {
+  createAgentWorktree,
+  listAgentWorktrees,
+  agentWorktreeDiff,
+  applyAgentWorktree,
+  removeAgentWorktree,
+  createWindow,
+  type AgentWorktreeStatus,
+} from "../../lib/tauri";
+import { initGit } from "../../stores/gitStore";
+
+interface RacePanelProps {
+  projectRoot: string;
+  currentModel: string;
+}
+
+const RacePanel: Component<RacePanelProps> = (props) => {
+  const [task, setTask] = createSignal("");
+  const [models, setModels] = createSignal(props.currentModel);
+  const [worktrees, setWorktrees] = createSignal<AgentWorktreeStatus[]>([]);
+  const [diffs, setDiffs] = createSignal<Record<string, string>>({});
+  const [busy, setBusy] = createSignal(false);
+  const [error, setError] = createSignal<string | null>(null);
+
+  async function refresh() {
+    try {
+      setWorktrees(await listAgentWorktrees(props.projectRoot));
+    } catch (e) {
+      setError(String(e));
+    }
+  }
+
+  onMount(() => {
+    refresh();
+    const timer = setInterval(refresh, 5000);
+    onCleanup(() => clearInterval(timer));
+  });
+
+  async function run<T>(action: () => Promise<T>) {
+    setBusy(true);
+    setError(null);
+    try {
+      await action();
+    } catch (e) {
+      setError(String(e));
+    } finally {
+      setBusy(false);
+      await refresh();
+    }
+  }
+
+  function startRace() {
+    const content = task().trim();
+    const racers = models().split(",").map((m) => m.trim()).filter(Boolean);
+    if (!content || racers.length === 0) return;
+    const raceId = `race-${Date.now().toString(36)}`;
+    run(async () => {
+      for (const model of racers) {
+        const wt = await createAgentWorktree(props.projectRoot, model, raceId);
+        await createWindow({ project_path: wt.path, agent_task: content, agent_model: model });
+      }
+      setTask("");
+    });
+  }
+
+  async function toggleDiff(id: string) {
+    const current = { ...diffs() };
+    if (id in current) {
+      delete current[id];
+      setDiffs(current);
+      return;
+    }
+    run(async () => {
+      const diff = await agentWorktreeDiff(props.projectRoot, id);
+      setDiffs({ ...diffs(), [id]: diff || "(no changes)" });
+    });
+  }
+
+  const apply = (id: string, strategy: "merge" | "cherry-pick") =>
+    run(async () => {
+      await applyAgentWorktree(props.projectRoot, id, strategy);
+      await initGit();
+    });
+
+  const smallButton = (label: string, onClick: () => void, danger = false) => (
+    <button
+      class="rounded-md px-2 py-0.5 transition-colors"
+      style={{
+        background: danger ? "color-mix(in srgb, var(--accent-red) 12%, transparent)" : "var(--bg-hover)",
+        color: danger ? "var(--accent-red)" : "var(--text-secondary)",
+        border: "1px solid var(--border-muted)",
+        cursor: busy() ? "default" : "pointer",
+        "font-size": "10.5px",
+      }}
+      disabled={busy()}
+      onClick={onClick}
+    >
+      {label}
+    </button>
+  );
+
+  return (
+    <div
+      class="shrink-0 px-2 py-2 flex flex-col gap-2"
+      style={{ "border-bottom": "1px solid var(--border-default)", "max-height": "50%", overflow: "auto" }}
+    >
+      <textarea
+        class="w-full rounded-md px-2 py-1 outline-none resize-none"
+        rows={2}
+        style={{
+          background: "var(--bg-base)",
+          color: "var(--text-primary)",
+          border: "1px solid var(--border-muted)",
+          "font-size": "11px",
+        }}
+        placeholder="Task to race, e.g. fix the failing date parser test"
+        value={task()}
+        onInput={(e) => setTask(e.currentTarget.value)}
+      />
+      <div class="flex items-center gap-1.5">
+        <input
+          class="flex-1 min-w-0 rounded-md px-2 py-1 outline-none"
+          style={{
+            background: "var(--bg-base)",
+            color: "var(--text-primary)",
+            border: "1px solid var(--border-muted)",
+            "font-size": "11px",
+          }}
+          placeholder="Models, comma separated"
+          value={models()}
+          onInput={(e) => setModels(e.currentTarget.value)}
+        />
+        <button
+          class="shrink-0 rounded-md px-2.5 py-1 transition-colors"
+          style={{
+            background: task().trim() && !busy() ? "var(--accent-primary)" : "var(--bg-hover)",
+            color: task().trim() && !busy() ? "#fff" : "var(--text-muted)",
+            border: "none",
+            cursor: task().trim() && !busy() ? "pointer" : "default",
+            "font-size": "11px",
+            "font-weight": "500",
+          }}
+          disabled={!task().trim() || busy()}
+          onClick={startRace}
+          title="Open one window per model, each on its own worktree"
+        >
+          Race
+        </button>
+      </div>
+
+      <Show when={error()}>
+        <span style={{ "font-size": "10.5px", color: "var(--accent-red)" }}>{error()}</span>
+      </Show>
+
+      <For each={worktrees()}>
+        {(wt) => (
+          <div class="rounded-md px-2 py-1.5" style={{ border: "1px solid var(--border-muted)" }}>
+            <div class="flex items-center gap-2" style={{ "font-size": "11px" }}>
+              <span class="flex-1 truncate" style={{ color: "var(--text-primary)" }} title={wt.branch}>
+                {wt.label}
+              </span>
+              <span style={{ "font-family": "var(--font-mono, monospace)", color: "var(--text-muted)" }}>
+                {wt.stat.files_changed}f{" "}
+                <span style={{ color: "var(--accent-green)" }}>+{wt.stat.insertions}</span>{" "}
+                <span style={{ color: "var(--accent-red)" }}>−{wt.stat.deletions}</span>
+              </span>
+            </div>
+            <div class="flex items-center gap-1 mt-1">
+              {smallButton(wt.id in diffs() ? "Hide diff" : "Diff", () => toggleDiff(wt.id))}
+              {smallButton("Merge", () => apply(wt.id, "merge"))}
+              {smallButton("Cherry-pick", () => apply(wt.id, "cherry-pick"))}
+              {smallButton("Discard", () => run(() => removeAgentWorktree(props.projectRoot, wt.id)), true)}
+            </div>
+            <Show when={diffs()[wt.id]}>
+              {(diff) => (
+                <pre
+                  class="mt-1 rounded-md px-2 py-1 overflow-x-auto"
+                  style={{
+                    margin: "0",
+                    background: "var(--bg-tertiary)",
+                    "font-size": "10.5px",
+                    "font-family": "var(--font-mono, monospace)",
+                    "max-height": "240px",
+                  }}
+                >
+                  {diff()}
+                </pre>
+              )}
+            </Show>
+          </div>
+        )}
+      </For>
+    </div>
+  );
+};
+
+export default RacePanel;
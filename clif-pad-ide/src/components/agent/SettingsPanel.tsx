@@ -2,6 +2,7 @@
 import { Component, For, Show } from "solid-js";
 import type { Accessor } from "solid-js";
 import { settings, updateSettings } from "../../stores/settingsStore";
+import type { Settings } from "../../stores/settingsStore";
 import { PROVIDERS, POPULAR_MODELS } from "./constants";
 
 interface SettingsPanelProps {
@@ -25,7 +26,7 @@ const SettingsPanel: Component<SettingsPanelProps> = (props) => {
         <label style={{ "font-size": "11px", color: "var(--text-muted)", "font-weight": "500" }}>
           Provider
         </label>
-        <div class="flex gap-1.5 mt-1">
+        <div class="flex flex-wrap gap-1.5 mt-1">
           <For each={PROVIDERS}>
             {(p) => (
               <button
@@ -48,6 +49,32 @@ const SettingsPanel: Component<SettingsPanelProps> = (props) => {
         </div>
       </div>
 
+      {/* Base URL */}
+      <Show when={settings().aiProvider === "custom" || settings().aiProvider === "azure"}>
+        <div>
+          <label style={{ "font-size": "11px", color: "var(--text-muted)", "font-weight": "500" }}>
+            Base URL
+          </label>
+          <input
+            type="text"
+            class="w-full mt-1 rounded-md px-2 py-1.5 outline-none"
+            style={{
+              background: "var(--bg-base)",
+              color: "var(--text-primary)",
+              border: "1px solid var(--border-muted)",
+              "font-size": "12px",
+            }}
+            placeholder={
+              settings().aiProvider === "azure"
+                ? "https://<resource>.openai.azure.com"
+                : "https://host/v1"
+            }
+            value={settings().aiBaseUrl}
+            onChange={(e) => updateSettings({ aiBaseUrl: e.currentTarget.value.trim() })}
+          />
+        </div>
+      </Show>
+
       {/* Model */}
       <div>
         <label style={{ "font-size": "11px", color: "var(--text-muted)", "font-weight": "500" }}>
@@ -155,6 +182,86 @@ const SettingsPanel: Component<SettingsPanelProps> = (props) => {
           />
         </button>
       </div>
+
+      {/* Edit review toggle */}
+      <div class="flex items-center justify-between">
+        <div>
+          <span style={{ "font-size": "11px", color: "var(--text-muted)", "font-weight": "500" }}>
+            Review Agent Edits
+          </span>
+          <p style={{ "font-size": "10px", color: "var(--text-muted)", margin: "2px 0 0 0", opacity: "0.7" }}>
+            Accept or reject each change before it is written
+          </p>
+        </div>
+        <button
+          onClick={() => updateSettings({ agentReviewEdits: !settings().agentReviewEdits })}
+          class="rounded-full transition-colors shrink-0"
+          style={{
+            width: "32px",
+            height: "18px",
+            background: settings().agentReviewEdits ? "var(--accent-primary)" : "var(--bg-hover)",
+            border: "none",
+            cursor: "pointer",
+            position: "relative",
+          }}
+          title={settings().agentReviewEdits ? "Write agent edits directly" : "Review agent edits first"}
+        >
+          <span
+            style={{
+              position: "absolute",
+              top: "2px",
+              left: settings().agentReviewEdits ? "16px" : "2px",
+              width: "14px",
+              height: "14px",
+              "border-radius": "50%",
+              background: "#fff",
+              transition: "left 0.15s ease",
+              display: "block",
+            }}
+          />
+        </button>
+      </div>
+
+      {/* Run budgets */}
+      <div>
+        <label style={{ "font-size": "11px", color: "var(--text-muted)", "font-weight": "500" }}>
+          Run Budget
+        </label>
+        <p style={{ "font-size": "10px", color: "var(--text-muted)", margin: "2px 0 0 0", opacity: "0.7" }}>
+          The agent stops with a summary when a limit is reached · 0 = no limit
+        </p>
+        <div class="grid grid-cols-2 gap-1.5 mt-1">
+          <For each={[
+            { key: "agentMaxIterations" as const, label: "Iterations", step: "1" },
+            { key: "agentMaxSeconds" as const, label: "Seconds", step: "1" },
+            { key: "agentMaxTokens" as const, label: "Tokens", step: "1000" },
+            { key: "agentMaxCostUsd" as const, label: "Cost ($)", step: "0.1" },
+          ]}>
+            {(field) => (
+              <label class="flex items-center gap-1.5" style={{ "font-size": "11px", color: "var(--text-secondary)" }}>
+                <span class="shrink-0" style={{ width: "56px" }}>{field.label}</span>
+                <input
+                  type="number"
+                  min="0"
+                  step={field.step}
+                  class="w-full rounded-md px-2 py-1 outline-none"
+                  style={{
+                    background: "var(--bg-base)",
+                    color: "var(--text-primary)",
+                    border: "1px solid var(--border-muted)",
+                    "font-size": "12px",
+                  }}
+                  value={settings()[field.key]}
+                  onChange={(e) => {
+                    const n = Number.parseFloat(e.currentTarget.value);
+                    updateSettings({ [field.key]: Number.isFinite(n) && n > 0 ? n : 0 } as Partial<Settings>);
+                  }}
+                />
+              </label>
+            )}
+          </For>
+        </div>
+      </div>
     </div>
   );
 };
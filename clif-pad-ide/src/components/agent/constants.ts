@@ -3,6 +3,11 @@
 export const PROVIDERS = [
   { value: "openrouter", label: "OpenRouter", hint: "openrouter.ai — access 100+ models" },
   { value: "ollama", label: "Ollama", hint: "Local models — no API key needed" },
+  { value: "anthropic", label: "Anthropic", hint: "Claude models, direct" },
+  { value: "openai", label: "OpenAI", hint: "GPT models, direct" },
+  { value: "gemini", label: "Gemini", hint: "Google AI Studio key" },
+  { value: "azure", label: "Azure", hint: "Azure OpenAI deployment" },
+  { value: "custom", label: "Custom", hint: "Any OpenAI-compatible URL" },
 ];
 
 export const POPULAR_MODELS: Record<string, { value: string; label: string }[]> = {
@@ -22,6 +27,20 @@ export const POPULAR_MODELS: Record<string, { value: string; label: string }[]>
     { value: "qwen3-coder:30b", label: "qwen3-coder:30b" },
     { value: "qwen2.5-coder", label: "Qwen 2.5 Coder" },
   ],
+  anthropic: [
+    { value: "claude-sonnet-4-20250514", label: "Claude Sonnet 4" },
+    { value: "claude-opus-4-20250514", label: "Claude Opus 4" },
+    { value: "claude-3-5-haiku-latest", label: "Claude Haiku 3.5" },
+  ],
+  openai: [
+    { value: "gpt-4o", label: "GPT-4o" },
+    { value: "gpt-4o-mini", label: "GPT-4o Mini" },
+    { value: "gpt-4.1", label: "GPT-4.1" },
+  ],
+  gemini: [
+    { value: "gemini-2.5-flash", label: "Gemini 2.5 Flash" },
+    { value: "gemini-2.5-pro", label: "Gemini 2.5 Pro" },
+  ],
 };
 
 export interface OpenRouterModel {
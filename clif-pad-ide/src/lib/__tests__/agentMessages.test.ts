@@ -1,5 +1,5 @@
 import { describe, it, expect } from "vitest";
-import { buildBackendMessages } from "../agentMessages";
+import { buildBackendMessages, agentMessagesFromBackend } from "../agentMessages";
 import type { AgentMessage } from "../../types/agent";
 
 function um(content: string, images?: string[]): AgentMessage {
@@ -175,3 +175,19 @@ describe("buildBackendMessages", () => {
     expect(out.some((m) => m.role === "tool")).toBe(false);
   });
 });
+
+describe("agentMessagesFromBackend", () => {
+  it("round-trips a tool turn through buildBackendMessages", () => {
+    const backend = buildBackendMessages([
+      um("fix it"),
+      am("Looking."),
+      tc("c1", "read_file", { path: "a.rs" }),
+      tr("c1", "fn main() {}"),
+      am("Done."),
+    ]);
+    const rows = agentMessagesFromBackend(backend);
+    expect(rows.map((r) => r.role)).toEqual(["user", "assistant", "tool_call", "tool_result", "assistant"]);
+    expect(rows[2].toolCalls?.[0].result).toBe("fn main() {}");
+    expect(buildBackendMessages(rows)).toEqual(backend);
+  });
+});
@@ -119,3 +119,48 @@ export function buildBackendMessages(messages: AgentMessage[]): BackendMessage[]
   flushAssistantTurn();
   return out;
 }
+
+/**
+ * Inverse of `buildBackendMessages`: expand a persisted backend thread into
+ * display rows. Each assistant tool call becomes its own `tool_call` row
+ * (marked done, with the result attached) followed by its `tool_result`.
+ */
+export function agentMessagesFromBackend(messages: BackendMessage[]): AgentMessage[] {
+  const out: AgentMessage[] = [];
+  const callRows = new Map<string, AgentMessage>();
+  messages.forEach((m, i) => {
+    const id = `thread_${i}`;
+    if (m.role === "user") {
+      out.push({ id, role: "user", content: m.content, images: m.images, timestamp: 0, status: "done" });
+    } else if (m.role === "assistant") {
+      if (m.content) {
+        out.push({ id, role: "assistant", content: m.content, timestamp: 0, status: "done" });
+      }
+      for (const tc of m.tool_calls ?? []) {
+        let args: Record<string, unknown> = {};
+        try {
+          args = JSON.parse(tc.function.arguments);
+        } catch {
+          args = { raw: tc.function.arguments };
+        }
+        const row: AgentMessage = {
+          id: `${id}_${tc.id}`,
+          role: "tool_call",
+          content: "",
+          timestamp: 0,
+          toolName: tc.function.name,
+          toolCallId: tc.id,
+          toolCalls: [{ id: tc.id, name: tc.function.name, arguments: args, argsRaw: tc.function.arguments, status: "done" }],
+          status: "done",
+        };
+        callRows.set(tc.id, row);
+        out.push(row);
+      }
+    } else if (m.role === "tool" && m.tool_call_id) {
+      const call = callRows.get(m.tool_call_id)?.toolCalls?.[0];
+      if (call) call.result = m.content;
+      out.push({ id, role: "tool_result", content: m.content, timestamp: 0, toolCallId: m.tool_call_id, status: "done" });
+    }
+  });
+  return out;
+}
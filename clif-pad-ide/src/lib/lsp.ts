@@ -84,27 +84,14 @@ export class LspClient {
     });
     this.eventUnlisten = unlisten;
 
-    // Start the server
-    const result = await invoke<string>("lsp_start", {
+    // Start the server. The backend owns the initialize handshake (the agent
+    // talks to the same process), so this resolves once the server is ready.
+    await invoke<string>("lsp_start", {
       language: this.language,
       workspaceRoot: this.workspaceRoot,
     });
-
-    if (result === "already_running") {
-      this.initialized = true;
-      this.registerMonacoProviders();
-      return;
-    }
-
-    // Wait for initialize response then send initialized notification
-    await new Promise<void>((resolve) => {
-      this.pendingRequests.set(1, (_caps) => {
-        this.sendNotification("initialized", {});
-        this.initialized = true;
-        this.registerMonacoProviders();
-        resolve();
-      });
-    });
+    this.initialized = true;
+    this.registerMonacoProviders();
 
     // Notify server about already-open file
     const model = editor.getModel();
@@ -4,6 +4,7 @@ import { open } from "@tauri-apps/plugin-shell";
 import type { FileEntry } from "../types/files";
 import type { ChatMessage, ModelInfo } from "../types/ai";
 import type { GitFileStatus, GitBranch, GitLogEntry, GitFileNumstat } from "../types/git";
+import type { BackendMessage } from "./agentMessages";
 
 // Shell commands
 export async function openExternal(url: string): Promise<void> {
@@ -85,6 +86,11 @@ export async function getApiKey(provider: string): Promise<string | null> {
   return invoke("get_api_key", { provider });
 }
 
+/** Unlock the encrypted key file when no OS credential store is available */
+export async function unlockApiKeys(passphrase: string): Promise<void> {
+  return invoke("unlock_api_keys", { passphrase });
+}
+
 export async function generateCommitMessage(
   diff: string,
   stagedFiles: string[],
@@ -340,6 +346,16 @@ export async function agentApproveCommand(sessionId: string, approved: boolean):
   return invoke("agent_approve_command", { sessionId, approved });
 }
 
+/** Accept one hunk of a staged agent edit, or the whole file when `hunk` is omitted */
+export async function agentReviewAccept(sessionId: string, path: string, hunk?: number): Promise<void> {
+  return invoke("agent_review_accept", { sessionId, path, hunk: hunk ?? null });
+}
+
+/** Reject one hunk of a staged agent edit, or the whole file when `hunk` is omitted */
+export async function agentReviewReject(sessionId: string, path: string, hunk?: number): Promise<void> {
+  return invoke("agent_review_reject", { sessionId, path, hunk: hunk ?? null });
+}
+
 // CLIF.md project context
 export async function clifProjectInitialized(workspaceDir: string): Promise<boolean> {
   return invoke("clif_project_initialized", { workspaceDir });
@@ -367,6 +383,191 @@ export async function loadAgentHistory(workspaceDir: string): Promise<unknown> {
   return invoke("load_agent_history", { workspaceDir });
 }
 
+// Agent conversations (persisted threads, one per chat tab)
+export interface ConversationSummary {
+  id: string;
+  title: string;
+  created_at: number;
+  updated_at: number;
+  message_count: number;
+  forked_from?: string;
+}
+
+export interface ConversationDetail extends ConversationSummary {
+  messages: BackendMessage[];
+  todos: { id: string; content: string; status: string }[];
+}
+
+export async function listAgentConversations(workspaceDir: string): Promise<ConversationSummary[]> {
+  return invoke("agent_list_conversations", { workspaceDir });
+}
+
+export async function loadAgentConversation(
+  workspaceDir: string,
+  conversationId: string,
+): Promise<ConversationDetail | null> {
+  return invoke("agent_load_conversation", { workspaceDir, conversationId });
+}
+
+export async function renameAgentConversation(
+  workspaceDir: string,
+  conversationId: string,
+  title: string,
+): Promise<void> {
+  return invoke("agent_rename_conversation", { workspaceDir, conversationId, title });
+}
+
+export async function deleteAgentConversation(workspaceDir: string, conversationId: string): Promise<void> {
+  return invoke("agent_delete_conversation", { workspaceDir, conversationId });
+}
+
+export async function forkAgentConversation(
+  workspaceDir: string,
+  conversationId: string,
+  messageCount: number | null = null,
+  title: string | null = null,
+): Promise<ConversationSummary> {
+  return invoke("agent_fork_conversation", { workspaceDir, conversationId, messageCount, title });
+}
+
+// Agent checkpoints — file snapshots taken before each user turn
+export interface CheckpointSummary {
+  id: string;
+  created_at: number;
+  message_count: number;
+  label: string;
+  files: string[];
+  /** Existing files a shell command changed; restoring can't put them back */
+  unrestorable: string[];
+}
+
+export interface RestoredCheckpoint {
+  message_count: number;
+  restored: string[];
+  removed: string[];
+  unrestorable: string[];
+}
+
+export async function listAgentCheckpoints(
+  workspaceDir: string,
+  conversationId: string,
+): Promise<CheckpointSummary[]> {
+  return invoke("agent_list_checkpoints", { workspaceDir, conversationId });
+}
+
+/** Undo every turn from `checkpointId` on: files and conversation both */
+export async function restoreAgentCheckpoint(
+  workspaceDir: string,
+  conversationId: string,
+  checkpointId: string,
+): Promise<RestoredCheckpoint> {
+  return invoke("agent_restore_checkpoint", { workspaceDir, conversationId, checkpointId });
+}
+
+// Agent worktrees — one isolated checkout per racing session
+export interface AgentWorktree {
+  id: string;
+  race_id: string | null;
+  label: string;
+  path: string;
+  branch: string;
+  base: string;
+  created_at: number;
+}
+
+export interface AgentWorktreeStatus extends AgentWorktree {
+  stat: { files_changed: number; insertions: number; deletions: number };
+  commits: number;
+}
+
+export async function createAgentWorktree(
+  workspaceDir: string,
+  label: string | null = null,
+  raceId: string | null = null,
+): Promise<AgentWorktree> {
+  return invoke("agent_worktree_create", { workspaceDir, label, raceId });
+}
+
+export async function listAgentWorktrees(workspaceDir: string): Promise<AgentWorktreeStatus[]> {
+  return invoke("agent_worktree_list", { workspaceDir });
+}
+
+export async function agentWorktreeDiff(workspaceDir: string, id: string): Promise<string> {
+  return invoke("agent_worktree_diff", { workspaceDir, id });
+}
+
+/** Merge or cherry-pick a worktree back, then clean up its whole race */
+export async function applyAgentWorktree(
+  workspaceDir: string,
+  id: string,
+  strategy: "merge" | "cherry-pick",
+  message: string | null = null,
+): Promise<string> {
+  return invoke("agent_worktree_apply", { workspaceDir, id, strategy, message });
+}
+
+export async function removeAgentWorktree(workspaceDir: string, id: string): Promise<void> {
+  return invoke("agent_worktree_remove", { workspaceDir, id });
+}
+
+// MCP — external tool servers for the agent, and ClifPad's own server
+export interface McpServerStatus {
+  name: string;
+  transport: "stdio" | "http";
+  connected: boolean;
+  tools: string[];
+  error?: string;
+  /** Defined in the repo's .clif/mcp.json and not trusted yet */
+  pending_trust: boolean;
+}
+
+/** A server from .clif/mcp.json that needs trust before it may start */
+export interface McpUntrustedServer {
+  name: string;
+  /** Command line or URL the server would use */
+  runs: string;
+}
+
+export interface McpEndpoint {
+  url: string;
+  token: string;
+  /** `mcpServers` snippet to paste into another agent's config */
+  config: Record<string, unknown>;
+}
+
+export async function mcpListServers(workspaceDir: string): Promise<McpServerStatus[]> {
+  return invoke("mcp_list_servers", { workspaceDir });
+}
+
+export async function mcpReconnect(workspaceDir: string): Promise<void> {
+  return invoke("mcp_reconnect", { workspaceDir });
+}
+
+export async function mcpServerInfo(workspaceDir: string): Promise<McpEndpoint> {
+  return invoke("mcp_server_info", { workspaceDir });
+}
+
+/** Trust or distrust the servers the workspace's .clif/mcp.json defines right now */
+export async function mcpTrustWorkspace(workspaceDir: string, trusted: boolean): Promise<void> {
+  return invoke("mcp_trust_workspace", { workspaceDir, trusted });
+}
+
+// Windows
+export interface WindowLaunch {
+  project_path: string | null;
+  agent_task: string | null;
+  agent_model: string | null;
+}
+
+export async function createWindow(launch: WindowLaunch | null = null): Promise<string> {
+  return invoke("create_window", { launch });
+}
+
+/** The launch request this window was opened with, if any (returned once) */
+export async function takeWindowLaunch(): Promise<WindowLaunch | null> {
+  return invoke("take_window_launch");
+}
+
 // Agent event listeners
 export function onAgentStream(callback: (chunk: string) => void): Promise<UnlistenFn> {
   return listen<string>("agent_stream", (event) => callback(event.payload));
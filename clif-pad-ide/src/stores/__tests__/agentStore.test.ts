@@ -20,6 +20,9 @@ vi.mock("../fileStore", () => ({
 vi.mock("../../lib/tauri", () => ({
   saveAgentHistory: vi.fn(async () => {}),
   loadAgentHistory: vi.fn(async () => null),
+  listAgentConversations: vi.fn(async () => []),
+  loadAgentConversation: vi.fn(async () => null),
+  deleteAgentConversation: vi.fn(async () => {}),
 }));
 
 // Import AFTER mocks are set up
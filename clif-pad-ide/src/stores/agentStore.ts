@@ -2,11 +2,20 @@ import { createSignal, createEffect } from "solid-js";
 import { createStore, produce } from "solid-js/store";
 import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
 import { invoke } from "@tauri-apps/api/core";
-import type { AgentMessage, ToolCall, AgentContext } from "../types/agent";
+import type { AgentMessage, ToolCall, AgentContext, AgentRunUsage } from "../types/agent";
 import { settings } from "./settingsStore";
 import { projectRoot } from "./fileStore";
-import { saveAgentHistory, loadAgentHistory } from "../lib/tauri";
-import { buildBackendMessages } from "../lib/agentMessages";
+import {
+  saveAgentHistory,
+  loadAgentHistory,
+  listAgentConversations,
+  loadAgentConversation,
+  deleteAgentConversation,
+  restoreAgentCheckpoint,
+  mcpTrustWorkspace,
+  type McpUntrustedServer,
+} from "../lib/tauri";
+import { buildBackendMessages, agentMessagesFromBackend } from "../lib/agentMessages";
 
 interface AgentTab {
   id: string;
@@ -21,12 +30,18 @@ const [agentSessionId, setAgentSessionId] = createSignal<string | null>(null);
 const [agentError, setAgentError] = createSignal<string | null>(null);
 const [agentTokens, setAgentTokens] = createSignal({ prompt: 0, completion: 0, context: 0 });
 const [agentStatus, setAgentStatus] = createSignal("");
+// Totals of the latest run against its budget, from `agent_usage`
+const [agentRunUsage, setAgentRunUsage] = createSignal<AgentRunUsage | null>(null);
 const [agentTabs, setAgentTabs] = createStore<AgentTab[]>([]);
 const [activeAgentTab, setActiveAgentTab] = createSignal("default");
 const [queuedMessages, setQueuedMessages] = createSignal<string[]>([]);
+// First message for a window opened with a task (e.g. one racer of an agent race)
+const [launchTask, setLaunchTask] = createSignal<{ content: string; model?: string } | null>(null);
 let tabCounter = 0;
 
 let unlisteners: UnlistenFn[] = [];
+/** Untrusted .clif/mcp.json contents the user already declined this session */
+const declinedMcpTrust = new Set<string>();
 let messageIdCounter = 0;
 let saveDebounceTimer: ReturnType<typeof setTimeout> | null = null;
 
@@ -51,25 +66,56 @@ function scheduleSave() {
 async function restoreAgentHistory(workspaceDir: string) {
   try {
     const data = await loadAgentHistory(workspaceDir) as any;
-    if (!data) return;
-    if (data.tabs && Array.isArray(data.tabs) && data.tabs.length > 0) {
-      setAgentTabs(data.tabs);
-      tabCounter = data.tabs.length;
-    }
-    if (data.messages && Array.isArray(data.messages) && data.messages.length > 0) {
-      setAgentMessages(data.messages);
-    }
-    if (data.tokens) {
-      setAgentTokens(data.tokens);
+    if (data) {
+      if (data.tabs && Array.isArray(data.tabs) && data.tabs.length > 0) {
+        setAgentTabs(data.tabs);
+        tabCounter = data.tabs.length;
+      }
+      if (data.messages && Array.isArray(data.messages) && data.messages.length > 0) {
+        setAgentMessages(data.messages);
+      }
+      if (data.tokens) {
+        setAgentTokens(data.tokens);
+      }
+      if (data.activeTab) {
+        setActiveAgentTab(data.activeTab);
+      }
     }
-    if (data.activeTab) {
-      setActiveAgentTab(data.activeTab);
+  } catch {
+    // ignore restore errors
+  }
+  await restoreBackendThreads(workspaceDir);
+}
+
+// The backend owns each tab's conversation log. Add tabs for threads this
+// window doesn't know about (forks, or turns saved after the last UI save)
+// and fill an empty active tab from its thread.
+async function restoreBackendThreads(workspaceDir: string) {
+  try {
+    const threads = await listAgentConversations(workspaceDir);
+    for (const t of threads) {
+      if (t.id === activeAgentTab() || agentTabs.some((tab) => tab.id === t.id)) continue;
+      setAgentTabs(produce((tabs) => tabs.push({
+        id: t.id,
+        label: t.title,
+        messages: [],
+        tokens: { prompt: 0, completion: 0, context: 0 },
+      })));
     }
+    if (agentMessages.length === 0) await hydrateActiveTab(workspaceDir);
   } catch {
     // ignore restore errors
   }
 }
 
+async function hydrateActiveTab(workspaceDir: string) {
+  const tabId = activeAgentTab();
+  const thread = await loadAgentConversation(workspaceDir, tabId).catch(() => null);
+  // The user may have switched tabs or started typing while we waited
+  if (!thread || activeAgentTab() !== tabId || agentMessages.length > 0) return;
+  setAgentMessages(agentMessagesFromBackend(thread.messages));
+}
+
 function genId(): string {
   return `msg_${Date.now()}_${++messageIdCounter}`;
 }
@@ -426,16 +472,42 @@ async function initAgentListeners() {
   );
 
   unlisteners.push(
-    await appWindow.listen<{ prompt_tokens: number; completion_tokens: number; estimated_context: number }>("agent_usage", (event) => {
-      const { prompt_tokens, completion_tokens, estimated_context } = event.payload;
+    await appWindow.listen<{ prompt_tokens: number; completion_tokens: number; estimated_context: number } & AgentRunUsage>("agent_usage", (event) => {
+      const { prompt_tokens, completion_tokens, estimated_context, ...run } = event.payload;
       setAgentTokens((prev) => ({
         prompt: prev.prompt + prompt_tokens,
         completion: prev.completion + completion_tokens,
         context: estimated_context,
       }));
+      setAgentRunUsage(run);
     })
   );
 
+  // The repo's .clif/mcp.json defines servers that have not been trusted.
+  // Nothing from it runs until the user says so; a "no" holds until the
+  // file changes or the app restarts.
+  unlisteners.push(
+    await appWindow.listen<{ workspace_dir: string; servers: McpUntrustedServer[] }>(
+      "agent_mcp_trust_required",
+      (event) => {
+        const { workspace_dir, servers } = event.payload;
+        const key = workspace_dir + "\0" + JSON.stringify(servers);
+        if (declinedMcpTrust.has(key)) return;
+        const list = servers.map((s) => `• ${s.name}: ${s.runs}`).join("\n");
+        const ok = window.confirm(
+          "This workspace's .clif/mcp.json wants to start MCP servers for the agent:\n\n" +
+            list +
+            "\n\nThey run with your user's permissions. Only trust them if you trust this repository.",
+        );
+        if (ok) {
+          void mcpTrustWorkspace(workspace_dir, true).catch((e) => console.error("MCP trust failed:", e));
+        } else {
+          declinedMcpTrust.add(key);
+        }
+      },
+    )
+  );
+
   unlisteners.push(
     await appWindow.listen<string>("agent_status", (event) => {
       setAgentStatus(event.payload);
@@ -603,6 +675,23 @@ function clearAgentMessages() {
   setAgentError(null);
 }
 
+/**
+ * Roll the active conversation back to a checkpoint. The backend restores the
+ * files and rewinds the thread log; the tab is then reloaded from that log.
+ */
+async function restoreCheckpoint(checkpointId: string) {
+  const workspaceDir = projectRoot();
+  if (!workspaceDir || agentStreaming()) return null;
+  const restored = await restoreAgentCheckpoint(workspaceDir, activeAgentTab(), checkpointId);
+  setAgentMessages([]);
+  setAgentError(null);
+  setAgentSessionId(null);
+  await hydrateActiveTab(workspaceDir);
+  saveCurrentTab();
+  scheduleSave();
+  return restored;
+}
+
 function saveCurrentTab() {
   const currentId = activeAgentTab();
   const msgs = [...agentMessages];
@@ -632,6 +721,8 @@ function switchAgentTab(tabId: string) {
     setAgentMessages(tab.messages);
     setAgentTokens(tab.tokens);
     setAgentError(null);
+    const root = projectRoot();
+    if (tab.messages.length === 0 && root) void hydrateActiveTab(root);
   }
   scheduleSave();
 }
@@ -639,10 +730,15 @@ function switchAgentTab(tabId: string) {
 function removeAgentTab(tabId: string) {
   if (agentStreaming()) return;
 
-  // Release backend-side conversation state (read-file set + todos) for
-  // the closing tab so a future tab with the same id starts clean and
-  // the maps don't grow unbounded across long IDE sessions.
-  invoke("agent_clear_conversation", { conversationId: tabId }).catch(() => {});
+  // Closing a tab deletes its persisted thread and releases backend-side
+  // conversation state (read-file set + todos) so the maps don't grow
+  // unbounded across long IDE sessions.
+  const root = projectRoot();
+  if (root) {
+    deleteAgentConversation(root, tabId).catch(() => {});
+  } else {
+    invoke("agent_clear_conversation", { conversationId: tabId }).catch(() => {});
+  }
 
   const isActive = activeAgentTab() === tabId;
 
@@ -688,7 +784,8 @@ function removeAgentTab(tabId: string) {
 
 /** Internal helper: reset state to a blank session without saving current tab */
 function _resetToNewSession() {
-  const newId = `chat-${++tabCounter}`;
+  // Tab ids name persisted threads, so they must not repeat across restarts
+  const newId = `chat-${++tabCounter}-${Date.now().toString(36)}`;
   setActiveAgentTab(newId);
   setAgentMessages([]);
   setAgentError(null);
@@ -766,6 +863,7 @@ function clearAgentState() {
   setAgentSessionId(null);
   setAgentStreaming(false);
   setAgentTokens({ prompt: 0, completion: 0, context: 0 });
+  setAgentRunUsage(null);
   setAgentStatus("");
   setActiveAgentTab("default");
   tabCounter = 0;
@@ -780,6 +878,7 @@ export {
   agentError,
   agentTokens,
   agentStatus,
+  agentRunUsage,
   agentTabs,
   activeAgentTab,
   initAgentListeners,
@@ -787,6 +886,7 @@ export {
   stopAgent,
   forcePushAgent,
   clearAgentMessages,
+  restoreCheckpoint,
   startNewSession,
   ensurePrChat,
   switchAgentTab,
@@ -794,4 +894,6 @@ export {
   restoreAgentHistory,
   clearAgentState,
   queuedMessages,
+  launchTask,
+  setLaunchTask,
 };
@@ -14,7 +14,16 @@ interface Settings {
   vimMode: boolean;
   aiProvider: string;
   aiModel: string;
+  /** Endpoint for the "custom" and "azure" providers */
+  aiBaseUrl: string;
   inlineAiEnabled: boolean;
+  /** Hold agent file edits for per-hunk review before they hit disk */
+  agentReviewEdits: boolean;
+  /** Per-run agent budgets; 0 means no limit (iterations fall back to 200) */
+  agentMaxIterations: number;
+  agentMaxSeconds: number;
+  agentMaxTokens: number;
+  agentMaxCostUsd: number;
 }
 
 const defaultSettings: Settings = {
@@ -29,7 +38,13 @@ const defaultSettings: Settings = {
   vimMode: false,
   aiProvider: "openrouter",
   aiModel: "anthropic/claude-sonnet-4",
+  aiBaseUrl: "",
   inlineAiEnabled: true,
+  agentReviewEdits: false,
+  agentMaxIterations: 200,
+  agentMaxSeconds: 0,
+  agentMaxTokens: 0,
+  agentMaxCostUsd: 0,
 };
 
 const [settings, setSettingsLocal] = createSignal<Settings>(defaultSettings);
@@ -53,6 +53,12 @@ export interface AgentContext {
   activeFile?: string;
   gitBranch?: string;
   agentMode?: "agent" | "ask" | "plan";
+  /** Stage file edits for per-hunk review instead of writing them directly */
+  reviewEdits?: boolean;
+  /** Per-request overrides of the budgets in settings */
+  budget?: { maxIterations?: number; maxSeconds?: number; maxTokens?: number; maxCostUsd?: number };
+  /** USD per token for the selected model, as the OpenRouter model list reports it */
+  pricing?: { prompt: string; completion: string };
   reviewPr?: {
     number: number;
     title: string;
@@ -85,3 +91,30 @@ export interface WritePreview {
   bytes: number;
   preview: string;
 }
+
+export interface EditReviewHunk {
+  index: number;
+  old_start: number;
+  old_lines: number;
+  new_start: number;
+  new_lines: number;
+  /** Unified diff body including the `@@` header */
+  diff: string;
+}
+
+/** Payload of the `agent_edit_review` event */
+export interface PendingEditReview {
+  session_id: string;
+  tool_call_id: string;
+  files: { path: string; created: boolean; hunks: EditReviewHunk[] }[];
+}
+
+/** Run totals carried by `agent_usage`, measured against the run's budget */
+export interface AgentRunUsage {
+  iterations: number;
+  tool_calls: number;
+  elapsed_ms: number;
+  total_tokens: number;
+  cost_usd: number;
+  budget: { max_iterations: number; max_seconds?: number; max_tokens?: number; max_cost_usd?: number };
+}
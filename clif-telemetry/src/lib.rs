@@ -0,0 +1,564 @@
+//! Trace export shared by `clifcode` and ClifPad — agent turns, model
+//! requests and tool calls as spans.
+//!
+//! Both agents open `turn`, `model_request` and `tool_call` spans with the
+//! `tracing` crate. This layer collects them on close and sends each one to:
+//!   - a JSON-lines trace file, one span per line
+//!   - an OTLP/HTTP collector, JSON-encoded and batched on a background
+//!     thread. `OTEL_EXPORTER_OTLP_HEADERS` adds headers, e.g. an auth token.
+//!
+//! Each app decides where its spans go (flags, its own config file) and
+//! passes a [`TraceConfig`] to [`init`]. With neither destination configured
+//! nothing is installed and the spans cost nothing. Since both apps share
+//! this code, their traces have the same line format and `args_hash` values.
+
+use serde_json::{json, Map, Value};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Spans per OTLP request
+const BATCH_SIZE: usize = 128;
+/// Longest a span waits before being sent
+const BATCH_DELAY: Duration = Duration::from_secs(2);
+/// Events kept per span
+const MAX_EVENTS: usize = 32;
+
+/// The app the spans come from, as reported to the collector
+#[derive(Debug, Clone, Copy)]
+pub struct Service {
+    pub name: &'static str,
+    pub version: &'static str,
+}
+
+/// Where spans go
+#[derive(Debug, Clone, Default)]
+pub struct TraceConfig {
+    pub file: Option<PathBuf>,
+    /// Collector base URL; spans are POSTed to `<endpoint>/v1/traces`
+    pub otlp_endpoint: Option<String>,
+    pub otlp_headers: Vec<(String, String)>,
+}
+
+impl TraceConfig {
+    /// `file` as given; the collector from `OTEL_EXPORTER_OTLP_ENDPOINT`,
+    /// else `otlp_endpoint` from the app's own config
+    pub fn from_env(file: Option<PathBuf>, otlp_endpoint: Option<String>) -> Self {
+        let env = |key: &str| std::env::var(key).ok().filter(|v| !v.trim().is_empty());
+        TraceConfig {
+            file,
+            otlp_endpoint: env("OTEL_EXPORTER_OTLP_ENDPOINT").or(otlp_endpoint),
+            otlp_headers: env("OTEL_EXPORTER_OTLP_HEADERS")
+                .map(|h| parse_headers(&h))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// `key=value,key2=value2`, as the OpenTelemetry spec defines it
+fn parse_headers(raw: &str) -> Vec<(String, String)> {
+    raw.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .filter(|(k, _)| !k.is_empty())
+        .collect()
+}
+
+/// Short stable hash of tool arguments, so calls can be grouped without
+/// exporting file contents: 64-bit FNV-1a over the arguments as compact JSON
+/// with sorted keys, so hashes from both apps' traces can be compared.
+pub fn args_hash(arguments: &str) -> String {
+    let canonical = serde_json::from_str::<Value>(arguments)
+        .map(|value| canonical_json(&value))
+        .unwrap_or_else(|_| arguments.to_string());
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in canonical.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{hash:016x}")
+}
+
+/// Compact JSON with object keys sorted, whatever order they arrived in
+fn canonical_json(value: &Value) -> String {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let fields: Vec<String> = entries
+                .iter()
+                .map(|(key, value)| format!("{}:{}", json!(key), canonical_json(value)))
+                .collect();
+            format!("{{{}}}", fields.join(","))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(canonical_json).collect();
+            format!("[{}]", items.join(","))
+        }
+        other => other.to_string(),
+    }
+}
+
+/// A finished span
+#[derive(Debug, Clone)]
+struct SpanRecord {
+    service: &'static str,
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Map<String, Value>,
+    events: Vec<(SystemTime, Map<String, Value>)>,
+}
+
+fn unix_nanos(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+impl SpanRecord {
+    /// Failed if it recorded an `error` or `success = false`
+    fn failed(&self) -> bool {
+        self.attributes.contains_key("error")
+            || self.attributes.get("success") == Some(&Value::Bool(false))
+    }
+
+    fn to_jsonl(&self) -> Value {
+        json!({
+            "service": self.service,
+            "trace_id": self.trace_id,
+            "span_id": self.span_id,
+            "parent_span_id": self.parent_span_id,
+            "name": self.name,
+            "start_unix_nano": unix_nanos(self.start).to_string(),
+            "duration_ms": self.end.duration_since(self.start).unwrap_or_default().as_secs_f64() * 1000.0,
+            "status": if self.failed() { "error" } else { "ok" },
+            "attributes": self.attributes,
+            "events": self.events.iter().map(|(time, fields)| json!({
+                "time_unix_nano": unix_nanos(*time).to_string(),
+                "fields": fields,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    fn to_otlp(&self) -> Value {
+        let mut span = json!({
+            "traceId": self.trace_id,
+            "spanId": self.span_id,
+            "name": self.name,
+            "kind": 1,
+            "startTimeUnixNano": unix_nanos(self.start).to_string(),
+            "endTimeUnixNano": unix_nanos(self.end).to_string(),
+            "attributes": otlp_attributes(&self.attributes),
+            "events": self.events.iter().map(|(time, fields)| json!({
+                "timeUnixNano": unix_nanos(*time).to_string(),
+                "name": fields.get("message").and_then(|m| m.as_str()).unwrap_or("event"),
+                "attributes": otlp_attributes(fields),
+            })).collect::<Vec<_>>(),
+            "status": if self.failed() {
+                json!({"code": 2, "message": self.attributes.get("error").and_then(|e| e.as_str()).unwrap_or("")})
+            } else {
+                json!({"code": 1})
+            },
+        });
+        if let Some(parent) = &self.parent_span_id {
+            span["parentSpanId"] = json!(parent);
+        }
+        span
+    }
+}
+
+fn otlp_attributes(fields: &Map<String, Value>) -> Vec<Value> {
+    fields
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::Bool(b) => json!({"boolValue": b}),
+                Value::Number(n) if n.is_i64() || n.is_u64() => json!({"intValue": n.to_string()}),
+                Value::Number(n) => json!({"doubleValue": n.as_f64()}),
+                Value::String(s) => json!({"stringValue": s}),
+                other => json!({"stringValue": other.to_string()}),
+            };
+            json!({"key": key, "value": value})
+        })
+        .collect()
+}
+
+fn otlp_payload(service: Service, spans: &[SpanRecord]) -> Value {
+    json!({
+        "resourceSpans": [{
+            "resource": {"attributes": [
+                {"key": "service.name", "value": {"stringValue": service.name}},
+                {"key": "service.version", "value": {"stringValue": service.version}},
+            ]},
+            "scopeSpans": [{
+                "scope": {"name": service.name},
+                "spans": spans.iter().map(SpanRecord::to_otlp).collect::<Vec<_>>(),
+            }],
+        }]
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Layer
+// ---------------------------------------------------------------------------
+
+/// Per-span state kept in the registry's extensions until close
+struct SpanState {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: Option<String>,
+    start: SystemTime,
+    attributes: Map<String, Value>,
+    events: Vec<(SystemTime, Map<String, Value>)>,
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), json!(value));
+    }
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().into(), json!(format!("{value:?}")));
+    }
+}
+
+/// Random hex ids — `RandomState` is seeded per process
+fn random_hex(bytes: usize) -> String {
+    let mut out = String::with_capacity(bytes * 2);
+    while out.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(unix_nanos(SystemTime::now()));
+        out.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    out.truncate(bytes * 2);
+    out
+}
+
+struct TraceLayer {
+    service: Service,
+    file: Option<Mutex<std::io::BufWriter<std::fs::File>>>,
+    otlp: Option<Mutex<mpsc::Sender<Message>>>,
+}
+
+enum Message {
+    Span(Box<SpanRecord>),
+    /// Send what's queued, then reply
+    Flush(mpsc::Sender<()>),
+}
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let (trace_id, parent_span_id) = match span.parent() {
+            Some(parent) => match parent.extensions().get::<SpanState>() {
+                Some(p) => (p.trace_id.clone(), Some(p.span_id.clone())),
+                None => (random_hex(16), None),
+            },
+            None => (random_hex(16), None),
+        };
+        let mut attributes = Map::new();
+        attrs.record(&mut FieldVisitor(&mut attributes));
+        span.extensions_mut().insert(SpanState {
+            trace_id,
+            span_id: random_hex(8),
+            parent_span_id,
+            start: SystemTime::now(),
+            attributes,
+            events: Vec::new(),
+        });
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            if let Some(state) = extensions.get_mut::<SpanState>() {
+                values.record(&mut FieldVisitor(&mut state.attributes));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.event_span(event) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(state) = extensions.get_mut::<SpanState>() {
+            if state.events.len() < MAX_EVENTS {
+                let mut fields = Map::new();
+                fields.insert("level".into(), json!(event.metadata().level().as_str()));
+                event.record(&mut FieldVisitor(&mut fields));
+                state.events.push((SystemTime::now(), fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(state) = span.extensions_mut().remove::<SpanState>() else {
+            return;
+        };
+        let record = SpanRecord {
+            service: self.service.name,
+            trace_id: state.trace_id,
+            span_id: state.span_id,
+            parent_span_id: state.parent_span_id,
+            name: span.name().to_string(),
+            start: state.start,
+            end: SystemTime::now(),
+            attributes: state.attributes,
+            events: state.events,
+        };
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            let _ = writeln!(file, "{}", record.to_jsonl());
+            let _ = file.flush();
+        }
+        if let Some(otlp) = &self.otlp {
+            let _ = otlp.lock().unwrap().send(Message::Span(Box::new(record)));
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// OTLP exporter
+// ---------------------------------------------------------------------------
+
+fn export(service: Service, endpoint: &str, headers: &[(String, String)], spans: &[SpanRecord]) {
+    if spans.is_empty() {
+        return;
+    }
+    let url = format!("{}/v1/traces", endpoint.trim_end_matches('/'));
+    let mut request = ureq::post(&url).timeout(Duration::from_secs(10));
+    for (key, value) in headers {
+        request = request.set(key, value);
+    }
+    // Telemetry must never disturb the app — failures are dropped
+    let _ = request.send_json(otlp_payload(service, spans));
+}
+
+/// The exporter gets its own thread, so it keeps draining while the app's
+/// async runtime, if any, is shutting down
+fn spawn_exporter(
+    service: Service,
+    endpoint: String,
+    headers: Vec<(String, String)>,
+) -> mpsc::Sender<Message> {
+    let (tx, rx) = mpsc::channel::<Message>();
+    std::thread::spawn(move || {
+        let mut batch = Vec::new();
+        loop {
+            match rx.recv_timeout(BATCH_DELAY) {
+                Ok(Message::Span(span)) => {
+                    batch.push(*span);
+                    if batch.len() >= BATCH_SIZE {
+                        export(service, &endpoint, &headers, &std::mem::take(&mut batch));
+                    }
+                }
+                Ok(Message::Flush(done)) => {
+                    export(service, &endpoint, &headers, &std::mem::take(&mut batch));
+                    let _ = done.send(());
+                }
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    export(service, &endpoint, &headers, &std::mem::take(&mut batch));
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    export(service, &endpoint, &headers, &batch);
+                    break;
+                }
+            }
+        }
+    });
+    tx
+}
+
+/// Flushes queued spans when dropped — keep it alive until exit
+pub struct TraceGuard {
+    otlp: Option<mpsc::Sender<Message>>,
+}
+
+impl Drop for TraceGuard {
+    fn drop(&mut self) {
+        if let Some(otlp) = &self.otlp {
+            let (done_tx, done_rx) = mpsc::channel();
+            if otlp.send(Message::Flush(done_tx)).is_ok() {
+                let _ = done_rx.recv_timeout(Duration::from_secs(5));
+            }
+        }
+    }
+}
+
+fn open_trace_file(path: &Path) -> Result<std::fs::File, String> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("Cannot open trace file {}: {e}", path.display()))
+}
+
+/// Install the trace layer as the global subscriber. Returns `None` when no
+/// destination is configured.
+pub fn init(service: Service, config: &TraceConfig) -> Result<Option<TraceGuard>, String> {
+    if config.file.is_none() && config.otlp_endpoint.is_none() {
+        return Ok(None);
+    }
+    let file = match &config.file {
+        Some(path) => Some(Mutex::new(std::io::BufWriter::new(open_trace_file(path)?))),
+        None => None,
+    };
+    let otlp = config
+        .otlp_endpoint
+        .clone()
+        .map(|endpoint| spawn_exporter(service, endpoint, config.otlp_headers.clone()));
+    let layer = TraceLayer {
+        service,
+        file,
+        otlp: otlp.clone().map(Mutex::new),
+    };
+    tracing_subscriber::registry()
+        .with(layer)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+    Ok(Some(TraceGuard { otlp }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::subscriber::with_default;
+
+    const SERVICE: Service = Service {
+        name: "clif-test",
+        version: "0.0.0",
+    };
+
+    #[test]
+    fn parses_otel_headers() {
+        assert_eq!(
+            parse_headers(" Authorization = Bearer abc ,x-team=core,broken,=empty"),
+            vec![
+                ("Authorization".to_string(), "Bearer abc".to_string()),
+                ("x-team".to_string(), "core".to_string()),
+            ]
+        );
+        assert!(parse_headers("").is_empty());
+    }
+
+    #[test]
+    fn args_hash_ignores_key_order_and_whitespace() {
+        let hash = args_hash(r#"{"b": 1, "a": [true, "x"]}"#);
+        assert_eq!(hash, args_hash(r#"{"a":[true,"x"],"b":1}"#));
+        // Fixed vector; traces already on disk rely on it
+        assert_eq!(hash, "53ff724af1f39aeb");
+        assert_ne!(hash, args_hash(r#"{"a":[true,"y"],"b":1}"#));
+        assert_eq!(args_hash("not json").len(), 16);
+    }
+
+    #[test]
+    fn layer_links_spans_and_exports_otlp() {
+        let (tx, rx) = mpsc::channel();
+        let layer = TraceLayer {
+            service: SERVICE,
+            file: None,
+            otlp: Some(Mutex::new(tx)),
+        };
+        with_default(tracing_subscriber::registry().with(layer), || {
+            let turn = tracing::info_span!("turn", iteration = 3_i64);
+            let _turn = turn.enter();
+            let call = tracing::info_span!(
+                "tool_call",
+                tool = "read_file",
+                success = tracing::field::Empty
+            );
+            let _call = call.enter();
+            tracing::warn!("slow read");
+            call.record("success", false);
+        });
+
+        let spans: Vec<SpanRecord> = rx
+            .try_iter()
+            .filter_map(|m| match m {
+                Message::Span(span) => Some(*span),
+                Message::Flush(_) => None,
+            })
+            .collect();
+        let [call, turn] = spans.as_slice() else {
+            panic!("expected two spans, got {}", spans.len());
+        };
+        assert_eq!(
+            (call.name.as_str(), turn.name.as_str()),
+            ("tool_call", "turn")
+        );
+        assert_eq!(call.trace_id, turn.trace_id);
+        assert_eq!(call.parent_span_id.as_ref(), Some(&turn.span_id));
+        assert_eq!(turn.parent_span_id, None);
+        assert_eq!((call.trace_id.len(), call.span_id.len()), (32, 16));
+        assert_eq!(call.events.len(), 1);
+        assert_eq!(call.events[0].1["level"], "WARN");
+        assert!(call.failed() && !turn.failed());
+
+        let otlp = call.to_otlp();
+        assert_eq!(otlp["parentSpanId"], json!(turn.span_id));
+        assert_eq!(otlp["status"]["code"], 2);
+        assert_eq!(otlp["events"][0]["name"], "slow read");
+        assert!(otlp["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({"key": "tool", "value": {"stringValue": "read_file"}})));
+        let otlp = turn.to_otlp();
+        assert!(otlp.get("parentSpanId").is_none());
+        assert_eq!(otlp["status"]["code"], 1);
+        assert_eq!(
+            otlp["attributes"][0],
+            json!({"key": "iteration", "value": {"intValue": "3"}})
+        );
+        assert_eq!(call.to_jsonl()["service"], "clif-test");
+        let payload = otlp_payload(SERVICE, &spans);
+        assert_eq!(
+            payload["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"],
+            "clif-test"
+        );
+        assert_eq!(
+            payload["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}